@@ -89,6 +89,19 @@ pub async fn restore(
             name: filename,
             compression_format: None,
             drop_database_first: Some(drop_database),
+            message_level: None,
+            no_tablespaces: None,
+            client_encoding: None,
+            schema_only: None,
+            termination_timeout: None,
+            terminate_connections: None,
+            run_analyze: None,
+            analyze_jobs: None,
+            jobs: None,
+            single_transaction: None,
+            validate_only: None,
+            rate_limit_bytes_per_sec: None,
+            progress: None,
         })
         .await
         .map_err(|e| format!("Failed to restore backup: {}", e))?;
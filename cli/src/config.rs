@@ -0,0 +1,104 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use dbkp_core::{databases::DatabaseConfig, storage::provider::StorageConfig};
+use serde::Deserialize;
+
+/// Database and storage read from `--config`, in the format of workspaces, e.g.
+///
+/// ```toml
+/// [database]
+/// connection_type = "PostgreSql"
+/// host = "db.example.com"
+/// database = "myapp"
+/// username = "backup"
+/// password = "${PGPASSWORD}"
+///
+/// [storage.Local]
+/// name = "default"
+/// location = "/backups/myapp"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub database: Option<DatabaseConfig>,
+    pub storage: Option<StorageConfig>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        if let Some("yaml" | "yml") = extension.as_deref() {
+            return Err(anyhow!(
+                "YAML config files are not supported, use TOML instead: {}",
+                path
+            ));
+        }
+
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+
+        Self::parse(&content, |name| env::var(name).ok())
+            .with_context(|| format!("Invalid config file {}", path))
+    }
+
+    /// Parses TOML, replacing `${NAME}` in strings with `lookup(NAME)` and `$${` with `${`.
+    pub fn parse(content: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut value: toml::Value = toml::from_str(content)?;
+        interpolate(&mut value, &lookup)?;
+
+        Ok(value.try_into()?)
+    }
+}
+
+fn interpolate(value: &mut toml::Value, lookup: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    match value {
+        toml::Value::String(string) => *string = interpolate_str(string, lookup)?,
+        toml::Value::Array(values) => {
+            for value in values {
+                interpolate(value, lookup)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                interpolate(value, lookup)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn interpolate_str(string: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let end = variable
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed ${{ in a config value"))?;
+            let name = &variable[..end];
+            let value =
+                lookup(name).ok_or_else(|| anyhow!("Environment variable {} is not set", name))?;
+
+            result.push_str(&value);
+            rest = &variable[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
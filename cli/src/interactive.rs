@@ -2,15 +2,19 @@ use anyhow::{anyhow, Result};
 use colored::*;
 use dbkp_core::{
     databases::{
-        ssh_tunnel::{SshAuthMethod, SshTunnelConfig},
+        ssh_tunnel::{HostKeyVerification, SshAuthMethod, SshTunnelConfig},
         ConnectionType, DatabaseConfig,
     },
-    storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig},
+    storage::provider::{
+        GcsStorageConfig, LocalStorageConfig, RetryConfig, S3StorageConfig, SftpStorageConfig,
+        StorageConfig, StorageCredentials, UploadOptions, WebDavStorageConfig,
+    },
 };
 use inquire::{Confirm, Password, Select, Text};
 
+use crate::cli::parse_jump_host;
 use crate::spinner::Spinner;
-use crate::workspace::{Workspace, WorkspaceCollection, WorkspaceManager};
+use crate::workspace::{Workspace, WorkspaceCollection, WorkspaceManager, WorkspaceRestoreOptions};
 
 pub struct InteractiveSetup {
     workspace_manager: WorkspaceManager,
@@ -184,6 +188,7 @@ impl InteractiveSetup {
             storage: storage_config,
             created_at: chrono::Utc::now().to_rfc3339(),
             last_used: None,
+            restore_options: WorkspaceRestoreOptions::default(),
         };
 
         spinner.stop();
@@ -240,7 +245,17 @@ impl InteractiveSetup {
             port,
             username,
             password,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         })
     }
 
@@ -249,31 +264,80 @@ impl InteractiveSetup {
 
         let username = Text::new("SSH Username:").prompt()?;
 
-        let key_path = Text::new("SSH Private Key Path:")
-            .with_help_message("Path to your SSH private key file")
+        let use_agent = Confirm::new("Authenticate with ssh-agent?")
+            .with_default(false)
+            .with_help_message("Use the keys of the running agent instead of a key file")
+            .prompt()?;
+
+        let auth_method = if use_agent {
+            SshAuthMethod::Agent
+        } else {
+            let key_path = Text::new("SSH Private Key Path:")
+                .with_help_message("Path to your SSH private key file")
+                .prompt()?;
+
+            SshAuthMethod::PrivateKey {
+                key_path,
+                passphrase_key: None,
+            }
+        };
+
+        let jumps = Text::new("Jump hosts (optional):")
+            .with_help_message(
+                "Comma separated [user@]host[:port] to go through first, like ssh -J",
+            )
+            .prompt()?;
+
+        let jump_hosts = jumps
+            .split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(|spec| parse_jump_host(spec, &username, &auth_method))
+            .collect::<Result<Vec<_>>>()?;
+
+        let accept_new = Confirm::new("Trust SSH hosts missing from known_hosts?")
+            .with_default(false)
+            .with_help_message(
+                "Their keys are recorded on first connection, changed keys are still refused",
+            )
             .prompt()?;
 
         Ok(SshTunnelConfig {
             port: 22,
             host,
             username,
-            auth_method: SshAuthMethod::PrivateKey {
-                key_path,
-                passphrase_key: None,
+            auth_method,
+            jump_hosts,
+            known_hosts: None,
+            local_ports: None,
+            host_key_verification: if accept_new {
+                HostKeyVerification::AcceptNew
+            } else {
+                HostKeyVerification::Strict
             },
         })
     }
 
     async fn setup_storage_interactive(&self) -> Result<StorageConfig> {
-        let storage_type =
-            Select::new("Storage type:", vec![StorageType::Local, StorageType::S3]).prompt()?;
+        let storage_type = Select::new(
+            "Storage type:",
+            vec![
+                StorageType::Local,
+                StorageType::S3,
+                StorageType::Gcs,
+                StorageType::WebDav,
+                StorageType::Sftp,
+            ],
+        )
+        .prompt()?;
 
         let name = Text::new("Storage name:")
             .with_default("default")
             .prompt()?;
 
         let location = Text::new("Location:")
-            .with_help_message("Directory path for local storage or prefix for S3")
+            .with_help_message(
+                "Directory path for local and SFTP storage or prefix for S3, GCS and WebDAV",
+            )
             .with_default("backups")
             .prompt()?;
 
@@ -292,11 +356,21 @@ impl InteractiveSetup {
                     .with_help_message("Custom S3 endpoint (optional for AWS)")
                     .prompt_skippable()?;
 
-                let access_key = Text::new("Access Key ID:").prompt()?;
-
-                let secret_key = Password::new("Secret Access Key:")
-                    .without_confirmation()
-                    .prompt()?;
+                let access_key = Text::new("Access Key ID:")
+                    .with_help_message(
+                        "Leave empty to use the default AWS credentials, e.g. an IAM role",
+                    )
+                    .prompt_skippable()?
+                    .filter(|key| !key.is_empty());
+
+                let secret_key = match access_key {
+                    Some(_) => Some(
+                        Password::new("Secret Access Key:")
+                            .without_confirmation()
+                            .prompt()?,
+                    ),
+                    None => None,
+                };
 
                 Ok(StorageConfig::S3(S3StorageConfig {
                     name,
@@ -307,6 +381,93 @@ impl InteractiveSetup {
                     secret_key,
                     location,
                     id: "".into(),
+                    retry: RetryConfig::default(),
+                    upload: UploadOptions::default(),
+                    server_side_encryption: None,
+                    sse_kms_key_id: None,
+                    storage_class: None,
+                    enable_virtual_host_style: false,
+                }))
+            }
+            StorageType::Gcs => {
+                let bucket = Text::new("GCS Bucket:").prompt()?;
+
+                let credential_path = Text::new("Service Account Key Path:")
+                    .with_help_message("Leave empty for anonymous access")
+                    .prompt_skippable()?
+                    .filter(|path| !path.is_empty());
+
+                Ok(StorageConfig::Gcs(GcsStorageConfig {
+                    name,
+                    bucket,
+                    credential_path,
+                    endpoint: None,
+                    location,
+                    id: "".into(),
+                    retry: RetryConfig::default(),
+                }))
+            }
+            StorageType::WebDav => {
+                let endpoint = Text::new("WebDAV Endpoint:").prompt()?;
+
+                let username = Text::new("Username:")
+                    .with_help_message("Leave empty for anonymous access")
+                    .prompt_skippable()?
+                    .filter(|username| !username.is_empty());
+
+                let credentials = match username {
+                    Some(username) => StorageCredentials::Basic {
+                        username,
+                        password: Password::new("Password:").without_confirmation().prompt()?,
+                    },
+                    None => StorageCredentials::None,
+                };
+
+                Ok(StorageConfig::WebDav(WebDavStorageConfig {
+                    name,
+                    endpoint,
+                    credentials,
+                    location,
+                    id: "".into(),
+                    retry: RetryConfig::default(),
+                }))
+            }
+            StorageType::Sftp => {
+                let host = Text::new("SFTP Host:").prompt()?;
+
+                let port = Text::new("SFTP Port:")
+                    .with_default("22")
+                    .prompt()?
+                    .parse::<u16>()?;
+
+                let username = Text::new("SSH Username:").prompt()?;
+
+                let key_path = Text::new("SSH Private Key Path:")
+                    .with_help_message("Leave empty to authenticate with a password")
+                    .prompt_skippable()?
+                    .filter(|key_path| !key_path.is_empty());
+
+                let credentials = match key_path {
+                    Some(key_path) => StorageCredentials::PrivateKey {
+                        username,
+                        key_path,
+                        passphrase: None,
+                    },
+                    None => StorageCredentials::Basic {
+                        username,
+                        password: Password::new("SSH Password:")
+                            .without_confirmation()
+                            .prompt()?,
+                    },
+                };
+
+                Ok(StorageConfig::Sftp(SftpStorageConfig {
+                    name,
+                    host,
+                    port,
+                    credentials,
+                    location,
+                    id: "".into(),
                 }))
             }
         }
@@ -483,6 +644,7 @@ impl InteractiveSetup {
             .list_with_options(ListOptions {
                 latest_only: Some(false),
                 limit: Some(50),
+                ..Default::default()
             })
             .await
         {
@@ -578,6 +740,19 @@ impl InteractiveSetup {
                 name: selected_backup.clone(),
                 compression_format: None,
                 drop_database_first: Some(drop_database),
+                message_level: None,
+                no_tablespaces: None,
+                client_encoding: None,
+                schema_only: None,
+                termination_timeout: None,
+                terminate_connections: None,
+                run_analyze: None,
+                analyze_jobs: None,
+                jobs: None,
+                single_transaction: None,
+                validate_only: None,
+                rate_limit_bytes_per_sec: None,
+                progress: None,
             })
             .await
         {
@@ -630,6 +805,7 @@ impl InteractiveSetup {
             .list_with_options(ListOptions {
                 latest_only: Some(false),
                 limit: Some(50),
+                ..Default::default()
             })
             .await
         {
@@ -728,6 +904,9 @@ impl std::fmt::Display for DatabaseType {
 enum StorageType {
     Local,
     S3,
+    Gcs,
+    WebDav,
+    Sftp,
 }
 
 impl std::fmt::Display for StorageType {
@@ -735,6 +914,9 @@ impl std::fmt::Display for StorageType {
         match self {
             StorageType::Local => write!(f, "Local filesystem"),
             StorageType::S3 => write!(f, "Amazon S3 / Compatible"),
+            StorageType::Gcs => write!(f, "Google Cloud Storage"),
+            StorageType::WebDav => write!(f, "WebDAV"),
+            StorageType::Sftp => write!(f, "SFTP"),
         }
     }
 }
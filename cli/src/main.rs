@@ -1,28 +1,64 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use cli::{
-    database_config_from_cli, parse_retention, storage_from_cli, Cli, Commands, WorkspaceCommands,
+    database_config_from_cli, override_storage_from_cli, parse_age, parse_date, parse_retention,
+    parse_size, rate_limit_from_cli, retention_policy_from_cli, storage_from_cli, CatalogCommands,
+    Cli, Commands, WorkspaceCommands,
 };
 use colored::*;
 use dbkp_core::{
-    databases::DatabaseConnection,
-    storage::provider::{ListOptions, StorageProvider},
-    DbBkp, RestoreOptions,
+    audit::AuditLog,
+    catalog::Catalog,
+    common::{extract_timestamp_from_filename, format_timestamp, parse_timezone},
+    compression::Decompressor,
+    databases::{
+        command_runner::CommandError,
+        diagnostics::{Diagnostic, Severity},
+        DatabaseConnection,
+    },
+    encryption::{DecryptingReader, EncryptionConfig},
+    inspect::validate_dump,
+    logging::{init_logging, LogFormat},
+    manifest::BackupManifest,
+    physical::{self, RecoveryOptions},
+    promote::PromoteOptions,
+    scheduler::{Schedule, Scheduler},
+    storage::provider::{
+        CleanupOptions, ListOptions, RetentionPolicy, StorageConfig, StorageProvider,
+    },
+    BackupOptions, DbBkp, RestoreOptions, VerifyAllOptions, VerifyOptions,
 };
+use std::path::Path;
 
 mod cli;
+mod config;
 mod interactive;
 mod spinner;
 mod tests;
 mod workspace;
 
+use config::ConfigFile;
 use interactive::InteractiveSetup;
 use spinner::Spinner;
-use workspace::WorkspaceManager;
+use workspace::{WorkspaceManager, WorkspaceRestoreOptions};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let audit_log = cli.audit_log.as_deref().map(AuditLog::new);
+    let catalog = cli.catalog.as_deref().map(Catalog::new);
+    let encryption = cli
+        .encryption_passphrase
+        .as_deref()
+        .map(EncryptionConfig::new);
+
+    let config_file = cli.config.as_deref().map(ConfigFile::load).transpose()?;
+
+    // Only the scheduler logs without being asked to
+    let log_format = cli.log_format;
+    if let Some(format) = log_format {
+        init_logging(format, "info")?;
+    }
 
     match cli.command.unwrap_or(Commands::Interactive) {
         Commands::Interactive => {
@@ -32,21 +68,540 @@ async fn main() -> Result<()> {
         Commands::Workspace { command } => {
             handle_workspace_command(command).await?;
         }
+        Commands::Catalog {
+            command: CatalogCommands::Reindex(args),
+        } => {
+            let catalog =
+                catalog.ok_or_else(|| anyhow!("--catalog or DBKP_CATALOG is required"))?;
+
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, listing backups...");
+                    config
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve storage configuration");
+                    return Err(e);
+                }
+            };
+
+            let storage = match StorageProvider::new(storage_config) {
+                Ok(provider) => provider,
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            match catalog.reindex(&storage).await {
+                Ok(count) => {
+                    spinner.success(format!("Catalog rebuilt with {} backups", count));
+                }
+                Err(e) => {
+                    spinner.error("Failed to rebuild the catalog");
+                    return Err(e);
+                }
+            }
+        }
         Commands::Backup(args) => {
+            let timezone = args.timezone.as_deref().map(parse_timezone).transpose()?;
+            let databases = &args.database_config.database;
+
+            if databases.len() > 1 && (args.dry_run || args.skip_unchanged || args.once_per_day) {
+                return Err(anyhow!(
+                    "--dry-run, --skip-unchanged and --once-per-day need a single --database"
+                ));
+            }
+
             let mut spinner = Spinner::new("Resolving configuration...");
             spinner.start();
 
-            let (database_config, storage_config) = match resolve_configs_for_backup(&args).await {
-                Ok(configs) => {
-                    spinner.update_message("Configuration resolved, connecting to database...");
-                    configs
+            let (database_config, storage_config) =
+                match resolve_configs_for_backup(&args, config_file.as_ref()).await {
+                    Ok(configs) => {
+                        spinner.update_message("Configuration resolved, connecting to database...");
+                        configs
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to resolve configuration");
+                        return Err(e);
+                    }
+                };
+
+            let database_connection = match DatabaseConnection::new(database_config).await {
+                Ok(conn) => {
+                    spinner.update_message("Database connected, connecting to storage...");
+                    conn
                 }
                 Err(e) => {
-                    spinner.error("Failed to resolve configuration");
+                    spinner.error("Failed to connect to database");
+                    return Err(e);
+                }
+            };
+
+            let storage_provider = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message("Storage connected, testing connections...");
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            let storage_provider = match &catalog {
+                Some(catalog) => storage_provider.with_catalog(catalog.clone()),
+                None => storage_provider,
+            };
+
+            let mut core = DbBkp::new(database_connection, storage_provider);
+            if let Some(audit_log) = &audit_log {
+                core = core.with_audit_log(audit_log.clone());
+            }
+            if let Some(encryption) = &encryption {
+                core = core.with_encryption(encryption.clone());
+            }
+
+            let backup_options = BackupOptions {
+                checksum: Some(args.checksum || args.checksum_algorithm.is_some()),
+                checksum_algorithm: args.checksum_algorithm,
+                jobs: args.jobs,
+                exclusions: (&args.exclusions).into(),
+                masking: args.masks.clone(),
+                snapshot: args.snapshot.clone(),
+                directory_format: args.directory_format,
+                scope: args.scope,
+                include_tables: args.include_tables.clone(),
+                exclude_tables: args.exclude_tables.clone(),
+                physical: args.physical,
+                rate_limit_bytes_per_sec: rate_limit_from_cli(&args.rate_limit)?,
+                ..Default::default()
+            };
+
+            if args.dry_run {
+                spinner.update_message("Running pre-flight checks...");
+
+                let retention = args
+                    .retention
+                    .as_deref()
+                    .map(parse_retention)
+                    .transpose()?
+                    .map(RetentionPolicy::MaxAge);
+                let plan = match core.plan_backup(Some(backup_options), retention).await {
+                    Ok(plan) => {
+                        spinner.success("Pre-flight checks passed, no backup was taken");
+                        plan
+                    }
+                    Err(e) => {
+                        spinner.error("Pre-flight checks failed");
+                        return Err(e);
+                    }
+                };
+
+                println!("\n{}:", "Backup plan".green().bold());
+                println!("  Target:          {}", plan.name);
+                println!("  Database:        {}", plan.database);
+                println!("  Server version:  {}", plan.server_version.major());
+                println!("  Client version:  {}", plan.client_version.major());
+                println!(
+                    "  Compression:     {:?} (level {})",
+                    plan.compression_format, plan.compression_level
+                );
+                println!("  Estimated size:  {}", format_size(plan.estimated_size));
+                if let Some(available_space) = plan.available_space {
+                    println!("  Free space:      {}", format_size(available_space));
+                }
+                if let Some(retained_size) = plan.retained_size {
+                    println!("  Retained size:   {}", format_size(retained_size));
+                }
+
+                for warning in plan.warnings {
+                    println!("{}", format!("[WARNING] {}", warning).yellow());
+                }
+
+                return Ok(());
+            }
+
+            // Test database & storage connection
+            match core.test().await {
+                Ok(_) => spinner.update_message("Connections verified, starting backup..."),
+                Err(e) => {
+                    spinner.error("Connection test failed");
+                    return Err(e);
+                }
+            }
+
+            if databases.len() > 1 {
+                spinner.stop();
+                println!(
+                    "{}",
+                    "[WARNING] Each database is dumped from its own snapshot: the backups are not consistent with each other"
+                        .yellow()
+                );
+                spinner.start();
+                spinner.update_message(format!("Backing up {} databases...", databases.len()));
+
+                let report = match core
+                    .backup_databases(databases, Some(backup_options), args.concurrency)
+                    .await
+                {
+                    Ok(report) => report,
+                    Err(e) => {
+                        spinner.error("Backup failed");
+                        return Err(e);
+                    }
+                };
+
+                if report.is_success() {
+                    spinner.success(format!("Backed up {} databases", report.succeeded.len()));
+                } else {
+                    spinner.error(format!(
+                        "{} of {} databases failed",
+                        report.failed.len(),
+                        databases.len()
+                    ));
+                }
+
+                for backup in &report.succeeded {
+                    println!("  {} {}", "completed".green(), backup.name);
+                }
+
+                for failure in &report.failed {
+                    println!(
+                        "  {} {}: {}",
+                        "failed".red(),
+                        failure.database,
+                        failure.error
+                    );
+                }
+
+                if !report.is_success() {
+                    return Err(anyhow!("{} database backups failed", report.failed.len()));
+                }
+
+                return Ok(());
+            }
+
+            if args.once_per_day && !args.force {
+                match core.find_backup_today(timezone.as_ref()).await {
+                    Ok(Some(entry)) => {
+                        spinner.success(format!(
+                            "A backup was already taken today, skipping: {}",
+                            entry.metadata.name
+                        ));
+                        return Ok(());
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        spinner.error("Failed to list existing backups");
+                        return Err(e);
+                    }
+                }
+            }
+
+            if args.skip_unchanged && !args.force {
+                match core.backup_if_changed(Some(backup_options)).await {
+                    Ok(Some(backup_file)) => {
+                        spinner.success(format!("Backup completed successfully: {}", backup_file));
+                    }
+                    Ok(None) => {
+                        spinner.success("No changes detected since the last backup, skipping");
+                    }
+                    Err(e) => {
+                        spinner.error("Backup failed");
+                        return Err(e);
+                    }
+                }
+
+                return Ok(());
+            }
+
+            match core.backup_with_report(Some(backup_options)).await {
+                Ok(report) => {
+                    spinner.success(format!("Backup completed successfully: {}", report.name));
+
+                    println!(
+                        "  {} written in {:.1}s",
+                        format_size(report.size),
+                        report.duration.as_secs_f64()
+                    );
+
+                    if let Some(checksum) = report.checksum {
+                        println!("  Checksum: {}", checksum);
+                    }
+
+                    print_diagnostics(&report.diagnostics, Severity::Warning);
+                }
+                Err(e) => {
+                    spinner.error("Backup failed");
+                    print_error_diagnostics(&e);
+                    return Err(e);
+                }
+            }
+        }
+        Commands::List(args) => {
+            let timezone = args.timezone.as_deref().map(parse_timezone).transpose()?;
+            let after = args.after.as_deref().map(parse_date).transpose()?;
+            let before = args.before.as_deref().map(parse_date).transpose()?;
+
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, connecting...");
+                    config
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve storage configuration");
+                    return Err(e);
+                }
+            };
+
+            let storage_provider = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message("Storage connected, testing connection...");
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
                     return Err(e);
                 }
             };
 
+            match storage_provider.test().await {
+                Ok(_) => spinner.update_message("Connection verified, fetching backup list..."),
+                Err(e) => {
+                    spinner.error("Storage connection test failed");
+                    return Err(e);
+                }
+            }
+
+            let options = ListOptions {
+                latest_only: Some(args.latest_only),
+                limit: args.limit,
+                name_prefix: args.database.clone(),
+                after,
+                before,
+            };
+
+            let listing = if args.page || args.cursor.is_some() {
+                storage_provider
+                    .list_page(options, args.cursor.clone())
+                    .await
+            } else {
+                storage_provider
+                    .list_with_options(options)
+                    .await
+                    .map(|entries| (entries, None))
+            };
+
+            let (entries, next_cursor) = match listing {
+                Ok(listing) => listing,
+                Err(e) => {
+                    spinner.error("Failed to fetch backup list");
+                    return Err(e);
+                }
+            };
+
+            // Manifests are only read when their details are shown
+            let entries = if args.details {
+                storage_provider.read_manifests(entries).await
+            } else {
+                entries.into_iter().map(|entry| (entry, None)).collect()
+            };
+
+            spinner.stop();
+
+            if entries.is_empty() {
+                println!("{}", "[INFO] No backups found".cyan());
+                return Ok(());
+            }
+
+            println!("\n{}:", "Available backups".green().bold());
+
+            for (index, (entry, manifest)) in entries.iter().enumerate() {
+                let filename = &entry.metadata.name;
+                let size_str = format_size(entry.metadata.content_length);
+
+                // Try to extract and format timestamp
+                let date_str = match extract_timestamp_from_filename(filename) {
+                    Ok(timestamp) => format_timestamp(&timestamp, timezone.as_ref()),
+                    Err(_) => "Unknown date".to_string(),
+                };
+
+                println!(
+                    "  {:2}. {} | {} | {}",
+                    index + 1,
+                    date_str,
+                    size_str,
+                    filename
+                );
+
+                if args.details {
+                    match manifest {
+                        Some(manifest) => println!("      {}", format_manifest_details(manifest)),
+                        None => println!("      {}", "No manifest".dimmed()),
+                    }
+                }
+            }
+
+            if let Some(cursor) = next_cursor {
+                println!(
+                    "\n{} --cursor {}",
+                    "[INFO] More backups follow, continue with".cyan(),
+                    cursor
+                );
+            }
+        }
+        Commands::Info(args) => {
+            let timezone = args.timezone.as_deref().map(parse_timezone).transpose()?;
+
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, connecting...");
+                    config
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve storage configuration");
+                    return Err(e);
+                }
+            };
+
+            let storage_provider = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message("Storage connected, fetching backup information...");
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            let info = match storage_provider.get_backup_info(&args.name).await {
+                Ok(info) => {
+                    spinner.stop();
+                    info
+                }
+                Err(e) => {
+                    spinner.error("Failed to fetch backup information");
+                    return Err(e);
+                }
+            };
+
+            println!("\n{}:", "Backup information".green().bold());
+            println!("  Name:            {}", info.name);
+            println!("  Size:            {}", format_size(info.size));
+            println!("  Compression:     {:?}", info.compression_format);
+            println!(
+                "  Encrypted:       {}",
+                if info.encrypted { "Yes" } else { "No" }
+            );
+            println!(
+                "  Server version:  {}",
+                info.server_version.as_deref().unwrap_or("Unknown")
+            );
+
+            if let Some(last_modified) = info.last_modified {
+                println!(
+                    "  Last modified:   {}",
+                    format_timestamp(&last_modified, timezone.as_ref())
+                );
+            }
+
+            match &info.manifest {
+                Some(manifest) => {
+                    println!("  Database:        {}", manifest.database);
+                    println!("  Database type:   {:?}", manifest.connection_type);
+                    println!("  Scope:           {}", manifest.scope);
+                    println!(
+                        "  Created at:      {}",
+                        format_timestamp(&manifest.created_at, timezone.as_ref())
+                    );
+
+                    if let Some(uncompressed_size) = manifest.uncompressed_size {
+                        println!("  Uncompressed:    {}", format_size(uncompressed_size));
+                    }
+
+                    if let Some(checksum) = &manifest.checksum {
+                        println!("  Checksum:        {}", checksum);
+                    }
+
+                    if let Some(change_token) = &manifest.change_token {
+                        println!("  Change token:    {}", change_token);
+                    }
+                }
+                None => {
+                    println!(
+                        "{}",
+                        "[INFO] No manifest found, details were read from the dump header".cyan()
+                    );
+                }
+            }
+        }
+        Commands::Restore(args) => {
+            let termination_timeout = match &args.termination_timeout {
+                Some(timeout) => Some(parse_age(timeout)?.to_std()?),
+                None => None,
+            };
+
+            let mut spinner = Spinner::new("Resolving configuration...");
+            spinner.start();
+
+            let (database_config, storage_config, restore_defaults) =
+                match resolve_configs_for_restore(&args, config_file.as_ref()).await {
+                    Ok(configs) => {
+                        spinner
+                            .update_message("Configuration resolved, determining backup name...");
+                        configs
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to resolve configuration");
+                        return Err(e);
+                    }
+                };
+
+            let backup_name =
+                match resolve_backup_name(&args, &database_config, &storage_config).await {
+                    Ok(name) => {
+                        spinner.update_message(format!(
+                            "Backup {} identified, connecting to database...",
+                            name
+                        ));
+                        name
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to resolve backup name");
+                        return Err(e);
+                    }
+                };
+
             let database_connection = match DatabaseConnection::new(database_config).await {
                 Ok(conn) => {
                     spinner.update_message("Database connected, connecting to storage...");
@@ -69,46 +624,454 @@ async fn main() -> Result<()> {
                 }
             };
 
-            let core = DbBkp::new(database_connection, storage_provider);
+            let mut core = DbBkp::new(database_connection, storage_provider);
+            if let Some(audit_log) = &audit_log {
+                core = core.with_audit_log(audit_log.clone());
+            }
+            if let Some(encryption) = &encryption {
+                core = core.with_encryption(encryption.clone());
+            }
 
             // Test database & storage connection
             match core.test().await {
-                Ok(_) => spinner.update_message("Connections verified, starting backup..."),
+                Ok(_) => spinner.update_message(format!(
+                    "Connections verified, starting restore of '{}'...",
+                    backup_name
+                )),
                 Err(e) => {
                     spinner.error("Connection test failed");
                     return Err(e);
                 }
-            }
+            }
+
+            if args.expect_schema || args.expect_data {
+                spinner.update_message("Inspecting backup content...");
+
+                match core.inspect_backup(&backup_name, None).await {
+                    Ok(content) => {
+                        let warnings = content.check(args.expect_schema, args.expect_data);
+
+                        if !warnings.is_empty() {
+                            spinner.stop();
+
+                            for warning in warnings {
+                                println!("{}", format!("[WARNING] {}", warning).yellow());
+                            }
+
+                            spinner.start();
+                        }
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to inspect backup");
+                        return Err(e);
+                    }
+                }
+            }
+
+            spinner.update_message("Checking encodings...");
+
+            match core
+                .check_restore_encoding(&backup_name, args.client_encoding.as_deref())
+                .await
+            {
+                Ok(warnings) if !warnings.is_empty() => {
+                    spinner.stop();
+
+                    for warning in warnings {
+                        println!("{}", format!("[WARNING] {}", warning).yellow());
+                    }
+
+                    spinner.start();
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    spinner.stop();
+                    println!(
+                        "{}",
+                        format!("[WARNING] Failed to compare encodings: {}", e).yellow()
+                    );
+                    spinner.start();
+                }
+            }
+
+            let restore_flags = restore_defaults.with_flags(&args);
+            let restore_options = RestoreOptions {
+                name: backup_name.clone(),
+                compression_format: None,
+                drop_database_first: Some(restore_flags.drop_database.unwrap_or(false)),
+                message_level: restore_flags.message_level,
+                no_tablespaces: Some(restore_flags.no_tablespaces.unwrap_or(false)),
+                client_encoding: args.client_encoding.clone(),
+                schema_only: Some(args.schema_only),
+                termination_timeout,
+                terminate_connections: args.no_terminate_connections.then_some(false),
+                run_analyze: args.no_analyze.then_some(false),
+                analyze_jobs: args.analyze_jobs,
+                jobs: args.jobs,
+                single_transaction: Some(restore_flags.single_transaction.unwrap_or(false)),
+                validate_only: Some(args.validate_only),
+                rate_limit_bytes_per_sec: rate_limit_from_cli(&args.rate_limit)?,
+                progress: None,
+            };
+
+            if args.promote {
+                spinner.update_message("Restoring into a new database...");
+
+                match core
+                    .restore_and_promote(PromoteOptions {
+                        restore: restore_options,
+                        checks: args.checks.clone(),
+                        keep_previous: args.keep_previous,
+                    })
+                    .await
+                {
+                    Ok(report) => {
+                        spinner.success(format!(
+                            "Restore of {} promoted after {} checks",
+                            backup_name, report.checks_passed
+                        ));
+                        println!("  Restored as: {}", report.candidate);
+
+                        if let Some(previous) = &report.previous {
+                            println!("  Previous database kept as: {}", previous);
+                        }
+
+                        print_diagnostics(&report.restore.diagnostics, Severity::Warning);
+                    }
+                    Err(e) => {
+                        spinner.error("Restore and promotion failed");
+                        print_error_diagnostics(&e);
+                        return Err(e);
+                    }
+                }
+
+                return Ok(());
+            }
+
+            if args.validate_only {
+                spinner.update_message("Validating backup...");
+            }
+
+            match core.restore_with_report(restore_options).await {
+                Ok(report) if args.validate_only => {
+                    spinner.success(format!(
+                        "Backup {} restores cleanly, the database was left untouched",
+                        backup_name
+                    ));
+
+                    print_diagnostics(&report.diagnostics, Severity::Warning);
+                }
+                Ok(report) => {
+                    spinner.success(format!("Restore completed successfully: {}", backup_name));
+
+                    if let Some(duration) = report.analyze_duration {
+                        println!("  Statistics gathered in {:.1}s", duration.as_secs_f64());
+                    }
+
+                    print_diagnostics(&report.diagnostics, Severity::Warning);
+                }
+                Err(e) => {
+                    spinner.error(if args.validate_only {
+                        "Validation failed"
+                    } else {
+                        "Restore failed"
+                    });
+                    print_error_diagnostics(&e);
+                    return Err(e);
+                }
+            }
+        }
+        Commands::Cleanup(args) => {
+            let retention = retention_policy_from_cli(&args.retention)?;
+
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, connecting...");
+                    config
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve storage configuration");
+                    return Err(e);
+                }
+            };
+
+            let storage = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message("Storage connected, testing connection...");
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            // Test storage connection
+            match storage.test().await {
+                Ok(_) => {
+                    let action = if args.dry_run {
+                        "analyzing"
+                    } else {
+                        "cleaning up"
+                    };
+                    spinner.update_message(format!("Connection verified, {} backups...", action));
+                }
+                Err(e) => {
+                    spinner.error("Storage connection test failed");
+                    return Err(e);
+                }
+            }
+
+            let storage = match &audit_log {
+                Some(audit_log) => storage.with_audit_log(audit_log.clone()),
+                None => storage,
+            };
+
+            let storage = match &catalog {
+                Some(catalog) => storage.with_catalog(catalog.clone()),
+                None => storage,
+            };
+
+            let mut options = CleanupOptions::new(retention, args.dry_run);
+            options.min_backup_age = parse_age(&args.min_backup_age)?;
+
+            if let Some(concurrency) = args.concurrency {
+                options.concurrency = concurrency;
+            }
+
+            let report = match storage.cleanup_with_options(&options).await {
+                Ok(report) => report,
+                Err(e) => {
+                    spinner.error("Cleanup failed");
+                    return Err(e);
+                }
+            };
+
+            if !report.is_success() {
+                spinner.error(format!(
+                    "Cleanup completed with errors: {} entries deleted, {} failed, {} storage reclaimed",
+                    report.deleted.len(),
+                    report.failed.len(),
+                    report.reclaimed
+                ));
+
+                for failure in &report.failed {
+                    println!("  {} {}: {}", "failed".red(), failure.name, failure.error);
+                }
+
+                return Err(anyhow!("{} deletions failed", report.failed.len()));
+            }
+
+            if args.dry_run {
+                spinner.success(format!(
+                    "Dry run completed: {} entries would be deleted, {} storage would be reclaimed",
+                    report.deleted.len(),
+                    report.reclaimed
+                ));
+            } else {
+                spinner.success(format!(
+                    "Cleanup completed: {} entries deleted, {} storage reclaimed",
+                    report.deleted.len(),
+                    report.reclaimed
+                ));
+            }
+        }
+        Commands::Bench(args) => {
+            let size = parse_size(&args.size)?;
+
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, connecting...");
+                    config
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve storage configuration");
+                    return Err(e);
+                }
+            };
+
+            let storage = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message(format!(
+                        "Storage connected, writing and reading {}...",
+                        format_size(size)
+                    ));
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            let report = match storage.bench(size).await {
+                Ok(report) => {
+                    spinner.stop();
+                    report
+                }
+                Err(e) => {
+                    spinner.error("Benchmark failed");
+                    return Err(e);
+                }
+            };
+
+            println!("\n{}:", "Storage benchmark".green().bold());
+            println!("  Object size:     {}", format_size(report.size));
+            println!(
+                "  Write:           {}/s ({:.2?}, latency {:.2?})",
+                format_size(report.write_throughput() as u64),
+                report.write_duration,
+                report.write_latency
+            );
+            println!(
+                "  Read:            {}/s ({:.2?}, latency {:.2?})",
+                format_size(report.read_throughput() as u64),
+                report.read_duration,
+                report.read_latency
+            );
+        }
+        Commands::VerifyLatest(args) => {
+            let mut spinner = Spinner::new("Resolving configuration...");
+            spinner.start();
+
+            let (database_config, storage_config) = match resolve_configs(
+                &args.workspace,
+                &args.database_config,
+                &args.storage_config,
+                "verify-latest",
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(configs) => {
+                    spinner.update_message("Configuration resolved, connecting to storage...");
+                    configs
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve configuration");
+                    return Err(e);
+                }
+            };
+
+            let database_connection = match DatabaseConnection::new(database_config).await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    spinner.error("Failed to connect to database");
+                    return Err(e);
+                }
+            };
+
+            let storage_provider = match StorageProvider::new(storage_config) {
+                Ok(provider) => {
+                    spinner.update_message(format!(
+                        "Storage connected, verifying latest backup ({})...",
+                        args.level
+                    ));
+                    provider
+                }
+                Err(e) => {
+                    spinner.error("Failed to connect to storage");
+                    return Err(e);
+                }
+            };
+
+            let mut core = DbBkp::new(database_connection, storage_provider);
+            if let Some(encryption) = &encryption {
+                core = core.with_encryption(encryption.clone());
+            }
+
+            let report = match core
+                .verify_latest(VerifyOptions {
+                    level: args.level,
+                    scratch_database: args.scratch_database,
+                    message_level: args.message_level,
+                })
+                .await
+            {
+                Ok(report) => {
+                    spinner.success(format!("Backup verified: {}", report.name));
+                    report
+                }
+                Err(e) => {
+                    spinner.error("Verification failed");
+                    return Err(e);
+                }
+            };
+
+            println!("  Level:           {}", report.level);
+            if let Some(created_at) = report.created_at {
+                println!(
+                    "  Created:         {}",
+                    created_at.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+            }
+            println!("  Size:            {}", format_size(report.size));
+            println!(
+                "  Checksum:        {}{}",
+                report.checksum,
+                if report.checksum_verified {
+                    ""
+                } else {
+                    " (no manifest checksum to compare)"
+                }
+            );
+            println!("  Duration:        {:.2?}", report.duration);
+        }
+        Commands::VerifyAll(args) => {
+            let mut spinner = Spinner::new("Resolving configuration...");
+            spinner.start();
+
+            let (database_config, storage_config) = match resolve_configs(
+                &args.workspace,
+                &args.database_config,
+                &args.storage_config,
+                "verify-all",
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(configs) => {
+                    spinner.update_message("Configuration resolved, connecting to storage...");
+                    configs
+                }
+                Err(e) => {
+                    spinner.error("Failed to resolve configuration");
+                    return Err(e);
+                }
+            };
 
-            match core.backup().await {
-                Ok(backup_file) => {
-                    spinner.success(format!("Backup completed successfully: {}", backup_file));
-                }
+            let database_connection = match DatabaseConnection::new(database_config).await {
+                Ok(conn) => conn,
                 Err(e) => {
-                    spinner.error("Backup failed");
+                    spinner.error("Failed to connect to database");
                     return Err(e);
                 }
-            }
-        }
-        Commands::List(args) => {
-            let mut spinner = Spinner::new("Resolving storage configuration...");
-            spinner.start();
-
-            let storage_config =
-                match resolve_storage_config(&args.workspace, &Some(args.storage)).await {
-                    Ok(config) => {
-                        spinner.update_message("Storage configuration resolved, connecting...");
-                        config
-                    }
-                    Err(e) => {
-                        spinner.error("Failed to resolve storage configuration");
-                        return Err(e);
-                    }
-                };
+            };
 
             let storage_provider = match StorageProvider::new(storage_config) {
                 Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connection...");
+                    spinner.update_message(format!(
+                        "Storage connected, verifying backups ({})...",
+                        args.level
+                    ));
                     provider
                 }
                 Err(e) => {
@@ -117,209 +1080,352 @@ async fn main() -> Result<()> {
                 }
             };
 
-            match storage_provider.test().await {
-                Ok(_) => spinner.update_message("Connection verified, fetching backup list..."),
-                Err(e) => {
-                    spinner.error("Storage connection test failed");
-                    return Err(e);
-                }
+            let mut core = DbBkp::new(database_connection, storage_provider);
+            if let Some(encryption) = &encryption {
+                core = core.with_encryption(encryption.clone());
             }
 
-            let entries = match storage_provider
-                .list_with_options(ListOptions {
-                    latest_only: Some(args.latest_only),
-                    limit: args.limit,
+            let report = match core
+                .verify_all(VerifyAllOptions {
+                    verify: VerifyOptions {
+                        level: args.level,
+                        scratch_database: args.scratch_database,
+                        message_level: args.message_level,
+                    },
+                    prefix: args.prefix,
+                    concurrency: args.concurrency,
                 })
                 .await
             {
-                Ok(entries) => {
-                    spinner.stop();
-                    entries
-                }
+                Ok(report) => report,
                 Err(e) => {
-                    spinner.error("Failed to fetch backup list");
+                    spinner.error("Verification failed");
                     return Err(e);
                 }
             };
 
-            if entries.is_empty() {
-                println!("{}", "[INFO] No backups found".cyan());
+            let total = report.passed.len() + report.failed.len();
+
+            if report.is_success() {
+                spinner.success(format!("{} backups verified", total));
+            } else {
+                spinner.error(format!(
+                    "{} of {} backups failed verification",
+                    report.failed.len(),
+                    total
+                ));
+            }
+
+            for verified in &report.passed {
+                println!(
+                    "  {} {} ({:.2?})",
+                    "passed".green(),
+                    verified.name,
+                    verified.duration
+                );
+            }
+
+            for failure in &report.failed {
+                println!("  {} {}: {}", "failed".red(), failure.name, failure.error);
+            }
+
+            if !report.is_success() {
+                return Err(anyhow!(
+                    "{} of {} backups failed verification",
+                    report.failed.len(),
+                    total
+                ));
+            }
+        }
+        Commands::Schedule(args) => {
+            if args.database_config.database.len() > 1 {
+                return Err(anyhow!("schedule takes a single --database"));
+            }
+
+            let schedule = match (&args.cron, &args.every) {
+                (Some(cron), _) => Schedule::Cron(cron.parse()?),
+                (None, Some(every)) => {
+                    let interval = parse_age(every)?;
+                    if interval <= chrono::Duration::zero() {
+                        return Err(anyhow!("--every must be longer than 0s"));
+                    }
+                    Schedule::Every(interval)
+                }
+                (None, None) => return Err(anyhow!("Either --cron or --every is required")),
+            };
+
+            let cleanup = if args.retention.is_set() {
+                let mut options =
+                    CleanupOptions::new(retention_policy_from_cli(&args.retention)?, false);
+                options.min_backup_age = parse_age(&args.min_backup_age)?;
+                Some(options)
+            } else {
+                None
+            };
+
+            let (database_config, storage_config) = resolve_configs(
+                &args.workspace,
+                &args.database_config,
+                &args.storage_config,
+                "schedule",
+                config_file.as_ref(),
+            )
+            .await?;
+
+            let database_connection = DatabaseConnection::new(database_config).await?;
+            let mut storage_provider = StorageProvider::new(storage_config)?;
+            if let Some(audit_log) = &audit_log {
+                storage_provider = storage_provider.with_audit_log(audit_log.clone());
+            }
+            if let Some(catalog) = &catalog {
+                storage_provider = storage_provider.with_catalog(catalog.clone());
+            }
+            #[cfg(feature = "metrics")]
+            let recorder = args
+                .metrics_address
+                .map(|_| dbkp_core::metrics::PrometheusRecorder::new());
+            #[cfg(feature = "metrics")]
+            if let Some(recorder) = &recorder {
+                storage_provider =
+                    storage_provider.with_metrics(std::sync::Arc::new(recorder.clone()));
+            }
+
+            let mut core = DbBkp::new(database_connection, storage_provider);
+            if let Some(audit_log) = &audit_log {
+                core = core.with_audit_log(audit_log.clone());
+            }
+            if let Some(encryption) = &encryption {
+                core = core.with_encryption(encryption.clone());
+            }
+
+            #[cfg(feature = "metrics")]
+            if let Some(recorder) = &recorder {
+                core = core.with_metrics(std::sync::Arc::new(recorder.clone()));
+            }
+
+            core.test().await?;
+
+            let mut scheduler = Scheduler::new(core, schedule).with_backup_options(BackupOptions {
+                checksum: Some(args.checksum),
+                rate_limit_bytes_per_sec: rate_limit_from_cli(&args.rate_limit)?,
+                ..Default::default()
+            });
+            if let Some(timezone) = &args.timezone {
+                scheduler = scheduler.with_timezone(parse_timezone(timezone)?);
+            }
+            if let Some(cleanup) = cleanup {
+                scheduler = scheduler.with_cleanup(cleanup);
+            }
+            if let Some(state_file) = &args.state_file {
+                scheduler = scheduler.with_state_file(state_file);
+            }
+
+            // Runs and failures are reported through the log, like any daemon
+            if log_format.is_none() {
+                init_logging(LogFormat::Pretty, "info")?;
+            }
+
+            let shutdown = || async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+
+            #[cfg(feature = "metrics")]
+            if let (Some(address), Some(recorder)) = (args.metrics_address, recorder) {
+                tokio::try_join!(
+                    scheduler.run(shutdown()),
+                    dbkp_core::metrics::serve(recorder, address, shutdown()),
+                )?;
                 return Ok(());
             }
 
-            println!("\n{}:", "Available backups".green().bold());
+            scheduler.run(shutdown()).await?;
+        }
+        Commands::WalPush(args) => {
+            // Run by the server for every segment: no spinner, the error is all it logs
+            let cluster = resolve_cluster(&args.cluster, &args.workspace)?;
+            let storage_config =
+                resolve_storage_config(&args.workspace, &Some(args.storage), config_file.as_ref())
+                    .await?;
+            let storage_provider = StorageProvider::new(storage_config)?;
 
-            for (index, entry) in entries.iter().enumerate() {
-                let filename = &entry.metadata.name;
-                let size = entry.metadata.content_length;
-                let size_str = if size < 1024 {
-                    format!("{}B", size)
-                } else if size < 1024 * 1024 {
-                    format!("{:.2}KB", size as f64 / 1024.0)
-                } else if size < 1024 * 1024 * 1024 {
-                    format!("{:.2}MB", size as f64 / (1024.0 * 1024.0))
-                } else {
-                    format!("{:.2}GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
-                };
+            physical::push_wal(
+                &storage_provider,
+                encryption.as_ref(),
+                &cluster,
+                Path::new(&args.path),
+            )
+            .await?;
+        }
+        Commands::WalFetch(args) => {
+            let cluster = resolve_cluster(&args.cluster, &args.workspace)?;
+            let storage_config =
+                resolve_storage_config(&args.workspace, &Some(args.storage), config_file.as_ref())
+                    .await?;
+            let storage_provider = StorageProvider::new(storage_config)?;
 
-                // Try to extract and format timestamp
-                let date_str = match dbkp_core::common::extract_timestamp_from_filename(filename) {
-                    Ok(timestamp) => timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-                    Err(_) => "Unknown date".to_string(),
-                };
+            let fetched = physical::fetch_wal(
+                &storage_provider,
+                encryption.as_ref(),
+                &cluster,
+                &args.file_name,
+                Path::new(&args.destination),
+            )
+            .await?;
 
-                println!(
-                    "  {:2}. {} | {} | {}",
-                    index + 1,
-                    date_str,
-                    size_str,
-                    filename
-                );
+            // A non-zero exit tells the server the archive has nothing more to replay
+            if !fetched {
+                return Err(anyhow!("{} is not archived", args.file_name));
             }
         }
-        Commands::Restore(args) => {
-            let mut spinner = Spinner::new("Resolving configuration...");
-            spinner.start();
+        Commands::RestoreBase(args) => {
+            let target_time = args.target_time.as_deref().map(parse_date).transpose()?;
+            let cluster = resolve_cluster(&args.cluster, &args.workspace)?;
 
-            let (database_config, storage_config) = match resolve_configs_for_restore(&args).await {
-                Ok(configs) => {
-                    spinner.update_message("Configuration resolved, determining backup name...");
-                    configs
+            let restore_command = match (&args.restore_command, &args.workspace) {
+                (Some(command), _) => command.clone(),
+                (None, Some(workspace)) => {
+                    let executable = std::env::current_exe()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|_| "dbkp".into());
+
+                    format!("{} wal-fetch --workspace {} %f %p", executable, workspace)
                 }
-                Err(e) => {
-                    spinner.error("Failed to resolve configuration");
-                    return Err(e);
+                (None, None) => {
+                    return Err(anyhow!("--restore-command is required without --workspace"))
                 }
             };
 
-            let backup_name = match resolve_backup_name(&args, &storage_config).await {
-                Ok(name) => {
-                    spinner.update_message("Backup identified, connecting to database...");
-                    name
+            let mut spinner = Spinner::new("Resolving storage configuration...");
+            spinner.start();
+
+            let storage_config = match resolve_storage_config(
+                &args.workspace,
+                &Some(args.storage),
+                config_file.as_ref(),
+            )
+            .await
+            {
+                Ok(config) => {
+                    spinner.update_message("Storage configuration resolved, connecting...");
+                    config
                 }
                 Err(e) => {
-                    spinner.error("Failed to resolve backup name");
+                    spinner.error("Failed to resolve storage configuration");
                     return Err(e);
                 }
             };
 
-            let database_connection = match DatabaseConnection::new(database_config).await {
-                Ok(conn) => {
-                    spinner.update_message("Database connected, connecting to storage...");
-                    conn
-                }
+            let storage_provider = match StorageProvider::new(storage_config) {
+                Ok(provider) => provider,
                 Err(e) => {
-                    spinner.error("Failed to connect to database");
+                    spinner.error("Failed to connect to storage");
                     return Err(e);
                 }
             };
 
-            let storage_provider = match StorageProvider::new(storage_config) {
-                Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connections...");
-                    provider
+            let name = match (&args.name, args.latest) {
+                (Some(name), _) => name.clone(),
+                (None, true) => {
+                    match physical::get_latest_base_backup(&storage_provider, &cluster).await {
+                        Ok(entry) => entry.metadata.name,
+                        Err(e) => {
+                            spinner.error("Failed to find the latest base backup");
+                            return Err(e);
+                        }
+                    }
                 }
-                Err(e) => {
-                    spinner.error("Failed to connect to storage");
-                    return Err(e);
+                (None, false) => {
+                    spinner.error("No base backup selected");
+                    return Err(anyhow!("Either --name or --latest is required"));
                 }
             };
 
-            let core = DbBkp::new(database_connection, storage_provider);
+            spinner.update_message(format!("Unpacking {}...", name));
 
-            // Test database & storage connection
-            match core.test().await {
-                Ok(_) => spinner.update_message(format!(
-                    "Connections verified, starting restore of '{}'...",
-                    backup_name
-                )),
-                Err(e) => {
-                    spinner.error("Connection test failed");
-                    return Err(e);
-                }
-            }
+            let recovery = RecoveryOptions {
+                restore_command,
+                target_time,
+            };
 
-            match core
-                .restore(RestoreOptions {
-                    name: backup_name.clone(),
-                    compression_format: None,
-                    drop_database_first: Some(args.drop_database),
-                })
-                .await
+            match physical::restore_base_backup(
+                &storage_provider,
+                encryption.as_ref(),
+                &name,
+                Path::new(&args.data_directory),
+                &recovery,
+            )
+            .await
             {
-                Ok(_) => {
-                    spinner.success(format!("Restore completed successfully: {}", backup_name));
+                Ok(()) => {
+                    spinner.success(format!("{} staged in {}", name, args.data_directory));
+                    println!(
+                        "  Start PostgreSQL on it to replay the WAL archive{}",
+                        match target_time {
+                            Some(target_time) => format!(" up to {}", target_time),
+                            None => String::new(),
+                        }
+                    );
                 }
                 Err(e) => {
-                    spinner.error("Restore failed");
+                    spinner.error("Failed to restore the base backup");
                     return Err(e);
                 }
             }
         }
-        Commands::Cleanup(args) => {
-            let mut spinner = Spinner::new("Resolving storage configuration...");
+        Commands::Validate(args) => {
+            let mut spinner = Spinner::new(format!("Validating {}...", args.file));
             spinner.start();
 
-            let storage_config =
-                match resolve_storage_config(&args.workspace, &Some(args.storage)).await {
-                    Ok(config) => {
-                        spinner.update_message("Storage configuration resolved, connecting...");
-                        config
-                    }
-                    Err(e) => {
-                        spinner.error("Failed to resolve storage configuration");
-                        return Err(e);
-                    }
-                };
-
-            let storage = match StorageProvider::new(storage_config) {
-                Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connection...");
-                    provider
-                }
+            let file = match std::fs::File::open(&args.file) {
+                Ok(file) => file,
                 Err(e) => {
-                    spinner.error("Failed to connect to storage");
-                    return Err(e);
+                    spinner.error("Failed to open dump file");
+                    return Err(e.into());
                 }
             };
 
-            // Test storage connection
-            match storage.test().await {
-                Ok(_) => {
-                    let action = if args.dry_run {
-                        "analyzing"
-                    } else {
-                        "cleaning up"
-                    };
-                    spinner.update_message(format!("Connection verified, {} backups...", action));
-                }
+            let file = match DecryptingReader::new(file, encryption.as_ref()) {
+                Ok(file) => file,
                 Err(e) => {
-                    spinner.error("Storage connection test failed");
+                    spinner.error("Failed to decrypt dump file");
                     return Err(e);
                 }
-            }
+            };
+            let (compression_format, file) = Decompressor::sniff_format(file)?;
+            let reader = Box::new(Decompressor::new(file, compression_format.clone()));
 
-            match storage
-                .cleanup(parse_retention(&args.retention)?, args.dry_run)
-                .await
-            {
-                Ok((entries_deleted, storage_reclaimed)) => {
-                    if args.dry_run {
-                        spinner.success(format!(
-                            "Dry run completed: {} entries would be deleted, {} storage would be reclaimed",
-                            entries_deleted, storage_reclaimed
-                        ));
-                    } else {
-                        spinner.success(format!(
-                            "Cleanup completed: {} entries deleted, {} storage reclaimed",
-                            entries_deleted, storage_reclaimed
-                        ));
-                    }
+            let validation = match validate_dump(reader).await {
+                Ok(validation) => {
+                    spinner.stop();
+                    validation
                 }
                 Err(e) => {
-                    spinner.error("Cleanup failed");
+                    spinner.error("Failed to validate dump");
                     return Err(e);
                 }
+            };
+
+            println!("\n{}:", "Dump validation".green().bold());
+            println!("  Compression:     {:?}", compression_format);
+            println!("  Format:          {:?}", validation.format);
+            println!(
+                "  pg_dump version: {}",
+                validation.pg_dump_version.as_deref().unwrap_or("Unknown")
+            );
+            if let Some(toc_entries) = validation.toc_entries {
+                println!("  TOC entries:     {}", toc_entries);
+            }
+            println!(
+                "  Complete:        {}",
+                if validation.complete { "yes" } else { "no" }
+            );
+
+            for issue in &validation.issues {
+                println!("  {}", issue.yellow());
+            }
+
+            if !validation.is_valid() {
+                return Err(anyhow!("{} is not a valid, complete dump", args.file));
             }
         }
     };
@@ -416,6 +1522,54 @@ async fn handle_workspace_command(command: WorkspaceCommands) -> Result<()> {
                 );
             }
         }
+        WorkspaceCommands::RestoreOptions {
+            name,
+            drop_database,
+            message_level,
+            no_tablespaces,
+            single_transaction,
+        } => {
+            let Some(workspace) = collection.workspaces.get_mut(&name) else {
+                println!(
+                    "{}",
+                    format!("[ERROR] Workspace '{}' not found.", name).red()
+                );
+                return Ok(());
+            };
+
+            if drop_database.is_some()
+                || message_level.is_some()
+                || no_tablespaces.is_some()
+                || single_transaction.is_some()
+            {
+                if drop_database.is_some() {
+                    workspace.restore_options.drop_database = drop_database;
+                }
+
+                if message_level.is_some() {
+                    workspace.restore_options.message_level = message_level;
+                }
+
+                if no_tablespaces.is_some() {
+                    workspace.restore_options.no_tablespaces = no_tablespaces;
+                }
+
+                if single_transaction.is_some() {
+                    workspace.restore_options.single_transaction = single_transaction;
+                }
+
+                let restore_options = workspace.restore_options.clone();
+                workspace_manager.save(&collection)?;
+                println!(
+                    "Restore defaults updated for workspace '{}'.",
+                    name.green().bold()
+                );
+                print_restore_options(&restore_options);
+            } else {
+                println!("Restore defaults for workspace '{}':", name.green().bold());
+                print_restore_options(&workspace.restore_options);
+            }
+        }
         WorkspaceCommands::Active => {
             if let Some(workspace) = collection.get_active() {
                 println!("Active workspace: {}", workspace.name.green().bold());
@@ -428,13 +1582,66 @@ async fn handle_workspace_command(command: WorkspaceCommands) -> Result<()> {
     Ok(())
 }
 
+fn print_restore_options(options: &WorkspaceRestoreOptions) {
+    println!(
+        "  Drop database:      {}",
+        options.drop_database.unwrap_or(false)
+    );
+    println!(
+        "  Message level:      {}",
+        options.message_level.unwrap_or_default()
+    );
+    println!(
+        "  No tablespaces:     {}",
+        options.no_tablespaces.unwrap_or(false)
+    );
+    println!(
+        "  Single transaction: {}",
+        options.single_transaction.unwrap_or(false)
+    );
+}
+
 async fn resolve_configs_for_backup(
     args: &cli::BackupArgs,
+    config_file: Option<&ConfigFile>,
 ) -> Result<(
     dbkp_core::databases::DatabaseConfig,
     dbkp_core::storage::provider::StorageConfig,
 )> {
-    if let Some(workspace_name) = &args.workspace {
+    // The connection goes to the first database, the others are validated against its server
+    let database_args = cli::DatabaseArgs {
+        database: args
+            .database_config
+            .database
+            .iter()
+            .take(1)
+            .cloned()
+            .collect(),
+        ..args.database_config.clone()
+    };
+
+    resolve_configs(
+        &args.workspace,
+        &database_args,
+        &args.storage_config,
+        "backup",
+        config_file,
+    )
+    .await
+}
+
+async fn resolve_configs(
+    workspace_name: &Option<String>,
+    database_args: &cli::DatabaseArgs,
+    storage_args: &cli::StorageArgs,
+    command: &str,
+    config_file: Option<&ConfigFile>,
+) -> Result<(
+    dbkp_core::databases::DatabaseConfig,
+    dbkp_core::storage::provider::StorageConfig,
+)> {
+    if let Some(workspace_name) = workspace_name {
+        check_no_config_file(config_file)?;
         let workspace_manager = WorkspaceManager::new()?;
         let collection = workspace_manager.load()?;
         let workspace = collection
@@ -443,23 +1650,28 @@ async fn resolve_configs_for_backup(
         Ok((workspace.database.clone(), workspace.storage.clone()))
     } else {
         // Check if we have direct CLI parameters
-        let database_config = if has_database_config(&args.database_config) {
-            database_config_from_cli(&args.database_config)?
+        let base = config_file.and_then(|config_file| config_file.database.as_ref());
+        let database_config = if has_database_config(database_args) || base.is_some() {
+            database_config_from_cli(database_args, base)?
         } else {
             return Err(anyhow!(
                 "Either --workspace or database configuration parameters are required.\n\
-                Database parameters: --database-type, --database, --host, --port, --username\n\
-                Use 'dbkp backup --help' for more details."
+                Database parameters: --database-type, --database, --host, --username (and --port), or --url\n\
+                Use 'dbkp {} --help' for more details.",
+                command
             ));
         };
 
-        let storage_config = if has_storage_config(&args.storage_config) {
-            storage_from_cli(&args.storage_config)?
+        let storage_config = if let Some(base) = config_storage(config_file) {
+            override_storage_from_cli(base, storage_args)
+        } else if has_storage_config(storage_args) {
+            storage_from_cli(storage_args)?
         } else {
             return Err(anyhow!(
                 "Either --workspace or storage configuration parameters are required.\n\
                 Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key)\n\
-                Use 'dbkp backup --help' for more details."
+                Use 'dbkp {} --help' for more details.",
+                command
             ));
         };
 
@@ -469,30 +1681,40 @@ async fn resolve_configs_for_backup(
 
 async fn resolve_configs_for_restore(
     args: &cli::RestoreArgs,
+    config_file: Option<&ConfigFile>,
 ) -> Result<(
     dbkp_core::databases::DatabaseConfig,
     dbkp_core::storage::provider::StorageConfig,
+    WorkspaceRestoreOptions,
 )> {
     if let Some(workspace_name) = &args.workspace {
+        check_no_config_file(config_file)?;
         let workspace_manager = WorkspaceManager::new()?;
         let collection = workspace_manager.load()?;
         let workspace = collection
             .get_workspace(workspace_name)
             .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
-        Ok((workspace.database.clone(), workspace.storage.clone()))
+        Ok((
+            workspace.database.clone(),
+            workspace.storage.clone(),
+            workspace.restore_options.clone(),
+        ))
     } else {
         // Check if we have direct CLI parameters
-        let database_config = if has_database_config(&args.database_config) {
-            database_config_from_cli(&args.database_config)?
+        let base = config_file.and_then(|config_file| config_file.database.as_ref());
+        let database_config = if has_database_config(&args.database_config) || base.is_some() {
+            database_config_from_cli(&args.database_config, base)?
         } else {
             return Err(anyhow!(
                 "Either --workspace or database configuration parameters are required.\n\
-                Database parameters: --database-type, --database, --host, --port, --username\n\
+                Database parameters: --database-type, --database, --host, --username (and --port), or --url\n\
                 Use 'dbkp restore --help' for more details."
             ));
         };
 
-        let storage_config = if has_storage_config(&args.storage_config) {
+        let storage_config = if let Some(base) = config_storage(config_file) {
+            override_storage_from_cli(base, &args.storage_config)
+        } else if has_storage_config(&args.storage_config) {
             storage_from_cli(&args.storage_config)?
         } else {
             return Err(anyhow!(
@@ -502,15 +1724,39 @@ async fn resolve_configs_for_restore(
             ));
         };
 
-        Ok((database_config, storage_config))
+        Ok((
+            database_config,
+            storage_config,
+            WorkspaceRestoreOptions::default(),
+        ))
     }
 }
 
+/// Name the WAL of a cluster is archived under: `--cluster`, or the name of the workspace
+/// database, as used in the names of its base backups.
+fn resolve_cluster(cluster: &Option<String>, workspace_name: &Option<String>) -> Result<String> {
+    if let Some(cluster) = cluster {
+        return Ok(cluster.clone());
+    }
+
+    let workspace_name = workspace_name
+        .as_ref()
+        .ok_or_else(|| anyhow!("Either --cluster or --workspace is required"))?;
+    let collection = WorkspaceManager::new()?.load()?;
+    let workspace = collection
+        .get_workspace(workspace_name)
+        .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+
+    Ok(workspace.database.name.clone())
+}
+
 async fn resolve_storage_config(
     workspace_name: &Option<String>,
     storage_args: &Option<cli::StorageArgs>,
+    config_file: Option<&ConfigFile>,
 ) -> Result<dbkp_core::storage::provider::StorageConfig> {
     if let Some(workspace_name) = workspace_name {
+        check_no_config_file(config_file)?;
         let workspace_manager = WorkspaceManager::new()?;
         let collection = workspace_manager.load()?;
         let workspace = collection
@@ -518,6 +1764,10 @@ async fn resolve_storage_config(
             .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
         Ok(workspace.storage.clone())
     } else {
+        if let (Some(base), Some(storage_args)) = (config_storage(config_file), storage_args) {
+            return Ok(override_storage_from_cli(base, storage_args));
+        }
+
         if let Some(storage_config) = storage_args {
             if has_storage_config(storage_config) {
                 storage_from_cli(storage_config)
@@ -540,10 +1790,18 @@ async fn resolve_storage_config(
 
 async fn resolve_backup_name(
     args: &cli::RestoreArgs,
+    database_config: &dbkp_core::databases::DatabaseConfig,
     storage_config: &dbkp_core::storage::provider::StorageConfig,
 ) -> Result<String> {
     if let Some(name) = &args.name {
         Ok(name.clone())
+    } else if let Some(offset) = args.offset {
+        let storage_provider = StorageProvider::new(storage_config.clone())?;
+        let entry = storage_provider
+            .get_nth_latest(&database_config.name, offset)
+            .await?;
+
+        Ok(entry.metadata.name)
     } else if args.latest {
         // Get the latest backup
         let storage_provider = StorageProvider::new(storage_config.clone())?;
@@ -551,6 +1809,7 @@ async fn resolve_backup_name(
             .list_with_options(ListOptions {
                 latest_only: Some(true),
                 limit: Some(1),
+                ..Default::default()
             })
             .await?;
 
@@ -560,16 +1819,90 @@ async fn resolve_backup_name(
             Err(anyhow!("No backups found"))
         }
     } else {
-        Err(anyhow!("Either --name or --latest must be specified"))
+        Err(anyhow!(
+            "Either --name, --latest or --offset must be specified"
+        ))
+    }
+}
+
+fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{}B", size)
+    } else if size < 1024 * 1024 {
+        format!("{:.2}KB", size as f64 / 1024.0)
+    } else if size < 1024 * 1024 * 1024 {
+        format!("{:.2}MB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.2}GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
+    }
+}
+
+/// One line summing up a manifest for `list --details`.
+fn format_manifest_details(manifest: &BackupManifest) -> String {
+    let mut details = vec![
+        format!("{:?}", manifest.connection_type),
+        manifest
+            .server_version
+            .as_ref()
+            .map(|version| version.to_string())
+            .unwrap_or_else(|| "unknown version".to_string()),
+        manifest.scope.to_string(),
+    ];
+
+    if let Some(compression_format) = &manifest.compression_format {
+        details.push(format!("{:?}", compression_format));
+    }
+
+    if manifest.encrypted {
+        details.push("encrypted".to_string());
+    }
+
+    if let Some(uncompressed_size) = manifest.uncompressed_size {
+        details.push(format!("{} uncompressed", format_size(uncompressed_size)));
+    }
+
+    details.join(" | ")
+}
+
+/// Prints the diagnostics of a database tool from `min_severity` up, errors in red.
+fn print_diagnostics(diagnostics: &[Diagnostic], min_severity: Severity) {
+    for diagnostic in diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity >= min_severity)
+    {
+        let text = diagnostic.to_string();
+
+        match diagnostic.severity {
+            Severity::Error | Severity::Fatal => println!("  {}", text.red()),
+            Severity::Warning => println!("  {}", text.yellow()),
+            _ => println!("  {}", text),
+        }
     }
 }
 
+/// Highlights the errors of a failed database tool, which are easy to miss among notices.
+fn print_error_diagnostics(error: &anyhow::Error) {
+    if let Some(error) = error.downcast_ref::<CommandError>() {
+        print_diagnostics(&error.diagnostics, Severity::Error);
+    }
+}
+
+/// Whether the database is given by parameters, the missing ones being reported when building
+/// its config.
 fn has_database_config(args: &cli::DatabaseArgs) -> bool {
-    args.database_type.is_some()
-        && args.database.is_some()
-        && args.host.is_some()
-        && args.port.is_some()
-        && args.username.is_some()
+    args.database_type.is_some() || args.url.is_some()
+}
+
+fn config_storage(config_file: Option<&ConfigFile>) -> Option<&StorageConfig> {
+    config_file?.storage.as_ref()
+}
+
+/// Workspaces hold a database and a storage of their own, a config file would be ignored.
+fn check_no_config_file(config_file: Option<&ConfigFile>) -> Result<()> {
+    match config_file {
+        Some(_) => Err(anyhow!("--workspace and --config cannot be used together")),
+        None => Ok(()),
+    }
 }
 
 fn has_storage_config(args: &cli::StorageArgs) -> bool {
@@ -577,12 +1910,8 @@ fn has_storage_config(args: &cli::StorageArgs) -> bool {
     if args.storage_type.as_deref() == Some("local") || args.storage_type.is_none() {
         args.location.is_some()
     } else if args.storage_type.as_deref() == Some("s3") {
-        // For S3, we need bucket, endpoint, access_key, secret_key, and location
-        args.bucket.is_some()
-            && args.endpoint.is_some()
-            && args.access_key.is_some()
-            && args.secret_key.is_some()
-            && args.location.is_some()
+        // For S3, we need bucket and location, the keys may come from the AWS environment
+        args.bucket.is_some() && args.location.is_some()
     } else {
         false
     }
@@ -106,6 +106,7 @@ mod tests {
             .arg(format!("--storage-type=local"))
             .arg(format!("--location=./test-backups-postgresql"))
             .arg("--retention=0d")
+            .arg("--min-backup-age=0s")
             .output()
             .expect("Faled to execute cleanup command");
 
@@ -1,10 +1,15 @@
 use anyhow::{anyhow, Result};
-use dbkp_core::{databases::DatabaseConfig, storage::provider::StorageConfig};
+use dbkp_core::{
+    databases::{DatabaseConfig, MessageLevel},
+    storage::provider::StorageConfig,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::cli::RestoreArgs;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workspace {
     pub name: String,
@@ -12,6 +17,30 @@ pub struct Workspace {
     pub storage: StorageConfig,
     pub created_at: String,
     pub last_used: Option<String>,
+    #[serde(default)]
+    pub restore_options: WorkspaceRestoreOptions,
+}
+
+/// Restore defaults applied when restoring through a workspace, explicit flags take precedence.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceRestoreOptions {
+    pub drop_database: Option<bool>,
+    pub message_level: Option<MessageLevel>,
+    pub no_tablespaces: Option<bool>,
+    pub single_transaction: Option<bool>,
+}
+
+impl WorkspaceRestoreOptions {
+    /// These defaults with the flags given to `dbkp restore` in their place, including those
+    /// turned off with `=false`.
+    pub fn with_flags(&self, args: &RestoreArgs) -> Self {
+        Self {
+            drop_database: args.drop_database.or(self.drop_database),
+            message_level: args.message_level.or(self.message_level),
+            no_tablespaces: args.no_tablespaces.or(self.no_tablespaces),
+            single_transaction: args.single_transaction.or(self.single_transaction),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,11 +38,13 @@ impl ArchiveInstaller {
         let (major_version, _, string_version) = match &self.database_version {
             Version::PostgreSQL(version) => (version.major, version.major, version.to_string()),
             Version::MySql(version) => (version.major, version.minor, version.to_string()),
+            Version::Sqlite(_) => return Err(anyhow!("SQLite needs no client utilities")),
         };
 
         let database_name = match self.database_version {
             Version::PostgreSQL(_) => "postgresql",
             Version::MySql(_) => "mysql",
+            Version::Sqlite(_) => "sqlite",
         };
 
         let databases = match metadata
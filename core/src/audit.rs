@@ -0,0 +1,214 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOperation {
+    Backup,
+    Restore,
+    Delete,
+}
+
+impl AuditOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::Backup => "backup",
+            AuditOperation::Restore => "restore",
+            AuditOperation::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Shared by every record of one run, e.g. all deletions of a cleanup.
+    pub correlation_id: String,
+    pub operation: AuditOperation,
+    pub operator: String,
+    pub database: Option<String>,
+    pub storage: Option<String>,
+    pub backup: Option<String>,
+    pub outcome: AuditOutcome,
+    pub bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(operation: AuditOperation, correlation_id: &str) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            correlation_id: correlation_id.into(),
+            operation,
+            operator: get_operator(),
+            database: None,
+            storage: None,
+            backup: None,
+            outcome: AuditOutcome::Success,
+            bytes: None,
+            error: None,
+        }
+    }
+
+    pub fn failed(mut self, error: &anyhow::Error) -> Self {
+        self.outcome = AuditOutcome::Failure;
+        self.error = Some(format!("{:#}", error));
+        self
+    }
+}
+
+pub fn new_correlation_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// The operator is taken from `DBKP_OPERATOR`, then the OS user.
+pub fn get_operator() -> String {
+    ["DBKP_OPERATOR", "USER", "USERNAME"]
+        .iter()
+        .find_map(|key| env::var(key).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Append-only JSON-lines record of the backups, restores and deletions performed.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Records the outcome of an operation without letting the audit log hide it: a failed
+    /// operation keeps its own error, and a successful one fails if it could not be recorded.
+    pub fn record<T>(&self, record: AuditRecord, result: Result<T>) -> Result<T> {
+        let record = match &result {
+            Ok(_) => record,
+            Err(e) => record.failed(e),
+        };
+
+        let operation = record.operation;
+
+        match (self.append(&record), result) {
+            (Ok(()), result) => result,
+            (Err(audit_error), Ok(_)) => Err(anyhow!(
+                "The {:?} operation succeeded but could not be recorded in the audit log: {:#}",
+                operation,
+                audit_error
+            )),
+            (Err(audit_error), Err(e)) => Err(e.context(format!(
+                "The {:?} operation failed and could not be recorded in the audit log: {:#}",
+                operation, audit_error
+            ))),
+        }
+    }
+}
+
+/// Records through `audit_log` when one is configured, otherwise passes `result` through.
+pub fn record<T>(
+    audit_log: Option<&AuditLog>,
+    record: AuditRecord,
+    result: Result<T>,
+) -> Result<T> {
+    match audit_log {
+        Some(audit_log) => audit_log.record(record, result),
+        None => result,
+    }
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use std::fs;
+
+    use anyhow::anyhow;
+    use tempfile::tempdir;
+
+    use super::{AuditLog, AuditOperation, AuditOutcome, AuditRecord};
+
+    #[test]
+    fn test_01_append_records() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+
+        let mut record = AuditRecord::new(AuditOperation::Backup, "abc");
+        record.bytes = Some(42);
+        assert_eq!(log.record(record, Ok(1)).unwrap(), 1);
+
+        let record = AuditRecord::new(AuditOperation::Delete, "abc");
+        let result: anyhow::Result<()> = Err(anyhow!("Permission denied"));
+        assert!(log.record(record, result).is_err());
+
+        let content = fs::read_to_string(dir.path().join("audit.jsonl")).unwrap();
+        let records: Vec<AuditRecord> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].outcome, AuditOutcome::Success);
+        assert_eq!(records[0].bytes, Some(42));
+        assert_eq!(records[1].outcome, AuditOutcome::Failure);
+        assert_eq!(records[1].error, Some("Permission denied".into()));
+    }
+
+    #[test]
+    fn test_02_audit_failure_is_reported() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        // A directory cannot be opened as the log file
+        let log = AuditLog::new(dir.path());
+
+        let record = AuditRecord::new(AuditOperation::Restore, "abc");
+        let error = log.record(record, Ok(())).unwrap_err();
+        assert!(error.to_string().contains("succeeded"));
+
+        let record = AuditRecord::new(AuditOperation::Restore, "abc");
+        let result: anyhow::Result<()> = Err(anyhow!("psql restore failed"));
+        let error = log.record(record, result).unwrap_err();
+        assert_eq!(error.root_cause().to_string(), "psql restore failed");
+    }
+}
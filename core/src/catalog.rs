@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::extract_timestamp_from_filename, manifest::BackupManifest,
+    storage::provider::StorageProvider,
+};
+
+/// One backup as recorded in the catalog.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub path: String,
+    /// Name of the storage holding the backup.
+    pub storage: String,
+    pub database: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub size: u64,
+    pub checksum: Option<String>,
+}
+
+impl CatalogEntry {
+    pub fn from_manifest(storage: &str, manifest: &BackupManifest) -> Self {
+        Self {
+            path: manifest.name.clone(),
+            storage: storage.into(),
+            database: Some(manifest.database.clone()),
+            created_at: Some(manifest.created_at),
+            size: manifest.size.unwrap_or(0),
+            checksum: manifest.checksum.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CatalogFile {
+    entries: Vec<CatalogEntry>,
+}
+
+/// Local JSON copy of the backup metadata of one or more storages, so dashboards and scripts
+/// do not have to list the storage. It is only a cache: `reindex` rebuilds it from storage.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    path: PathBuf,
+    /// Serializes the read-modify-write cycles of concurrent backups.
+    lock: Arc<Mutex<()>>,
+}
+
+impl Catalog {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Every entry, sorted by storage and path. A missing catalog is empty.
+    pub fn load(&self) -> Result<Vec<CatalogEntry>> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read catalog {}", self.path.display()))
+            }
+        };
+
+        let file: CatalogFile = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse catalog {}", self.path.display()))?;
+
+        Ok(file.entries)
+    }
+
+    fn save(&self, mut entries: Vec<CatalogEntry>) -> Result<()> {
+        entries.sort_by(|a, b| (&a.storage, &a.path).cmp(&(&b.storage, &b.path)));
+
+        let content = serde_json::to_vec_pretty(&CatalogFile { entries })?;
+        // Written aside and renamed so readers never see a partial catalog
+        let temp_path = self.path.with_extension("tmp");
+
+        fs::write(&temp_path, content)
+            .with_context(|| format!("Failed to write catalog {}", temp_path.display()))?;
+        fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("Failed to write catalog {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    fn update(&self, update: impl FnOnce(&mut Vec<CatalogEntry>)) -> Result<()> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|_| anyhow!("Catalog lock poisoned"))?;
+
+        let mut entries = self.load()?;
+        update(&mut entries);
+        self.save(entries)
+    }
+
+    pub fn upsert(&self, entry: CatalogEntry) -> Result<()> {
+        self.update(|entries| {
+            entries.retain(|existing| {
+                existing.storage != entry.storage || existing.path != entry.path
+            });
+            entries.push(entry);
+        })
+    }
+
+    pub fn remove(&self, storage: &str, path: &str) -> Result<()> {
+        self.update(|entries| {
+            entries.retain(|existing| existing.storage != storage || existing.path != path)
+        })
+    }
+
+    /// Replaces the entries of `storage`, leaving other storages untouched.
+    pub fn replace(&self, storage: &str, storage_entries: Vec<CatalogEntry>) -> Result<()> {
+        self.update(|entries| {
+            entries.retain(|existing| existing.storage != storage);
+            entries.extend(storage_entries);
+        })
+    }
+
+    /// Rebuilds the entries of `storage_provider` from a full listing and its manifests.
+    /// Backups without a manifest are recorded from their name and storage metadata.
+    pub async fn reindex(&self, storage_provider: &StorageProvider) -> Result<usize> {
+        let storage = storage_provider.config.name().to_string();
+        let mut entries = vec![];
+
+        for backup in storage_provider.list().await? {
+            let entry = match storage_provider.read_manifest(&backup.path).await? {
+                Some(manifest) => CatalogEntry {
+                    path: backup.path.clone(),
+                    size: backup.metadata.content_length,
+                    ..CatalogEntry::from_manifest(&storage, &manifest)
+                },
+                None => CatalogEntry {
+                    path: backup.path.clone(),
+                    storage: storage.clone(),
+                    database: None,
+                    created_at: extract_timestamp_from_filename(&backup.metadata.name)
+                        .ok()
+                        .or(backup.metadata.last_modified),
+                    size: backup.metadata.content_length,
+                    checksum: None,
+                },
+            };
+
+            entries.push(entry);
+        }
+
+        let count = entries.len();
+        self.replace(&storage, entries)?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use tempfile::tempdir;
+
+    use super::{Catalog, CatalogEntry};
+
+    fn entry(storage: &str, path: &str, size: u64) -> CatalogEntry {
+        CatalogEntry {
+            path: path.into(),
+            storage: storage.into(),
+            database: Some("app".into()),
+            created_at: None,
+            size,
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn test_01_update_catalog() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let catalog = Catalog::new(dir.path().join("catalog.json"));
+
+        assert!(catalog.load().unwrap().is_empty());
+
+        catalog.upsert(entry("local", "app-b.gz", 1)).unwrap();
+        catalog.upsert(entry("local", "app-a.gz", 1)).unwrap();
+        catalog.upsert(entry("local", "app-b.gz", 2)).unwrap();
+        catalog.upsert(entry("s3", "app-a.gz", 3)).unwrap();
+
+        let entries = catalog.load().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, "app-a.gz");
+        assert_eq!(entries[1].size, 2);
+
+        catalog.remove("local", "app-a.gz").unwrap();
+        catalog
+            .replace("s3", vec![entry("s3", "app-c.gz", 4)])
+            .unwrap();
+
+        let entries = catalog.load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry("local", "app-b.gz", 2));
+        assert_eq!(entries[1], entry("s3", "app-c.gz", 4));
+    }
+}
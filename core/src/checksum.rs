@@ -0,0 +1,282 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Reads the algorithm from a `<algorithm>:<hex>` checksum.
+    pub fn from_checksum(checksum: &str) -> anyhow::Result<Self> {
+        let (algorithm, _) = checksum
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed checksum: {}", checksum))?;
+
+        algorithm.parse()
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sha256" => Ok(ChecksumAlgorithm::Sha256),
+            "sha512" => Ok(ChecksumAlgorithm::Sha512),
+            "blake3" => Ok(ChecksumAlgorithm::Blake3),
+            _ => Err(anyhow!("Unsupported checksum algorithm: {}", s)),
+        }
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            ChecksumAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Formats the digest as `<algorithm>:<hex>`.
+    fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("sha256:{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("sha512:{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => format!("blake3:{}", hasher.finalize().to_hex()),
+        }
+    }
+}
+
+/// Hashes bytes as they are written through to the inner writer.
+pub struct HashingWriter<W: Write + Send + Unpin> {
+    inner: W,
+    hasher: Option<Hasher>,
+    bytes_written: u64,
+}
+
+impl<W: Write + Send + Unpin> HashingWriter<W> {
+    /// Hashes nothing when `algorithm` is `None`.
+    pub fn new(inner: W, algorithm: Option<ChecksumAlgorithm>) -> Self {
+        Self {
+            inner,
+            hasher: algorithm.map(Hasher::new),
+            bytes_written: 0,
+        }
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the inner writer and the checksum formatted as `<algorithm>:<hex>`.
+    pub fn finish(self) -> (W, Option<String>) {
+        (self.inner, self.hasher.map(Hasher::finish))
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+
+        self.bytes_written += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes bytes as they are read from the inner reader.
+pub struct HashingReader<R: Read + Send + Unpin> {
+    inner: R,
+    hasher: Hasher,
+    bytes_read: u64,
+}
+
+impl<R: Read + Send + Unpin> HashingReader<R> {
+    pub fn new(inner: R, algorithm: ChecksumAlgorithm) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(algorithm),
+            bytes_read: 0,
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the checksum of everything read so far, formatted as `<algorithm>:<hex>`.
+    pub fn finish(self) -> String {
+        self.hasher.finish()
+    }
+}
+
+impl<R: Read + Send + Unpin> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        self.hasher.update(&buf[..n]);
+        self.bytes_read += n as u64;
+
+        Ok(n)
+    }
+}
+
+/// Extension of the SHA-256 checksum written next to every backup.
+pub const SIDECAR_EXTENSION: &str = ".sha256";
+
+pub fn get_sidecar_name(backup_name: &str) -> String {
+    format!("{}{}", backup_name, SIDECAR_EXTENSION)
+}
+
+pub fn is_sidecar(name: &str) -> bool {
+    name.ends_with(SIDECAR_EXTENSION)
+}
+
+/// Formats a `sha256:<hex>` checksum like `sha256sum`, so a downloaded backup can be checked
+/// with `sha256sum -c` from its directory.
+pub fn format_sidecar(checksum: &str, backup_name: &str) -> anyhow::Result<String> {
+    let digest = checksum.strip_prefix("sha256:").ok_or_else(|| {
+        anyhow!(
+            "Checksum sidecars only hold SHA-256 checksums: {}",
+            checksum
+        )
+    })?;
+    let file_name = backup_name.rsplit('/').next().unwrap_or(backup_name);
+
+    Ok(format!("{}  {}\n", digest, file_name))
+}
+
+/// Reads the checksum of a sidecar as `sha256:<hex>`.
+pub fn parse_sidecar(content: &str) -> anyhow::Result<String> {
+    let digest = content
+        .split_whitespace()
+        .next()
+        .filter(|digest| digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("Malformed checksum sidecar"))?;
+
+    Ok(format!("sha256:{}", digest.to_lowercase()))
+}
+
+#[cfg(test)]
+mod checksum_tests {
+    use std::io::Write;
+
+    use super::{format_sidecar, parse_sidecar, ChecksumAlgorithm, HashingWriter};
+
+    #[test]
+    fn test_01_hashing_writer() {
+        let mut writer = HashingWriter::new(vec![], Some(ChecksumAlgorithm::Sha256));
+
+        writer.write_all(b"hello ").expect("Failed to write");
+        writer.write_all(b"world").expect("Failed to write");
+
+        assert_eq!(writer.bytes_written(), 11);
+
+        let (inner, checksum) = writer.finish();
+
+        assert_eq!(inner, b"hello world");
+        assert_eq!(
+            checksum,
+            Some("sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".into())
+        );
+    }
+
+    #[test]
+    fn test_02_disabled_hashing_writer() {
+        let mut writer = HashingWriter::new(vec![], None);
+        writer.write_all(b"hello").expect("Failed to write");
+
+        let (_, checksum) = writer.finish();
+
+        assert!(checksum.is_none());
+    }
+
+    #[test]
+    fn test_03_checksum_algorithms() {
+        let checksum = |algorithm| {
+            let mut writer = HashingWriter::new(vec![], Some(algorithm));
+            writer.write_all(b"hello world").expect("Failed to write");
+            writer.finish().1.unwrap()
+        };
+
+        let sha512 = checksum(ChecksumAlgorithm::Sha512);
+        let blake3 = checksum(ChecksumAlgorithm::Blake3);
+
+        assert!(sha512.starts_with("sha512:309ecc489c12d6eb4cc40f50c902f2b4"));
+        assert_eq!(
+            blake3,
+            "blake3:d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+        assert_eq!(
+            ChecksumAlgorithm::from_checksum(&blake3).unwrap(),
+            ChecksumAlgorithm::Blake3
+        );
+        assert!(ChecksumAlgorithm::from_checksum("md5:abc").is_err());
+    }
+
+    #[test]
+    fn test_04_sidecar() {
+        let checksum = "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let sidecar = format_sidecar(checksum, "backups/db-2024.sql.gz").unwrap();
+
+        assert_eq!(
+            sidecar,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9  db-2024.sql.gz\n"
+        );
+        assert_eq!(parse_sidecar(&sidecar).unwrap(), checksum);
+        assert_eq!(parse_sidecar(&sidecar.to_uppercase()).unwrap(), checksum);
+        assert!(parse_sidecar("not a checksum").is_err());
+        assert!(format_sidecar("blake3:d749", "db.sql.gz").is_err());
+    }
+}
@@ -1,11 +1,13 @@
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use dirs::cache_dir;
 use regex::Regex;
 use std::{
     borrow::Borrow,
     env,
     path::{Path, PathBuf},
+    sync::LazyLock,
 };
 
 use uuid::Uuid;
@@ -77,6 +79,7 @@ pub fn get_db_name(version: &Version) -> String {
     match version {
         Version::PostgreSQL(_) => "postgresql".into(),
         Version::MySql(_) => "mysql".into(),
+        Version::Sqlite(_) => "sqlite".into(),
     }
 }
 
@@ -84,31 +87,171 @@ pub fn get_version_name(version: &Version) -> String {
     match version {
         Version::PostgreSQL(version) => version.to_string(),
         Version::MySql(version) => version.to_string(),
+        Version::Sqlite(version) => version.to_string(),
     }
 }
 
+/// Timestamps accepted in backup names, in the order they are tried, with an example of each.
+/// Names of other tools are recognized too, so their backups sort and expire with dbkp's.
+pub const TIMESTAMP_FORMATS: &[(&str, &str)] = &[
+    ("dbkp (UTC)", "app-2025-03-29-014500-a1b2c3d4.gz"),
+    (
+        "ISO 8601, UTC unless an offset is given",
+        "app-2025-03-29T01:45:00Z.sql.gz",
+    ),
+    ("YYYYMMDD_HHMMSS (UTC)", "app_20250329_014500.sql.gz"),
+    ("Unix epoch in seconds", "app-1743212700.sql.gz"),
+];
+
+// Compiled once, since listing a large storage parses thousands of names
+static DBKP_TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{4}-\d{2}-\d{2}-\d{6})-[a-f0-9]+\.\w*$").unwrap());
+static ISO_TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(\d{4})-?(\d{2})-?(\d{2})T(\d{2}):?(\d{2}):?(\d{2})(?:\.\d+)?(Z|[+-]\d{2}:?\d{2})?",
+    )
+    .unwrap()
+});
+static COMPACT_TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\D)(\d{8}_\d{6})(?:\D|$)").unwrap());
+static EPOCH_TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\D)(\d{10})(?:\D|$)").unwrap());
+
+/// Matches the names `get_default_backup_name` gives the backups of the config `config_name`,
+/// and not those of a config whose name starts with it, such as `app-staging` for `app`.
+pub fn get_backup_name_pattern(config_name: &str) -> Regex {
+    Regex::new(&format!(
+        r"^{}-\d{{4}}-\d{{2}}-\d{{2}}-\d{{6}}-[0-9a-f]+\.",
+        regex::escape(config_name)
+    ))
+    .expect("An escaped name always makes a valid pattern")
+}
+
+/// The part of a backup name before its timestamp, without separators, e.g. `app` for
+/// `app-2025-03-29-014500-a1b2c3d4.gz` and `base-app` for the base backups of `app`. Retention
+/// ranks the backups of each group separately. Names without a timestamp are their own group.
+pub fn get_backup_group(filename: &str) -> &str {
+    let start = [
+        &*DBKP_TIMESTAMP,
+        &*ISO_TIMESTAMP,
+        &*COMPACT_TIMESTAMP,
+        &*EPOCH_TIMESTAMP,
+    ]
+    .iter()
+    .find_map(|pattern| {
+        let captures = pattern.captures(filename)?;
+        captures.get(1).map(|timestamp| timestamp.start())
+    });
+
+    match start {
+        Some(start) => filename[..start].trim_end_matches(['-', '_', '.']),
+        None => filename,
+    }
+}
+
+type TimestampParser = fn(&str) -> Option<DateTime<Utc>>;
+
+fn parse_dbkp_timestamp(filename: &str) -> Option<DateTime<Utc>> {
+    // Which files are backups is decided by `StorageProvider::is_backup`, so any extension,
+    // including none for uncompressed backups, is accepted here
+    let caps = DBKP_TIMESTAMP.captures(filename)?;
+
+    let naive_datetime = NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d-%H%M%S").ok()?;
+    Some(Utc.from_utc_datetime(&naive_datetime))
+}
+
+fn parse_iso_timestamp(filename: &str) -> Option<DateTime<Utc>> {
+    let caps = ISO_TIMESTAMP.captures(filename)?;
+
+    let offset = match caps.get(7).map(|offset| offset.as_str()) {
+        Some("Z") | None => "+0000".to_string(),
+        Some(offset) => offset.replace(':', ""),
+    };
+
+    let datetime = DateTime::parse_from_str(
+        &format!(
+            "{}{}{}{}{}{}{}",
+            &caps[1], &caps[2], &caps[3], &caps[4], &caps[5], &caps[6], offset
+        ),
+        "%Y%m%d%H%M%S%z",
+    )
+    .ok()?;
+
+    Some(datetime.with_timezone(&Utc))
+}
+
+fn parse_compact_timestamp(filename: &str) -> Option<DateTime<Utc>> {
+    let caps = COMPACT_TIMESTAMP.captures(filename)?;
+
+    let naive_datetime = NaiveDateTime::parse_from_str(&caps[1], "%Y%m%d_%H%M%S").ok()?;
+    Some(Utc.from_utc_datetime(&naive_datetime))
+}
+
+fn parse_epoch_timestamp(filename: &str) -> Option<DateTime<Utc>> {
+    let caps = EPOCH_TIMESTAMP.captures(filename)?;
+
+    DateTime::from_timestamp(caps[1].parse().ok()?, 0)
+}
+
+/// Reads the creation time embedded in a backup name, in one of the `TIMESTAMP_FORMATS`.
 pub fn extract_timestamp_from_filename(filename: &str) -> Result<DateTime<Utc>> {
-    let re = Regex::new(r"(\d{4}-\d{2}-\d{2}-\d{6})-[a-f0-9]+\.(gz|dump|tar|zip|sql)$")
-        .map_err(|e| anyhow!("Failed to compile regex: {}", e))?;
+    let parsers: [TimestampParser; 4] = [
+        parse_dbkp_timestamp,
+        parse_iso_timestamp,
+        parse_compact_timestamp,
+        parse_epoch_timestamp,
+    ];
 
-    let caps = re.captures(filename).ok_or_else(|| {
-        anyhow!(
-            "Filename doesn't match expected timestamp format: {}",
-            filename
-        )
-    })?;
+    parsers
+        .iter()
+        .find_map(|parse| parse(filename))
+        .ok_or_else(|| {
+            anyhow!(
+                "Filename doesn't match any supported timestamp format: {}",
+                filename
+            )
+        })
+}
 
-    let timestamp_str = caps
-        .get(1)
-        .ok_or_else(|| anyhow!("Failed to extract timestamp from filename: {}", filename))?
-        .as_str();
+/// Retention is computed on UTC instants so DST transitions never shift the cutoff.
+pub fn get_retention_cutoff(now: DateTime<Utc>, retention_days: u64) -> Result<DateTime<Utc>> {
+    let retention = i64::try_from(retention_days)
+        .ok()
+        .and_then(Duration::try_days)
+        .ok_or_else(|| anyhow!("Invalid retention period: {} days", retention_days))?;
 
-    let naive_datetime = NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%d-%H%M%S")
-        .map_err(|e| anyhow!("Failed to parse timestamp {}: {}", timestamp_str, e))?;
+    now.checked_sub_signed(retention)
+        .ok_or_else(|| anyhow!("Failed to calculate cutoff date"))
+}
+
+/// A backup can be pruned once it is older than the retention cutoff, unless it is younger
+/// than `min_age`. Its age is taken from the newest of its name timestamp and its storage
+/// modification time, since the name is set when the backup starts, not when it completes.
+pub fn is_prunable(
+    timestamp: DateTime<Utc>,
+    last_modified: Option<DateTime<Utc>>,
+    cutoff: DateTime<Utc>,
+    now: DateTime<Utc>,
+    min_age: Duration,
+) -> bool {
+    let newest = last_modified.map_or(timestamp, |modified| modified.max(timestamp));
+    timestamp < cutoff && now - newest >= min_age
+}
 
-    let datetime = DateTime::<Utc>::from_utc(naive_datetime, Utc);
+pub fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|e| anyhow!("Invalid timezone {}: {}", timezone, e))
+}
 
-    Ok(datetime)
+pub fn format_timestamp(timestamp: &DateTime<Utc>, timezone: Option<&Tz>) -> String {
+    match timezone {
+        Some(timezone) => timestamp
+            .with_timezone(timezone)
+            .format("%Y-%m-%d %H:%M:%S %Z")
+            .to_string(),
+        None => timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    }
 }
 
 pub fn get_arch() -> Result<String> {
@@ -168,3 +311,142 @@ pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()>
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod common_tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{
+        extract_timestamp_from_filename, format_timestamp, get_backup_group, get_retention_cutoff,
+        is_prunable, parse_timezone, TIMESTAMP_FORMATS,
+    };
+
+    #[test]
+    fn test_01_extract_timestamp_is_utc() {
+        let timestamp = extract_timestamp_from_filename("db-2025-03-29-014500-a1b2c3d4.gz")
+            .expect("Failed to extract timestamp");
+
+        assert_eq!(
+            timestamp,
+            Utc.with_ymd_and_hms(2025, 3, 29, 1, 45, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_02_retention_across_dst_boundary() {
+        // Europe/Paris switches from CET to CEST at 2025-03-30 01:00 UTC, so the
+        // wall clock shows 24h30 between these instants while only 23h30 elapsed.
+        let backup = extract_timestamp_from_filename("db-2025-03-29-014500-a1b2c3d4.gz")
+            .expect("Failed to extract timestamp");
+        let now = Utc.with_ymd_and_hms(2025, 3, 30, 1, 15, 0).unwrap();
+
+        let cutoff = get_retention_cutoff(now, 1).expect("Failed to get cutoff");
+        assert!(backup >= cutoff, "Backup should not be pruned early");
+
+        let cutoff = get_retention_cutoff(now + chrono::Duration::hours(1), 1)
+            .expect("Failed to get cutoff");
+        assert!(backup < cutoff, "Backup should be pruned after 24 hours");
+
+        let paris = parse_timezone("Europe/Paris").expect("Failed to parse timezone");
+        assert_eq!(
+            format_timestamp(&backup, Some(&paris)),
+            "2025-03-29 02:45:00 CET"
+        );
+        assert_eq!(
+            format_timestamp(&now, Some(&paris)),
+            "2025-03-30 03:15:00 CEST"
+        );
+        assert_eq!(format_timestamp(&now, None), "2025-03-30 01:15:00 UTC");
+    }
+
+    #[test]
+    fn test_03_grace_period_at_cutoff() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 30, 12, 0, 0).unwrap();
+        let min_age = chrono::Duration::hours(1);
+
+        // With no retention the cutoff is now, so only the grace period protects the backup
+        let cutoff = get_retention_cutoff(now, 0).expect("Failed to get cutoff");
+        let just_created = now - chrono::Duration::seconds(1);
+        assert!(!is_prunable(just_created, None, cutoff, now, min_age));
+        assert!(is_prunable(
+            just_created,
+            None,
+            cutoff,
+            now,
+            chrono::Duration::zero()
+        ));
+
+        let old = now - chrono::Duration::hours(2);
+        assert!(is_prunable(old, None, cutoff, now, min_age));
+        assert!(!is_prunable(old, Some(just_created), cutoff, now, min_age));
+
+        // Exactly at the cutoff is still within retention
+        let cutoff = get_retention_cutoff(now, 1).expect("Failed to get cutoff");
+        assert!(!is_prunable(cutoff, None, cutoff, now, min_age));
+        assert!(is_prunable(
+            cutoff - chrono::Duration::seconds(1),
+            None,
+            cutoff,
+            now,
+            min_age
+        ));
+    }
+
+    #[test]
+    fn test_04_timestamp_formats() {
+        let expected = Utc.with_ymd_and_hms(2025, 3, 29, 1, 45, 0).unwrap();
+
+        for (_, example) in TIMESTAMP_FORMATS {
+            assert_eq!(
+                extract_timestamp_from_filename(example).ok(),
+                Some(expected),
+                "{}",
+                example
+            );
+        }
+
+        for name in [
+            "db-2025-03-29-014500-a1b2c3d4.",
+            "db-20250329T014500Z.dump",
+            "db-2025-03-29T01:45:00.123Z.sql",
+            "db-2025-03-29T03:45:00+02:00.sql.gz",
+            "db-2025-03-28T21:45:00-0400.sql.gz",
+            "db-2025-03-29T01:45:00.sql.gz",
+            "backup_20250329_014500.tar.gz",
+            "20250329_014500.sql",
+            "db.1743212700.gz",
+        ] {
+            assert_eq!(
+                extract_timestamp_from_filename(name).ok(),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+
+        for name in [
+            "db.gz",
+            "db-2025-13-29-014500-a1b2c3d4.gz",
+            "db_20251329_014500.gz",
+            "db-174321270.gz",
+            "db-17432127001.gz",
+        ] {
+            assert!(extract_timestamp_from_filename(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_05_backup_group() {
+        for (name, group) in [
+            ("app-2025-03-29-014500-a1b2c3d4.gz", "app"),
+            ("app-staging-2025-03-29-014500-a1b2c3d4.gz", "app-staging"),
+            ("base-app-2025-03-29-014500-a1b2c3d4.gz", "base-app"),
+            ("app-2025-03-29T01:45:00Z.sql.gz", "app"),
+            ("app_20250329_014500.sql.gz", "app"),
+            ("app.1743212700.gz", "app"),
+            ("notes.gz", "notes.gz"),
+        ] {
+            assert_eq!(get_backup_group(name), group, "{}", name);
+        }
+    }
+}
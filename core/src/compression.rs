@@ -3,7 +3,7 @@ use flate2::Compression;
 use serde::{Deserialize, Serialize};
 use std::io::{self, Seek, SeekFrom, Write};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CompressionFormat {
     Gzip,
     Zlib,
@@ -59,7 +59,19 @@ impl<W: Write + Send + Unpin> Write for Compressor<W> {
 }
 
 use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
-use std::io::Read;
+use std::io::{Chain, Cursor, Read};
+
+/// A reader with the bytes read from it to identify its format put back in front.
+pub type Sniffed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Raw deflate streams have no signature and are reported as `None`.
+fn format_from_signature(signature: &[u8]) -> CompressionFormat {
+    match signature {
+        [0x1F, 0x8B, ..] => CompressionFormat::Gzip,
+        [0x78, 0x01 | 0x9C | 0xDA, ..] => CompressionFormat::Zlib,
+        _ => CompressionFormat::None,
+    }
+}
 
 pub enum Decompressor<R: Read + Send + Unpin> {
     Gzip(GzDecoder<R>),
@@ -87,19 +99,19 @@ impl<R: Read + Send + Unpin> Decompressor<R> {
         let bytes_read = reader.read(&mut signature)?;
         reader.seek(SeekFrom::Start(start_pos))?;
 
-        if bytes_read < 2 {
-            return Ok((CompressionFormat::None, reader));
-        }
+        Ok((format_from_signature(&signature[..bytes_read]), reader))
+    }
 
-        if signature[0] == 0x1F && signature[1] == 0x8B {
-            Ok((CompressionFormat::Gzip, reader))
-        } else if signature[0] == 0x78
-            && (signature[1] == 0x01 || signature[1] == 0x9C || signature[1] == 0xDA)
-        {
-            Ok((CompressionFormat::Zlib, reader))
-        } else {
-            Ok((CompressionFormat::None, reader))
-        }
+    /// Like `detect_format`, for readers that cannot seek: the bytes read to identify the
+    /// format are chained back in front of the returned reader.
+    pub fn sniff_format(mut reader: R) -> io::Result<(CompressionFormat, Sniffed<R>)> {
+        let mut signature = vec![];
+        (&mut reader).take(2).read_to_end(&mut signature)?;
+
+        Ok((
+            format_from_signature(&signature),
+            Cursor::new(signature).chain(reader),
+        ))
     }
 
     pub fn into_inner(self) -> R {
@@ -155,4 +167,40 @@ mod compression_test {
 
         assert_eq!(message.as_bytes(), decompressed_bytes);
     }
+
+    #[test]
+    fn sniff_format() {
+        let message = "-- PostgreSQL database dump";
+
+        let mut compressor = Compressor::new(vec![], CompressionFormat::Gzip, Compression::fast());
+        compressor
+            .write_all(message.as_bytes())
+            .expect("Failed to write bytes");
+        let compressed = compressor.finish().expect("Unable to finish compressor");
+
+        for (bytes, expected) in [
+            (compressed, CompressionFormat::Gzip),
+            (message.as_bytes().to_vec(), CompressionFormat::None),
+            (vec![0x1F], CompressionFormat::None),
+        ] {
+            let (format, reader) =
+                Decompressor::sniff_format(bytes.as_slice()).expect("Failed to sniff format");
+            assert!(matches!(
+                (&format, &expected),
+                (CompressionFormat::Gzip, CompressionFormat::Gzip)
+                    | (CompressionFormat::None, CompressionFormat::None)
+            ));
+
+            let mut content = vec![];
+            Decompressor::new(reader, format)
+                .read_to_end(&mut content)
+                .expect("Failed to read bytes");
+
+            if bytes.len() > 1 {
+                assert_eq!(content, message.as_bytes());
+            } else {
+                assert_eq!(content, bytes);
+            }
+        }
+    }
 }
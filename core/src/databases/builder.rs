@@ -0,0 +1,365 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use super::{connection_url, ssh_tunnel::SshTunnelConfig, ConnectionType, DatabaseConfig, SslMode};
+
+/// Builds a `DatabaseConfig`, checking it before any connection is attempted.
+///
+/// `build` reports every missing or invalid field at once. The port defaults to the one of the
+/// connection type, the name to the database, and the database of a SQLite file to its stem.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfigBuilder {
+    connection_type: ConnectionType,
+    id: Option<String>,
+    name: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    database: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    application_name: Option<String>,
+    pgpass_file: Option<String>,
+    ssh_tunnel: Option<SshTunnelConfig>,
+    path: Option<String>,
+    pool_max_connections: Option<u32>,
+    acquire_timeout_secs: Option<u64>,
+    statement_timeout_secs: Option<u64>,
+    connect_timeout_secs: Option<u64>,
+    keepalive_idle_secs: Option<u64>,
+    binary_path_override: Option<String>,
+    ssl_mode: Option<SslMode>,
+}
+
+impl DatabaseConfigBuilder {
+    pub fn new(connection_type: ConnectionType) -> Self {
+        Self {
+            connection_type,
+            id: None,
+            name: None,
+            host: None,
+            port: None,
+            database: None,
+            username: None,
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        }
+    }
+
+    /// Starts from a connection URL, see `DatabaseConfig::from_url`.
+    pub fn from_url(url: &str) -> Result<Self> {
+        connection_url::parse_url(url)
+    }
+
+    /// Starts from `config`, to replace some of its fields. A port of 0 is the default one.
+    pub fn from_config(config: DatabaseConfig) -> Self {
+        Self {
+            connection_type: config.connection_type,
+            id: Some(config.id),
+            name: Some(config.name),
+            host: Some(config.host),
+            port: Some(config.port).filter(|port| *port != 0),
+            database: Some(config.database),
+            username: Some(config.username),
+            password: config.password,
+            application_name: config.application_name,
+            pgpass_file: config.pgpass_file,
+            ssh_tunnel: config.ssh_tunnel,
+            path: config.path,
+            pool_max_connections: config.pool_max_connections,
+            acquire_timeout_secs: config.acquire_timeout_secs,
+            statement_timeout_secs: config.statement_timeout_secs,
+            connect_timeout_secs: config.connect_timeout_secs,
+            keepalive_idle_secs: config.keepalive_idle_secs,
+            binary_path_override: config.binary_path_override,
+            ssl_mode: config.ssl_mode,
+        }
+    }
+
+    pub fn connection_type(&self) -> &ConnectionType {
+        &self.connection_type
+    }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn with_database(mut self, database: impl Into<String>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    pub fn with_username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    pub fn with_application_name(mut self, application_name: Option<String>) -> Self {
+        self.application_name = application_name;
+        self
+    }
+
+    /// PostgreSQL only.
+    pub fn with_pgpass_file(mut self, pgpass_file: Option<String>) -> Self {
+        self.pgpass_file = pgpass_file;
+        self
+    }
+
+    /// PostgreSQL and MySQL only.
+    pub fn with_ssh_tunnel(mut self, ssh_tunnel: Option<SshTunnelConfig>) -> Self {
+        self.ssh_tunnel = ssh_tunnel;
+        self
+    }
+
+    /// The database file, SQLite only.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_pool_max_connections(mut self, pool_max_connections: Option<u32>) -> Self {
+        self.pool_max_connections = pool_max_connections;
+        self
+    }
+
+    pub fn with_acquire_timeout_secs(mut self, acquire_timeout_secs: Option<u64>) -> Self {
+        self.acquire_timeout_secs = acquire_timeout_secs;
+        self
+    }
+
+    pub fn with_statement_timeout_secs(mut self, statement_timeout_secs: Option<u64>) -> Self {
+        self.statement_timeout_secs = statement_timeout_secs;
+        self
+    }
+
+    pub fn with_connect_timeout_secs(mut self, connect_timeout_secs: Option<u64>) -> Self {
+        self.connect_timeout_secs = connect_timeout_secs;
+        self
+    }
+
+    pub fn with_keepalive_idle_secs(mut self, keepalive_idle_secs: Option<u64>) -> Self {
+        self.keepalive_idle_secs = keepalive_idle_secs;
+        self
+    }
+
+    pub fn with_binary_path_override(mut self, binary_path_override: Option<String>) -> Self {
+        self.binary_path_override = binary_path_override;
+        self
+    }
+
+    /// PostgreSQL and MySQL only.
+    pub fn with_ssl_mode(mut self, ssl_mode: Option<SslMode>) -> Self {
+        self.ssl_mode = ssl_mode;
+        self
+    }
+
+    pub fn build(self) -> Result<DatabaseConfig> {
+        let mut problems = vec![];
+        let is_sqlite = self.connection_type == ConnectionType::Sqlite;
+
+        let path = non_empty(self.path);
+        let database = non_empty(self.database).or_else(|| {
+            let path = path.as_ref().filter(|_| is_sqlite)?;
+            Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        });
+        let host = non_empty(self.host);
+        let username = non_empty(self.username);
+        let port = self.port.or(self.connection_type.default_port());
+
+        if database.is_none() {
+            problems.push("database is required");
+        }
+
+        if is_sqlite {
+            if path.is_none() {
+                problems.push("path is required for SQLite");
+            }
+            if self.ssh_tunnel.is_some() {
+                problems.push("SSH tunnels are not supported by SQLite");
+            }
+            if self.ssl_mode.is_some() {
+                problems.push("SSL is not supported by SQLite");
+            }
+        } else {
+            if host.is_none() {
+                problems.push("host is required");
+            }
+            if port == Some(0) {
+                problems.push("port must be between 1 and 65535");
+            }
+            if username.is_none() {
+                problems.push("username is required");
+            }
+            if path.is_some() {
+                problems.push("path is only supported by SQLite");
+            }
+        }
+
+        if self.pgpass_file.is_some() && self.connection_type != ConnectionType::PostgreSql {
+            problems.push("pgpass file is only supported by PostgreSQL");
+        }
+        if self.pool_max_connections == Some(0) {
+            problems.push("the connection pool needs at least one connection");
+        }
+
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "Invalid database configuration: {}",
+                problems.join(", ")
+            ));
+        }
+
+        let database = database.unwrap_or_default();
+
+        Ok(DatabaseConfig {
+            id: self.id.unwrap_or_default(),
+            name: non_empty(self.name).unwrap_or_else(|| database.clone()),
+            connection_type: self.connection_type,
+            host: host.unwrap_or_default(),
+            port: port.unwrap_or_default(),
+            database,
+            username: username.unwrap_or_default(),
+            password: self.password,
+            application_name: self.application_name,
+            pgpass_file: self.pgpass_file,
+            ssh_tunnel: self.ssh_tunnel,
+            path,
+            pool_max_connections: self.pool_max_connections,
+            acquire_timeout_secs: self.acquire_timeout_secs,
+            statement_timeout_secs: self.statement_timeout_secs,
+            connect_timeout_secs: self.connect_timeout_secs,
+            keepalive_idle_secs: self.keepalive_idle_secs,
+            binary_path_override: self.binary_path_override,
+            ssl_mode: self.ssl_mode,
+        })
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::DatabaseConfigBuilder;
+    use crate::databases::{ConnectionType, DatabaseConfig};
+
+    #[test]
+    fn test_01_build_with_defaults() {
+        let config = DatabaseConfig::builder(ConnectionType::PostgreSql)
+            .with_host("localhost")
+            .with_database("app")
+            .with_username("postgres")
+            .build()
+            .expect("Failed to build the config");
+
+        assert_eq!(config.port, 5432);
+        assert_eq!(config.name, "app");
+        assert_eq!(config.id, "");
+
+        let config = DatabaseConfig::builder(ConnectionType::MySql)
+            .with_host("localhost")
+            .with_port(3307)
+            .with_database("app")
+            .with_name("Production")
+            .with_username("root")
+            .build()
+            .expect("Failed to build the config");
+
+        assert_eq!(config.port, 3307);
+        assert_eq!(config.name, "Production");
+    }
+
+    #[test]
+    fn test_02_report_every_problem() {
+        let error = DatabaseConfig::builder(ConnectionType::MySql)
+            .with_host(" ")
+            .with_port(0)
+            .with_pgpass_file(Some("/root/.pgpass".into()))
+            .with_pool_max_connections(Some(0))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid database configuration: database is required, host is required, \
+            port must be between 1 and 65535, username is required, pgpass file is only \
+            supported by PostgreSQL, the connection pool needs at least one connection"
+        );
+    }
+
+    #[test]
+    fn test_03_build_sqlite() {
+        let config = DatabaseConfig::builder(ConnectionType::Sqlite)
+            .with_path("/var/lib/app/app.db")
+            .build()
+            .expect("Failed to build the config");
+
+        assert_eq!(config.database, "app");
+        assert_eq!(config.port, 0);
+        assert_eq!(config.path, Some("/var/lib/app/app.db".into()));
+
+        let error = DatabaseConfig::builder(ConnectionType::Sqlite)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid database configuration: database is required, path is required for SQLite"
+        );
+    }
+
+    #[test]
+    fn test_04_build_from_config() {
+        let config = DatabaseConfig::builder(ConnectionType::PostgreSql)
+            .with_host("localhost")
+            .with_database("app")
+            .with_username("postgres")
+            .with_connect_timeout_secs(Some(5))
+            .build()
+            .expect("Failed to build the config");
+
+        let config = DatabaseConfigBuilder::from_config(DatabaseConfig { port: 0, ..config })
+            .with_database("analytics")
+            .build()
+            .expect("Failed to build the config");
+
+        assert_eq!(config.port, 5432);
+        assert_eq!(config.database, "analytics");
+        // The name stays the one of the first database
+        assert_eq!(config.name, "app");
+        assert_eq!(config.connect_timeout_secs, Some(5));
+    }
+}
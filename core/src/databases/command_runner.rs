@@ -0,0 +1,484 @@
+use std::{
+    fmt,
+    io::{ErrorKind, Read, Write},
+    path::Path,
+    process::{Output, Stdio},
+};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::Command,
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use super::diagnostics::{parse_diagnostics, Diagnostic};
+
+const BUFFER_SIZE: usize = 16384;
+/// Output of `pipe_to` is read ahead of the writer in chunks of this size, at most
+/// `PIPE_CHUNKS_IN_FLIGHT` of them, so a slow write never stalls the tool for long.
+const PIPE_CHUNK_SIZE: usize = 256 * 1024;
+const PIPE_CHUNKS_IN_FLIGHT: usize = 8;
+const REDACTED: &str = "***";
+
+/// A tool that exited unsuccessfully, with its stderr split into diagnostics.
+#[derive(Debug)]
+pub struct CommandError {
+    pub message: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[derive(Debug)]
+pub struct PipeOutput {
+    /// Bytes copied from stdout.
+    pub bytes: u64,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandRunner {
+    envs: Vec<(String, String)>,
+    secrets: Vec<String>,
+}
+
+impl CommandRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn secret_env(mut self, key: &str, value: &str) -> Self {
+        if !value.is_empty() {
+            self.secrets.push(value.into());
+        }
+
+        self.env(key, value)
+    }
+
+    pub fn apply(&self, cmd: &mut Command) {
+        for (key, value) in &self.envs {
+            cmd.env(key, value);
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+
+    /// Parses a tool's stderr, secrets redacted.
+    pub fn diagnostics(&self, stderr: &[u8]) -> Vec<Diagnostic> {
+        parse_diagnostics(&self.redact(&String::from_utf8_lossy(stderr)))
+    }
+
+    pub fn format_error(
+        &self,
+        program: &str,
+        exit_code: Option<i32>,
+        stderr: &str,
+        stdout: &str,
+    ) -> anyhow::Error {
+        let mut message = format!(
+            "{} failed with exit code {}.\nError: {}",
+            program,
+            exit_code.unwrap_or(-1),
+            self.redact(stderr.trim())
+        );
+
+        if !stdout.trim().is_empty() {
+            message.push_str(&format!("\nOutput: {}", self.redact(stdout.trim())));
+        }
+
+        anyhow::Error::new(CommandError {
+            message,
+            diagnostics: self.diagnostics(stderr.as_bytes()),
+        })
+    }
+
+    /// Runs the command to completion and fails if it exits unsuccessfully.
+    pub async fn run(&self, mut cmd: Command) -> Result<Output> {
+        let program = get_program_name(&cmd);
+        self.apply(&mut cmd);
+
+        let output = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .context(format!("Failed to execute {}", program))?;
+
+        if !output.status.success() {
+            return Err(self.format_error(
+                &program,
+                output.status.code(),
+                &String::from_utf8_lossy(&output.stderr),
+                &String::from_utf8_lossy(&output.stdout),
+            ));
+        }
+
+        Ok(output)
+    }
+
+    /// Streams the command's stdout into `writer`. The output is read on another task while
+    /// the previous chunks are written, see `PIPE_CHUNK_SIZE`.
+    pub async fn pipe_to(
+        &self,
+        mut cmd: Command,
+        writer: &mut (dyn Write + Send + Unpin),
+    ) -> Result<PipeOutput> {
+        let program = get_program_name(&cmd);
+        self.apply(&mut cmd);
+
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start {}: {}", program, e))?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Failed to capture {} stdout", program))?;
+
+        let stderr_handle = drain(child.stderr.take());
+
+        let (sender, mut receiver) = mpsc::channel(PIPE_CHUNKS_IN_FLIGHT);
+        let reader = tokio::spawn(read_chunks(stdout, sender));
+        let mut total_bytes = 0u64;
+
+        while let Some(chunk) = receiver.recv().await {
+            let result = chunk
+                .map_err(|e| anyhow!("Failed to read from {}: {}", program, e))
+                .and_then(|chunk| {
+                    writer
+                        .write_all(&chunk)
+                        .map_err(|e| anyhow!("Failed to write {} output: {}", program, e))?;
+                    Ok(chunk.len())
+                });
+
+            match result {
+                Ok(n) => total_bytes += n as u64,
+                Err(e) => {
+                    // Closes stdout, so the tool stops on a broken pipe
+                    reader.abort();
+                    return Err(e);
+                }
+            }
+        }
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| anyhow!("{} process failed: {}", program, e))?;
+
+        let stderr = stderr_handle.await.unwrap_or_default();
+
+        if !status.success() {
+            return Err(self.format_error(
+                &program,
+                status.code(),
+                &String::from_utf8_lossy(&stderr),
+                "",
+            ));
+        }
+
+        Ok(PipeOutput {
+            bytes: total_bytes,
+            stderr,
+        })
+    }
+
+    /// Streams `reader` into the command's stdin while draining its output.
+    pub async fn pipe_from(
+        &self,
+        mut cmd: Command,
+        reader: &mut (dyn Read + Send + Unpin),
+    ) -> Result<Output> {
+        let program = get_program_name(&cmd);
+        self.apply(&mut cmd);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start {}: {}", program, e))?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Failed to capture {} stdin", program))?;
+
+        let stdout_handle = drain(child.stdout.take());
+        let stderr_handle = drain(child.stderr.take());
+
+        let mut buffer = [0u8; BUFFER_SIZE];
+
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break, // EOF
+                Ok(n) => match stdin.write_all(&buffer[..n]).await {
+                    Ok(()) => {}
+                    // The process exited early, its exit status explains why
+                    Err(e) if e.kind() == ErrorKind::BrokenPipe => break,
+                    Err(e) => return Err(anyhow!("Failed to write to {}: {}", program, e)),
+                },
+                Err(e) => {
+                    return Err(anyhow!("Failed to read backup data: {}", e));
+                }
+            }
+        }
+
+        drop(stdin);
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| anyhow!("{} process failed: {}", program, e))?;
+
+        let output = Output {
+            status,
+            stdout: stdout_handle.await.unwrap_or_default(),
+            stderr: stderr_handle.await.unwrap_or_default(),
+        };
+
+        if !output.status.success() {
+            return Err(self.format_error(
+                &program,
+                output.status.code(),
+                &String::from_utf8_lossy(&output.stderr),
+                &String::from_utf8_lossy(&output.stdout),
+            ));
+        }
+
+        Ok(output)
+    }
+}
+
+fn get_program_name(cmd: &Command) -> String {
+    let program = cmd.as_std().get_program();
+
+    Path::new(program)
+        .file_name()
+        .unwrap_or(program)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Sends `stream` in chunks of `PIPE_CHUNK_SIZE` until it ends, fails or `sender` is closed.
+async fn read_chunks<R>(mut stream: R, sender: mpsc::Sender<std::io::Result<Vec<u8>>>)
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        let mut chunk = vec![0u8; PIPE_CHUNK_SIZE];
+        let mut filled = 0;
+
+        while filled < PIPE_CHUNK_SIZE {
+            match stream.read(&mut chunk[filled..]).await {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            }
+        }
+
+        chunk.truncate(filled);
+        let end = filled < PIPE_CHUNK_SIZE;
+
+        if (filled > 0 && sender.send(Ok(chunk)).await.is_err()) || end {
+            return;
+        }
+    }
+}
+
+fn drain<R>(stream: Option<R>) -> JoinHandle<Vec<u8>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut content = vec![];
+
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut content).await;
+        }
+
+        content
+    })
+}
+
+#[cfg(test)]
+mod command_runner_tests {
+    use std::{
+        io::{self, Cursor, Write},
+        path::PathBuf,
+        thread,
+        time::Duration,
+    };
+
+    use tempfile::tempdir;
+    use tokio::process::Command;
+
+    use crate::databases::diagnostics::Severity;
+
+    use super::{CommandError, CommandRunner};
+
+    fn get_runner() -> CommandRunner {
+        CommandRunner::new()
+            .secret_env("DBKP_TEST_PASSWORD", "s3cr3t")
+            .env("DBKP_TEST_NAME", "dbkp")
+    }
+
+    #[test]
+    fn test_01_format_error() {
+        let runner = get_runner();
+        let error = runner.format_error("psql", Some(2), "  FATAL: password s3cr3t rejected\n", "");
+
+        assert_eq!(
+            error.to_string(),
+            "psql failed with exit code 2.\nError: FATAL: password *** rejected"
+        );
+    }
+
+    #[test]
+    fn test_02_format_error_with_output() {
+        let runner = get_runner();
+        let error = runner.format_error("pg_dump", None, "boom", "partial s3cr3t");
+
+        assert_eq!(
+            error.to_string(),
+            "pg_dump failed with exit code -1.\nError: boom\nOutput: partial ***"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_03_run_failure_is_redacted() {
+        let runner = get_runner();
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg("echo \"auth $DBKP_TEST_PASSWORD for $DBKP_TEST_NAME\" >&2; exit 3");
+
+        let error = runner.run(cmd).await.expect_err("Command should fail");
+
+        assert_eq!(
+            error.to_string(),
+            "sh failed with exit code 3.\nError: auth *** for dbkp"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_04_pipe_roundtrip() {
+        let runner = get_runner();
+        let content = vec![b'x'; 200_000];
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("cat >&2; echo done");
+
+        let mut reader = Cursor::new(content.clone());
+        let output = runner
+            .pipe_from(cmd, &mut reader)
+            .await
+            .expect("Failed to pipe into command");
+
+        assert_eq!(output.stderr, content);
+        assert_eq!(output.stdout, b"done\n");
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg("head -c 100000 /dev/zero; head -c 100000 /dev/zero >&2");
+
+        let mut writer = vec![];
+        let output = runner
+            .pipe_to(cmd, &mut writer)
+            .await
+            .expect("Failed to pipe from command");
+
+        assert_eq!(output.bytes, 100_000);
+        assert_eq!(writer.len(), 100_000);
+    }
+    #[tokio::test]
+    async fn test_05_failure_diagnostics() {
+        let runner = get_runner();
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(
+            "echo 'psql:<stdin>:3: NOTICE:  extension \"x\" exists' >&2; \
+            echo 'psql:<stdin>:7: ERROR:  role \"s3cr3t\" does not exist' >&2; exit 1",
+        );
+
+        let error = runner.run(cmd).await.expect_err("Command should fail");
+        let error = error
+            .downcast_ref::<CommandError>()
+            .expect("Expected a command error");
+
+        assert_eq!(error.diagnostics.len(), 2);
+        assert_eq!(error.diagnostics[1].severity, Severity::Error);
+        assert_eq!(error.diagnostics[1].line, Some(7));
+        assert_eq!(error.diagnostics[1].object, Some("***".into()));
+    }
+
+    /// Records whether the command had finished at each write.
+    struct SlowWriter {
+        done: PathBuf,
+        finished: Vec<bool>,
+        bytes: usize,
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.finished.push(self.done.exists());
+            self.bytes += buf.len();
+            thread::sleep(Duration::from_millis(200));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_06_pipe_reads_ahead_of_writer() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let done = dir.path().join("done");
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!(
+            "head -c 1048576 /dev/zero; touch {}",
+            done.display()
+        ));
+
+        let mut writer = SlowWriter {
+            done,
+            finished: vec![],
+            bytes: 0,
+        };
+        let output = get_runner()
+            .pipe_to(cmd, &mut writer)
+            .await
+            .expect("Failed to pipe from command");
+
+        assert_eq!(output.bytes, 1_048_576);
+        assert_eq!(writer.bytes, 1_048_576);
+        // Written in 4 chunks, the command exited while the first one was written
+        assert_eq!(writer.finished, vec![false, true, true, true]);
+    }
+}
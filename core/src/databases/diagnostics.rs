@@ -0,0 +1,228 @@
+use std::fmt;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+const PROGRAMS: [&str; 6] = [
+    "psql",
+    "pg_dump",
+    "pg_restore",
+    "vacuumdb",
+    "mysql",
+    "mysqldump",
+];
+
+/// `ERROR 1050 (42S01) at line 25: Table 'users' already exists`
+const MYSQL_ERROR: &str = r"^ERROR \d+(?: \(\w+\))?(?: at line (\d+))?: (.*)$";
+const OBJECT: &str = r#"(?i)\b(?:relation|table|column|function|schema|type|index|constraint|role|database|extension|sequence|view|trigger)\s+["'`]([^"'`]+)["'`]"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Progress and other lines without a severity.
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self {
+            Severity::Info => "INFO",
+            Severity::Notice => "NOTICE",
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+            Severity::Fatal => "FATAL",
+        };
+
+        write!(f, "{}", severity)
+    }
+}
+
+/// One message printed by pg_dump, psql, mysqldump or mysql on stderr.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Line of the SQL input the message is about, when the tool reports it.
+    pub line: Option<u64>,
+    /// Object named in the message, e.g. `users` in `relation "users" already exists`.
+    pub object: Option<String>,
+    /// DETAIL, HINT, CONTEXT and similar lines that followed the message.
+    pub details: Vec<String>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: &str, line: Option<u64>, object: &Regex) -> Self {
+        Self {
+            severity,
+            message: message.trim().to_string(),
+            line,
+            object: object
+                .captures(message)
+                .map(|captures| captures[1].to_string()),
+            details: vec![],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {}): {}", self.severity, line, self.message)?,
+            None => write!(f, "{}: {}", self.severity, self.message)?,
+        }
+
+        for detail in &self.details {
+            write!(f, "\n  {}", detail)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Strips the program prefix, e.g. `psql:<stdin>:42:` or `pg_dump:`, returning the input line
+/// it names if any.
+fn strip_program(line: &str) -> (&str, Option<u64>) {
+    for program in PROGRAMS {
+        let Some(rest) = line
+            .strip_prefix(program)
+            .and_then(|rest| rest.strip_prefix(':'))
+        else {
+            continue;
+        };
+
+        // psql:<file>:<line>: MESSAGE
+        let mut parts = rest.splitn(3, ':');
+        if let (Some(_), Some(number), Some(message)) = (parts.next(), parts.next(), parts.next()) {
+            if let Ok(number) = number.parse() {
+                return (message.trim_start(), Some(number));
+            }
+        }
+
+        return (rest.trim_start(), None);
+    }
+
+    (line, None)
+}
+
+fn parse_severity(message: &str) -> Option<(Severity, &str)> {
+    if let Some(message) = message.strip_prefix("[Warning]") {
+        return Some((Severity::Warning, message));
+    }
+
+    if let Some(message) = message.strip_prefix("[Note]") {
+        return Some((Severity::Notice, message));
+    }
+
+    let (keyword, message) = message.split_once(':')?;
+
+    let severity = match keyword.to_uppercase().as_str() {
+        "NOTICE" | "INFO" | "LOG" | "DEBUG" => Severity::Notice,
+        "WARNING" => Severity::Warning,
+        "ERROR" => Severity::Error,
+        "FATAL" | "PANIC" => Severity::Fatal,
+        _ => return None,
+    };
+
+    Some((severity, message))
+}
+
+fn is_continuation(message: &str) -> bool {
+    [
+        "DETAIL:",
+        "HINT:",
+        "CONTEXT:",
+        "STATEMENT:",
+        "LINE ",
+        "QUERY:",
+    ]
+    .iter()
+    .any(|prefix| message.to_uppercase().starts_with(prefix))
+}
+
+/// Splits the stderr of a database tool into diagnostics. Continuation lines (DETAIL, HINT,
+/// the `LINE n:` excerpt and its caret) are attached to the message above them.
+pub fn parse_diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    let mysql_error = Regex::new(MYSQL_ERROR).expect("Invalid MySQL error regex");
+    let object = Regex::new(OBJECT).expect("Invalid object regex");
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+
+    for raw_line in stderr.lines() {
+        if raw_line.trim().is_empty() {
+            continue;
+        }
+
+        let (message, line) = strip_program(raw_line);
+
+        if is_continuation(message) || raw_line.starts_with(char::is_whitespace) {
+            if let Some(last) = diagnostics.last_mut() {
+                last.details.push(message.trim().to_string());
+                continue;
+            }
+        }
+
+        let diagnostic = if let Some(captures) = mysql_error.captures(message) {
+            let line = captures.get(1).and_then(|line| line.as_str().parse().ok());
+            Diagnostic::new(Severity::Error, &captures[2], line, &object)
+        } else if let Some((severity, message)) = parse_severity(message) {
+            Diagnostic::new(severity, message, line, &object)
+        } else {
+            Diagnostic::new(Severity::Info, message, line, &object)
+        };
+
+        diagnostics.push(diagnostic);
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use super::{parse_diagnostics, Severity};
+
+    #[test]
+    fn test_01_parse_psql_diagnostics() {
+        let stderr = "psql:<stdin>:12: NOTICE:  table \"old_users\" does not exist, skipping\n\
+            psql:<stdin>:42: ERROR:  relation \"users\" already exists\n\
+            LINE 1: CREATE TABLE users (id integer);\n\
+            \x20                    ^\n\
+            HINT:  Drop it first.\n\
+            pg_dump: warning: there are circular foreign-key constraints on this table:\n\
+            pg_dump: detail: orders\n\
+            pg_dump: dumping contents of table \"public.users\"\n";
+
+        let diagnostics = parse_diagnostics(stderr);
+
+        assert_eq!(diagnostics.len(), 4);
+        assert_eq!(diagnostics[0].severity, Severity::Notice);
+        assert_eq!(diagnostics[0].object, Some("old_users".into()));
+
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(diagnostics[1].message, "relation \"users\" already exists");
+        assert_eq!(diagnostics[1].line, Some(42));
+        assert_eq!(diagnostics[1].object, Some("users".into()));
+        assert_eq!(diagnostics[1].details.len(), 3);
+
+        assert_eq!(diagnostics[2].severity, Severity::Warning);
+        assert_eq!(diagnostics[2].details, vec!["detail: orders"]);
+        assert_eq!(diagnostics[3].severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_02_parse_mysql_diagnostics() {
+        let stderr =
+            "mysql: [Warning] Using a password on the command line interface can be insecure.\n\
+            ERROR 1050 (42S01) at line 25: Table 'users' already exists\n";
+
+        let diagnostics = parse_diagnostics(stderr);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(diagnostics[1].line, Some(25));
+        assert_eq!(diagnostics[1].object, Some("users".into()));
+    }
+}
@@ -1,37 +1,78 @@
 use std::{
     io::{Read, Write},
-    process::Stdio,
     time::Duration,
 };
 
 use crate::databases::{
+    command_runner::CommandRunner,
+    diagnostics::Diagnostic,
+    progress::{ProgressReader, ProgressWriter},
+    ssh_tunnel::{SshRemoteConfig, SshTunnel},
     version::{Version, VersionTrait},
-    DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, RestoreOptions, UtilitiesTrait,
+    BackupOptions, BackupScope, DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata,
+    DumpExclusions, RestoreOptions, SslMode, UtilitiesTrait,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use log::warn;
 use sqlx::{
-    mysql::{MySqlConnectOptions, MySqlPoolOptions},
-    MySql, Pool,
-};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    process::Command,
+    mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlSslMode},
+    ConnectOptions, MySql, Pool,
 };
+use tokio::process::Command;
 
 use super::{utilities::MySqlUtilities, version::MySqlVersion};
 
+fn mysql_ssl_mode(ssl_mode: SslMode) -> MySqlSslMode {
+    match ssl_mode {
+        SslMode::Disable => MySqlSslMode::Disabled,
+        SslMode::Allow | SslMode::Prefer => MySqlSslMode::Preferred,
+        SslMode::Require => MySqlSslMode::Required,
+        SslMode::VerifyCa => MySqlSslMode::VerifyCa,
+        SslMode::VerifyFull => MySqlSslMode::VerifyIdentity,
+    }
+}
+
 pub struct MySqlConnection {
     pub config: DatabaseConfig,
+    /// Connected to the server rather than the database, which may not exist yet.
     pub pool: Pool<MySql>,
+    connect_options: MySqlConnectOptions,
+    /// Declared after the pool so its connections are closed before the tunnel they go
+    /// through.
+    _ssh_tunnel: Option<SshTunnel>,
 }
 
 impl MySqlConnection {
+    fn get_runner(&self) -> CommandRunner {
+        match &self.config.password {
+            Some(password) => CommandRunner::new().secret_env("MYSQL_PWD", password),
+            None => CommandRunner::new(),
+        }
+    }
+
     pub async fn new(config: DatabaseConfig) -> Result<Self> {
+        let mut config = config.clone();
+
+        let ssh_tunnel = match &config.ssh_tunnel {
+            Some(ssh_config) => Some(SshTunnel::new(
+                ssh_config.clone(),
+                SshRemoteConfig {
+                    host: config.host.clone(),
+                    port: config.port,
+                },
+            )?),
+            None => None,
+        };
+
+        if let Some(ssh_tunnel) = &ssh_tunnel {
+            config.host = "localhost".into();
+            config.port = ssh_tunnel.local_port;
+        }
+
         let mut connect_options = MySqlConnectOptions::new()
             .host(&config.host)
             .username(&config.username)
-            .database(&config.database)
             .port(config.port);
 
         connect_options = match &config.password {
@@ -39,13 +80,64 @@ impl MySqlConnection {
             None => connect_options,
         };
 
-        let pool = MySqlPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(Duration::from_secs(5))
-            .connect_with(connect_options)
+        if let Some(ssl_mode) = config.ssl_mode {
+            connect_options = connect_options.ssl_mode(mysql_ssl_mode(ssl_mode));
+        }
+
+        let pool = config
+            .with_connect_timeout(
+                MySqlPoolOptions::new()
+                    .max_connections(config.get_pool_max_connections()?)
+                    .acquire_timeout(config.get_acquire_timeout(Duration::from_secs(5)))
+                    .connect_with(connect_options.clone()),
+            )
             .await?;
 
-        Ok(Self { config, pool })
+        Ok(Self {
+            config,
+            pool,
+            connect_options,
+            _ssh_tunnel: ssh_tunnel,
+        })
+    }
+
+    /// Opens a dedicated connection using the configured database as the default one.
+    pub(super) async fn connect_database(&self) -> Result<sqlx::MySqlConnection> {
+        self.connect_options
+            .clone()
+            .database(&self.config.database)
+            .connect()
+            .await
+            .context("Failed to connect to the database")
+    }
+
+    fn quote(name: &str) -> String {
+        format!("`{}`", name.replace('`', "``"))
+    }
+
+    /// Kills the other sessions using the configured database, which would otherwise block
+    /// `DROP DATABASE` on their metadata locks.
+    async fn kill_connections(&self) -> Result<()> {
+        let ids: Vec<(u64,)> = sqlx::query_as(
+            "SELECT CAST(id AS UNSIGNED) FROM information_schema.processlist
+            WHERE db = ? AND id <> CONNECTION_ID()",
+        )
+        .bind(&self.config.database)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list database connections")?;
+
+        for (id,) in ids {
+            // The session may have ended since it was listed
+            if let Err(e) = sqlx::query(&format!("KILL {}", id))
+                .execute(&self.pool)
+                .await
+            {
+                warn!("Failed to kill connection {}: {}", id, e);
+            }
+        }
+
+        Ok(())
     }
 
     async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
@@ -56,14 +148,9 @@ impl MySqlConnection {
             _ => return Err(anyhow!("Wrong version type")),
         };
 
-        let utilities = MySqlUtilities::new(version);
-        let mut cmd = utilities.get_command(bin_name).await?;
-
-        if let Some(password) = &self.config.password {
-            cmd.env("MYSQL_PWD", password.as_str());
-        }
-
-        Ok(cmd)
+        let utilities =
+            MySqlUtilities::new(version).with_binary_directory(self.config.get_binary_directory());
+        utilities.get_command(bin_name).await
     }
 
     async fn get_command(&self, bin_name: &str) -> Result<Command> {
@@ -72,8 +159,13 @@ impl MySqlConnection {
         cmd.arg(format!("--host={}", self.config.host))
             .arg(format!("--port={}", self.config.port))
             .arg(format!("--user={}", self.config.username))
-            .arg("--protocol=TCP")
-            .arg(self.config.database.clone());
+            .arg("--protocol=TCP");
+
+        if let Some(ssl_mode) = self.config.ssl_mode {
+            cmd.arg(format!("--ssl-mode={}", ssl_mode.as_mysql_str()));
+        }
+
+        cmd.arg(self.config.database.clone());
 
         Ok(cmd)
     }
@@ -94,9 +186,66 @@ impl DatabaseConnectionTrait for MySqlConnection {
 
         Ok(DatabaseMetadata {
             version: Version::MySql(version),
+            encoding: None,
         })
     }
 
+    async fn get_client_version(&self) -> Result<Version> {
+        let mut cmd = self.get_base_command("mysqldump").await?;
+        cmd.arg("--version");
+
+        let output = self.get_runner().run(cmd).await?;
+
+        let version_string = String::from_utf8_lossy(&output.stdout);
+
+        match MySqlVersion::parse_string_version(version_string.trim()) {
+            Some(version) => Ok(Version::MySql(version)),
+            None => Err(anyhow!(
+                "Failed to parse mysqldump version string: {}",
+                version_string.trim()
+            )),
+        }
+    }
+
+    async fn get_database_size(&self) -> Result<u64> {
+        let size: (u64,) = sqlx::query_as(
+            "SELECT CAST(COALESCE(SUM(data_length + index_length), 0) AS UNSIGNED)
+            FROM information_schema.tables WHERE table_schema = ?",
+        )
+        .bind(&self.config.database)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to get database size: {}", e))?;
+
+        Ok(size.0)
+    }
+
+    async fn get_change_token(&self) -> Result<Option<String>> {
+        let (rows, update_time): (u64, Option<String>) = sqlx::query_as(
+            "SELECT CAST(COALESCE(SUM(table_rows), 0) AS UNSIGNED), CAST(MAX(update_time) AS CHAR)
+            FROM information_schema.tables WHERE table_schema = ?",
+        )
+        .bind(&self.config.database)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to get database statistics: {}", e))?;
+
+        Ok(update_time.map(|update_time| format!("{}-{}", rows, update_time)))
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let databases: Vec<(String,)> = sqlx::query_as(
+            "SELECT CAST(schema_name AS CHAR) FROM information_schema.schemata
+            WHERE schema_name NOT IN ('information_schema', 'performance_schema', 'mysql', 'sys')
+            ORDER BY schema_name",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to list databases: {}", e))?;
+
+        Ok(databases.into_iter().map(|(name,)| name).collect())
+    }
+
     async fn test(&self) -> Result<bool> {
         sqlx::query("SELECT 1")
             .execute(&self.pool)
@@ -106,6 +255,58 @@ impl DatabaseConnectionTrait for MySqlConnection {
     }
 
     async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut writer = ProgressWriter::new(writer, options.progress.clone());
+        let writer: &mut (dyn Write + Send + Unpin) = &mut writer;
+
+        if options.jobs.is_some_and(|jobs| jobs > 1) {
+            return Err(anyhow!(
+                "Parallel table dumps are only supported for PostgreSQL"
+            ));
+        }
+
+        if options.snapshot.is_some() {
+            return Err(anyhow!(
+                "Exported snapshots are only supported for PostgreSQL"
+            ));
+        }
+
+        if options.directory_format {
+            return Err(anyhow!(
+                "Directory-format dumps are only supported for PostgreSQL"
+            ));
+        }
+
+        if options.physical {
+            return Err(anyhow!("Base backups are only supported for PostgreSQL"));
+        }
+
+        if options.has_table_filters() {
+            return Err(anyhow!("Table filters are only supported for PostgreSQL"));
+        }
+
+        // Tablespaces are always left out of MySQL dumps
+        let exclusions = DumpExclusions {
+            no_tablespaces: false,
+            ..options.exclusions
+        };
+
+        if exclusions != DumpExclusions::default() {
+            return Err(anyhow!(
+                "Object type exclusions are only supported for PostgreSQL"
+            ));
+        }
+
         let mut cmd = self.get_command("mysqldump").await?;
 
         cmd.arg("--opt")
@@ -117,127 +318,78 @@ impl DatabaseConnectionTrait for MySqlConnection {
             .arg("--no-tablespaces")
             .arg("--skip-triggers");
 
-        let mut child = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| anyhow!("Failed to start mysqldump: {}", e))?;
-
-        let mut stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture mysqldump stdout".to_string()))?;
-
-        let mut buffer = [0u8; 16384];
-
-        loop {
-            match stdout.read(&mut buffer).await {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    writer
-                        .write_all(&buffer[..n])
-                        .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from mysqldump: {}", e));
-                }
+        match options.scope {
+            BackupScope::All => {}
+            BackupScope::SchemaOnly => {
+                cmd.arg("--no-data");
+            }
+            // Rows are loaded into existing tables, which must not be dropped
+            BackupScope::DataOnly => {
+                cmd.arg("--no-create-info").arg("--skip-add-drop-table");
             }
         }
 
-        let status = child
-            .wait()
-            .await
-            .map_err(|e| anyhow!("mysqldump process failed: {}", e))?;
-
-        if !status.success() {
-            let mut stderr = child
-                .stderr
-                .take()
-                .ok_or_else(|| anyhow!("Failed to capture mysqldump stderr".to_string()))?;
-
-            let mut error_message = String::new();
-            stderr
-                .read_to_string(&mut error_message)
-                .await
-                .map_err(|e| anyhow!("Failed to read mysqldump stderr: {}", e))?;
+        let runner = self.get_runner();
+        let output = runner.pipe_to(cmd, writer).await?;
 
-            return Err(anyhow!("mysqldump failed: {}", error_message));
-        }
-
-        Ok(())
+        Ok(runner.diagnostics(&output.stderr))
     }
 
     async fn restore_with_options(
         &self,
         reader: &mut (dyn Read + Send + Unpin),
-        _options: RestoreOptions,
-    ) -> Result<()> {
-        let mut cmd = self.get_base_command("mysql").await?;
+        options: RestoreOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut reader = ProgressReader::new(reader, options.progress.clone());
+        let reader: &mut (dyn Read + Send + Unpin) = &mut reader;
 
-        cmd.arg(format!("--host={}", self.config.host))
-            .arg(format!("--port={}", self.config.port))
-            .arg(format!("--user={}", self.config.username))
-            .arg("--protocol=TCP")
-            .arg("-e")
-            .arg(format!(
-                "SELECT CONCAT('KILL ', id, ';') FROM information_schema.processlist 
-                WHERE user = '{}' AND db = '{}' AND id != CONNECTION_ID();",
-                self.config.username, self.config.database
+        if options.schema_only {
+            return Err(anyhow!(
+                "Schema-only restores are only supported for PostgreSQL"
             ));
+        }
 
-        let drop_connections_output = cmd
-            .output()
-            .await
-            .context(format!("Failed to execute connection termination command"))?;
-
-        if !drop_connections_output.status.success() {
-            let stderr = String::from_utf8_lossy(&drop_connections_output.stderr);
-            let exit_code = drop_connections_output.status.code().unwrap_or(-1);
+        // DDL statements commit implicitly, so a MySQL dump cannot be rolled back as a whole
+        if options.single_transaction {
+            return Err(anyhow!(
+                "Single-transaction restores are only supported for PostgreSQL"
+            ));
+        }
 
+        if options.validate_only {
             return Err(anyhow!(
-                "Failed to drop connections with exit code {}.\nError details: {}",
-                exit_code,
-                stderr.trim()
+                "Validating a restore is only supported for PostgreSQL and SQLite"
             ));
         }
 
-        let mut cmd = self.get_command("mysql").await?;
-        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        if options.drop_database_first {
+            // A drop waits on the metadata locks of other sessions, a plain restore does not
+            if options.terminate_connections {
+                self.kill_connections().await?;
+            }
 
-        let mut stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture psql stdin".to_string()))?;
+            let database = Self::quote(&self.config.database);
 
-        let mut buffer = [0u8; 16384];
+            sqlx::query(&format!("DROP DATABASE IF EXISTS {}", database))
+                .execute(&self.pool)
+                .await
+                .context("Failed to drop database")?;
 
-        loop {
-            match reader.read(&mut buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    stdin.write_all(&buffer[..n]).await?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from pg_dump: {}", e));
-                }
-            }
+            sqlx::query(&format!("CREATE DATABASE {}", database))
+                .execute(&self.pool)
+                .await
+                .context("Failed to create database")?;
         }
 
-        drop(stdin);
+        let cmd = self.get_command("mysql").await?;
 
-        let output = child
-            .wait_with_output()
+        let runner = self.get_runner();
+        let output = runner
+            .pipe_from(cmd, reader)
             .await
-            .map_err(|e| anyhow!("mysql process failed: {}", e))?;
+            .context("mysql restore failed")?;
 
-        if !output.status.success() {
-            return Err(anyhow!(
-                "mysql restore failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-
-        Ok(())
+        Ok(runner.diagnostics(&output.stderr))
     }
 
     async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
@@ -245,8 +397,64 @@ impl DatabaseConnectionTrait for MySqlConnection {
             reader,
             RestoreOptions {
                 drop_database_first: true,
+                ..Default::default()
             },
         )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn analyze(&self, _jobs: Option<usize>) -> Result<()> {
+        let tables: Vec<(String,)> = sqlx::query_as(
+            "SELECT CAST(table_name AS CHAR) FROM information_schema.tables
+            WHERE table_schema = ? AND table_type = 'BASE TABLE'",
+        )
+        .bind(&self.config.database)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to list tables: {}", e))?;
+
+        for (table,) in tables {
+            sqlx::query(&format!(
+                "ANALYZE TABLE {}.{}",
+                Self::quote(&self.config.database),
+                Self::quote(&table)
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!("Failed to analyze {}: {}", table, e))?;
+        }
+
+        Ok(())
+    }
+
+    async fn run_check(&self, query: &str) -> Result<bool> {
+        let mut connection = self.connect_database().await?;
+
+        sqlx::query_scalar::<_, bool>(query)
+            .fetch_one(&mut connection)
+            .await
+            .context(format!("Check query failed: {}", query))
+    }
+
+    async fn rename_databases(
+        &self,
+        _renames: &[(&str, &str)],
+        _termination_timeout: Option<Duration>,
+    ) -> Result<()> {
+        Err(anyhow!("MySQL cannot rename databases"))
+    }
+
+    async fn drop_database(&self, database: &str) -> Result<()> {
+        sqlx::query(&format!(
+            "DROP DATABASE IF EXISTS {}",
+            Self::quote(database)
+        ))
+        .execute(&self.pool)
         .await
+        .context(format!("Failed to drop database {}", database))?;
+
+        Ok(())
     }
 }
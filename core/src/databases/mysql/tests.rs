@@ -24,7 +24,17 @@ mod mysql_connection_tests {
             username: env::var("MYSQL_USERNAME").unwrap_or_default(),
             database: env::var("MYSQL_NAME").unwrap_or_default(),
             port,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         };
 
         Ok(config)
@@ -86,6 +96,10 @@ mod mysql_connection_tests {
         let connection = MySqlConnection::new(config.clone())
             .await
             .expect("Failed to get connection");
+        let mut database = connection
+            .connect_database()
+            .await
+            .expect("Failed to connect to the database");
 
         sqlx::query(
             format!(
@@ -94,7 +108,7 @@ mod mysql_connection_tests {
             )
             .as_str(),
         )
-        .execute(&connection.pool)
+        .execute(&mut database)
         .await
         .expect("Failed to create test table");
 
@@ -105,14 +119,14 @@ mod mysql_connection_tests {
             )
             .as_str(),
         )
-        .execute(&connection.pool)
+        .execute(&mut database)
         .await
         .expect("Failed to insert test data");
 
         let rows: Vec<(String, i32)> = sqlx::query_as(
             format!("SELECT name, value FROM {} ORDER BY id", test_table_name).as_str(),
         )
-        .fetch_all(&connection.pool)
+        .fetch_all(&mut database)
         .await
         .expect("Failed to fetch test data");
 
@@ -133,19 +147,19 @@ mod mysql_connection_tests {
             )
             .as_str(),
         )
-        .execute(&connection.pool)
+        .execute(&mut database)
         .await
         .expect("Failed to update test data");
 
         sqlx::query(format!("DELETE FROM {} WHERE name = 'test3'", test_table_name).as_str())
-            .execute(&connection.pool)
+            .execute(&mut database)
             .await
             .expect("Failed to delete test data");
 
         let modified_rows: Vec<(String, i32)> = sqlx::query_as(
             format!("SELECT name, value FROM {} ORDER BY id", test_table_name).as_str(),
         )
-        .fetch_all(&connection.pool)
+        .fetch_all(&mut database)
         .await
         .expect("Failed to fetch modified data");
 
@@ -169,10 +183,15 @@ mod mysql_connection_tests {
             .await
             .expect("Failed to get connection");
 
+        let mut database = verify_connection
+            .connect_database()
+            .await
+            .expect("Failed to connect to the database");
+
         let restored_rows: Vec<(String, i32)> = sqlx::query_as(
             format!("SELECT name, value FROM {} ORDER BY id", test_table_name).as_str(),
         )
-        .fetch_all(&verify_connection.pool)
+        .fetch_all(&mut database)
         .await
         .expect("Failed to fetch restored data");
 
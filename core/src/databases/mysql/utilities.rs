@@ -14,11 +14,21 @@ use async_trait::async_trait;
 
 pub struct MySqlUtilities {
     version: MySqlVersion,
+    binary_directory: Option<PathBuf>,
 }
 
 impl MySqlUtilities {
     pub fn new(version: MySqlVersion) -> Self {
-        MySqlUtilities { version }
+        MySqlUtilities {
+            version,
+            binary_directory: None,
+        }
+    }
+
+    /// Uses the tools of `directory`, when set, instead of those matching the version.
+    pub fn with_binary_directory(mut self, directory: Option<PathBuf>) -> Self {
+        self.binary_directory = directory;
+        self
     }
 
     pub async fn install(&self) -> Result<()> {
@@ -37,6 +47,10 @@ impl MySqlUtilities {
 #[async_trait]
 impl UtilitiesTrait for MySqlUtilities {
     fn get_base_path(&self) -> Result<PathBuf> {
+        if let Some(directory) = &self.binary_directory {
+            return Ok(directory.clone());
+        }
+
         let path = get_binaries_base_path(&Version::MySql(self.version.clone())).join("bin");
         Ok(path)
     }
@@ -45,6 +59,10 @@ impl UtilitiesTrait for MySqlUtilities {
         let base_path = self.get_base_path()?;
         let bin_path = base_path.join(bin_name);
 
+        if self.binary_directory.is_some() && !bin_path.exists() {
+            return Err(anyhow!("Binary {} not found", bin_path.display()));
+        }
+
         if !bin_path.exists() {
             debug!("MySql utilities not found, attempting to download and install");
             self.install().await?;
@@ -1,36 +1,180 @@
 use std::{
     io::{Read, Write},
-    process::Stdio,
-    time::Duration,
+    path::PathBuf,
+    time::{Duration, Instant},
 };
 
 use crate::databases::{
+    command_runner::{CommandError, CommandRunner},
+    diagnostics::{Diagnostic, Severity},
+    progress::{ProgressReader, ProgressWriter},
     ssh_tunnel::{SshRemoteConfig, SshTunnel},
     version::{Version, VersionTrait},
-    DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, RestoreOptions, UtilitiesTrait,
+    BackupOptions, BackupScope, DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata,
+    RestoreOptions, SslMode, UtilitiesTrait,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use log::warn;
 use sqlx::{
-    postgres::{PgConnectOptions, PgPoolOptions},
-    Pool, Postgres,
+    postgres::{PgConnectOptions, PgPoolOptions, PgSslMode},
+    ConnectOptions, PgConnection, Pool, Postgres,
 };
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    process::Command,
+use tokio::{process::Command, time};
+
+use super::{
+    directory::split_dump_header,
+    encoding::EncodingFilter,
+    filter::{SchemaOnlyFilter, TablespaceFilter},
+    parallel::validate_snapshot,
+    pgpass::{load_password, resolve_pgpass_file},
+    utilities::PostgreSqlUtilities,
+    validate::{add_single_transaction_args, describe_first_error},
+    version::PostgreSQLVersion,
 };
 
-use super::{utilities::PostgreSqlUtilities, version::PostgreSQLVersion};
+const DEFAULT_TERMINATION_TIMEOUT: Duration = Duration::from_secs(30);
+const TERMINATION_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// SQLSTATE raised when renaming or dropping a database other sessions are connected to.
+const OBJECT_IN_USE: &str = "55006";
+
+/// A session still connected to the database being restored.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct BlockingSession {
+    pub pid: i32,
+    pub application_name: Option<String>,
+}
+
+/// Settings of the configuration applied to every session, by dbkp and the PostgreSQL tools.
+pub fn get_session_settings(config: &DatabaseConfig) -> Vec<(&'static str, String)> {
+    let mut settings = vec![];
+
+    if let Some(timeout) = config.statement_timeout_secs {
+        settings.push(("statement_timeout", format!("{}s", timeout)));
+    }
+
+    if let Some(idle) = config.keepalive_idle_secs {
+        settings.push(("tcp_keepalives_idle", idle.to_string()));
+    }
+
+    settings
+}
+
+/// `PGOPTIONS` setting each of `settings` at connection time.
+pub fn format_pg_options<K: AsRef<str>, V: AsRef<str>>(settings: &[(K, V)]) -> String {
+    settings
+        .iter()
+        .map(|(key, value)| format!("-c {}={}", key.as_ref(), value.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether psql failed because other sessions are connected to the database it works on.
+pub fn is_database_in_use(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<CommandError>().is_some_and(|error| {
+        error.diagnostics.iter().any(|diagnostic| {
+            diagnostic.severity >= Severity::Error
+                && diagnostic
+                    .message
+                    .contains("is being accessed by other users")
+        })
+    })
+}
+
+/// `name` as a double-quoted identifier, with its own double quotes doubled.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Warning for a pg_dump of an older major version than the server.
+pub fn get_version_warning(
+    server: &PostgreSQLVersion,
+    client: &PostgreSQLVersion,
+) -> Option<Diagnostic> {
+    if client.major >= server.major {
+        return None;
+    }
+
+    Some(Diagnostic {
+        severity: Severity::Warning,
+        message: format!(
+            "pg_dump {} is older than the server ({}), the backup may be incomplete or fail",
+            client.major, server.major
+        ),
+        line: None,
+        object: None,
+        details: vec![format!(
+            "Install PostgreSQL {} client tools, or point binary_path_override at them",
+            server.major
+        )],
+    })
+}
+
+fn describe_sessions(sessions: &[BlockingSession]) -> String {
+    sessions
+        .iter()
+        .map(|session| match session.application_name.as_deref() {
+            Some(name) if !name.is_empty() => format!("{} ({})", session.pid, name),
+            _ => session.pid.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Database the server-level queries and commands connect to.
+pub const MAINTENANCE_DATABASE: &str = "postgres";
+
+/// Database the pool of `config` connects to.
+pub fn get_pool_database(config: &DatabaseConfig) -> &str {
+    match config.database.as_str() {
+        "" => MAINTENANCE_DATABASE,
+        database => database,
+    }
+}
+
+fn pg_ssl_mode(ssl_mode: SslMode) -> PgSslMode {
+    match ssl_mode {
+        SslMode::Disable => PgSslMode::Disable,
+        SslMode::Allow => PgSslMode::Allow,
+        SslMode::Prefer => PgSslMode::Prefer,
+        SslMode::Require => PgSslMode::Require,
+        SslMode::VerifyCa => PgSslMode::VerifyCa,
+        SslMode::VerifyFull => PgSslMode::VerifyFull,
+    }
+}
 
 pub struct PostgreSqlConnection {
     pub config: DatabaseConfig,
+    /// Connected to the configured database on first use, which restores may create.
     pub pool: Pool<Postgres>,
+    /// Connected to `MAINTENANCE_DATABASE`, for queries on the server rather than the
+    /// database, and those dropping, renaming or terminating the sessions of databases.
+    maintenance_pool: Pool<Postgres>,
+    connect_options: PgConnectOptions,
+    pgpass_file: Option<PathBuf>,
+    pgpass_password: Option<String>,
+    /// Declared after the pools so their connections are closed before the tunnel they go
+    /// through. Dropping the tunnel stops it and frees its local port.
     _ssh_tunnel: Option<SshTunnel>,
 }
 
 impl PostgreSqlConnection {
     pub async fn new(config: DatabaseConfig) -> Result<Self> {
         let mut config = config.clone();
+
+        // Looked up before the SSH tunnel rewrites the host, so entries match the real server
+        let pgpass_file = resolve_pgpass_file(&config);
+        let pgpass_password = match &pgpass_file {
+            Some(path) => load_password(
+                path,
+                &config.host,
+                config.port,
+                &config.database,
+                &config.username,
+            )?,
+            None => None,
+        };
+
         let ssh_tunnel = match &config.ssh_tunnel {
             Some(ssh_config) => {
                 let tunnel = SshTunnel::new(
@@ -54,45 +198,118 @@ impl PostgreSqlConnection {
         let mut connect_options = PgConnectOptions::new()
             .host(&config.host)
             .username(&config.username)
-            .database("postgres")
-            .port(config.port);
+            .database(MAINTENANCE_DATABASE)
+            .port(config.port)
+            .application_name(&config.get_application_name());
 
-        connect_options = match &config.password {
+        connect_options = match pgpass_password.as_ref().or(config.password.as_ref()) {
             Some(password) => connect_options.password(&password),
             None => connect_options,
         };
 
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(Duration::from_secs(30))
-            .connect_with(connect_options)
+        let settings = get_session_settings(&config);
+        if !settings.is_empty() {
+            connect_options = connect_options.options(settings);
+        }
+
+        if let Some(ssl_mode) = config.ssl_mode {
+            connect_options = connect_options.ssl_mode(pg_ssl_mode(ssl_mode));
+        }
+
+        let pool_options = PgPoolOptions::new()
+            .max_connections(config.get_pool_max_connections()?)
+            .acquire_timeout(config.get_acquire_timeout(Duration::from_secs(30)));
+
+        let maintenance_pool = config
+            .with_connect_timeout(pool_options.clone().connect_with(connect_options.clone()))
             .await?;
 
+        let pool = pool_options
+            .connect_lazy_with(connect_options.clone().database(get_pool_database(&config)));
+
         Ok(Self {
             config,
             pool,
+            maintenance_pool,
+            connect_options,
+            pgpass_file,
+            pgpass_password,
             _ssh_tunnel: ssh_tunnel,
         })
     }
 
-    async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
+    /// Opens a dedicated connection to the configured database rather than `postgres`.
+    pub(super) async fn connect_database(&self) -> Result<PgConnection> {
+        self.config
+            .with_connect_timeout(
+                self.connect_options
+                    .clone()
+                    .database(&self.config.database)
+                    .connect(),
+            )
+            .await
+            .context("Failed to connect to the database")
+    }
+
+    pub(super) fn get_runner(&self) -> CommandRunner {
+        self.get_runner_with(&[])
+    }
+
+    /// Like `get_runner`, setting `settings` in the sessions of the tools on top of those of
+    /// the configuration.
+    pub(super) fn get_runner_with(&self, settings: &[(&str, String)]) -> CommandRunner {
+        let mut runner = CommandRunner::new().env("PGAPPNAME", &self.config.get_application_name());
+
+        let mut settings_with_config = get_session_settings(&self.config);
+        settings_with_config.extend(settings.iter().cloned());
+
+        if !settings_with_config.is_empty() {
+            runner = runner.env("PGOPTIONS", &format_pg_options(&settings_with_config));
+        }
+
+        if let Some(timeout) = self.config.connect_timeout_secs {
+            runner = runner.env("PGCONNECT_TIMEOUT", &timeout.to_string());
+        }
+
+        if let Some(ssl_mode) = self.config.ssl_mode {
+            runner = runner.env("PGSSLMODE", ssl_mode.as_str());
+        }
+
+        // Behind an SSH tunnel the tools connect to localhost, which the pgpass entry won't match
+        if self._ssh_tunnel.is_none() {
+            if let Some(path) = &self.pgpass_file {
+                return runner.env("PGPASSFILE", &path.to_string_lossy());
+            }
+        }
+
+        match self
+            .pgpass_password
+            .as_ref()
+            .or(self.config.password.as_ref())
+        {
+            Some(pass) => runner.secret_env("PGPASSWORD", pass),
+            None => runner,
+        }
+    }
+
+    pub(super) async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
         let metadata = self.get_metadata().await?;
         let version = match metadata.version {
             Version::PostgreSQL(version) => version,
             _ => return Err(anyhow!("Wrong version type")),
         };
 
-        let utilities = PostgreSqlUtilities::new(version);
-        let mut cmd = utilities.get_command(bin_name).await?;
-
-        if let Some(pass) = &self.config.password {
-            cmd.env("PGPASSWORD", pass);
-        }
+        let utilities = PostgreSqlUtilities::new(version)
+            .with_binary_directory(self.config.get_binary_directory());
+        utilities.get_command(bin_name).await
+    }
 
-        Ok(cmd)
+    pub(super) async fn get_command(&self, bin_name: &str) -> Result<Command> {
+        self.get_command_on(bin_name, &self.config.database).await
     }
 
-    async fn get_command(&self, bin_name: &str) -> Result<Command> {
+    /// Like `get_command`, connecting to `database` instead of the configured one.
+    pub(super) async fn get_command_on(&self, bin_name: &str, database: &str) -> Result<Command> {
         let mut cmd = self.get_base_command(bin_name).await?;
 
         cmd.arg("-h")
@@ -102,274 +319,570 @@ impl PostgreSqlConnection {
             .arg("-U")
             .arg(&self.config.username)
             .arg("-d")
-            .arg(&self.config.database);
+            .arg(database);
 
         Ok(cmd)
     }
-}
 
-#[async_trait]
-impl DatabaseConnectionTrait for PostgreSqlConnection {
-    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
-        let version_string: (String,) = sqlx::query_as("SELECT version()")
-            .fetch_one(&self.pool)
-            .await
-            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+    pub(super) async fn run_maintenance_query(&self, query: &str) -> Result<()> {
+        let mut cmd = self.get_command_on("psql", MAINTENANCE_DATABASE).await?;
+        cmd.arg("-c").arg(query);
 
-        let version = match PostgreSQLVersion::parse_string_version(version_string.0.as_str()) {
-            Some(version) => version,
-            None => return Err(anyhow!("Fauiled to parse PostgreSQL version string")),
-        };
+        self.get_runner().run(cmd).await?;
 
-        Ok(DatabaseMetadata {
-            version: Version::PostgreSQL(version),
-        })
+        Ok(())
     }
 
-    async fn test(&self) -> Result<bool> {
-        sqlx::query("SELECT 1")
-            .execute(&self.pool)
-            .await
-            .map(|_| true)
-            .map_err(|e| anyhow!("Connection test failed: {}", e))
+    /// Applies the filters of `options` to a plain SQL dump, returning it with the runner psql
+    /// reads it with.
+    pub(super) fn prepare_plain_restore<'a>(
+        &self,
+        reader: Box<dyn Read + Send + Unpin + 'a>,
+        options: &RestoreOptions,
+    ) -> (Box<dyn Read + Send + Unpin + 'a>, CommandRunner) {
+        let mut reader = reader;
+
+        if options.no_tablespaces {
+            reader = Box::new(TablespaceFilter::new(reader));
+        }
+
+        if options.schema_only {
+            reader = Box::new(SchemaOnlyFilter::new(reader));
+        }
+
+        let mut runner =
+            self.get_runner_with(&[("client_min_messages", options.message_level.to_string())]);
+
+        if let Some(client_encoding) = &options.client_encoding {
+            reader = Box::new(EncodingFilter::new(reader, client_encoding));
+            // Covers dumps without a SET client_encoding statement
+            runner = runner.env("PGCLIENTENCODING", client_encoding);
+        }
+
+        (reader, runner)
     }
 
-    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+    /// Warns when pg_dump is older than the server, which it may fail to dump or dump
+    /// incompletely.
+    async fn check_client_version(&self) -> Result<Option<Diagnostic>> {
+        let server_version = self.get_metadata().await?.version;
+        let client_version = self.get_client_version().await?;
+
+        let warning = match (&server_version, &client_version) {
+            (Version::PostgreSQL(server), Version::PostgreSQL(client)) => {
+                get_version_warning(server, client)
+            }
+            _ => None,
+        };
+
+        if let Some(warning) = &warning {
+            warn!("{}", warning.message);
+        }
+
+        Ok(warning)
+    }
+
+    async fn dump(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        if options.physical {
+            return self.backup_physical(writer, &options).await;
+        }
+
+        if options.directory_format {
+            return self.backup_directory(writer, &options).await;
+        }
+
+        if let Some(jobs) = options.jobs.filter(|jobs| *jobs > 1) {
+            if options.scope != BackupScope::All || options.has_table_filters() {
+                return Err(anyhow!(
+                    "Parallel plain dumps include every table, schema and data, use the directory format to filter them"
+                ));
+            }
+
+            return self
+                .backup_parallel(
+                    writer,
+                    jobs,
+                    &options.exclusions,
+                    options.snapshot.as_deref(),
+                )
+                .await;
+        }
+
         let mut cmd = self.get_command("pg_dump").await?;
 
         cmd.arg("--format=plain")
             .arg("--encoding=UTF8")
             .arg("--schema=*")
-            .arg("--clean")
-            .arg("--if-exists")
             .arg("--no-owner")
             .arg("--blobs")
             .arg("--exclude-schema=information_schema")
             .arg("--exclude-schema=pg_catalog")
             .arg("--exclude-schema=pg_toast")
             .arg("--exclude-schema=pg_temp*")
-            .arg("--exclude-schema=pg_toast_temp*");
+            .arg("--exclude-schema=pg_toast_temp*")
+            .args(options.exclusions.pg_dump_args())
+            .args(options.scope.pg_dump_args())
+            .args(options.pg_dump_table_args()?);
+
+        // Data-only dumps are loaded into existing tables, which must not be dropped
+        if options.scope != BackupScope::DataOnly {
+            cmd.arg("--clean").arg("--if-exists");
+        }
 
-        let mut child = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| anyhow!("Failed to start pg_dump: {}", e))?;
+        if let Some(snapshot) = &options.snapshot {
+            validate_snapshot(snapshot)?;
+            cmd.arg(format!("--snapshot={}", snapshot));
+        }
 
-        let mut stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture pg_dump stdout".to_string()))?;
+        let runner = self.get_runner();
+        let output = runner.pipe_to(cmd, writer).await?;
 
-        let mut buffer = [0u8; 16384];
+        Ok(runner.diagnostics(&output.stderr))
+    }
 
-        loop {
-            match stdout.read(&mut buffer).await {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    writer
-                        .write_all(&buffer[..n])
-                        .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from pg_dump: {}", e));
+    /// Terminates the other sessions of `database` until none is left. Clients that reconnect
+    /// right away are terminated again until `timeout`, after which it fails with the sessions
+    /// still blocking it.
+    async fn terminate_connections(&self, database: &str, timeout: Duration) -> Result<()> {
+        let mut blocking: Vec<BlockingSession> = vec![];
+
+        let result = time::timeout(timeout, async {
+            loop {
+                sqlx::query(
+                    "SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+                    WHERE datname = $1 AND pid <> pg_backend_pid()",
+                )
+                .bind(database)
+                .execute(&self.maintenance_pool)
+                .await?;
+
+                // Terminated backends can take a moment to leave pg_stat_activity
+                time::sleep(TERMINATION_RETRY_DELAY).await;
+
+                blocking = sqlx::query_as(
+                    "SELECT pid, application_name FROM pg_stat_activity
+                    WHERE datname = $1 AND pid <> pg_backend_pid() ORDER BY pid",
+                )
+                .bind(database)
+                .fetch_all(&self.maintenance_pool)
+                .await?;
+
+                if blocking.is_empty() {
+                    return Ok::<(), sqlx::Error>(());
                 }
             }
+        })
+        .await;
+
+        match result {
+            Ok(result) => result.context("Failed to terminate database connections"),
+            Err(_) if blocking.is_empty() => Err(anyhow!(
+                "Timed out after {:?} terminating the connections to {}",
+                timeout,
+                database
+            )),
+            Err(_) => Err(anyhow!(
+                "{} sessions still connected to {} after {:?}: {}",
+                blocking.len(),
+                database,
+                timeout,
+                describe_sessions(&blocking)
+            )),
         }
+    }
+}
 
-        let status = child
-            .wait()
-            .await
-            .map_err(|e| anyhow!("pg_dump process failed: {}", e))?;
+#[async_trait]
+impl DatabaseConnectionTrait for PostgreSqlConnection {
+    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
+        let version_string: (String, String) = sqlx::query_as(
+            "SELECT version(), COALESCE(
+                (SELECT pg_encoding_to_char(encoding) FROM pg_database WHERE datname = $1),
+                current_setting('server_encoding')
+            )",
+        )
+        .bind(&self.config.database)
+        .fetch_one(&self.maintenance_pool)
+        .await
+        .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+
+        let version = match PostgreSQLVersion::parse_string_version(version_string.0.as_str()) {
+            Some(version) => version,
+            None => {
+                return Err(anyhow!(
+                    "Failed to parse PostgreSQL version string: {}",
+                    version_string.0
+                ))
+            }
+        };
+
+        Ok(DatabaseMetadata {
+            version: Version::PostgreSQL(version),
+            encoding: Some(version_string.1),
+        })
+    }
 
-        if !status.success() {
-            let mut stderr = child
-                .stderr
-                .take()
-                .ok_or_else(|| anyhow!("Failed to capture pg_dump stderr".to_string()))?;
+    async fn get_client_version(&self) -> Result<Version> {
+        let mut cmd = self.get_base_command("pg_dump").await?;
+        cmd.arg("--version");
 
-            let mut error_message = String::new();
-            stderr
-                .read_to_string(&mut error_message)
-                .await
-                .map_err(|e| anyhow!("Failed to read pg_dump stderr: {}", e))?;
+        let output = self.get_runner().run(cmd).await?;
 
-            return Err(anyhow!("pg_dump failed: {}", error_message));
+        let version_string = String::from_utf8_lossy(&output.stdout);
+
+        match PostgreSQLVersion::parse_string_version(version_string.trim()) {
+            Some(version) => Ok(Version::PostgreSQL(version)),
+            None => Err(anyhow!(
+                "Failed to parse pg_dump version string: {}",
+                version_string.trim()
+            )),
         }
+    }
+
+    async fn get_database_size(&self) -> Result<u64> {
+        let size: (i64,) = sqlx::query_as("SELECT pg_database_size($1)")
+            .bind(&self.config.database)
+            .fetch_one(&self.maintenance_pool)
+            .await
+            .map_err(|e| anyhow!("Failed to get database size: {}", e))?;
+
+        Ok(size.0.max(0) as u64)
+    }
+
+    async fn get_change_token(&self) -> Result<Option<String>> {
+        let counters: Option<(i64, i64, i64)> = sqlx::query_as(
+            "SELECT tup_inserted, tup_updated, tup_deleted
+            FROM pg_stat_database WHERE datname = $1",
+        )
+        .bind(&self.config.database)
+        .fetch_optional(&self.maintenance_pool)
+        .await
+        .map_err(|e| anyhow!("Failed to get database statistics: {}", e))?;
+
+        Ok(counters
+            .map(|(inserted, updated, deleted)| format!("{}-{}-{}", inserted, updated, deleted)))
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let databases: Vec<(String,)> = sqlx::query_as(
+            "SELECT datname FROM pg_database
+            WHERE NOT datistemplate AND datallowconn ORDER BY datname",
+        )
+        .fetch_all(&self.maintenance_pool)
+        .await
+        .map_err(|e| anyhow!("Failed to list databases: {}", e))?;
+
+        Ok(databases.into_iter().map(|(name,)| name).collect())
+    }
+
+    async fn test(&self) -> Result<bool> {
+        sqlx::query("SELECT 1")
+            .execute(&self.maintenance_pool)
+            .await
+            .map(|_| true)
+            .map_err(|e| anyhow!("Connection test failed: {}", e))
+    }
+
+    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await?;
 
         Ok(())
     }
 
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let version_warning = self.check_client_version().await?;
+
+        let mut writer = ProgressWriter::new(writer, options.progress.clone());
+        let mut diagnostics = self.dump(&mut writer, options).await?;
+        diagnostics.splice(0..0, version_warning);
+
+        Ok(diagnostics)
+    }
+
     async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
         self.restore_with_options(
             reader,
             RestoreOptions {
                 drop_database_first: true,
+                ..Default::default()
             },
         )
-        .await
+        .await?;
+
+        Ok(())
     }
 
     async fn restore_with_options(
         &self,
         reader: &mut (dyn Read + Send + Unpin),
         options: RestoreOptions,
-    ) -> Result<()> {
-        let mut cmd = self.get_base_command("psql").await?;
+    ) -> Result<Vec<Diagnostic>> {
+        if options.validate_only {
+            return self.validate_restore(reader, &options).await;
+        }
 
-        cmd.arg("-h")
-            .arg(&self.config.host)
-            .arg("-p")
-            .arg(self.config.port.to_string())
-            .arg("-U")
-            .arg(&self.config.username)
-            .arg("-d")
-            .arg("postgres")
-            .arg("-c")
-            .arg(format!(
-                "SELECT pg_terminate_backend(pg_stat_activity.pid) 
-                FROM pg_stat_activity 
-                WHERE pg_stat_activity.datname = '{}' 
-                AND pid <> pg_backend_pid();",
-                self.config.database
-            ));
-
-        let drop_connections_output = cmd
-            .output()
+        if options.terminate_connections {
+            self.terminate_connections(
+                &self.config.database,
+                options
+                    .termination_timeout
+                    .unwrap_or(DEFAULT_TERMINATION_TIMEOUT),
+            )
+            .await?;
+        }
+
+        if options.drop_database_first {
+            let result = self
+                .run_maintenance_query(&format!(
+                    "DROP DATABASE IF EXISTS {};",
+                    quote_identifier(&self.config.database)
+                ))
+                .await;
+
+            match result {
+                Ok(()) => {}
+                Err(e) if !options.terminate_connections && is_database_in_use(&e) => {
+                    return Err(e.context(format!(
+                        "Failed to drop database: other sessions are connected to {}, disconnect them or let the restore terminate them",
+                        self.config.database
+                    )))
+                }
+                Err(e) => return Err(e.context("Failed to drop database")),
+            }
+
+            self.run_maintenance_query(&format!(
+                "CREATE DATABASE {};",
+                quote_identifier(&self.config.database)
+            ))
             .await
-            .context("Failed to execute connection termination command")?;
+            .context("Failed to create database")?;
+        }
 
-        if !drop_connections_output.status.success() {
-            let stderr = String::from_utf8_lossy(&drop_connections_output.stderr);
-            let exit_code = drop_connections_output.status.code().unwrap_or(-1);
+        let reader = ProgressReader::new(reader, options.progress.clone());
+        let (is_directory_dump, mut reader) = split_dump_header(reader)?;
 
-            return Err(anyhow!(
-                "Failed to terminate database connections with exit code {}.\nError details: {}",
-                exit_code,
-                stderr.trim()
-            ));
+        if is_directory_dump {
+            return self.restore_directory(&mut reader, &options).await;
         }
 
-        if options.drop_database_first {
-            let mut cmd = self.get_base_command("psql").await?;
-
-            cmd.arg("-h")
-                .arg(&self.config.host)
-                .arg("-p")
-                .arg(self.config.port.to_string())
-                .arg("-U")
-                .arg(&self.config.username)
-                .arg("-d")
-                .arg("postgres")
-                .arg("-c")
-                .arg(format!(
-                    "DROP DATABASE IF EXISTS \"{}\";",
-                    self.config.database
-                ));
+        let mut cmd = self.get_command("psql").await?;
 
-            let output = cmd
-                .output()
-                .await
-                .context("Failed to execute drop database command")?;
+        if options.single_transaction {
+            add_single_transaction_args(&mut cmd);
+        }
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                let exit_code = output.status.code().unwrap_or(-1);
+        let (mut reader, runner) = self.prepare_plain_restore(Box::new(reader), &options);
 
-                return Err(anyhow!(
-                    "Failed to drop database with exit code {}.\nError: {}",
-                    exit_code,
-                    stderr.trim()
-                ));
+        let output = match runner.pipe_from(cmd, reader.as_mut()).await {
+            Ok(output) => output,
+            Err(e) if options.single_transaction => {
+                return Err(describe_first_error(
+                    e,
+                    "psql restore failed and was rolled back",
+                ))
             }
+            Err(e) => return Err(e.context("psql restore failed")),
+        };
 
-            let mut create_cmd = self.get_base_command("psql").await?;
-
-            create_cmd
-                .arg("-h")
-                .arg(&self.config.host)
-                .arg("-p")
-                .arg(self.config.port.to_string())
-                .arg("-U")
-                .arg(&self.config.username)
-                .arg("-d")
-                .arg("postgres")
-                .arg("-c")
-                .arg(format!("CREATE DATABASE \"{}\";", self.config.database));
-
-            let create_output = create_cmd
-                .output()
-                .await
-                .context("Failed to create database")?;
-
-            if !create_output.status.success() {
-                let stderr = String::from_utf8_lossy(&create_output.stderr);
-                let exit_code = create_output.status.code().unwrap_or(-1);
+        Ok(runner.diagnostics(&output.stderr))
+    }
 
-                return Err(anyhow!(
-                    "Failed to create database with exit code {}.\nError: {}",
-                    exit_code,
-                    stderr.trim()
-                ));
-            }
+    async fn analyze(&self, jobs: Option<usize>) -> Result<()> {
+        let mut cmd = self.get_command("vacuumdb").await?;
+        cmd.arg("--analyze-only");
+
+        if let Some(jobs) = jobs.filter(|jobs| *jobs > 1) {
+            cmd.arg(format!("--jobs={}", jobs));
         }
 
-        let mut cmd = self.get_base_command("psql").await?;
+        self.get_runner()
+            .run(cmd)
+            .await
+            .context("vacuumdb --analyze-only failed")?;
 
-        cmd.arg("-h")
-            .arg(&self.config.host)
-            .arg("-p")
-            .arg(self.config.port.to_string())
-            .arg("-U")
-            .arg(&self.config.username)
-            .arg("-d")
-            .arg(&self.config.database);
+        Ok(())
+    }
 
-        let mut child = cmd
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+    async fn run_check(&self, query: &str) -> Result<bool> {
+        let mut connection = self.connect_database().await?;
 
-        let mut stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture psql stdin"))?;
+        sqlx::query_scalar::<_, bool>(query)
+            .fetch_one(&mut connection)
+            .await
+            .context(format!("Check query failed: {}", query))
+    }
 
-        let mut buffer = [0u8; 16384];
+    async fn rename_databases(
+        &self,
+        renames: &[(&str, &str)],
+        termination_timeout: Option<Duration>,
+    ) -> Result<()> {
+        let deadline = Instant::now() + termination_timeout.unwrap_or(DEFAULT_TERMINATION_TIMEOUT);
+        let description = renames
+            .iter()
+            .map(|(from, to)| format!("{} to {}", from, to))
+            .collect::<Vec<_>>()
+            .join(", ");
 
         loop {
-            match reader.read(&mut buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    stdin.write_all(&buffer[..n]).await?;
+            for (from, _) in renames {
+                self.terminate_connections(
+                    from,
+                    deadline.saturating_duration_since(Instant::now()),
+                )
+                .await?;
+            }
+
+            // Either every database has its new name or none, a database is never missing
+            let result = async {
+                let mut transaction = self.maintenance_pool.begin().await?;
+                for (from, to) in renames {
+                    sqlx::query(&format!(
+                        "ALTER DATABASE {} RENAME TO {}",
+                        quote_identifier(from),
+                        quote_identifier(to)
+                    ))
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+                transaction.commit().await
+            }
+            .await;
+
+            match result {
+                Ok(_) => return Ok(()),
+                // A client reconnected between the termination and the rename
+                Err(sqlx::Error::Database(e))
+                    if e.code().as_deref() == Some(OBJECT_IN_USE) && Instant::now() < deadline =>
+                {
+                    continue
                 }
                 Err(e) => {
-                    return Err(anyhow!("Failed to read backup data: {}", e));
+                    return Err(e).context(format!("Failed to rename databases {}", description))
                 }
             }
         }
+    }
 
-        drop(stdin);
+    async fn drop_database(&self, database: &str) -> Result<()> {
+        self.terminate_connections(database, DEFAULT_TERMINATION_TIMEOUT)
+            .await?;
 
-        let output = child
-            .wait_with_output()
-            .await
-            .map_err(|e| anyhow!("psql process failed: {}", e))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let exit_code = output.status.code().unwrap_or(-1);
-
-            return Err(anyhow!(
-                "psql restore failed with exit code {}.\nStderr: {}\nStdout: {}",
-                exit_code,
-                stderr.trim(),
-                stdout.trim()
-            ));
-        }
+        sqlx::query(&format!(
+            "DROP DATABASE IF EXISTS {}",
+            quote_identifier(database)
+        ))
+        .execute(&self.maintenance_pool)
+        .await
+        .context(format!("Failed to drop database {}", database))?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod connection_tests {
+    use anyhow::anyhow;
+
+    use crate::databases::{
+        command_runner::CommandError,
+        diagnostics::{parse_diagnostics, Severity},
+        postgres::version::PostgreSQLVersion,
+    };
+
+    use super::{
+        describe_sessions, get_version_warning, is_database_in_use, quote_identifier,
+        BlockingSession,
+    };
+
+    #[test]
+    fn test_01_describe_sessions() {
+        let sessions = vec![
+            BlockingSession {
+                pid: 4242,
+                application_name: Some("worker".into()),
+            },
+            BlockingSession {
+                pid: 4243,
+                application_name: Some("".into()),
+            },
+        ];
+
+        assert_eq!(describe_sessions(&sessions), "4242 (worker), 4243");
+    }
+
+    #[test]
+    fn test_02_is_database_in_use() {
+        let error = anyhow::Error::new(CommandError {
+            message: "psql failed".into(),
+            diagnostics: parse_diagnostics(
+                "ERROR:  database \"app\" is being accessed by other users\n\
+                DETAIL:  There is 1 other session using the database.\n",
+            ),
+        });
+
+        assert!(is_database_in_use(&error));
+        assert!(is_database_in_use(
+            &error.context("Failed to drop database")
+        ));
+        assert!(!is_database_in_use(&anyhow!("connection refused")));
+    }
+
+    #[test]
+    fn test_03_version_warning() {
+        let server = PostgreSQLVersion {
+            major: 17,
+            minor: 2,
+        };
+
+        let warning = get_version_warning(
+            &server,
+            &PostgreSQLVersion {
+                major: 15,
+                minor: 10,
+            },
+        )
+        .expect("Missing version warning");
+
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(
+            warning.message,
+            "pg_dump 15 is older than the server (17), the backup may be incomplete or fail"
+        );
+
+        assert!(get_version_warning(
+            &server,
+            &PostgreSQLVersion {
+                major: 17,
+                minor: 0
+            }
+        )
+        .is_none());
+        assert!(get_version_warning(
+            &server,
+            &PostgreSQLVersion {
+                major: 18,
+                minor: 1
+            }
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_04_quote_identifier() {
+        assert_eq!(quote_identifier("app"), "\"app\"");
+        assert_eq!(
+            quote_identifier("app\"; DROP DATABASE prod; --"),
+            "\"app\"\"; DROP DATABASE prod; --\""
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{self, Chain, Cursor, Read, Write};
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+
+use crate::databases::{diagnostics::Diagnostic, BackupOptions, RestoreOptions};
+
+use super::{
+    connection::PostgreSqlConnection,
+    parallel::{validate_snapshot, EXCLUDED_SCHEMAS},
+};
+
+/// Bytes to read before telling a tar archive from a plain dump.
+pub const TAR_HEADER_SIZE: u64 = 512;
+/// Name of the dump directory inside the archive.
+const DUMP_DIRECTORY: &str = "dump";
+
+/// Whether `header`, the first decompressed bytes of a backup, starts a tar archive.
+pub fn is_tar_archive(header: &[u8]) -> bool {
+    header.get(257..262) == Some(b"ustar".as_slice())
+}
+
+/// A backup whose header was read, put back in front of the rest.
+pub type HeaderReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads the header of a backup to tell a directory dump archive from a plain dump, returning
+/// the whole backup again.
+pub fn split_dump_header<R: Read>(mut reader: R) -> io::Result<(bool, HeaderReader<R>)> {
+    let mut header = vec![];
+    (&mut reader)
+        .take(TAR_HEADER_SIZE)
+        .read_to_end(&mut header)?;
+
+    Ok((is_tar_archive(&header), Cursor::new(header).chain(reader)))
+}
+
+impl PostgreSqlConnection {
+    /// Dumps the database with `pg_dump --format=directory`, `options.jobs` tables at a time,
+    /// into a temporary directory and writes it to `writer` as a single tar archive.
+    ///
+    /// The files are left uncompressed by pg_dump, the backup compression applies to the whole
+    /// archive. The temporary directory is removed whether the dump succeeds or not, and needs
+    /// room for the uncompressed dump.
+    pub async fn backup_directory(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: &BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let jobs = options.jobs.unwrap_or(1);
+        let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+        let dump_path = temp_dir.path().join(DUMP_DIRECTORY);
+
+        let mut cmd = self.get_command("pg_dump").await?;
+
+        cmd.arg("--format=directory")
+            .arg(format!("--jobs={}", jobs.max(1)))
+            .arg("--compress=0")
+            .arg(format!("--file={}", dump_path.display()))
+            .arg("--encoding=UTF8")
+            .arg("--no-owner")
+            .arg("--blobs")
+            .arg("--schema=*")
+            .args(
+                EXCLUDED_SCHEMAS
+                    .iter()
+                    .map(|schema| format!("--exclude-schema={}", schema)),
+            )
+            .args(options.exclusions.pg_dump_args())
+            .args(options.scope.pg_dump_args())
+            .args(options.pg_dump_table_args()?);
+
+        if let Some(snapshot) = &options.snapshot {
+            validate_snapshot(snapshot)?;
+            cmd.arg(format!("--snapshot={}", snapshot));
+        }
+
+        info!("Dumping to a directory with {} parallel jobs", jobs.max(1));
+
+        let runner = self.get_runner();
+        let output = runner
+            .run(cmd)
+            .await
+            .context("pg_dump --format=directory failed")?;
+
+        let mut archive = tar::Builder::new(writer);
+        archive
+            .append_dir_all(DUMP_DIRECTORY, &dump_path)
+            .context("Failed to archive the dump directory")?;
+        archive.finish()?;
+
+        Ok(runner.diagnostics(&output.stderr))
+    }
+
+    /// Unpacks an archive written by `backup_directory` into a temporary directory and restores
+    /// it with `pg_restore`, `options.jobs` tables at a time.
+    pub(super) async fn restore_directory(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: &RestoreOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        if options.client_encoding.is_some() {
+            return Err(anyhow!(
+                "A client encoding can only be set when restoring plain SQL dumps"
+            ));
+        }
+
+        let jobs = options.jobs.unwrap_or(1).max(1);
+
+        if options.single_transaction && jobs > 1 {
+            return Err(anyhow!(
+                "A directory-format backup cannot be restored in a single transaction with several jobs"
+            ));
+        }
+
+        let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+
+        tar::Archive::new(reader)
+            .unpack(temp_dir.path())
+            .context("Failed to unpack the dump archive")?;
+
+        let mut cmd = self.get_command("pg_restore").await?;
+
+        cmd.arg("--clean")
+            .arg("--if-exists")
+            .arg("--no-owner")
+            .arg(format!("--jobs={}", jobs));
+
+        if options.single_transaction {
+            cmd.arg("--single-transaction").arg("--exit-on-error");
+        }
+
+        if options.no_tablespaces {
+            cmd.arg("--no-tablespaces");
+        }
+
+        if options.schema_only {
+            cmd.arg("--schema-only");
+        }
+
+        cmd.arg(temp_dir.path().join(DUMP_DIRECTORY));
+
+        let runner =
+            self.get_runner_with(&[("client_min_messages", options.message_level.to_string())]);
+
+        let output = runner.run(cmd).await.context("pg_restore failed")?;
+
+        Ok(runner.diagnostics(&output.stderr))
+    }
+
+    /// Unpacks an archive written by `backup_directory` and lists it with `pg_restore --list`,
+    /// which reads the table of contents of every entry without connecting to the server.
+    pub(super) async fn list_directory(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+    ) -> Result<Vec<Diagnostic>> {
+        let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+
+        tar::Archive::new(reader)
+            .unpack(temp_dir.path())
+            .context("Failed to unpack the dump archive")?;
+
+        let mut cmd = self.get_base_command("pg_restore").await?;
+        cmd.arg("--list").arg(temp_dir.path().join(DUMP_DIRECTORY));
+
+        let runner = self.get_runner();
+        let output = runner.run(cmd).await.context("pg_restore --list failed")?;
+
+        Ok(runner.diagnostics(&output.stderr))
+    }
+}
+
+#[cfg(test)]
+mod directory_tests {
+    use super::is_tar_archive;
+
+    #[test]
+    fn test_01_is_tar_archive() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "dump/toc.dat", "PGDM".as_bytes())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        assert!(is_tar_archive(&archive));
+        assert!(!is_tar_archive(b"--\n-- PostgreSQL database dump\n--\n"));
+    }
+}
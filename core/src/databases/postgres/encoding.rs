@@ -0,0 +1,162 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+const CLIENT_ENCODING_PREFIX: &str = "SET client_encoding = '";
+/// pg_dump sets the client encoding within its first lines.
+const HEADER_LINES: usize = 100;
+
+/// Returns the encoding declared by the `SET client_encoding` statement of a plain dump.
+pub fn parse_client_encoding(header: &str) -> Option<String> {
+    header.lines().take(HEADER_LINES).find_map(|line| {
+        line.strip_prefix(CLIENT_ENCODING_PREFIX)?
+            .strip_suffix("';")
+            .map(|encoding| encoding.to_string())
+    })
+}
+
+fn normalize(encoding: &str) -> String {
+    match encoding.to_uppercase().replace('-', "").as_str() {
+        "UTF8" | "UNICODE" => "UTF8".into(),
+        "LATIN1" | "ISO88591" => "LATIN1".into(),
+        encoding => encoding.into(),
+    }
+}
+
+/// Warns about conversions PostgreSQL will reject or cannot do faithfully when a dump in
+/// `source` encoding (the declared one unless overridden) is restored into `target`.
+pub fn encoding_warnings(source: Option<&str>, target: Option<&str>) -> Vec<String> {
+    let (Some(source), Some(target)) = (source, target) else {
+        return vec![];
+    };
+
+    let (source, target) = (normalize(source), normalize(target));
+
+    if source == target {
+        return vec![];
+    }
+
+    if source == "SQL_ASCII" {
+        return vec![format!(
+            "The dump is SQL_ASCII, which PostgreSQL does not convert: non-ASCII bytes will be rejected by the {} database. Set the client encoding to the real source encoding (e.g. LATIN1)",
+            target
+        )];
+    }
+
+    if target == "SQL_ASCII" {
+        return vec![format!(
+            "The target database is SQL_ASCII: {} text will be stored as raw bytes without validation",
+            source
+        )];
+    }
+
+    if target != "UTF8" {
+        return vec![format!(
+            "Converting {} to {} is lossy: characters without a {} equivalent will make the restore fail",
+            source, target, target
+        )];
+    }
+
+    vec![]
+}
+
+/// Replaces the encoding of the dump's `SET client_encoding` statement so the server converts
+/// text from that encoding, e.g. to restore a SQL_ASCII dump holding LATIN1 bytes into UTF8.
+/// Only the header is inspected, the rest of the stream is passed through as is.
+pub struct EncodingFilter<R: Read> {
+    reader: BufReader<R>,
+    client_encoding: String,
+    line: Vec<u8>,
+    position: usize,
+    lines: usize,
+}
+
+impl<R: Read> EncodingFilter<R> {
+    pub fn new(reader: R, client_encoding: &str) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            client_encoding: client_encoding.into(),
+            line: vec![],
+            position: 0,
+            lines: 0,
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.position = 0;
+
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+
+        self.lines += 1;
+
+        if self.line.starts_with(CLIENT_ENCODING_PREFIX.as_bytes()) {
+            self.line =
+                format!("{}{}';\n", CLIENT_ENCODING_PREFIX, self.client_encoding).into_bytes();
+            // Nothing left to rewrite
+            self.lines = HEADER_LINES;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for EncodingFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.lines >= HEADER_LINES && self.position >= self.line.len() {
+            return self.reader.read(buf);
+        }
+
+        while self.position >= self.line.len() {
+            if !self.next_line()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.line.len() - self.position);
+        buf[..n].copy_from_slice(&self.line[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod encoding_tests {
+    use std::io::{Cursor, Read};
+
+    use super::{encoding_warnings, parse_client_encoding, EncodingFilter};
+
+    #[test]
+    fn test_01_override_client_encoding() {
+        let dump = "SET statement_timeout = 0;\n\
+            SET client_encoding = 'SQL_ASCII';\n\
+            COPY public.notes (body) FROM stdin;\n\
+            caf\u{e9}\n\
+            \\.\n";
+
+        assert_eq!(parse_client_encoding(dump), Some("SQL_ASCII".into()));
+
+        let mut content = String::new();
+        EncodingFilter::new(Cursor::new(dump.to_string()), "LATIN1")
+            .read_to_string(&mut content)
+            .expect("Failed to filter dump");
+
+        assert_eq!(
+            content,
+            dump.replace("'SQL_ASCII'", "'LATIN1'"),
+            "Only the client encoding should change"
+        );
+    }
+
+    #[test]
+    fn test_02_encoding_warnings() {
+        assert!(encoding_warnings(Some("UTF8"), Some("utf-8")).is_empty());
+        assert!(encoding_warnings(Some("LATIN1"), Some("UTF8")).is_empty());
+        assert!(encoding_warnings(Some("UTF8"), None).is_empty());
+
+        assert_eq!(encoding_warnings(Some("SQL_ASCII"), Some("UTF8")).len(), 1);
+        assert_eq!(encoding_warnings(Some("UTF8"), Some("LATIN1")).len(), 1);
+        assert_eq!(encoding_warnings(Some("UTF8"), Some("SQL_ASCII")).len(), 1);
+    }
+}
@@ -0,0 +1,466 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use regex::Regex;
+
+const COPY_END: &[u8] = b"\\.";
+
+/// Statements whose tablespace assignments `TablespaceFilter` strips.
+const TABLESPACE_STATEMENTS: &[&str] = &[
+    "CREATE TABLE ",
+    "CREATE UNLOGGED TABLE ",
+    "CREATE INDEX ",
+    "CREATE UNIQUE INDEX ",
+    "CREATE MATERIALIZED VIEW ",
+    "ALTER TABLE ",
+    "ALTER INDEX ",
+    "ALTER MATERIALIZED VIEW ",
+    "SET default_tablespace",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpStatement {
+    None,
+    Copy,
+    /// A statement spanning several lines, and whether it assigns tablespaces.
+    Sql {
+        tablespace: bool,
+    },
+}
+
+/// Quotes left open at the end of a line of SQL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SqlQuotes {
+    in_string: bool,
+    in_identifier: bool,
+    /// Tag of the dollar-quoted string, such as a function body, being read.
+    dollar_tag: Option<Vec<u8>>,
+}
+
+impl SqlQuotes {
+    /// Follows the quotes through `line` and tells whether a statement ends on it, with a
+    /// semicolon outside of any quote or comment.
+    fn scan(&mut self, line: &[u8]) -> bool {
+        let mut ends = false;
+        let mut i = 0;
+
+        while i < line.len() {
+            let byte = line[i];
+
+            if let Some(tag) = &self.dollar_tag {
+                if line[i..].starts_with(tag) {
+                    i += tag.len();
+                    self.dollar_tag = None;
+                    ends = false;
+                } else {
+                    i += 1;
+                }
+                continue;
+            }
+
+            if self.in_string || self.in_identifier {
+                // Quotes doubled to escape them close and reopen the literal
+                if (self.in_string && byte == b'\'') || (self.in_identifier && byte == b'"') {
+                    self.in_string = false;
+                    self.in_identifier = false;
+                }
+                ends = false;
+                i += 1;
+                continue;
+            }
+
+            match byte {
+                b'\'' => self.in_string = true,
+                b'"' => self.in_identifier = true,
+                b'-' if line[i..].starts_with(b"--") => break,
+                b'$' => {
+                    if let Some(len) = get_dollar_tag_len(&line[i..]) {
+                        self.dollar_tag = Some(line[i..i + len].to_vec());
+                        ends = false;
+                        i += len;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            if byte == b';' {
+                ends = true;
+            } else if !byte.is_ascii_whitespace() {
+                ends = false;
+            }
+            i += 1;
+        }
+
+        ends && *self == Self::default()
+    }
+}
+
+/// Length of the `$tag$` starting `text`, if it opens a dollar-quoted string rather than
+/// being a parameter such as `$1`.
+fn get_dollar_tag_len(text: &[u8]) -> Option<usize> {
+    let tag = &text[1..];
+    let len = tag
+        .iter()
+        .position(|byte| !(byte.is_ascii_alphanumeric() || *byte == b'_'))?;
+
+    if tag[len] != b'$' || tag.first().is_some_and(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(len + 2)
+}
+
+/// Strips tablespace assignments from a plain SQL dump so every object lands in the
+/// default tablespace of the target database. Only the DDL of `TABLESPACE_STATEMENTS` is
+/// rewritten: COPY data, INSERT statements, function bodies and comments pass through
+/// untouched.
+pub struct TablespaceFilter<R: Read> {
+    reader: BufReader<R>,
+    tablespace_clause: Regex,
+    line: Vec<u8>,
+    position: usize,
+    statement: DumpStatement,
+    quotes: SqlQuotes,
+}
+
+impl<R: Read> TablespaceFilter<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            tablespace_clause: Regex::new(r#"(?:^|\s+)(USING INDEX )?TABLESPACE\s+("[^"]*"|\w+)"#)
+                .expect("Invalid tablespace regex"),
+            line: vec![],
+            position: 0,
+            statement: DumpStatement::None,
+            quotes: SqlQuotes::default(),
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.position = 0;
+
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+
+        let tablespace = match self.statement {
+            DumpStatement::Copy => {
+                if self.line.trim_ascii_end() == COPY_END {
+                    self.statement = DumpStatement::None;
+                }
+                return Ok(true);
+            }
+            DumpStatement::Sql { tablespace } => tablespace,
+            DumpStatement::None => {
+                let line = self.line.trim_ascii();
+                if line.is_empty() || line.starts_with(b"--") {
+                    return Ok(true);
+                }
+
+                if self.line.starts_with(b"COPY ") && line.ends_with(b"FROM stdin;") {
+                    self.statement = DumpStatement::Copy;
+                    return Ok(true);
+                }
+
+                TABLESPACE_STATEMENTS
+                    .iter()
+                    .any(|statement| self.line.starts_with(statement.as_bytes()))
+            }
+        };
+
+        let ends = self.quotes.scan(&self.line);
+        self.statement = match ends {
+            true => DumpStatement::None,
+            false => DumpStatement::Sql { tablespace },
+        };
+
+        if tablespace {
+            if let Some(line) = self.filter_line() {
+                self.line = line.into_bytes();
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn filter_line(&self) -> Option<String> {
+        let line = std::str::from_utf8(&self.line).ok()?;
+
+        if !line.contains("TABLESPACE") && !line.starts_with("SET default_tablespace") {
+            return None;
+        }
+
+        if line.starts_with("SET default_tablespace") {
+            return Some("SET default_tablespace = '';\n".into());
+        }
+
+        if line.starts_with("ALTER ") && line.contains(" SET TABLESPACE ") {
+            return Some(String::new());
+        }
+
+        Some(self.tablespace_clause.replace_all(line, "").into_owned())
+    }
+}
+
+impl<R: Read> Read for TablespaceFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.line.len() {
+            if !self.next_line()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.line.len() - self.position);
+        buf[..n].copy_from_slice(&self.line[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+/// Statements holding data, which `SchemaOnlyFilter` drops.
+const DATA_STATEMENTS: &[&str] = &["INSERT INTO ", "SELECT pg_catalog.setval("];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DataStatement {
+    None,
+    Copy,
+    /// A statement spanning several lines, and whether it holds data.
+    Sql {
+        data: bool,
+    },
+}
+
+/// Drops the data of a plain SQL dump, keeping only its DDL: COPY blocks up to their
+/// terminating `\.`, INSERT statements (dumps taken with `--inserts`) and sequence `setval`
+/// calls are removed, like `pg_restore --schema-only` does for custom-format archives. They
+/// are only recognized at the start of a statement, so function bodies and string literals
+/// are kept whole.
+pub struct SchemaOnlyFilter<R: Read> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+    position: usize,
+    statement: DataStatement,
+    quotes: SqlQuotes,
+}
+
+impl<R: Read> SchemaOnlyFilter<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: vec![],
+            position: 0,
+            statement: DataStatement::None,
+            quotes: SqlQuotes::default(),
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.position = 0;
+
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+
+        let data = match self.statement {
+            DataStatement::Copy => {
+                if self.line.trim_ascii_end() == COPY_END {
+                    self.statement = DataStatement::None;
+                }
+                self.line.clear();
+                return Ok(true);
+            }
+            DataStatement::Sql { data } => data,
+            DataStatement::None => {
+                let line = self.line.trim_ascii();
+                if line.is_empty() || line.starts_with(b"--") {
+                    return Ok(true);
+                }
+
+                if self.line.starts_with(b"COPY ") && line.ends_with(b"FROM stdin;") {
+                    self.statement = DataStatement::Copy;
+                    self.line.clear();
+                    return Ok(true);
+                }
+
+                DATA_STATEMENTS
+                    .iter()
+                    .any(|statement| self.line.starts_with(statement.as_bytes()))
+            }
+        };
+
+        let ends = self.quotes.scan(&self.line);
+        self.statement = match ends {
+            true => DataStatement::None,
+            false => DataStatement::Sql { data },
+        };
+
+        if data {
+            self.line.clear();
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for SchemaOnlyFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.line.len() {
+            if !self.next_line()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.line.len() - self.position);
+        buf[..n].copy_from_slice(&self.line[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use std::io::{Cursor, Read};
+
+    use super::{SchemaOnlyFilter, TablespaceFilter};
+
+    fn filter(dump: &str) -> String {
+        let mut content = String::new();
+
+        TablespaceFilter::new(Cursor::new(dump.to_string()))
+            .read_to_string(&mut content)
+            .expect("Failed to filter dump");
+
+        content
+    }
+
+    #[test]
+    fn test_01_strip_tablespaces() {
+        let dump = "SET default_tablespace = fast_ssd;\n\
+            CREATE TABLE public.users (id integer) TABLESPACE fast_ssd;\n\
+            ALTER TABLE ONLY public.users ADD CONSTRAINT users_pkey PRIMARY KEY (id) USING INDEX TABLESPACE \"Index Space\";\n\
+            ALTER TABLE public.logs SET TABLESPACE archive;\n\
+            CREATE INDEX users_idx ON public.users USING btree (id);\n";
+
+        assert_eq!(
+            filter(dump),
+            "SET default_tablespace = '';\n\
+            CREATE TABLE public.users (id integer);\n\
+            ALTER TABLE ONLY public.users ADD CONSTRAINT users_pkey PRIMARY KEY (id);\n\
+            CREATE INDEX users_idx ON public.users USING btree (id);\n"
+        );
+    }
+
+    #[test]
+    fn test_02_copy_data_is_untouched() {
+        let dump = "COPY public.notes (body) FROM stdin;\n\
+            moved to TABLESPACE archive\n\
+            \\.\n\
+            CREATE TABLE public.other (id integer) TABLESPACE archive;\n";
+
+        assert_eq!(
+            filter(dump),
+            "COPY public.notes (body) FROM stdin;\n\
+            moved to TABLESPACE archive\n\
+            \\.\n\
+            CREATE TABLE public.other (id integer);\n"
+        );
+    }
+
+    #[test]
+    fn test_03_schema_only() {
+        let dump = "CREATE TABLE public.notes (id integer, body text);\n\
+            COPY public.notes (id, body) FROM stdin;\n\
+            1\tCREATE TABLE fake (id integer);\n\
+            \\.\n\
+            INSERT INTO public.notes VALUES (2, 'multi\n\
+            line; with it''s own quote');\n\
+            INSERT INTO public.notes VALUES (3, 'one line');\n\
+            SELECT pg_catalog.setval('public.notes_id_seq', 3, true);\n\
+            ALTER TABLE ONLY public.notes ADD CONSTRAINT notes_pkey PRIMARY KEY (id);\n";
+
+        let mut content = String::new();
+        SchemaOnlyFilter::new(Cursor::new(dump.to_string()))
+            .read_to_string(&mut content)
+            .expect("Failed to filter dump");
+
+        assert_eq!(
+            content,
+            "CREATE TABLE public.notes (id integer, body text);\n\
+            ALTER TABLE ONLY public.notes ADD CONSTRAINT notes_pkey PRIMARY KEY (id);\n"
+        );
+    }
+
+    #[test]
+    fn test_04_only_ddl_is_rewritten() {
+        let dump = "-- Moved to TABLESPACE archive last year\n\
+            INSERT INTO public.notes VALUES (1, 'moved to TABLESPACE archive');\n\
+            INSERT INTO public.notes VALUES (2, 'multi\n\
+            line; it''s in USING INDEX TABLESPACE fast');\n\
+            CREATE FUNCTION public.move() RETURNS void\n    \
+            LANGUAGE plpgsql\n    \
+            AS $_$\n\
+            BEGIN\n    \
+            EXECUTE 'ALTER TABLE public.logs SET TABLESPACE archive';\n\
+            END;\n\
+            $_$;\n\
+            CREATE TABLE public.logs (\n    \
+            id integer\n\
+            )\n\
+            TABLESPACE archive;\n\
+            COMMENT ON TABLE public.logs IS 'In TABLESPACE archive';\n";
+
+        assert_eq!(
+            filter(dump),
+            "-- Moved to TABLESPACE archive last year\n\
+            INSERT INTO public.notes VALUES (1, 'moved to TABLESPACE archive');\n\
+            INSERT INTO public.notes VALUES (2, 'multi\n\
+            line; it''s in USING INDEX TABLESPACE fast');\n\
+            CREATE FUNCTION public.move() RETURNS void\n    \
+            LANGUAGE plpgsql\n    \
+            AS $_$\n\
+            BEGIN\n    \
+            EXECUTE 'ALTER TABLE public.logs SET TABLESPACE archive';\n\
+            END;\n\
+            $_$;\n\
+            CREATE TABLE public.logs (\n    \
+            id integer\n\
+            )\n\
+            ;\n\
+            COMMENT ON TABLE public.logs IS 'In TABLESPACE archive';\n"
+        );
+    }
+
+    #[test]
+    fn test_05_schema_only_keeps_function_bodies() {
+        let ddl = "CREATE FUNCTION public.log_note() RETURNS trigger\n\
+            LANGUAGE plpgsql\n\
+            AS $$\n\
+            BEGIN\n\
+            INSERT INTO public.audit VALUES (NEW.id);\n\
+            SELECT pg_catalog.setval('public.audit_id_seq', 1, true);\n\
+            RETURN NEW;\n\
+            END;\n\
+            $$;\n\
+            COMMENT ON TABLE public.notes IS 'Notes, filled by\n\
+            INSERT INTO public.notes VALUES (1);\n\
+            and friends';\n";
+        let dump = format!(
+            "{}INSERT INTO public.audit VALUES (1);\n\
+            COPY public.audit (id) FROM stdin;\n\
+            1\n\
+            \\.\n",
+            ddl
+        );
+
+        let mut content = String::new();
+        SchemaOnlyFilter::new(Cursor::new(dump))
+            .read_to_string(&mut content)
+            .expect("Failed to filter dump");
+
+        assert_eq!(content, ddl);
+    }
+}
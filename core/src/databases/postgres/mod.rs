@@ -1,4 +1,12 @@
 pub mod connection;
+pub mod directory;
+pub mod encoding;
+pub mod filter;
+mod parallel;
+pub mod pgpass;
+mod physical;
+pub mod statements;
 mod tests;
 pub mod utilities;
+pub mod validate;
 pub mod version;
@@ -0,0 +1,281 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use log::info;
+use tokio::process::Command;
+
+use crate::databases::{diagnostics::Diagnostic, DumpExclusions};
+
+use super::connection::PostgreSqlConnection;
+
+pub(super) const EXCLUDED_SCHEMAS: [&str; 5] = [
+    "information_schema",
+    "pg_catalog",
+    "pg_toast",
+    "pg_temp*",
+    "pg_toast_temp*",
+];
+
+const TABLES_QUERY: &str = "SELECT format('%I.%I', n.nspname, c.relname)
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind = 'r'
+    AND n.nspname NOT IN ('information_schema', 'pg_catalog')
+    AND n.nspname NOT LIKE 'pg\\_toast%'
+    AND n.nspname NOT LIKE 'pg\\_temp%'
+    AND NOT EXISTS (
+        SELECT 1 FROM pg_depend d
+        WHERE d.classid = 'pg_class'::regclass AND d.objid = c.oid AND d.deptype = 'e'
+    )
+    ORDER BY 1";
+
+const SEQUENCES_QUERY: &str = "SELECT format(
+        'SELECT pg_catalog.setval(%L, %s, %s);',
+        format('%I.%I', schemaname, sequencename),
+        COALESCE(last_value, start_value),
+        last_value IS NOT NULL
+    )
+    FROM pg_sequences
+    WHERE schemaname NOT IN ('information_schema', 'pg_catalog')
+    ORDER BY 1";
+
+/// Exported snapshot ids look like `00000003-0000001B-1`. Anything else is rejected before
+/// being interpolated into `SET TRANSACTION SNAPSHOT`.
+pub(super) fn validate_snapshot(snapshot: &str) -> Result<()> {
+    let parts: Vec<&str> = snapshot.split('-').collect();
+
+    if parts.len() < 2
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err(anyhow!("Invalid snapshot id: {}", snapshot));
+    }
+
+    Ok(())
+}
+
+/// Keeps the DROP statements a `--clean` schema dump prints before its first object.
+///
+/// Session guards (`\restrict`) are removed since the rest of that dump is discarded.
+pub fn extract_clean_statements(schema: &str) -> String {
+    let end = schema
+        .find("\n-- Name: ")
+        .map_or(schema.len(), |index| index + 1);
+
+    schema[..end]
+        .lines()
+        .filter(|line| !line.starts_with("\\restrict") && !line.starts_with("\\unrestrict"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+impl PostgreSqlConnection {
+    async fn get_dump_command(
+        &self,
+        snapshot: &str,
+        exclusions: &DumpExclusions,
+    ) -> Result<Command> {
+        let mut cmd = self.get_command("pg_dump").await?;
+
+        cmd.arg("--format=plain")
+            .arg("--encoding=UTF8")
+            .arg("--no-owner")
+            .arg(format!("--snapshot={}", snapshot))
+            .args(exclusions.pg_dump_args());
+
+        Ok(cmd)
+    }
+
+    async fn get_schema_command(
+        &self,
+        snapshot: &str,
+        exclusions: &DumpExclusions,
+    ) -> Result<Command> {
+        let mut cmd = self.get_dump_command(snapshot, exclusions).await?;
+
+        cmd.arg("--schema=*");
+
+        for schema in EXCLUDED_SCHEMAS {
+            cmd.arg(format!("--exclude-schema={}", schema));
+        }
+
+        Ok(cmd)
+    }
+
+    async fn dump_table(
+        &self,
+        snapshot: &str,
+        table: &str,
+        exclusions: &DumpExclusions,
+    ) -> Result<(std::fs::File, Vec<Diagnostic>)> {
+        let mut cmd = self.get_dump_command(snapshot, exclusions).await?;
+        cmd.arg("--data-only").arg(format!("--table={}", table));
+
+        let mut file = tempfile::tempfile()?;
+
+        let runner = self.get_runner();
+        let output = runner
+            .pipe_to(cmd, &mut file)
+            .await
+            .with_context(|| format!("Failed to dump table {}", table))?;
+
+        file.seek(SeekFrom::Start(0))?;
+        Ok((file, runner.diagnostics(&output.stderr)))
+    }
+
+    /// Dumps a database with one `pg_dump --data-only --table` process per table, up to `jobs`
+    /// at a time, and concatenates the parts into a plain SQL dump that psql can replay.
+    ///
+    /// Every process reads the same exported snapshot, so the parts are consistent with each
+    /// other. The snapshot is exported here unless `snapshot` gives one exported by another
+    /// session, e.g. to line the dump up with what that session already read. The output is
+    /// ordered as: DROP statements, pre-data schema (tables, types, functions), table data
+    /// sorted by table name, sequence values, then post-data schema (indexes, constraints,
+    /// triggers). Foreign keys are only created once all data is loaded,
+    /// so tables can be restored in any order.
+    ///
+    /// Constraints:
+    /// - Requires PostgreSQL 10 or later, and a direct connection: the snapshot cannot be
+    ///   shared through a transaction pooler.
+    /// - Large objects and data of extension configuration tables are not included.
+    /// - Tables are dumped to temporary files before being concatenated, so the local disk
+    ///   needs room for the largest `jobs` tables.
+    pub async fn backup_parallel(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        jobs: usize,
+        exclusions: &DumpExclusions,
+        snapshot: Option<&str>,
+    ) -> Result<Vec<Diagnostic>> {
+        // The transaction must stay open until every pg_dump has started from its snapshot
+        let mut connection = self.connect_database().await?;
+
+        sqlx::query("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut connection)
+            .await?;
+
+        let snapshot = match snapshot {
+            Some(snapshot) => {
+                validate_snapshot(snapshot)?;
+
+                // Tables and sequence values are read from the same snapshot as the data
+                sqlx::query(&format!("SET TRANSACTION SNAPSHOT '{}'", snapshot))
+                    .execute(&mut connection)
+                    .await
+                    .with_context(|| format!("Failed to import snapshot {}", snapshot))?;
+
+                snapshot.to_string()
+            }
+            None => {
+                let (snapshot,): (String,) = sqlx::query_as("SELECT pg_export_snapshot()")
+                    .fetch_one(&mut connection)
+                    .await
+                    .context("Failed to export snapshot")?;
+
+                snapshot
+            }
+        };
+
+        let tables: Vec<String> = sqlx::query_scalar(TABLES_QUERY)
+            .fetch_all(&mut connection)
+            .await
+            .context("Failed to list tables")?;
+
+        let sequences: Vec<String> = sqlx::query_scalar(SEQUENCES_QUERY)
+            .fetch_all(&mut connection)
+            .await
+            .context("Failed to read sequence values")?;
+
+        info!(
+            "Dumping {} tables with {} parallel jobs",
+            tables.len(),
+            jobs
+        );
+
+        let mut cmd = self.get_schema_command(&snapshot, exclusions).await?;
+        cmd.arg("--schema-only").arg("--clean").arg("--if-exists");
+
+        let runner = self.get_runner();
+        let mut diagnostics = vec![];
+
+        let mut schema = vec![];
+        let output = runner
+            .pipe_to(cmd, &mut schema)
+            .await
+            .context("Failed to dump DROP statements")?;
+        diagnostics.extend(runner.diagnostics(&output.stderr));
+        writer.write_all(extract_clean_statements(&String::from_utf8_lossy(&schema)).as_bytes())?;
+
+        let mut cmd = self.get_schema_command(&snapshot, exclusions).await?;
+        cmd.arg("--section=pre-data");
+        let output = runner
+            .pipe_to(cmd, writer)
+            .await
+            .context("Failed to dump pre-data schema")?;
+        diagnostics.extend(runner.diagnostics(&output.stderr));
+
+        // Parts come back in table order whatever order they finish in
+        let parts: Vec<_> = tables
+            .iter()
+            .map(|table| self.dump_table(&snapshot, table, exclusions))
+            .collect();
+        let mut parts = stream::iter(parts).buffered(jobs);
+
+        while let Some((mut part, part_diagnostics)) = parts.try_next().await? {
+            io::copy(&mut part, writer)?;
+            diagnostics.extend(part_diagnostics);
+        }
+
+        if !sequences.is_empty() {
+            writer.write_all(b"\n--\n-- Sequence values\n--\n\n")?;
+            for sequence in &sequences {
+                writeln!(writer, "{}", sequence)?;
+            }
+        }
+
+        let mut cmd = self.get_schema_command(&snapshot, exclusions).await?;
+        cmd.arg("--section=post-data");
+        let output = runner
+            .pipe_to(cmd, writer)
+            .await
+            .context("Failed to dump post-data schema")?;
+        diagnostics.extend(runner.diagnostics(&output.stderr));
+
+        sqlx::query("ROLLBACK").execute(&mut connection).await?;
+
+        Ok(diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod parallel_tests {
+    use super::{extract_clean_statements, validate_snapshot};
+
+    #[test]
+    fn test_01_extract_clean_statements() {
+        let schema = "--\n-- PostgreSQL database dump\n--\n\n\
+            \\restrict abc\n\
+            SET client_encoding = 'UTF8';\n\
+            ALTER TABLE IF EXISTS ONLY public.orders DROP CONSTRAINT IF EXISTS orders_user_fkey;\n\
+            DROP TABLE IF EXISTS public.users;\n\
+            --\n-- Name: users; Type: TABLE; Schema: public; Owner: -\n--\n\n\
+            CREATE TABLE public.users (id integer);\n\
+            \\unrestrict abc\n";
+
+        let clean = extract_clean_statements(schema);
+
+        assert!(clean.contains("DROP CONSTRAINT IF EXISTS orders_user_fkey;"));
+        assert!(clean.contains("DROP TABLE IF EXISTS public.users;"));
+        assert!(!clean.contains("CREATE TABLE"));
+        assert!(!clean.contains("restrict"));
+    }
+    #[test]
+    fn test_02_validate_snapshot() {
+        assert!(validate_snapshot("00000003-0000001B-1").is_ok());
+        assert!(validate_snapshot("00000003-0000001B").is_ok());
+        assert!(validate_snapshot("00000003").is_err());
+        assert!(validate_snapshot("0003-'; DROP TABLE users; --").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::databases::DatabaseConfig;
+
+/// Returns the pgpass file to use for a connection.
+///
+/// An explicitly configured file always wins. Otherwise, when no password is configured,
+/// `PGPASSFILE` or `~/.pgpass` is used if it exists, mirroring libpq.
+pub fn resolve_pgpass_file(config: &DatabaseConfig) -> Option<PathBuf> {
+    if let Some(path) = &config.pgpass_file {
+        return Some(expand_home(path));
+    }
+
+    if config.password.is_some() {
+        return None;
+    }
+
+    env::var_os("PGPASSFILE")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".pgpass")))
+        .filter(|path| path.is_file())
+}
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Looks up the password of the first matching `hostname:port:database:username:password` line.
+pub fn load_password(
+    path: &Path,
+    host: &str,
+    port: u16,
+    database: &str,
+    username: &str,
+) -> Result<Option<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pgpass file {}", path.display()))?;
+    let port = port.to_string();
+
+    for line in content.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = split_fields(line);
+
+        if fields.len() != 5 {
+            continue;
+        }
+
+        let matches = [host, port.as_str(), database, username]
+            .iter()
+            .zip(&fields)
+            .all(|(value, field)| field == "*" || field == value);
+
+        if matches {
+            return Ok(Some(fields[4].clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Splits a pgpass line on `:`, honouring `\:` and `\\` escapes.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    fields.last_mut().unwrap().push(escaped);
+                }
+            }
+            ':' => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod pgpass_tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::load_password;
+
+    #[test]
+    fn test_01_load_password() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join(".pgpass");
+
+        fs::write(
+            &path,
+            "# comment\n\
+            db.internal:5432:app:reader:first\n\
+            *:5432:*:admin:pa\\:ss\\\\word\n\
+            *:*:*:*:fallback\n",
+        )
+        .expect("Failed to write pgpass file");
+
+        let lookup = |host, database, username| {
+            load_password(&path, host, 5432, database, username).expect("Failed to read pgpass")
+        };
+
+        assert_eq!(lookup("db.internal", "app", "reader"), Some("first".into()));
+        assert_eq!(
+            lookup("localhost", "other", "admin"),
+            Some("pa:ss\\word".into())
+        );
+        assert_eq!(
+            lookup("db.internal", "other", "reader"),
+            Some("fallback".into())
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+
+use crate::databases::{diagnostics::Diagnostic, BackupOptions, BackupScope, DumpExclusions};
+
+use super::connection::PostgreSqlConnection;
+
+/// Label of the base backups, shown in `pg_stat_progress_basebackup` and the backup label.
+const BACKUP_LABEL: &str = "dbkp";
+
+impl PostgreSqlConnection {
+    /// Copies the data directory of the whole server with `pg_basebackup` and writes it to
+    /// `writer` as a single tar archive, with the WAL needed to make it consistent.
+    ///
+    /// The role needs the `REPLICATION` attribute and `pg_hba.conf` a `replication` entry for
+    /// it. Servers with tablespaces outside the data directory cannot be written as one
+    /// archive and are refused by pg_basebackup.
+    pub(super) async fn backup_physical(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: &BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        if options.jobs.is_some_and(|jobs| jobs > 1)
+            || options.snapshot.is_some()
+            || options.directory_format
+            || options.exclusions != DumpExclusions::default()
+            || options.scope != BackupScope::All
+            || options.has_table_filters()
+        {
+            return Err(anyhow!(
+                "Base backups copy the whole server, parallel jobs, snapshots, directory formats, exclusions, scopes and table filters do not apply"
+            ));
+        }
+
+        // pg_basebackup reads -d as a connection string and copies every database anyway
+        let mut cmd = self.get_base_command("pg_basebackup").await?;
+
+        cmd.arg("-h")
+            .arg(&self.config.host)
+            .arg("-p")
+            .arg(self.config.port.to_string())
+            .arg("-U")
+            .arg(&self.config.username)
+            .arg("--pgdata=-")
+            .arg("--format=tar")
+            .arg("--wal-method=fetch")
+            .arg("--checkpoint=fast")
+            .arg(format!("--label={}", BACKUP_LABEL));
+
+        info!("Taking a base backup of {}", self.config.host);
+
+        let runner = self.get_runner();
+        let output = runner
+            .pipe_to(cmd, writer)
+            .await
+            .context("pg_basebackup failed")?;
+
+        Ok(runner.diagnostics(&output.stderr))
+    }
+}
@@ -0,0 +1,431 @@
+use std::io::{BufRead, BufReader, Read};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use super::filter::TablespaceFilter;
+
+const COPY_END: &str = "\\.";
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// psql meta-commands emitted by recent pg_dump versions that only guard the psql session.
+const IGNORED_META_COMMANDS: [&str; 2] = ["\\restrict", "\\unrestrict"];
+
+/// How `restore_into` treats `COPY ... FROM stdin` blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CopyMode {
+    /// Fail on the first COPY block, for callers that only expect INSERT based dumps.
+    #[default]
+    Reject,
+    /// Stream the rows of each COPY block through the COPY protocol of the connection.
+    Stream,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreIntoOptions {
+    pub copy_mode: CopyMode,
+    pub no_tablespaces: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Sql(String),
+    /// A `COPY ... FROM stdin` statement, its rows follow and are read with `read_copy_data`.
+    Copy(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Normal,
+    SingleQuote { escapes: bool },
+    DoubleQuote,
+    LineComment,
+    BlockComment(usize),
+    DollarQuote(String),
+}
+
+/// Splits a plain SQL dump into statements, honouring quotes, dollar quotes and comments.
+pub struct StatementReader<R: Read> {
+    reader: BufReader<R>,
+    buffer: String,
+    position: usize,
+    content_start: Option<usize>,
+    state: State,
+    in_copy: bool,
+}
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$'
+}
+
+impl<R: Read> StatementReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buffer: String::new(),
+            position: 0,
+            content_start: None,
+            state: State::Normal,
+            in_copy: false,
+        }
+    }
+
+    pub fn next_statement(&mut self) -> Result<Option<Statement>> {
+        if self.in_copy {
+            return Err(anyhow!(
+                "The rows of the previous COPY statement were not read"
+            ));
+        }
+
+        loop {
+            if let Some(end) = self.scan() {
+                let start = self.content_start.take().unwrap_or(end);
+                let statement = self.buffer[start..end].trim().to_string();
+
+                self.buffer.drain(..end);
+                self.position = 0;
+
+                if is_copy_from_stdin(&statement) {
+                    // Rows start on the line following the statement
+                    self.buffer.clear();
+                    self.in_copy = true;
+                    return Ok(Some(Statement::Copy(statement)));
+                }
+
+                return Ok(Some(Statement::Sql(statement)));
+            }
+
+            let mut line = String::new();
+
+            if self.reader.read_line(&mut line)? == 0 {
+                let statement = self
+                    .content_start
+                    .take()
+                    .map(|start| self.buffer[start..].trim().to_string())
+                    .filter(|statement| !statement.is_empty())
+                    .map(Statement::Sql);
+
+                self.buffer.clear();
+                self.position = 0;
+
+                return Ok(statement);
+            }
+
+            if self.content_start.is_none() && self.state == State::Normal {
+                let command = line.trim();
+
+                if command.starts_with('\\') {
+                    let name = command.split_whitespace().next().unwrap_or_default();
+
+                    if IGNORED_META_COMMANDS.contains(&name) {
+                        continue;
+                    }
+
+                    return Err(anyhow!(
+                        "psql meta-command {} cannot be executed through a connection",
+                        name
+                    ));
+                }
+            }
+
+            self.buffer.push_str(&line);
+        }
+    }
+
+    /// Appends COPY rows to `data` until about `limit` bytes are buffered, returning `false`
+    /// once the end of the COPY block has been reached.
+    pub fn read_copy_data(&mut self, data: &mut Vec<u8>, limit: usize) -> Result<bool> {
+        if !self.in_copy {
+            return Ok(false);
+        }
+
+        let mut line = vec![];
+
+        while data.len() < limit {
+            line.clear();
+
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                return Err(anyhow!("Unexpected end of dump inside a COPY block"));
+            }
+
+            if line.trim_ascii_end() == COPY_END.as_bytes() {
+                self.in_copy = false;
+                return Ok(false);
+            }
+
+            data.extend_from_slice(&line);
+        }
+
+        Ok(true)
+    }
+
+    /// Advances through the buffered lines and returns the end of the next complete statement.
+    fn scan(&mut self) -> Option<usize> {
+        let bytes = self.buffer.as_bytes();
+        let mut i = self.position;
+
+        while i < bytes.len() {
+            let byte = bytes[i];
+            let next = bytes.get(i + 1).copied();
+
+            match &mut self.state {
+                State::Normal => match byte {
+                    b'-' if next == Some(b'-') => {
+                        self.state = State::LineComment;
+                        i += 1;
+                    }
+                    b'/' if next == Some(b'*') => {
+                        self.state = State::BlockComment(1);
+                        i += 1;
+                    }
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => {
+                        self.content_start.get_or_insert(i);
+
+                        match byte {
+                            b'\'' => {
+                                let escapes = i > 0
+                                    && matches!(bytes[i - 1], b'E' | b'e')
+                                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                                self.state = State::SingleQuote { escapes };
+                            }
+                            b'"' => self.state = State::DoubleQuote,
+                            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                                let mut j = i + 1;
+                                while j < bytes.len()
+                                    && (bytes[j].is_ascii_alphabetic()
+                                        || bytes[j] == b'_'
+                                        || (j > i + 1 && bytes[j].is_ascii_digit()))
+                                {
+                                    j += 1;
+                                }
+                                if bytes.get(j) == Some(&b'$') {
+                                    self.state = State::DollarQuote(self.buffer[i..=j].to_string());
+                                    i = j;
+                                }
+                            }
+                            b';' => {
+                                self.position = i + 1;
+                                return Some(i + 1);
+                            }
+                            _ => {}
+                        }
+                    }
+                },
+                State::SingleQuote { escapes } => match byte {
+                    b'\\' if *escapes => i += 1,
+                    b'\'' if next == Some(b'\'') => i += 1,
+                    b'\'' => self.state = State::Normal,
+                    _ => {}
+                },
+                State::DoubleQuote => match byte {
+                    b'"' if next == Some(b'"') => i += 1,
+                    b'"' => self.state = State::Normal,
+                    _ => {}
+                },
+                State::LineComment => {
+                    if byte == b'\n' {
+                        self.state = State::Normal;
+                    }
+                }
+                State::BlockComment(depth) => match byte {
+                    b'/' if next == Some(b'*') => {
+                        *depth += 1;
+                        i += 1;
+                    }
+                    b'*' if next == Some(b'/') => {
+                        *depth -= 1;
+                        i += 1;
+                        if *depth == 0 {
+                            self.state = State::Normal;
+                        }
+                    }
+                    _ => {}
+                },
+                State::DollarQuote(tag) => {
+                    if bytes[i..].starts_with(tag.as_bytes()) {
+                        i += tag.len() - 1;
+                        self.state = State::Normal;
+                    }
+                }
+            }
+
+            i += 1;
+        }
+
+        self.position = bytes.len();
+        None
+    }
+}
+
+fn is_copy_from_stdin(statement: &str) -> bool {
+    let statement = statement.trim_end_matches(';').trim_end().to_lowercase();
+    statement.starts_with("copy ") && statement.ends_with("from stdin")
+}
+
+/// Restores a plain SQL dump by executing its statements on a caller-provided connection,
+/// instead of piping the dump to psql.
+///
+/// Pass `&mut *transaction` to restore inside a transaction the caller commits or rolls back,
+/// so the restored data can be swapped in atomically with other changes. Returns the number of
+/// executed statements.
+///
+/// Constraints:
+/// - Only plain SQL dumps (`pg_dump --format=plain`) are supported, encoded in UTF-8.
+/// - psql meta-commands such as `\connect` are rejected, so dumps made with `--create` or
+///   covering several databases cannot be restored this way.
+/// - `COPY ... FROM stdin` blocks fail unless `copy_mode` is `CopyMode::Stream`. Dumps made
+///   with `--inserts` need neither.
+/// - Statements that cannot run inside a transaction block (`CREATE DATABASE`, `VACUUM`,
+///   `CREATE INDEX CONCURRENTLY`, ...) fail when a transaction is used.
+/// - The database is not dropped or recreated and other connections are left alone, the
+///   target is expected to be prepared by the caller.
+pub async fn restore_into(
+    connection: &mut PgConnection,
+    reader: &mut (dyn Read + Send + Unpin),
+    options: RestoreIntoOptions,
+) -> Result<u64> {
+    let reader: Box<dyn Read + Send + Unpin + '_> = if options.no_tablespaces {
+        Box::new(TablespaceFilter::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    let mut statements = StatementReader::new(reader);
+    let mut count = 0;
+
+    while let Some(statement) = statements.next_statement()? {
+        count += 1;
+
+        match statement {
+            Statement::Sql(sql) => {
+                sqlx::raw_sql(&sql)
+                    .execute(&mut *connection)
+                    .await
+                    .with_context(|| format!("Failed to execute statement {}: {}", count, sql))?;
+            }
+            Statement::Copy(sql) => {
+                if options.copy_mode == CopyMode::Reject {
+                    return Err(anyhow!(
+                        "Statement {} is a COPY FROM stdin, which is rejected: {}",
+                        count,
+                        sql
+                    ));
+                }
+
+                let mut copy = connection
+                    .copy_in_raw(&sql)
+                    .await
+                    .with_context(|| format!("Failed to start statement {}: {}", count, sql))?;
+                let mut data = Vec::with_capacity(COPY_CHUNK_SIZE);
+
+                loop {
+                    data.clear();
+                    let more = statements.read_copy_data(&mut data, COPY_CHUNK_SIZE)?;
+
+                    if !data.is_empty() {
+                        copy.send(data.as_slice()).await?;
+                    }
+
+                    if !more {
+                        break;
+                    }
+                }
+
+                copy.finish()
+                    .await
+                    .with_context(|| format!("Failed to copy rows of statement {}", count))?;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod statements_tests {
+    use std::io::Cursor;
+
+    use super::{Statement, StatementReader};
+
+    fn read_all(dump: &str) -> Vec<Statement> {
+        let mut reader = StatementReader::new(Cursor::new(dump.to_string()));
+        let mut statements = vec![];
+
+        while let Some(statement) = reader.next_statement().expect("Failed to read statement") {
+            if let Statement::Copy(_) = statement {
+                let mut data = vec![];
+                while reader
+                    .read_copy_data(&mut data, 1024)
+                    .expect("Failed to read rows")
+                {}
+            }
+            statements.push(statement);
+        }
+
+        statements
+    }
+
+    #[test]
+    fn test_01_split_statements() {
+        let dump = "--\n-- Name: users; Type: TABLE\n--\n\
+            SET client_encoding = 'UTF8';\n\
+            CREATE TABLE public.users (\n    id integer, \"odd;name\" text\n);\n\
+            INSERT INTO public.users VALUES (1, 'it''s; fine'), (2, E'back\\\\'';');\n\
+            /* block; /* nested; */ comment */ SELECT 1;\n\
+            CREATE FUNCTION public.f() RETURNS text AS $body$\nBEGIN RETURN 'a;b'; END;\n$body$ LANGUAGE plpgsql;\n\
+            -- trailing comment\n";
+
+        let statements = read_all(dump);
+
+        assert_eq!(statements.len(), 5);
+        assert_eq!(
+            statements[0],
+            Statement::Sql("SET client_encoding = 'UTF8';".into())
+        );
+        assert!(matches!(&statements[1], Statement::Sql(sql) if sql.ends_with("text\n);")));
+        assert!(matches!(&statements[2], Statement::Sql(sql) if sql.ends_with("';');")));
+        assert_eq!(statements[3], Statement::Sql("SELECT 1;".into()));
+        assert!(
+            matches!(&statements[4], Statement::Sql(sql) if sql.ends_with("LANGUAGE plpgsql;"))
+        );
+    }
+
+    #[test]
+    fn test_02_copy_blocks() {
+        let dump = "COPY public.notes (body) FROM stdin;\nrow; with 'quote\n\\.\nSELECT 2;\n";
+        let mut reader = StatementReader::new(Cursor::new(dump.to_string()));
+
+        assert_eq!(
+            reader.next_statement().expect("Failed to read statement"),
+            Some(Statement::Copy(
+                "COPY public.notes (body) FROM stdin;".into()
+            ))
+        );
+
+        let mut data = vec![];
+        assert!(!reader
+            .read_copy_data(&mut data, 1024)
+            .expect("Failed to read rows"));
+        assert_eq!(data, b"row; with 'quote\n");
+
+        assert_eq!(
+            reader.next_statement().expect("Failed to read statement"),
+            Some(Statement::Sql("SELECT 2;".into()))
+        );
+        assert_eq!(
+            reader.next_statement().expect("Failed to read statement"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_03_meta_commands() {
+        let statements = read_all("\\restrict abc\nSELECT 1;\n\\unrestrict abc\n");
+        assert_eq!(statements, vec![Statement::Sql("SELECT 1;".into())]);
+
+        let mut reader = StatementReader::new(Cursor::new("\\connect other\n".to_string()));
+        assert!(reader.next_statement().is_err());
+    }
+}
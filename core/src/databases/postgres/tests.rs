@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod postgresql_connection_test {
-    use crate::databases::postgres::connection::PostgreSqlConnection;
-    use crate::databases::ssh_tunnel::{SshAuthMethod, SshTunnelConfig};
-    use crate::databases::version::Version;
+    use crate::databases::postgres::connection::{
+        format_pg_options, get_pool_database, get_session_settings, PostgreSqlConnection,
+    };
+    use crate::databases::postgres::utilities::PostgreSqlUtilities;
+    use crate::databases::postgres::version::PostgreSQLVersion;
+    use crate::databases::ssh_tunnel::{HostKeyVerification, SshAuthMethod, SshTunnelConfig};
+    use crate::databases::version::{Version, VersionTrait};
     use crate::databases::{
-        ConnectionType, DatabaseConfig, DatabaseConnectionTrait, RestoreOptions,
+        ConnectionType, DatabaseConfig, DatabaseConnectionTrait, RestoreOptions, UtilitiesTrait,
     };
     use crate::test_utils::test_utils::{
         get_postgresql_connection, get_postgresql_pool, initialize_test,
@@ -13,6 +17,7 @@ mod postgresql_connection_test {
     use anyhow::Result;
     use dotenv::dotenv;
     use std::env;
+    use std::net::TcpListener;
     use std::thread::sleep;
     use std::time::Duration;
 
@@ -31,6 +36,8 @@ mod postgresql_connection_test {
             username: env::var("DB_USERNAME").unwrap_or_default(),
             database: env::var("DB_NAME").unwrap_or_default(),
             port,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: Some(SshTunnelConfig {
                 host: env::var("SSH_HOST").unwrap_or_default(),
                 username: env::var("SSH_USERNAME").unwrap_or_default(),
@@ -39,7 +46,19 @@ mod postgresql_connection_test {
                     key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                     passphrase_key: None,
                 },
+                jump_hosts: vec![],
+                known_hosts: None,
+                host_key_verification: HostKeyVerification::Strict,
+                local_ports: None,
             }),
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         };
 
         let connection = PostgreSqlConnection::new(config).await?;
@@ -188,6 +207,7 @@ mod postgresql_connection_test {
                 &mut backup_cursor,
                 RestoreOptions {
                     drop_database_first: false,
+                    ..Default::default()
                 },
             )
             .await
@@ -250,4 +270,212 @@ mod postgresql_connection_test {
 
         assert!(buf.len() > 0);
     }
+
+    #[test]
+    fn test_06_parse_client_version() {
+        let version = PostgreSQLVersion::parse_string_version("pg_dump (PostgreSQL) 17.3")
+            .expect("Failed to parse pg_dump version");
+
+        assert_eq!(version.major, 17);
+        assert_eq!(version.minor, 3);
+    }
+
+    #[tokio::test]
+    async fn test_07_tunnel_released_on_failure() {
+        initialize_test();
+
+        // Nothing listens on the SSH port, so the connection fails once the tunnel port is bound
+        let ssh_port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .port();
+        let local_port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .port();
+
+        let config = DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::PostgreSql,
+            host: "db.internal".into(),
+            password: None,
+            username: "postgres".into(),
+            database: "app".into(),
+            port: 5432,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: Some(SshTunnelConfig {
+                host: "127.0.0.1".into(),
+                username: "ubuntu".into(),
+                port: ssh_port,
+                auth_method: SshAuthMethod::Agent,
+                jump_hosts: vec![],
+                known_hosts: None,
+                host_key_verification: HostKeyVerification::Strict,
+                local_ports: Some(local_port..=local_port),
+            }),
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        };
+
+        assert!(PostgreSqlConnection::new(config).await.is_err());
+        assert!(TcpListener::bind(("127.0.0.1", local_port)).is_ok());
+    }
+
+    fn get_local_config() -> DatabaseConfig {
+        DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::PostgreSql,
+            host: "localhost".into(),
+            password: None,
+            username: "postgres".into(),
+            database: "app".into(),
+            port: 5432,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: Some(10),
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        }
+    }
+
+    #[test]
+    fn test_08_session_settings() {
+        let mut config = get_local_config();
+
+        assert!(get_session_settings(&config).is_empty());
+
+        config.statement_timeout_secs = Some(600);
+        config.keepalive_idle_secs = Some(60);
+
+        let mut settings = get_session_settings(&config);
+        settings.push(("client_min_messages", "warning".into()));
+
+        assert_eq!(
+            format_pg_options(&settings),
+            "-c statement_timeout=600s -c tcp_keepalives_idle=60 -c client_min_messages=warning"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_09_pool_database() {
+        initialize_test();
+        let connection = get_postgresql_connection(false)
+            .await
+            .expect("Failed to get connection");
+
+        let database: String = sqlx::query_scalar("SELECT current_database()")
+            .fetch_one(&connection.pool)
+            .await
+            .expect("Failed to get the current database");
+
+        assert_eq!(database, connection.config.database);
+
+        let mut config = connection.config.clone();
+        config.database = String::new();
+        assert_eq!(get_pool_database(&config), "postgres");
+    }
+
+    #[tokio::test]
+    async fn test_10_binary_path_override() {
+        let directory = tempfile::tempdir().unwrap();
+        let pg_dump = directory.path().join("pg_dump");
+        std::fs::write(&pg_dump, "").unwrap();
+
+        let mut config = get_local_config();
+        assert_eq!(config.get_binary_directory(), None);
+
+        config.binary_path_override = Some(pg_dump.to_string_lossy().to_string());
+        assert_eq!(
+            config.get_binary_directory().as_deref(),
+            Some(directory.path())
+        );
+
+        config.binary_path_override = Some(directory.path().to_string_lossy().to_string());
+        let version = PostgreSQLVersion::parse_string_version("pg_dump (PostgreSQL) 17.3").unwrap();
+        let utilities =
+            PostgreSqlUtilities::new(version).with_binary_directory(config.get_binary_directory());
+
+        let command = utilities.get_command("pg_dump").await.unwrap();
+        assert_eq!(command.as_std().get_program(), pg_dump.as_os_str());
+
+        // A missing tool is an error rather than a download of another version
+        assert!(utilities.get_command("psql").await.is_err());
+    }
+
+    #[test]
+    fn test_11_parse_server_versions() {
+        let versions = [
+            (
+                "PostgreSQL 15.4 (Ubuntu 15.4-1.pgdg22.04+1) on x86_64-pc-linux-gnu, compiled by gcc (Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0, 64-bit",
+                (15, 4),
+            ),
+            (
+                "PostgreSQL 16.1 on x86_64-pc-linux-musl, compiled by gcc (Alpine 12.2.1_git20220924-r10) 12.2.1 20220924, 64-bit",
+                (16, 1),
+            ),
+            (
+                "PostgreSQL 15.4 on aarch64-unknown-linux-gnu, compiled by aarch64-unknown-linux-gnu-gcc (GCC) 9.5.0, 64-bit",
+                (15, 4),
+            ),
+            (
+                "PostgreSQL 11.9 on x86_64-pc-linux-gnu, compiled by x86_64-pc-linux-gnu-gcc (GCC) 7.4.0, 64-bit",
+                (11, 9),
+            ),
+            ("Aurora PostgreSQL-compatible 15.4", (15, 4)),
+            ("PostgreSQL compatible 13.12 (Aurora Serverless)", (13, 12)),
+            (
+                "PostgreSQL 14.9 (EnterpriseDB Advanced Server 14.9.0) on x86_64-pc-linux-gnu, compiled by gcc (GCC) 8.5.0 20210514 (Red Hat 8.5.0-18), 64-bit",
+                (14, 9),
+            ),
+            (
+                "EnterpriseDB 11.5.12 on x86_64-pc-linux-gnu, compiled by gcc (GCC) 4.8.5 20150623 (Red Hat 4.8.5-36), 64-bit",
+                (11, 5),
+            ),
+            (
+                "PostgreSQL 11.2-YB-2.18.0.0-b0 on x86_64-pc-linux-gnu, compiled by clang version 15.0.3, 64-bit",
+                (11, 2),
+            ),
+            (
+                "PostgreSQL 9.6.24 on x86_64-pc-linux-gnu, compiled by gcc (GCC) 4.8.5, 64-bit",
+                (9, 6),
+            ),
+            (
+                "PostgreSQL 17beta2 on x86_64-pc-linux-gnu, compiled by gcc (Debian 12.2.0-14) 12.2.0, 64-bit",
+                (17, 0),
+            ),
+            ("PostgreSQL 16rc1 on x86_64-apple-darwin23.0.0", (16, 0)),
+            ("pg_dump (PostgreSQL) 15.4 (Ubuntu 15.4-1.pgdg22.04+1)", (15, 4)),
+            ("pg_dump (PostgreSQL) 18devel", (18, 0)),
+            ("pg_dump (EnterpriseDB) 14.9.0", (14, 9)),
+        ];
+
+        for (version_string, (major, minor)) in versions {
+            let version = PostgreSQLVersion::parse_string_version(version_string)
+                .unwrap_or_else(|| panic!("Failed to parse {}", version_string));
+
+            assert_eq!(
+                (version.major, version.minor),
+                (major, minor),
+                "{}",
+                version_string
+            );
+        }
+
+        assert!(PostgreSQLVersion::parse_string_version("MySQL 8.0.35").is_none());
+    }
 }
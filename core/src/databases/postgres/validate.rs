@@ -0,0 +1,128 @@
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use tokio::process::Command;
+
+use crate::{
+    databases::{
+        command_runner::CommandError,
+        diagnostics::{Diagnostic, Severity},
+        progress::ProgressReader,
+        DatabaseConnectionTrait, RestoreOptions,
+    },
+    promote::get_promotion_name,
+};
+
+use super::{
+    connection::{quote_identifier, PostgreSqlConnection},
+    directory::split_dump_header,
+};
+
+/// The first error or fatal message among `diagnostics`.
+pub fn first_error(diagnostics: &[Diagnostic]) -> Option<&Diagnostic> {
+    diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.severity >= Severity::Error)
+}
+
+/// Makes psql apply a plain dump in one transaction, rolled back at the first error. Reading
+/// stdin as a file makes psql prefix its messages with the line of the dump.
+pub(super) fn add_single_transaction_args(cmd: &mut Command) {
+    cmd.arg("--single-transaction")
+        .arg("--variable=ON_ERROR_STOP=1")
+        .arg("--file=-");
+}
+
+/// Adds `message` and the first SQL error psql reported, line and excerpt included, to the
+/// error of a psql run stopped by `ON_ERROR_STOP`.
+pub(super) fn describe_first_error(e: anyhow::Error, message: &str) -> anyhow::Error {
+    let error = e
+        .downcast_ref::<CommandError>()
+        .and_then(|error| first_error(&error.diagnostics))
+        .map(|diagnostic| diagnostic.to_string());
+
+    match error {
+        Some(error) => e.context(format!("{}: {}", message, error)),
+        None => e.context(message.to_string()),
+    }
+}
+
+impl PostgreSqlConnection {
+    /// Checks that a backup restores without touching the configured database.
+    ///
+    /// Plain dumps are restored in a single transaction into a temporary database, dropped
+    /// afterwards, and psql stops at the first error, which is returned with its line and
+    /// excerpt. Directory archives are only listed with `pg_restore --list`.
+    pub(super) async fn validate_restore(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: &RestoreOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let reader = ProgressReader::new(reader, options.progress.clone());
+        let (is_directory_dump, mut reader) = split_dump_header(reader)?;
+
+        if is_directory_dump {
+            return self.list_directory(&mut reader).await;
+        }
+
+        let database = get_promotion_name(&self.config.database, "validate", Utc::now());
+
+        self.run_maintenance_query(&format!("CREATE DATABASE {};", quote_identifier(&database)))
+            .await
+            .context("Failed to create the validation database")?;
+
+        info!("Validating the backup in {}", database);
+
+        let result = self.validate_plain(&mut reader, options, &database).await;
+
+        if let Err(e) = self.drop_database(&database).await {
+            warn!("Failed to drop {}: {:#}", database, e);
+        }
+
+        result
+    }
+
+    async fn validate_plain(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: &RestoreOptions,
+        database: &str,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut cmd = self.get_command_on("psql", database).await?;
+        add_single_transaction_args(&mut cmd);
+
+        let (mut reader, runner) = self.prepare_plain_restore(Box::new(reader), options);
+
+        match runner.pipe_from(cmd, reader.as_mut()).await {
+            Ok(output) => Ok(runner.diagnostics(&output.stderr)),
+            Err(e) => Err(describe_first_error(e, "The backup does not restore")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use crate::databases::diagnostics::parse_diagnostics;
+
+    use super::first_error;
+
+    #[test]
+    fn test_01_first_error() {
+        let diagnostics = parse_diagnostics(
+            "psql:<stdin>:12: NOTICE:  table \"old_users\" does not exist, skipping\n\
+            psql:<stdin>:42: ERROR:  syntax error at or near \"TABLEE\"\n\
+            LINE 1: CREATE TABLEE users (id integer);\n",
+        );
+
+        let error = first_error(&diagnostics).expect("Missing error");
+
+        assert_eq!(error.line, Some(42));
+        assert_eq!(
+            error.to_string(),
+            "ERROR (line 42): syntax error at or near \"TABLEE\"\n  LINE 1: CREATE TABLEE users (id integer);"
+        );
+        assert!(first_error(&diagnostics[..1]).is_none());
+    }
+}
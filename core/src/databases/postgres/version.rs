@@ -6,6 +6,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::databases::version::VersionTrait;
 
+/// The product name, e.g. `PostgreSQL`, `(PostgreSQL)` or `PostgreSQL-compatible`, followed
+/// by the version, e.g. `15.4`, `16beta1` or `11.2-YB-2.18.0.0`.
+const VERSION_PATTERN: &str =
+    r"(?i)(?:PostgreSQL|EnterpriseDB)\S*(?:\s+compatible\S*)?\s+v?(\d+)(?:\.(\d+))?";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostgreSQLVersion {
     pub major: u16,
@@ -22,12 +27,18 @@ impl VersionTrait for PostgreSQLVersion {
         Some(PostgreSQLVersion { major, minor })
     }
 
+    /// Reads the output of `SELECT version()` or `pg_dump --version`, including the banners of
+    /// distributions, forks and managed services. Beta and release candidate versions such as
+    /// `17beta2` have no minor version, read as 0.
     fn parse_string_version(version_string: &str) -> Option<Self> {
-        let pg_regex = Regex::new(r"PostgreSQL (\d+)\.(\d+)").ok()?;
+        let pg_regex = Regex::new(VERSION_PATTERN).ok()?;
         let captures = pg_regex.captures(version_string)?;
 
         let major = captures.get(1)?.as_str().parse::<u16>().ok()?;
-        let minor = captures.get(2)?.as_str().parse::<u16>().ok()?;
+        let minor = match captures.get(2) {
+            Some(minor) => minor.as_str().parse::<u16>().ok()?,
+            None => 0,
+        };
 
         Some(PostgreSQLVersion { major, minor })
     }
@@ -0,0 +1,126 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// Called with the cumulative number of bytes dumped or restored so far, once per chunk
+/// copied between the database tool and the backup stream.
+#[derive(Clone)]
+pub struct ProgressCallback(pub Arc<dyn Fn(u64) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Reports the bytes written through it, or only passes them through without a callback.
+pub struct ProgressWriter<W: Write + Send + Unpin> {
+    inner: W,
+    callback: Option<ProgressCallback>,
+    bytes: u64,
+}
+
+impl<W: Write + Send + Unpin> ProgressWriter<W> {
+    pub fn new(inner: W, callback: Option<ProgressCallback>) -> Self {
+        Self {
+            inner,
+            callback,
+            bytes: 0,
+        }
+    }
+
+    /// Bytes written so far, counted with or without a callback.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+
+        if let Some(callback) = &self.callback {
+            (callback.0)(self.bytes);
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reports the bytes read through it, or only passes them through without a callback.
+pub struct ProgressReader<R: Read + Send + Unpin> {
+    inner: R,
+    callback: Option<ProgressCallback>,
+    bytes: u64,
+}
+
+impl<R: Read + Send + Unpin> ProgressReader<R> {
+    pub fn new(inner: R, callback: Option<ProgressCallback>) -> Self {
+        Self {
+            inner,
+            callback,
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: Read + Send + Unpin> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if let Some(callback) = self.callback.as_ref().filter(|_| n > 0) {
+            self.bytes += n as u64;
+            (callback.0)(self.bytes);
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use std::{
+        io::{Read, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{ProgressCallback, ProgressReader, ProgressWriter};
+
+    #[test]
+    fn test_01_progress() {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let callback = {
+            let reported = reported.clone();
+            ProgressCallback::new(move |bytes| reported.lock().unwrap().push(bytes))
+        };
+
+        let mut writer = ProgressWriter::new(vec![], Some(callback.clone()));
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+
+        let mut reader = ProgressReader::new(b"hello world".as_slice(), Some(callback));
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported[..2], [6, 11]);
+        assert_eq!(reported.last(), Some(&11));
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::databases::{
+    diagnostics::Diagnostic,
+    progress::{ProgressReader, ProgressWriter},
+    version::{Version, VersionTrait},
+    BackupOptions, BackupScope, DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata,
+    DumpExclusions, RestoreOptions,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, SqliteConnection as Connection};
+
+use super::version::SqliteVersion;
+
+/// A SQLite database file. There is no server: host, port and credentials of the
+/// configuration are ignored and `DatabaseConfig.path` locates the file.
+pub struct SqliteConnection {
+    pub config: DatabaseConfig,
+    pub path: PathBuf,
+}
+
+impl SqliteConnection {
+    pub async fn new(config: DatabaseConfig) -> Result<Self> {
+        let path = config
+            .path
+            .as_ref()
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("A path is required for SQLite databases"))?;
+
+        Ok(Self { config, path })
+    }
+
+    async fn connect(&self, path: &Path) -> Result<Connection> {
+        SqliteConnectOptions::new()
+            .filename(path)
+            .connect()
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))
+    }
+
+    async fn check_integrity(&self, path: &Path) -> Result<()> {
+        let mut connection = self.connect(path).await?;
+
+        let result: String = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_one(&mut connection)
+            .await
+            .context("Failed to check database integrity")?;
+
+        if result != "ok" {
+            return Err(anyhow!(
+                "{} failed its integrity check: {}",
+                path.display(),
+                result
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DatabaseConnectionTrait for SqliteConnection {
+    async fn test(&self) -> Result<bool> {
+        // Restoring creates the file, so only its directory has to exist
+        if !self.path.exists() {
+            return match self.path.parent() {
+                Some(parent) if parent.as_os_str().is_empty() || parent.is_dir() => Ok(true),
+                _ => Err(anyhow!(
+                    "Connection test failed: {} does not exist",
+                    self.path.display()
+                )),
+            };
+        }
+
+        self.check_integrity(&self.path)
+            .await
+            .map(|_| true)
+            .map_err(|e| anyhow!("Connection test failed: {:#}", e))
+    }
+
+    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
+        let mut connection = SqliteConnectOptions::from_str("sqlite::memory:")?
+            .connect()
+            .await?;
+
+        let version_string: String = sqlx::query_scalar("SELECT sqlite_version()")
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+
+        let version = SqliteVersion::parse_string_version(&version_string)
+            .ok_or_else(|| anyhow!("Failed to parse SQLite version string"))?;
+
+        Ok(DatabaseMetadata {
+            version: Version::Sqlite(version),
+            encoding: None,
+        })
+    }
+
+    async fn get_client_version(&self) -> Result<Version> {
+        // The library that reads the file also writes the backup
+        Ok(self.get_metadata().await?.version)
+    }
+
+    async fn get_database_size(&self) -> Result<u64> {
+        let metadata = fs::metadata(&self.path)
+            .with_context(|| format!("Failed to get the size of {}", self.path.display()))?;
+
+        Ok(metadata.len())
+    }
+
+    async fn get_change_token(&self) -> Result<Option<String>> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Ok(Some(format!("{}-{}", metadata.len(), modified.as_nanos())))
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        Ok(vec![self.config.database.clone()])
+    }
+
+    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await?;
+
+        Ok(())
+    }
+
+    /// `VACUUM INTO` writes a consistent copy of the database to a temporary file, even while
+    /// other processes write to it, which is then streamed to `writer`.
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        if options.jobs.is_some_and(|jobs| jobs > 1)
+            || options.snapshot.is_some()
+            || options.directory_format
+            || options.exclusions != DumpExclusions::default()
+            || options.scope != BackupScope::All
+            || options.has_table_filters()
+            || options.physical
+        {
+            return Err(anyhow!(
+                "Parallel dumps, snapshots, directory formats, exclusions, scopes, table filters and base backups are not supported for SQLite"
+            ));
+        }
+
+        if !self.path.exists() {
+            return Err(anyhow!("{} does not exist", self.path.display()));
+        }
+
+        let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+        let snapshot_path = temp_dir.path().join("backup.sqlite");
+
+        let mut connection = self.connect(&self.path).await?;
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(snapshot_path.to_string_lossy().to_string())
+            .execute(&mut connection)
+            .await
+            .context("VACUUM INTO failed")?;
+
+        let mut snapshot = fs::File::open(&snapshot_path)?;
+        io::copy(
+            &mut snapshot,
+            &mut ProgressWriter::new(writer, options.progress),
+        )?;
+
+        Ok(vec![])
+    }
+
+    async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
+        self.restore_with_options(reader, RestoreOptions::default())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Writes the backup next to the database file, checks it, then renames it over the file.
+    /// The file is replaced as a whole, so `drop_database_first` changes nothing. Processes
+    /// holding the database open must be stopped first, since SQLite has no sessions to
+    /// terminate. With `validate_only` the checked copy is discarded instead. The rename makes
+    /// every restore all-or-nothing, so `single_transaction` changes nothing either.
+    async fn restore_with_options(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        if options.schema_only || options.client_encoding.is_some() {
+            return Err(anyhow!(
+                "Schema-only restores and client encodings are not supported for SQLite"
+            ));
+        }
+
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let mut temp_file = tempfile::NamedTempFile::new_in(&directory).with_context(|| {
+            format!(
+                "Failed to create a temporary file in {}",
+                directory.display()
+            )
+        })?;
+
+        io::copy(
+            &mut ProgressReader::new(reader, options.progress),
+            &mut temp_file,
+        )?;
+        temp_file.as_file().sync_all()?;
+
+        self.check_integrity(temp_file.path())
+            .await
+            .context("The backup is not a valid SQLite database")?;
+
+        if options.validate_only {
+            return Ok(vec![]);
+        }
+
+        // A journal left by the replaced database would be replayed into the restored one
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut journal = self.path.clone().into_os_string();
+            journal.push(suffix);
+
+            match fs::remove_file(&journal) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).context("Failed to remove the database journal"),
+            }
+        }
+
+        temp_file
+            .persist(&self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+
+        Ok(vec![])
+    }
+
+    async fn analyze(&self, _jobs: Option<usize>) -> Result<()> {
+        let mut connection = self.connect(&self.path).await?;
+
+        sqlx::query("ANALYZE")
+            .execute(&mut connection)
+            .await
+            .context("ANALYZE failed")?;
+
+        Ok(())
+    }
+
+    async fn run_check(&self, query: &str) -> Result<bool> {
+        let mut connection = self.connect(&self.path).await?;
+
+        sqlx::query_scalar::<_, bool>(query)
+            .fetch_one(&mut connection)
+            .await
+            .context(format!("Check query failed: {}", query))
+    }
+
+    async fn rename_databases(
+        &self,
+        _renames: &[(&str, &str)],
+        _termination_timeout: Option<Duration>,
+    ) -> Result<()> {
+        Err(anyhow!("SQLite databases are files and cannot be renamed"))
+    }
+
+    async fn drop_database(&self, _database: &str) -> Result<()> {
+        Err(anyhow!("SQLite databases are files and cannot be dropped"))
+    }
+}
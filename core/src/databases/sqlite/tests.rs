@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod sqlite_connection_tests {
+    use std::{io::Cursor, path::Path};
+
+    use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions};
+    use tempfile::tempdir;
+
+    use crate::databases::{
+        sqlite::connection::SqliteConnection, version::Version, ConnectionType, DatabaseConfig,
+        DatabaseConnectionTrait,
+    };
+
+    fn get_sqlite_config(path: &Path) -> DatabaseConfig {
+        DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::Sqlite,
+            host: "".into(),
+            port: 0,
+            database: "test".into(),
+            username: "".into(),
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: Some(path.to_string_lossy().to_string()),
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        }
+    }
+
+    async fn query_names(path: &Path) -> Vec<String> {
+        let mut connection = SqliteConnectOptions::new()
+            .filename(path)
+            .connect()
+            .await
+            .expect("Failed to open database");
+
+        sqlx::query_scalar("SELECT name FROM users ORDER BY id")
+            .fetch_all(&mut connection)
+            .await
+            .expect("Failed to query users")
+    }
+
+    #[tokio::test]
+    async fn test_01_sqlite_backup_restore() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("app.db");
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .expect("Failed to create database");
+
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create table");
+        sqlx::query("INSERT INTO users (name) VALUES ('alice'), ('bob')")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to insert rows");
+
+        let sqlite = SqliteConnection::new(get_sqlite_config(&path))
+            .await
+            .expect("Failed to get connection");
+
+        assert!(sqlite.test().await.expect("Failed to test connection"));
+
+        let metadata = sqlite.get_metadata().await.expect("Failed to get metadata");
+        assert!(matches!(metadata.version, Version::Sqlite(_)));
+
+        let mut backup = vec![];
+        sqlite
+            .backup(&mut backup)
+            .await
+            .expect("Failed to backup database");
+        assert!(backup.starts_with(b"SQLite format 3\0"));
+
+        sqlx::query("DELETE FROM users WHERE name = 'bob'")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to delete row");
+        drop(connection);
+
+        assert_eq!(query_names(&path).await, vec!["alice"]);
+
+        sqlite
+            .restore(&mut Cursor::new(backup))
+            .await
+            .expect("Failed to restore database");
+
+        assert_eq!(query_names(&path).await, vec!["alice", "bob"]);
+        assert!(sqlite
+            .run_check("SELECT count(*) = 2 FROM users")
+            .await
+            .expect("Failed to run check"));
+
+        let error = sqlite
+            .restore(&mut Cursor::new(b"not a database".to_vec()))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not a valid SQLite database"));
+        assert_eq!(query_names(&path).await, vec!["alice", "bob"]);
+    }
+}
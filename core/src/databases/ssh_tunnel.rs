@@ -1,6 +1,11 @@
 use std::{
-    io::{Read, Write},
+    ffi::OsStr,
+    fmt, fs,
+    io::{self, Read, Write},
     net::{Shutdown, TcpListener, TcpStream},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
@@ -11,9 +16,20 @@ use std::{
 };
 
 use anyhow::{anyhow, Context, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
 use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
-use ssh2::{ErrorCode, Session};
+use sha2::{Digest, Sha256};
+use ssh2::{CheckResult, ErrorCode, HostKeyType, KnownHostFileKind, Session};
+
+use super::postgres::pgpass::expand_home;
+use crate::redact::{redact_option, REDACTED};
+
+/// How long closing the SSH session may wait for the server.
+const DISCONNECT_TIMEOUT_MS: u32 = 1_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SshTunnelConfig {
@@ -21,6 +37,103 @@ pub struct SshTunnelConfig {
     pub port: u16,
     pub username: String,
     pub auth_method: SshAuthMethod,
+    /// Hosts to hop through, in order, before reaching `host`, like `ssh -J`.
+    #[serde(default)]
+    pub jump_hosts: Vec<SshJumpHost>,
+    /// File the host keys are checked against, `~/.ssh/known_hosts` by default. Jump hosts
+    /// are checked against it too.
+    #[serde(default)]
+    pub known_hosts: Option<String>,
+    #[serde(default)]
+    pub host_key_verification: HostKeyVerification,
+    /// Local ports the tunnel may listen on, for firewalls allowing only some. Any free port
+    /// when unset; jump hosts always use one.
+    #[serde(default)]
+    pub local_ports: Option<RangeInclusive<u16>>,
+}
+
+/// How the key an SSH server presents is checked against `known_hosts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostKeyVerification {
+    /// Only servers listed with the same key are trusted.
+    #[default]
+    Strict,
+    /// Servers seen for the first time are trusted and recorded, changed keys are refused.
+    AcceptNew,
+    /// Any key is trusted, leaving the connection open to interception.
+    None,
+}
+
+impl HostKeyVerification {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostKeyVerification::Strict => "strict",
+            HostKeyVerification::AcceptNew => "accept-new",
+            HostKeyVerification::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for HostKeyVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for HostKeyVerification {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(HostKeyVerification::Strict),
+            "accept-new" => Ok(HostKeyVerification::AcceptNew),
+            "none" => Ok(HostKeyVerification::None),
+            _ => Err(anyhow!("Unsupported host key verification: {}", s)),
+        }
+    }
+}
+
+/// A bastion the tunnel goes through, with its own credentials.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SshJumpHost {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth_method: SshAuthMethod,
+}
+
+impl SshTunnelConfig {
+    /// A copy with `***` in place of the passwords and key passphrases.
+    pub fn redacted(&self) -> Self {
+        Self {
+            auth_method: self.auth_method.redacted(),
+            jump_hosts: self
+                .jump_hosts
+                .iter()
+                .map(|jump_host| SshJumpHost {
+                    auth_method: jump_host.auth_method.redacted(),
+                    ..jump_host.clone()
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl SshJumpHost {
+    /// Logs in to this host with the host key checks of the tunnel going through it.
+    fn tunnel_config(&self, tunnel: &SshTunnelConfig) -> SshTunnelConfig {
+        SshTunnelConfig {
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            auth_method: self.auth_method.clone(),
+            jump_hosts: vec![],
+            known_hosts: tunnel.known_hosts.clone(),
+            host_key_verification: tunnel.host_key_verification,
+            local_ports: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,7 +142,8 @@ pub struct SshRemoteConfig {
     pub port: u16,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// `Debug` hides the password and the key passphrase.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum SshAuthMethod {
     Password {
         password: String,
@@ -38,18 +152,344 @@ pub enum SshAuthMethod {
         key_path: String,
         passphrase_key: Option<String>,
     },
+    /// Keys held by the ssh-agent listening on `SSH_AUTH_SOCK`, never read from disk.
+    Agent,
+}
+
+impl SshAuthMethod {
+    pub fn redacted(&self) -> Self {
+        match self {
+            SshAuthMethod::Password { .. } => SshAuthMethod::Password {
+                password: REDACTED.into(),
+            },
+            SshAuthMethod::PrivateKey {
+                key_path,
+                passphrase_key,
+            } => SshAuthMethod::PrivateKey {
+                key_path: key_path.clone(),
+                passphrase_key: passphrase_key.as_ref().map(|_| REDACTED.into()),
+            },
+            SshAuthMethod::Agent => SshAuthMethod::Agent,
+        }
+    }
+}
+
+impl fmt::Debug for SshAuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SshAuthMethod::Password { .. } => f
+                .debug_struct("Password")
+                .field("password", &REDACTED)
+                .finish(),
+            SshAuthMethod::PrivateKey {
+                key_path,
+                passphrase_key,
+            } => f
+                .debug_struct("PrivateKey")
+                .field("key_path", key_path)
+                .field("passphrase_key", &redact_option(passphrase_key))
+                .finish(),
+            SshAuthMethod::Agent => f.write_str("Agent"),
+        }
+    }
+}
+
+/// `SHA256:...`, as printed by `ssh-keygen -l`.
+pub fn get_fingerprint(key: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(key)))
+}
+
+fn get_key_type_name(key_type: HostKeyType) -> Option<&'static str> {
+    match key_type {
+        HostKeyType::Rsa => Some("ssh-rsa"),
+        HostKeyType::Dss => Some("ssh-dss"),
+        HostKeyType::Ecdsa256 => Some("ecdsa-sha2-nistp256"),
+        HostKeyType::Ecdsa384 => Some("ecdsa-sha2-nistp384"),
+        HostKeyType::Ecdsa521 => Some("ecdsa-sha2-nistp521"),
+        HostKeyType::Ed25519 => Some("ssh-ed25519"),
+        HostKeyType::Unknown => None,
+    }
+}
+
+fn get_known_hosts_path(config: &SshTunnelConfig) -> PathBuf {
+    match &config.known_hosts {
+        Some(path) => expand_home(path),
+        None => dirs::home_dir()
+            .unwrap_or_default()
+            .join(".ssh")
+            .join("known_hosts"),
+    }
+}
+
+fn verify_host_key(session: &Session, config: &SshTunnelConfig) -> Result<()> {
+    if config.host_key_verification == HostKeyVerification::None {
+        warn!("The host key of {} is not verified", config.host);
+        return Ok(());
+    }
+
+    let (key, key_type) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("{} sent no host key", config.host))?;
+
+    check_host_key(
+        session,
+        &get_known_hosts_path(config),
+        (&config.host, config.port),
+        (key, key_type),
+        config.host_key_verification,
+    )
+}
+
+/// Checks the key of `host` against the known_hosts file at `path`, appending it when `policy`
+/// accepts new hosts. Mismatches fail with the fingerprint of the key presented.
+pub(crate) fn check_host_key(
+    session: &Session,
+    path: &Path,
+    (host, port): (&str, u16),
+    (key, key_type): (&[u8], HostKeyType),
+    policy: HostKeyVerification,
+) -> Result<()> {
+    let fingerprint = get_fingerprint(key);
+    let mut known_hosts = session.known_hosts()?;
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+    };
+
+    // Lines libssh2 cannot parse, such as security key entries, are skipped instead of failing
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        if let Err(e) = known_hosts.read_str(line, KnownHostFileKind::OpenSSH) {
+            trace!("Skipping a line of {}: {}", path.display(), e);
+        }
+    }
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => {
+            trace!("Host key of {} verified: {}", host, fingerprint);
+            Ok(())
+        }
+        CheckResult::Mismatch => Err(anyhow!(
+            "The host key of {} does not match {}, it presented {}. The server was reinstalled or the connection is intercepted",
+            host,
+            path.display(),
+            fingerprint
+        )),
+        CheckResult::NotFound if policy == HostKeyVerification::AcceptNew => {
+            let key_type = get_key_type_name(key_type)
+                .ok_or_else(|| anyhow!("Unsupported host key type for {}", host))?;
+
+            let name = match port {
+                22 => host.to_string(),
+                port => format!("[{}]:{}", host, port),
+            };
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+
+            let separator = if content.is_empty() || content.ends_with('\n') {
+                ""
+            } else {
+                "\n"
+            };
+
+            writeln!(file, "{}{} {} {}", separator, name, key_type, STANDARD.encode(key))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+
+            info!("Added {} ({}) to {}", host, fingerprint, path.display());
+            Ok(())
+        }
+        CheckResult::NotFound => Err(anyhow!(
+            "{} is not in {}, it presented {}. Add it with ssh-keyscan or accept new hosts",
+            host,
+            path.display(),
+            fingerprint
+        )),
+        CheckResult::Failure => Err(anyhow!(
+            "Failed to check the host key of {} against {}",
+            host,
+            path.display()
+        )),
+    }
+}
+
+/// Binds the first free port of `ports`, or any free port. The listener is kept open and handed
+/// to the tunnel, so the port cannot be taken between its choice and its use.
+pub(crate) fn bind_local_port(ports: Option<&RangeInclusive<u16>>) -> Result<TcpListener> {
+    let ports = match ports {
+        Some(ports) => ports,
+        None => {
+            return TcpListener::bind("127.0.0.1:0")
+                .map_err(|e| anyhow!("Failed to find available port: {}", e))
+        }
+    };
+
+    for port in ports.clone() {
+        match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => trace!("Local port {} unavailable: {}", port, e),
+        }
+    }
+
+    Err(anyhow!(
+        "No free local port between {} and {}",
+        ports.start(),
+        ports.end()
+    ))
+}
+
+/// Fails early with a clear message when no agent runs, instead of libssh2's generic error.
+/// Windows agents such as Pageant are found without the variable.
+fn require_agent_socket(socket: Option<&OsStr>) -> Result<()> {
+    match socket {
+        Some(socket) if !socket.is_empty() => Ok(()),
+        _ if cfg!(windows) => Ok(()),
+        _ => Err(anyhow!(
+            "SSH agent authentication requires a running ssh-agent, but SSH_AUTH_SOCK is not set"
+        )),
+    }
+}
+
+/// Tries every key of the agent in turn, like `ssh` does.
+fn authenticate_with_agent(session: &Session, username: &str) -> Result<()> {
+    require_agent_socket(std::env::var_os("SSH_AUTH_SOCK").as_deref())?;
+
+    let mut agent = session.agent()?;
+    agent
+        .connect()
+        .context("Failed to connect to the ssh-agent")?;
+    agent
+        .list_identities()
+        .context("Failed to list the ssh-agent keys")?;
+
+    let identities = agent.identities()?;
+
+    if identities.is_empty() {
+        return Err(anyhow!("The ssh-agent holds no keys, add one with ssh-add"));
+    }
+
+    let accepted = identities.iter().any(|identity| {
+        let accepted = agent.userauth(username, identity).is_ok();
+        trace!(
+            "ssh-agent key {} accepted: {}",
+            identity.comment(),
+            accepted
+        );
+        accepted
+    });
+
+    agent.disconnect().ok();
+
+    if !accepted {
+        return Err(anyhow!(
+            "None of the {} ssh-agent keys were accepted for {}",
+            identities.len(),
+            username
+        ));
+    }
+
+    Ok(())
 }
 
 pub struct SshTunnel {
     pub local_port: u16,
     shutdown_signal: Arc<AtomicBool>,
     thread_handle: Option<JoinHandle<()>>,
+    /// Tunnels to each jump host, dropped after the tunnel going through them.
+    _jumps: JumpTunnels,
+}
+
+/// Closes the tunnels to jump hosts last to first, each one running through the previous.
+#[derive(Default)]
+struct JumpTunnels(Vec<SshTunnel>);
+
+impl Drop for JumpTunnels {
+    fn drop(&mut self) {
+        while let Some(jump) = self.0.pop() {
+            drop(jump);
+        }
+    }
 }
 
 impl SshTunnel {
+    /// Forwards a local port to `remote_config` through `ssh_config.host`, after hopping
+    /// through each of its jump hosts.
     pub fn new(ssh_config: SshTunnelConfig, remote_config: SshRemoteConfig) -> Result<Self> {
+        let (address, jumps) = Self::open_jumps(&ssh_config)?;
+        let mut tunnel = Self::open(ssh_config, address, remote_config)?;
+        tunnel._jumps = jumps;
+
+        Ok(tunnel)
+    }
+
+    /// Tunnels from each jump host to the SSH port of the next one, returning the address
+    /// reaching `ssh_config.host` through them.
+    fn open_jumps(ssh_config: &SshTunnelConfig) -> Result<(String, JumpTunnels)> {
+        let hops = &ssh_config.jump_hosts;
+        let mut jumps = JumpTunnels::default();
+
+        for (i, hop) in hops.iter().enumerate() {
+            let (next_host, next_port) = match hops.get(i + 1) {
+                Some(next) => (next.host.clone(), next.port),
+                None => (ssh_config.host.clone(), ssh_config.port),
+            };
+
+            let address = match jumps.0.last() {
+                Some(previous) => format!("127.0.0.1:{}", previous.local_port),
+                None => format!("{}:{}", hop.host, hop.port),
+            };
+
+            debug!(
+                "Jumping through {} to {}:{}",
+                hop.host, next_host, next_port
+            );
+
+            let jump = Self::open(
+                hop.tunnel_config(ssh_config),
+                address,
+                SshRemoteConfig {
+                    host: next_host,
+                    port: next_port,
+                },
+            )
+            .with_context(|| format!("Failed to jump through {}", hop.host))?;
+
+            jumps.0.push(jump);
+        }
+
+        let address = match jumps.0.last() {
+            Some(last) => format!("127.0.0.1:{}", last.local_port),
+            None => format!("{}:{}", ssh_config.host, ssh_config.port),
+        };
+
+        Ok((address, jumps))
+    }
+
+    /// Connects to the SSH server of `ssh_config` at `address`, which is a jump tunnel when
+    /// there are jump hosts, and forwards a local port to `remote_config`.
+    fn open(
+        ssh_config: SshTunnelConfig,
+        address: String,
+        remote_config: SshRemoteConfig,
+    ) -> Result<Self> {
         let shutdown_signal = Arc::new(AtomicBool::new(false));
-        let local_port = Self::find_available_port()?;
+        let listener = bind_local_port(ssh_config.local_ports.as_ref())?;
+        let local_port = listener
+            .local_addr()
+            .map_err(|e| anyhow!("Failed to get local address: {}", e))?
+            .port();
         let (setup_tx, setup_rx) = channel();
 
         let thread_handle = {
@@ -60,8 +500,9 @@ impl SshTunnel {
             thread::spawn(move || {
                 Self::run_tunnel(
                     ssh_config,
+                    address,
                     remote_config,
-                    local_port,
+                    listener,
                     setup_tx,
                     shutdown_signal,
                 );
@@ -69,38 +510,33 @@ impl SshTunnel {
         };
 
         let status = setup_rx.recv()?;
-        match status {
-            Ok(()) => {}
-            Err(e) => return Err(anyhow!("Failed to start ssh tunnel: {}", e.to_string())),
+        if let Err(e) = status {
+            // The local port is free again once the thread has dropped its listener
+            if thread_handle.join().is_err() {
+                warn!("SSH tunnel thread panicked");
+            }
+            return Err(anyhow!("Failed to start ssh tunnel: {}", e.to_string()));
         }
 
         Ok(Self {
             thread_handle: Some(thread_handle),
             shutdown_signal,
             local_port,
+            _jumps: JumpTunnels::default(),
         })
     }
 
-    fn find_available_port() -> Result<u16> {
-        let listener = TcpListener::bind("127.0.0.1:0")
-            .map_err(|e| anyhow!("Failed to find available port: {}", e))?;
-
-        let port = listener
-            .local_addr()
-            .map_err(|e| anyhow!("Failed to get local address: {}", e))?
-            .port();
-
-        Ok(port)
-    }
-
     fn run_tunnel(
         ssh_config: SshTunnelConfig,
+        address: String,
         remote_config: SshRemoteConfig,
-        local_port: u16,
+        listener: TcpListener,
         setup_tx: Sender<Result<()>>,
         shutdown_signal: Arc<AtomicBool>,
     ) {
-        let tcp = match TcpStream::connect(format!("{}:{}", ssh_config.host, ssh_config.port)) {
+        let local_port = listener.local_addr().map(|addr| addr.port()).unwrap_or(0);
+
+        let tcp = match TcpStream::connect(&address) {
             Ok(tcp) => tcp,
             Err(e) => {
                 shutdown_signal.store(true, Ordering::Relaxed);
@@ -140,6 +576,15 @@ impl SshTunnel {
 
         trace!("SSH handshake successful");
 
+        if let Err(e) = verify_host_key(&session, &ssh_config) {
+            shutdown_signal.store(true, Ordering::Relaxed);
+            if let Err(e) = setup_tx.send(Err(anyhow!("SSH host key verification failed: {:#}", e)))
+            {
+                warn!("Failed to send setup message: {}", e);
+            };
+            return;
+        };
+
         match &ssh_config.auth_method {
             SshAuthMethod::Password { password } => {
                 if let Err(e) = session.userauth_password(&ssh_config.username, &password) {
@@ -176,20 +621,18 @@ impl SshTunnel {
                     return;
                 };
             }
-        }
-
-        let listener = match TcpListener::bind(format!("127.0.0.1:{}", local_port)) {
-            Ok(listener) => listener,
-            Err(e) => {
-                shutdown_signal.store(true, Ordering::Relaxed);
-                if let Err(e) = setup_tx.send(Err(anyhow!("Failed to bind to local port: {}", e))) {
-                    warn!("Failed to send setup message: {}", e);
+            SshAuthMethod::Agent => {
+                if let Err(e) = authenticate_with_agent(&session, &ssh_config.username) {
+                    shutdown_signal.store(true, Ordering::Relaxed);
+                    if let Err(e) =
+                        setup_tx.send(Err(anyhow!("SSH agent authentication failed: {:#}", e)))
+                    {
+                        warn!("Failed to send setup message: {}", e);
+                    };
+                    return;
                 };
-                return;
             }
-        };
-
-        trace!("TCP listener created");
+        }
 
         if let Err(e) = listener.set_nonblocking(true) {
             shutdown_signal.store(true, Ordering::Relaxed);
@@ -236,6 +679,10 @@ impl SshTunnel {
                             info!("Local connection thread created: {}", thread_name);
 
                             loop {
+                                if shutdown_signal.load(Ordering::Relaxed) {
+                                    break;
+                                }
+
                                 match session_clone.channel_direct_tcpip(
                                     &remote_host_clone,
                                     remote_port_clone,
@@ -294,6 +741,16 @@ impl SshTunnel {
                 error!("Failed to join connection thread");
             }
         }
+
+        // The listener is closed when it goes out of scope, the session is ended explicitly so
+        // the server does not wait for a timeout to release it
+        session.set_blocking(true);
+        session.set_timeout(DISCONNECT_TIMEOUT_MS);
+        if let Err(e) = session.disconnect(None, "Tunnel closed", None) {
+            debug!("Failed to disconnect the SSH session: {}", e);
+        }
+
+        debug!("SSH tunnel on local port {} closed", local_port);
     }
 
     fn copy_loop(
@@ -393,6 +850,8 @@ impl SshTunnel {
     }
 }
 
+/// Stops accepting connections, closes the forwarded ones, the session and the local port, then
+/// the tunnels to the jump hosts. Nothing outlives the tunnel: it runs no `ssh` process.
 impl Drop for SshTunnel {
     fn drop(&mut self) {
         debug!("Dropping SSH tunnel");
@@ -411,12 +870,18 @@ impl Drop for SshTunnel {
 #[cfg(test)]
 mod ssh_tunnel_tests {
 
+    use base64::{engine::general_purpose::STANDARD, Engine};
     use dotenv::dotenv;
-    use std::env;
+    use ssh2::{HostKeyType, Session};
+    use std::{env, ffi::OsStr, fs, net::TcpListener};
 
     use crate::databases::{
         postgres::connection::PostgreSqlConnection,
-        ssh_tunnel::{SshAuthMethod, SshRemoteConfig, SshTunnel, SshTunnelConfig},
+        ssh_tunnel::{
+            bind_local_port, check_host_key, get_fingerprint, require_agent_socket,
+            HostKeyVerification, SshAuthMethod, SshJumpHost, SshRemoteConfig, SshTunnel,
+            SshTunnelConfig,
+        },
         ConnectionType, DatabaseConfig, DatabaseConnectionTrait,
     };
 
@@ -438,6 +903,10 @@ mod ssh_tunnel_tests {
                 key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                 passphrase_key: None,
             },
+            jump_hosts: vec![],
+            known_hosts: None,
+            host_key_verification: HostKeyVerification::Strict,
+            local_ports: None,
         };
 
         let remote_port: u16 = env::var("POSTGRESQL_PORT")
@@ -463,7 +932,17 @@ mod ssh_tunnel_tests {
             username: env::var("DB_USERNAME").unwrap_or_default(),
             database: env::var("DB_NAME").unwrap_or_default(),
             password: Some(password),
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         };
 
         let postgres_connection = PostgreSqlConnection::new(database_config)
@@ -477,4 +956,157 @@ mod ssh_tunnel_tests {
 
         assert!(is_connected);
     }
+
+    #[test]
+    fn test_02_jump_hosts_config() {
+        let config: SshTunnelConfig = serde_json::from_value(serde_json::json!({
+            "host": "db-gateway", "port": 22, "username": "ubuntu",
+            "auth_method": {"Password": {"password": "secret"}}
+        }))
+        .expect("Failed to parse config");
+        assert!(config.jump_hosts.is_empty());
+
+        let config: SshTunnelConfig = serde_json::from_value(serde_json::json!({
+            "host": "db-gateway", "port": 22, "username": "ubuntu",
+            "auth_method": {"Password": {"password": "secret"}},
+            "jump_hosts": [{"host": "bastion", "port": 2222, "username": "admin",
+                "auth_method": {"PrivateKey": {"key_path": "~/.ssh/bastion", "passphrase_key": null}}}]
+        }))
+        .expect("Failed to parse config");
+
+        assert_eq!(
+            config.jump_hosts,
+            vec![SshJumpHost {
+                host: "bastion".into(),
+                port: 2222,
+                username: "admin".into(),
+                auth_method: SshAuthMethod::PrivateKey {
+                    key_path: "~/.ssh/bastion".into(),
+                    passphrase_key: None,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_03_agent_auth() {
+        let config: SshTunnelConfig = serde_json::from_value(serde_json::json!({
+            "host": "db-gateway", "port": 22, "username": "ubuntu", "auth_method": "Agent"
+        }))
+        .expect("Failed to parse config");
+        assert_eq!(config.auth_method, SshAuthMethod::Agent);
+
+        assert!(require_agent_socket(Some(OsStr::new("/tmp/ssh-agent.sock"))).is_ok());
+
+        if cfg!(unix) {
+            let error = require_agent_socket(None).unwrap_err().to_string();
+            assert!(error.contains("SSH_AUTH_SOCK is not set"));
+        }
+    }
+
+    #[test]
+    fn test_04_check_host_key() {
+        let key = STANDARD
+            .decode("AAAAC3NzaC1lZDI1NTE5AAAAIDOwRdqI2MjB1HC+gLltgW0bElVMiGtKuRTwoNkt7yIR")
+            .unwrap();
+        let other_key = STANDARD
+            .decode("AAAAC3NzaC1lZDI1NTE5AAAAIIeFjF/CocTEEl1UxoC3n8awmtuBDLpHYc/ikL+d8x5N")
+            .unwrap();
+
+        let fingerprint = get_fingerprint(&key);
+        assert_eq!(
+            fingerprint,
+            "SHA256:Q/G/XMj6lnXxnZiqr7HuVKN7AMTMAlsSwwvNG2a4Dz0"
+        );
+
+        let session = Session::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ssh").join("known_hosts");
+
+        let check = |host: &str, port: u16, key: &[u8], policy: HostKeyVerification| {
+            check_host_key(
+                &session,
+                &path,
+                (host, port),
+                (key, HostKeyType::Ed25519),
+                policy,
+            )
+        };
+
+        let error = check("bastion", 22, &key, HostKeyVerification::Strict).unwrap_err();
+        assert!(error.to_string().contains(&fingerprint));
+        assert!(!path.exists());
+
+        check("bastion", 22, &key, HostKeyVerification::AcceptNew).expect("Failed to accept");
+        check("db-gateway", 2222, &key, HostKeyVerification::AcceptNew).expect("Failed to accept");
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("bastion ssh-ed25519 AAAAC3NzaC1lZDI1NTE5"));
+        assert!(content.contains("\n[db-gateway]:2222 ssh-ed25519 "));
+
+        check("bastion", 22, &key, HostKeyVerification::Strict).expect("Failed to verify");
+        check("db-gateway", 2222, &key, HostKeyVerification::Strict).expect("Failed to verify");
+
+        let error = check("bastion", 22, &other_key, HostKeyVerification::AcceptNew).unwrap_err();
+        assert!(error.to_string().contains(&get_fingerprint(&other_key)));
+        assert!(error.to_string().contains("does not match"));
+    }
+
+    #[test]
+    fn test_05_bind_local_port() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let error = bind_local_port(Some(&(port..=port))).unwrap_err();
+        assert!(error.to_string().contains("No free local port"));
+
+        let end = port.saturating_add(50);
+        let listener = bind_local_port(Some(&(port..=end))).expect("Failed to bind");
+        let chosen = listener.local_addr().unwrap().port();
+        assert!(chosen > port && chosen <= end);
+
+        assert_ne!(
+            bind_local_port(None).unwrap().local_addr().unwrap().port(),
+            0
+        );
+    }
+
+    #[ignore]
+    #[test]
+    fn test_06_tunnel_released_on_drop() {
+        dotenv().ok();
+
+        let ssh_config = SshTunnelConfig {
+            host: env::var("SSH_HOST").unwrap_or_default(),
+            username: env::var("SSH_USERNAME").unwrap_or_default(),
+            port: 22,
+            auth_method: SshAuthMethod::PrivateKey {
+                key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
+                passphrase_key: None,
+            },
+            jump_hosts: vec![],
+            known_hosts: None,
+            host_key_verification: HostKeyVerification::Strict,
+            local_ports: None,
+        };
+
+        let remote_port: u16 = env::var("POSTGRESQL_PORT")
+            .unwrap_or("0".into())
+            .parse()
+            .expect("Unable to parse remote port");
+
+        let ssh_remote_config = SshRemoteConfig {
+            host: "localhost".into(),
+            port: remote_port,
+        };
+
+        let tunnel =
+            SshTunnel::new(ssh_config, ssh_remote_config).expect("Failed to get ssh tunnel");
+        let local_port = tunnel.local_port;
+        assert!(TcpListener::bind(("127.0.0.1", local_port)).is_err());
+
+        drop(tunnel);
+
+        assert!(TcpListener::bind(("127.0.0.1", local_port)).is_ok());
+    }
 }
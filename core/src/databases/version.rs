@@ -1,11 +1,47 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
-use super::{mysql::version::MySqlVersion, postgres::version::PostgreSQLVersion};
+use super::{
+    mysql::version::MySqlVersion, postgres::version::PostgreSQLVersion,
+    sqlite::version::SqliteVersion,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Version {
     PostgreSQL(PostgreSQLVersion),
     MySql(MySqlVersion),
+    Sqlite(SqliteVersion),
+}
+
+impl Version {
+    pub fn major(&self) -> u16 {
+        match self {
+            Version::PostgreSQL(version) => version.major,
+            Version::MySql(version) => version.major,
+            Version::Sqlite(version) => version.major,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::PostgreSQL(version) => {
+                write!(f, "PostgreSQL {}.{}", version.major, version.minor)
+            }
+            Version::MySql(version) => write!(
+                f,
+                "MySQL {}.{}.{}",
+                version.major, version.minor, version.patch
+            ),
+            Version::Sqlite(version) => write!(
+                f,
+                "SQLite {}.{}.{}",
+                version.major, version.minor, version.patch
+            ),
+        }
+    }
 }
 
 pub trait VersionTrait: Sized + ToString {
@@ -0,0 +1,504 @@
+use std::io::{self, Read, Write};
+
+use anyhow::{anyhow, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::Sniffed;
+
+/// Written at the start of every encrypted backup.
+pub const MAGIC: &[u8; 7] = b"DBKPENC";
+const VERSION: u8 = 1;
+const ALGORITHM_AES_256_GCM: u8 = 1;
+const KDF_ARGON2ID: u8 = 1;
+/// A header asking for more memory is rejected rather than allocating it.
+const MAX_ARGON2_MEMORY: u32 = 1024 * 1024;
+const SALT_LEN: usize = 16;
+/// The rest of each nonce is the chunk counter and a flag marking the last chunk.
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 5;
+const HEADER_LEN: usize = MAGIC.len() + 3 + 12 + SALT_LEN + NONCE_PREFIX_LEN;
+/// Plaintext bytes sealed at once, only one chunk is held in memory.
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// The AES-256 key is derived from it with Argon2id and a random salt.
+    pub passphrase: String,
+    /// Cost of deriving the key of new backups. Backups store theirs, so changing it does not
+    /// break older ones.
+    #[serde(default)]
+    pub kdf_params: KdfParams,
+}
+
+/// Argon2id cost parameters, written to the header in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory in KiB.
+    pub memory: u32,
+    pub passes: u32,
+    pub lanes: u32,
+}
+
+impl Default for KdfParams {
+    /// The OWASP recommendation for Argon2id.
+    fn default() -> Self {
+        Self {
+            memory: 19 * 1024,
+            passes: 2,
+            lanes: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    fn to_bytes(self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[..4].copy_from_slice(&self.memory.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.passes.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.lanes.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let params = Self {
+            memory: u32::from_be_bytes(bytes[..4].try_into()?),
+            passes: u32::from_be_bytes(bytes[4..8].try_into()?),
+            lanes: u32::from_be_bytes(bytes[8..12].try_into()?),
+        };
+
+        if params.memory > MAX_ARGON2_MEMORY {
+            return Err(anyhow!(
+                "The backup asks for {} KiB of memory to derive its key, more than the {} KiB allowed",
+                params.memory,
+                MAX_ARGON2_MEMORY
+            ));
+        }
+
+        Ok(params)
+    }
+}
+
+impl EncryptionConfig {
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            kdf_params: KdfParams::default(),
+        }
+    }
+
+    pub fn with_kdf_params(mut self, kdf_params: KdfParams) -> Self {
+        self.kdf_params = kdf_params;
+        self
+    }
+
+    fn derive_key(&self, salt: &[u8], params: KdfParams) -> Result<LessSafeKey> {
+        let params = Params::new(params.memory, params.passes, params.lanes, Some(32))
+            .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow!("Failed to derive the encryption key: {}", e))?;
+
+        let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("Invalid key"))?;
+
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+/// Whether `header`, the first bytes of a stored backup, starts an encrypted stream.
+pub fn is_encrypted(header: &[u8]) -> bool {
+    header.starts_with(MAGIC)
+}
+
+/// Seals chunks of plaintext with a counter in their nonce, so chunks cannot be reordered,
+/// and flags the last one, so a truncated stream does not decrypt.
+struct Sealer {
+    key: LessSafeKey,
+    header: [u8; HEADER_LEN],
+    counter: u32,
+}
+
+impl Sealer {
+    fn nonce(&self, last: bool) -> Nonce {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.header[HEADER_LEN - NONCE_PREFIX_LEN..]);
+        nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[NONCE_LEN - 1] = last as u8;
+
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    fn seal(&mut self, chunk: &mut Vec<u8>, last: bool) -> io::Result<()> {
+        self.key
+            .seal_in_place_append_tag(self.nonce(last), Aad::from(&self.header), chunk)
+            .map_err(|_| io::Error::other("Failed to encrypt the backup"))?;
+        self.advance()
+    }
+
+    fn open(&mut self, chunk: &mut Vec<u8>, last: bool) -> io::Result<()> {
+        let length = self
+            .key
+            .open_in_place(self.nonce(last), Aad::from(&self.header), chunk)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Failed to decrypt the backup, the passphrase is wrong or the backup is corrupted",
+                )
+            })?
+            .len();
+        chunk.truncate(length);
+        self.advance()
+    }
+
+    fn advance(&mut self) -> io::Result<()> {
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("The backup has too many chunks"))?;
+
+        Ok(())
+    }
+}
+
+/// Encrypts bytes written through it with AES-256-GCM, or passes them through when there is
+/// no configuration. `finish` must be called to write the last chunk.
+pub struct EncryptingWriter<W: Write + Send + Unpin> {
+    inner: W,
+    sealer: Option<Sealer>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write + Send + Unpin> EncryptingWriter<W> {
+    pub fn new(mut inner: W, config: Option<&EncryptionConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self {
+                inner,
+                sealer: None,
+                buffer: vec![],
+            });
+        };
+
+        let random = SystemRandom::new();
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        random
+            .fill(&mut salt)
+            .and_then(|_| random.fill(&mut nonce_prefix))
+            .map_err(|_| anyhow!("Failed to generate a salt"))?;
+
+        let mut header = [0u8; HEADER_LEN];
+        let mut offset = 0;
+        for part in [
+            MAGIC.as_slice(),
+            &[VERSION, ALGORITHM_AES_256_GCM, KDF_ARGON2ID],
+            &config.kdf_params.to_bytes(),
+            &salt,
+            &nonce_prefix,
+        ] {
+            header[offset..offset + part.len()].copy_from_slice(part);
+            offset += part.len();
+        }
+
+        let key = config.derive_key(&salt, config.kdf_params)?;
+
+        inner
+            .write_all(&header)
+            .context("Failed to write the encryption header")?;
+
+        Ok(Self {
+            inner,
+            sealer: Some(Sealer {
+                key,
+                header,
+                counter: 0,
+            }),
+            buffer: Vec::with_capacity(CHUNK_SIZE + TAG_LEN),
+        })
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        if let Some(sealer) = &mut self.sealer {
+            sealer.seal(&mut self.buffer, last)?;
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last chunk and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sealer.is_none() {
+            return self.inner.write(buf);
+        }
+
+        // A full chunk is only sealed once more data arrives, as it may be the last one
+        if self.buffer.len() == CHUNK_SIZE {
+            self.write_chunk(false)?;
+        }
+
+        let n = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a backup written by `EncryptingWriter`, or reads it as it is when it does not
+/// start with the encryption header.
+pub struct DecryptingReader<R: Read + Send + Unpin> {
+    inner: Sniffed<R>,
+    sealer: Option<Sealer>,
+    /// Bytes of the next sealed chunk already read.
+    pending: Vec<u8>,
+    plaintext: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read + Send + Unpin> DecryptingReader<R> {
+    /// Decrypts the first chunk right away, so a wrong passphrase fails here rather than
+    /// after the restore started.
+    pub fn new(mut inner: R, config: Option<&EncryptionConfig>) -> Result<Self> {
+        let mut header = vec![];
+        (&mut inner)
+            .take(HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        if !is_encrypted(&header) {
+            return Ok(Self {
+                inner: io::Cursor::new(header).chain(inner),
+                sealer: None,
+                pending: vec![],
+                plaintext: vec![],
+                position: 0,
+                finished: false,
+            });
+        }
+
+        let config =
+            config.ok_or_else(|| anyhow!("The backup is encrypted, a passphrase is required"))?;
+
+        if header.len() < HEADER_LEN {
+            return Err(anyhow!("The encryption header is truncated"));
+        }
+
+        let version = header[MAGIC.len()];
+        let algorithm = header[MAGIC.len() + 1];
+        let kdf = header[MAGIC.len() + 2];
+        if version != VERSION || algorithm != ALGORITHM_AES_256_GCM || kdf != KDF_ARGON2ID {
+            return Err(anyhow!(
+                "Unsupported encryption version {}, algorithm {} or key derivation {}",
+                version,
+                algorithm,
+                kdf
+            ));
+        }
+
+        let offset = MAGIC.len() + 3;
+        let params = KdfParams::from_bytes(&header[offset..offset + 12])?;
+        let salt = &header[offset + 12..offset + 12 + SALT_LEN];
+
+        let mut reader = Self {
+            inner: io::Cursor::new(vec![]).chain(inner),
+            sealer: Some(Sealer {
+                key: config.derive_key(salt, params)?,
+                header: header.as_slice().try_into()?,
+                counter: 0,
+            }),
+            pending: vec![],
+            plaintext: vec![],
+            position: 0,
+            finished: false,
+        };
+        reader.next_chunk()?;
+
+        Ok(reader)
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let Some(sealer) = &mut self.sealer else {
+            return Ok(());
+        };
+
+        // One byte past a full chunk tells whether it is the last one
+        let wanted = CHUNK_SIZE + TAG_LEN + 1;
+        (&mut self.inner)
+            .take((wanted - self.pending.len()) as u64)
+            .read_to_end(&mut self.pending)?;
+
+        let last = self.pending.len() < wanted;
+        let next = match last {
+            true => vec![],
+            false => self.pending.split_off(CHUNK_SIZE + TAG_LEN),
+        };
+
+        let mut chunk = std::mem::replace(&mut self.pending, next);
+        sealer.open(&mut chunk, last)?;
+
+        self.plaintext = chunk;
+        self.position = 0;
+        self.finished = last;
+
+        Ok(())
+    }
+
+    /// Returns the inner reader, positioned after the encrypted stream when it was read to
+    /// the end.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().1
+    }
+}
+
+impl<R: Read + Send + Unpin> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.sealer.is_none() {
+            return self.inner.read(buf);
+        }
+
+        while self.position == self.plaintext.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            self.next_chunk()?;
+        }
+
+        let n = buf.len().min(self.plaintext.len() - self.position);
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod encryption_tests {
+    use std::io::{Read, Write};
+
+    use super::{
+        is_encrypted, DecryptingReader, EncryptingWriter, EncryptionConfig, KdfParams, CHUNK_SIZE,
+        MAGIC,
+    };
+
+    /// Cheap parameters keeping the tests fast, the defaults are covered by test_04.
+    const TEST_KDF_PARAMS: KdfParams = KdfParams {
+        memory: 64,
+        passes: 1,
+        lanes: 1,
+    };
+
+    fn get_config(passphrase: &str) -> EncryptionConfig {
+        EncryptionConfig::new(passphrase).with_kdf_params(TEST_KDF_PARAMS)
+    }
+
+    fn encrypt(data: &[u8], config: &EncryptionConfig) -> Vec<u8> {
+        let mut writer = EncryptingWriter::new(vec![], Some(config)).expect("Failed to encrypt");
+        writer.write_all(data).expect("Failed to write");
+        writer.finish().expect("Failed to finish")
+    }
+
+    fn decrypt(data: &[u8], config: Option<&EncryptionConfig>) -> anyhow::Result<Vec<u8>> {
+        let mut content = vec![];
+        DecryptingReader::new(data, config)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn test_01_encryption_round_trip() {
+        let config = get_config("correct horse battery staple");
+
+        // Empty, partial and exactly full last chunks
+        for size in [0, 1000, CHUNK_SIZE, 2 * CHUNK_SIZE + 17] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let encrypted = encrypt(&data, &config);
+
+            assert!(is_encrypted(&encrypted));
+            assert_eq!(decrypt(&encrypted, Some(&config)).unwrap(), data);
+        }
+
+        let plain = b"-- PostgreSQL database dump".to_vec();
+        assert_eq!(decrypt(&plain, None).unwrap(), plain);
+        assert_eq!(decrypt(&plain, Some(&config)).unwrap(), plain);
+    }
+
+    #[test]
+    fn test_02_decryption_failures() {
+        let config = get_config("correct horse battery staple");
+        let data = vec![42u8; CHUNK_SIZE + 100];
+        let encrypted = encrypt(&data, &config);
+
+        let error = decrypt(&encrypted, None).unwrap_err();
+        assert!(error.to_string().contains("passphrase is required"));
+
+        let error = decrypt(&encrypted, Some(&get_config("wrong"))).unwrap_err();
+        assert!(error.to_string().contains("passphrase is wrong"));
+
+        // Dropping the last chunk must not go unnoticed
+        let truncated = &encrypted[..encrypted.len() - 116];
+        assert!(decrypt(truncated, Some(&config)).is_err());
+
+        let mut tampered = encrypted.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypt(&tampered, Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_03_key_derivation_header() {
+        let config = get_config("correct horse battery staple");
+        let encrypted = encrypt(b"SELECT 1;", &config);
+
+        let mut unknown_kdf = encrypted.clone();
+        unknown_kdf[MAGIC.len() + 2] = 2;
+        let error = decrypt(&unknown_kdf, Some(&config)).unwrap_err();
+        assert!(error.to_string().contains("key derivation 2"));
+
+        // A hostile header must not make the restore allocate unbounded memory
+        let mut huge_memory = encrypted.clone();
+        huge_memory[MAGIC.len() + 3..MAGIC.len() + 7].copy_from_slice(&u32::MAX.to_be_bytes());
+        let error = decrypt(&huge_memory, Some(&config)).unwrap_err();
+        assert!(error.to_string().contains("KiB of memory"));
+    }
+
+    #[test]
+    fn test_04_default_kdf_params() {
+        let config = EncryptionConfig::new("correct horse battery staple");
+        let data = b"SELECT 1;".to_vec();
+        let encrypted = encrypt(&data, &config);
+
+        let offset = MAGIC.len() + 3;
+        assert_eq!(
+            &encrypted[offset..offset + 12],
+            KdfParams::default().to_bytes().as_slice()
+        );
+        assert_eq!(decrypt(&encrypted, Some(&config)).unwrap(), data);
+
+        // Decryption follows the header, whatever the configured parameters
+        assert_eq!(
+            decrypt(
+                &encrypted,
+                Some(&get_config("correct horse battery staple"))
+            )
+            .unwrap(),
+            data
+        );
+    }
+}
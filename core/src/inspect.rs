@@ -0,0 +1,513 @@
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::databases::{
+    command_runner::CommandRunner,
+    postgres::{utilities::PostgreSqlUtilities, version::PostgreSQLVersion},
+    version::VersionTrait,
+    UtilitiesTrait,
+};
+
+const CUSTOM_MAGIC: &[u8] = b"PGDMP";
+const CUSTOM_FORMAT: u8 = 1;
+const HEADER_SIZE: usize = 64 * 1024;
+const PLAIN_HEADER: &str = "-- PostgreSQL database dump";
+const PLAIN_FOOTER: &str = "-- PostgreSQL database dump complete";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpContent {
+    pub has_schema: bool,
+    pub has_data: bool,
+}
+
+impl DumpContent {
+    /// Returns a warning for each expectation the dump does not meet.
+    pub fn check(&self, expect_schema: bool, expect_data: bool) -> Vec<String> {
+        let mut warnings = vec![];
+
+        if expect_schema && !self.has_schema {
+            warnings.push("Backup contains no schema (CREATE) statements".into());
+        }
+
+        if expect_data && !self.has_data {
+            warnings.push(
+                "Backup contains no data (COPY/INSERT) statements, it may be schema-only".into(),
+            );
+        }
+
+        warnings
+    }
+}
+
+/// Scans a plain SQL dump for schema and data statements, stopping as soon as both are found.
+///
+/// Dumps usually list the schema before the data, so a complete dump is only read up to its
+/// first data statement while a schema-only dump is read to the end.
+pub fn inspect_dump<R: Read>(reader: R) -> io::Result<DumpContent> {
+    let mut reader = BufReader::new(reader);
+    let mut content = DumpContent::default();
+    let mut line = vec![];
+    let mut in_copy = false;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        // Rows of a COPY block are raw data and must not be mistaken for statements
+        if in_copy {
+            in_copy = line.as_slice() != b"\\.\n" && line.as_slice() != b"\\.";
+            continue;
+        }
+
+        if line.starts_with(b"CREATE ") || line.starts_with(b"ALTER TABLE ") {
+            content.has_schema = true;
+        } else if line.starts_with(b"INSERT INTO ") {
+            content.has_data = true;
+        } else if line.starts_with(b"COPY ") {
+            in_copy = true;
+            content.has_data = true;
+        }
+
+        if content.has_schema && content.has_data {
+            break;
+        }
+    }
+
+    Ok(content)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DumpFormat {
+    Plain,
+    Custom,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DumpValidation {
+    pub format: DumpFormat,
+    /// Plain dumps end with pg_dump's completion footer, custom archives have a readable TOC.
+    pub complete: bool,
+    pub pg_dump_version: Option<String>,
+    pub toc_entries: Option<usize>,
+    pub issues: Vec<String>,
+}
+
+impl DumpValidation {
+    pub fn is_valid(&self) -> bool {
+        self.complete && self.issues.is_empty()
+    }
+}
+
+/// Header of a custom-format archive, as written by `WriteHead` in pg_dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveHeader {
+    pub version: (u8, u8, u8),
+    pub format: u8,
+    pub database: Option<String>,
+    pub server_version: Option<String>,
+    pub pg_dump_version: Option<String>,
+}
+
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    int_size: usize,
+}
+
+impl HeaderReader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| anyhow!("Archive header is truncated"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        let negative = self.byte()? != 0;
+        let mut value: i64 = 0;
+
+        for shift in 0..self.int_size {
+            value |= (self.byte()? as i64) << (shift * 8);
+        }
+
+        Ok(if negative { -value } else { value })
+    }
+
+    fn string(&mut self) -> Result<Option<String>> {
+        let len = self.int()?;
+
+        if len < 0 {
+            return Ok(None);
+        }
+
+        let end = self.position + len as usize;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| anyhow!("Archive header is truncated"))?;
+        self.position = end;
+
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+pub fn parse_archive_header(bytes: &[u8]) -> Result<ArchiveHeader> {
+    if !bytes.starts_with(CUSTOM_MAGIC) {
+        return Err(anyhow!("Missing PGDMP signature"));
+    }
+
+    let mut reader = HeaderReader {
+        bytes,
+        position: CUSTOM_MAGIC.len(),
+        int_size: 4,
+    };
+
+    let major = reader.byte()?;
+    let minor = reader.byte()?;
+    let revision = if (major, minor) > (1, 0) {
+        reader.byte()?
+    } else {
+        0
+    };
+    let version = (major, minor, revision);
+
+    reader.int_size = reader.byte()? as usize;
+
+    if version >= (1, 7, 0) {
+        // Offset size, only needed to read data positions
+        reader.byte()?;
+    }
+
+    let format = reader.byte()?;
+
+    if version >= (1, 15, 0) {
+        reader.byte()?;
+    } else if version >= (1, 4, 0) {
+        reader.int()?;
+    } else if version >= (1, 2, 0) {
+        reader.byte()?;
+    }
+
+    let mut header = ArchiveHeader {
+        version,
+        format,
+        database: None,
+        server_version: None,
+        pg_dump_version: None,
+    };
+
+    if version >= (1, 4, 0) {
+        // Creation date as a struct tm
+        for _ in 0..7 {
+            reader.int()?;
+        }
+
+        header.database = reader.string()?;
+    }
+
+    if version >= (1, 10, 0) {
+        header.server_version = reader.string()?;
+        header.pg_dump_version = reader.string()?;
+    }
+
+    Ok(header)
+}
+
+/// Checks a plain SQL dump for pg_dump's header and footer, and for a truncated tail.
+pub fn validate_plain_dump<R: Read>(reader: R) -> io::Result<DumpValidation> {
+    let mut reader = BufReader::new(reader);
+    let mut validation = DumpValidation {
+        format: DumpFormat::Plain,
+        complete: false,
+        pg_dump_version: None,
+        toc_entries: None,
+        issues: vec![],
+    };
+
+    let mut line = vec![];
+    let mut line_count = 0;
+    let mut has_header = false;
+    let mut in_copy = false;
+    let mut last_statement_line: Option<String> = None;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        line_count += 1;
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+
+        if in_copy {
+            in_copy = text != "\\.";
+            continue;
+        }
+
+        if text.starts_with("--") {
+            if line_count <= 10 && text == PLAIN_HEADER {
+                has_header = true;
+            } else if text == PLAIN_FOOTER {
+                validation.complete = true;
+            } else if let Some(version) = text.strip_prefix("-- Dumped by pg_dump version") {
+                validation.pg_dump_version = Some(version.trim().into());
+            }
+            continue;
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        // Anything after the footer means it was not the end of the dump
+        validation.complete = false;
+
+        if text.starts_with("COPY ") && text.ends_with("FROM stdin;") {
+            in_copy = true;
+        }
+
+        last_statement_line = Some(text.to_string());
+    }
+
+    if line_count == 0 {
+        validation.format = DumpFormat::Unknown;
+        validation.issues.push("Dump is empty".into());
+        return Ok(validation);
+    }
+
+    if !has_header {
+        validation
+            .issues
+            .push("Missing pg_dump header, this may not be a PostgreSQL plain dump".into());
+    }
+
+    if in_copy {
+        validation
+            .issues
+            .push("Dump ends inside a COPY block, it is truncated".into());
+    } else if let Some(last) = last_statement_line {
+        if !last.ends_with(';') && !last.starts_with('\\') {
+            validation
+                .issues
+                .push("Dump ends in the middle of a statement, it is truncated".into());
+        }
+    }
+
+    Ok(validation)
+}
+
+async fn validate_custom_archive(
+    header_bytes: Vec<u8>,
+    reader: Box<dyn Read + Send + Unpin>,
+) -> Result<DumpValidation> {
+    let mut validation = DumpValidation {
+        format: DumpFormat::Custom,
+        complete: false,
+        pg_dump_version: None,
+        toc_entries: None,
+        issues: vec![],
+    };
+
+    let header = match parse_archive_header(&header_bytes) {
+        Ok(header) => header,
+        Err(e) => {
+            validation
+                .issues
+                .push(format!("Invalid archive header: {}", e));
+            return Ok(validation);
+        }
+    };
+
+    validation.pg_dump_version = header.pg_dump_version.clone();
+
+    if header.format != CUSTOM_FORMAT {
+        validation.issues.push(format!(
+            "Archive format {} is not supported, only custom-format files can be validated",
+            header.format
+        ));
+        return Ok(validation);
+    }
+
+    // pg_restore must be at least as recent as the pg_dump that wrote the archive
+    let Some(version) = header
+        .pg_dump_version
+        .as_deref()
+        .and_then(|version| version.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|major| PostgreSQLVersion::from_str(&format!("{}.0", major)))
+    else {
+        validation
+            .issues
+            .push("Unknown pg_dump version, cannot select a pg_restore to read the TOC".into());
+        return Ok(validation);
+    };
+
+    let mut cmd = PostgreSqlUtilities::new(version)
+        .get_command("pg_restore")
+        .await?;
+    cmd.arg("--list");
+
+    let mut reader = Cursor::new(header_bytes).chain(reader);
+
+    match CommandRunner::new().pipe_from(cmd, &mut reader).await {
+        Ok(output) => {
+            validation.complete = true;
+            validation.toc_entries = Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with(';'))
+                    .count(),
+            );
+        }
+        Err(e) => validation
+            .issues
+            .push(format!("pg_restore could not read the TOC: {}", e)),
+    }
+
+    Ok(validation)
+}
+
+/// Validates an uncompressed dump without any database connection.
+///
+/// Custom-format archives have their header parsed and their TOC listed with the matching
+/// `pg_restore --list`, which does not read the data blocks. Plain dumps are scanned to the end
+/// for pg_dump's header and completion footer and for a truncated COPY block or statement.
+pub async fn validate_dump(reader: Box<dyn Read + Send + Unpin>) -> Result<DumpValidation> {
+    let (header_bytes, reader) = tokio::task::spawn_blocking(move || -> io::Result<_> {
+        let mut header_bytes = vec![];
+        let mut reader = reader;
+        (&mut reader)
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut header_bytes)?;
+        Ok((header_bytes, reader))
+    })
+    .await??;
+
+    if header_bytes.starts_with(CUSTOM_MAGIC) {
+        return validate_custom_archive(header_bytes, reader).await;
+    }
+
+    let validation = tokio::task::spawn_blocking(move || {
+        validate_plain_dump(Cursor::new(header_bytes).chain(reader))
+    })
+    .await??;
+
+    Ok(validation)
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use std::io::Cursor;
+
+    use super::{inspect_dump, parse_archive_header, validate_plain_dump, DumpContent, DumpFormat};
+
+    #[test]
+    fn test_01_inspect_full_dump() {
+        let dump = "SET client_encoding = 'UTF8';\n\
+            CREATE TABLE public.users (id integer);\n\
+            COPY public.users (id) FROM stdin;\n1\n2\n\\.\n";
+
+        let content = inspect_dump(Cursor::new(dump)).expect("Failed to inspect dump");
+
+        assert_eq!(
+            content,
+            DumpContent {
+                has_schema: true,
+                has_data: true
+            }
+        );
+        assert!(content.check(true, true).is_empty());
+    }
+
+    #[test]
+    fn test_02_inspect_schema_only_dump() {
+        let dump = "CREATE TABLE public.users (id integer);\n\
+            ALTER TABLE ONLY public.users ADD CONSTRAINT users_pkey PRIMARY KEY (id);\n";
+
+        let content = inspect_dump(Cursor::new(dump)).expect("Failed to inspect dump");
+
+        assert!(content.has_schema);
+        assert!(!content.has_data);
+        assert_eq!(content.check(false, true).len(), 1);
+    }
+
+    #[test]
+    fn test_03_copy_rows_are_not_statements() {
+        let dump = "COPY public.notes (body) FROM stdin;\nCREATE TABLE in a row\n\\.\n";
+
+        let content = inspect_dump(Cursor::new(dump)).expect("Failed to inspect dump");
+
+        assert!(content.has_data);
+        assert!(!content.has_schema);
+        assert_eq!(content.check(true, false).len(), 1);
+    }
+
+    #[test]
+    fn test_04_validate_plain_dump() {
+        let dump = "--\n-- PostgreSQL database dump\n--\n\n\
+            -- Dumped by pg_dump version 16.2\n\n\
+            CREATE TABLE public.users (id integer);\n\
+            COPY public.users (id) FROM stdin;\n1\n\\.\n\n\
+            --\n-- PostgreSQL database dump complete\n--\n\n";
+
+        let validation = validate_plain_dump(Cursor::new(dump)).expect("Failed to validate dump");
+
+        assert_eq!(validation.format, DumpFormat::Plain);
+        assert_eq!(validation.pg_dump_version, Some("16.2".into()));
+        assert!(validation.is_valid());
+
+        let truncated = &dump[..dump.find("1\n").unwrap() + 2];
+        let validation =
+            validate_plain_dump(Cursor::new(truncated)).expect("Failed to validate dump");
+
+        assert!(!validation.complete);
+        assert_eq!(validation.issues.len(), 1);
+
+        let truncated = "--\n-- PostgreSQL database dump\n--\nCREATE TABLE public.users (\n";
+        let validation =
+            validate_plain_dump(Cursor::new(truncated)).expect("Failed to validate dump");
+
+        assert!(validation.issues[0].contains("middle of a statement"));
+    }
+
+    #[test]
+    fn test_05_parse_archive_header() {
+        fn int(value: u8) -> Vec<u8> {
+            vec![0, value, 0, 0, 0]
+        }
+
+        fn string(value: &str) -> Vec<u8> {
+            let mut bytes = int(value.len() as u8);
+            bytes.extend_from_slice(value.as_bytes());
+            bytes
+        }
+
+        let mut header = b"PGDMP".to_vec();
+        header.extend_from_slice(&[1, 15, 0, 4, 8, 1, 0]);
+        for _ in 0..7 {
+            header.extend(int(1));
+        }
+        header.extend(string("app"));
+        header.extend(string("16.2"));
+        header.extend(string("16.3"));
+
+        let header = parse_archive_header(&header).expect("Failed to parse header");
+
+        assert_eq!(header.version, (1, 15, 0));
+        assert_eq!(header.format, 1);
+        assert_eq!(header.database, Some("app".into()));
+        assert_eq!(header.server_version, Some("16.2".into()));
+        assert_eq!(header.pg_dump_version, Some("16.3".into()));
+
+        assert!(parse_archive_header(b"PGDMP\x01\x0e").is_err());
+    }
+}
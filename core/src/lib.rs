@@ -1,27 +1,120 @@
-use anyhow::{anyhow, Result};
-use common::get_default_backup_name;
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use audit::{new_correlation_id, AuditLog, AuditOperation, AuditOutcome, AuditRecord};
+use checksum::{ChecksumAlgorithm, HashingWriter};
+use chrono::Utc;
+use chrono_tz::Tz;
+use common::{get_backup_name_pattern, get_default_backup_name};
 use compression::{CompressionFormat, Compressor, Decompressor};
-use databases::DatabaseConnection;
+use databases::{
+    diagnostics::Diagnostic,
+    postgres::{
+        connection::get_version_warning,
+        encoding::{encoding_warnings, parse_client_encoding},
+    },
+    progress::{ProgressCallback, ProgressWriter},
+    version::Version,
+    BackupScope, ConnectionType, DatabaseConnection, DumpExclusions, MessageLevel,
+};
+use encryption::{DecryptingReader, EncryptingWriter, EncryptionConfig};
 use flate2::Compression;
+use futures::{stream, StreamExt};
+use inspect::{inspect_dump, DumpContent};
+use log::{info, warn};
+use manifest::BackupManifest;
+use masking::{MaskRule, MaskingWriter};
+use physical::get_base_backup_name;
+use promote::{get_promotion_name, PromoteOptions, PromoteReport};
 use serde::{Deserialize, Serialize};
-use storage::provider::{ListOptions, StorageProvider};
+use storage::{
+    fanout::{FanoutReport, FanoutWriter, RequirePolicy},
+    provider::{CleanupOptions, ListOptions, RetentionPolicy, StorageProvider},
+};
 
 use crate::storage::Entry;
+use throttle::{ThrottledReader, ThrottledWriter};
+use verify::{check_integrity, get_verify_concurrency, VerifyAllReport, VerifyLevel, VerifyReport};
 
 pub mod archives;
+pub mod audit;
+pub mod catalog;
+pub mod checksum;
 pub mod common;
 pub mod compression;
 pub mod databases;
+pub mod encryption;
 pub mod folders;
+pub mod inspect;
+pub mod logging;
+pub mod manifest;
+pub mod masking;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod physical;
+pub mod promote;
+pub mod redact;
+pub mod scheduler;
 pub mod storage;
+pub mod tee;
 mod test_utils;
 mod tests;
+pub mod throttle;
+pub mod verify;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct BackupOptions {
-    name: Option<String>,
-    compression_format: Option<CompressionFormat>,
-    compression_level: Option<u32>,
+    pub name: Option<String>,
+    pub compression_format: Option<CompressionFormat>,
+    pub compression_level: Option<u32>,
+    pub checksum: Option<bool>,
+    /// Algorithm used when `checksum` is enabled, SHA-256 by default.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+    /// Dump this many tables concurrently (PostgreSQL only), see `backup_parallel`.
+    pub jobs: Option<usize>,
+    #[serde(default)]
+    pub exclusions: DumpExclusions,
+    /// Columns rewritten in the dump's COPY data (PostgreSQL only), see `MaskingWriter`.
+    #[serde(default)]
+    pub masking: Vec<MaskRule>,
+    /// Exported snapshot to dump from (PostgreSQL only), see `databases::BackupOptions`.
+    #[serde(default)]
+    pub snapshot: Option<String>,
+    /// Store a `pg_dump --format=directory` dump as one tar archive (PostgreSQL only), dumped
+    /// and restored `jobs` tables at a time.
+    #[serde(default)]
+    pub directory_format: bool,
+    /// Dump the schema, the data or both, recorded in the manifest.
+    #[serde(default)]
+    pub scope: BackupScope,
+    /// Table patterns the dump is limited to (PostgreSQL only), see `databases::BackupOptions`.
+    #[serde(default)]
+    pub include_tables: Vec<String>,
+    /// Table patterns left out of the dump (PostgreSQL only).
+    #[serde(default)]
+    pub exclude_tables: Vec<String>,
+    /// Take a base backup of the whole server for point-in-time recovery instead of a dump
+    /// (PostgreSQL only), see `physical`.
+    #[serde(default)]
+    pub physical: bool,
+    /// Bytes uploaded per second at most, after compression and encryption. Zero or none is
+    /// unlimited.
+    #[serde(default)]
+    pub rate_limit_bytes_per_sec: Option<u64>,
+    /// Called with the bytes dumped so far, before compression.
+    #[serde(skip)]
+    pub progress: Option<ProgressCallback>,
+}
+
+impl BackupOptions {
+    fn resolve_checksum(&self) -> Option<ChecksumAlgorithm> {
+        self.checksum
+            .unwrap_or(false)
+            .then(|| self.checksum_algorithm.unwrap_or_default())
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -29,11 +122,152 @@ pub struct RestoreOptions {
     pub name: String,
     pub compression_format: Option<CompressionFormat>,
     pub drop_database_first: Option<bool>,
+    pub message_level: Option<MessageLevel>,
+    pub no_tablespaces: Option<bool>,
+    /// Encoding the dump's text is read as, overriding its `SET client_encoding` (PostgreSQL
+    /// only).
+    pub client_encoding: Option<String>,
+    /// Restore only the schema of a full backup (PostgreSQL only).
+    pub schema_only: Option<bool>,
+    /// Time allowed to terminate the sessions of the target database (PostgreSQL only).
+    pub termination_timeout: Option<Duration>,
+    /// Terminate the sessions of the target database first, by default (PostgreSQL only).
+    pub terminate_connections: Option<bool>,
+    /// Gather planner statistics once restored, by default unless restoring the schema only.
+    pub run_analyze: Option<bool>,
+    /// Tables analyzed at once (PostgreSQL only).
+    pub analyze_jobs: Option<usize>,
+    /// Tables restored at once from a directory-format backup (PostgreSQL only).
+    pub jobs: Option<usize>,
+    /// Apply the backup in one transaction rolled back at the first error (PostgreSQL only).
+    pub single_transaction: Option<bool>,
+    /// Only check that the backup restores, leaving the database untouched (PostgreSQL and
+    /// SQLite). Statistics are not gathered.
+    pub validate_only: Option<bool>,
+    /// Bytes downloaded per second at most, before decryption and decompression. Zero or none
+    /// is unlimited.
+    pub rate_limit_bytes_per_sec: Option<u64>,
+    /// Called with the bytes restored so far, after decompression.
+    #[serde(skip)]
+    pub progress: Option<ProgressCallback>,
+}
+
+impl RestoreOptions {
+    /// Restores `name` with the default of every option.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            compression_format: None,
+            drop_database_first: None,
+            message_level: None,
+            no_tablespaces: None,
+            client_encoding: None,
+            schema_only: None,
+            termination_timeout: None,
+            terminate_connections: None,
+            run_analyze: None,
+            analyze_jobs: None,
+            jobs: None,
+            single_transaction: None,
+            validate_only: None,
+            rate_limit_bytes_per_sec: None,
+            progress: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreReport {
+    /// The backup restored.
+    #[serde(default)]
+    pub name: String,
+    /// Time spent gathering statistics, when they were.
+    pub analyze_duration: Option<Duration>,
+    /// What the restore tool reported on stderr, see `DatabaseConnectionTrait`.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VerifyOptions {
+    pub level: VerifyLevel,
+    /// Database the backup is restored into with `VerifyLevel::Restore`. It is dropped and
+    /// recreated, and must differ from the configured database.
+    pub scratch_database: Option<String>,
+    pub message_level: Option<MessageLevel>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VerifyAllOptions {
+    pub verify: VerifyOptions,
+    /// Only verify backups whose name starts with this, e.g. `mydb-`.
+    pub prefix: Option<String>,
+    /// Backups verified at once, see `get_verify_concurrency`.
+    pub concurrency: Option<usize>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupPlan {
+    pub name: String,
+    pub database: String,
+    pub server_version: Version,
+    pub client_version: Version,
+    pub compression_format: CompressionFormat,
+    pub compression_level: u32,
+    /// Size of the database, before compression.
+    pub estimated_size: u64,
+    /// Bytes free on the storage, `None` when it cannot tell, like object stores.
+    pub available_space: Option<u64>,
+    /// Bytes the backups kept by the retention would take after this backup, counting it at
+    /// its estimated size. `None` without a retention.
+    pub retained_size: Option<u64>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupReport {
+    pub name: String,
+    /// Bytes written to the storage, after compression and encryption.
+    pub size: u64,
+    pub checksum: Option<String>,
+    /// Time from the start of the dump until the backup and its manifest were stored.
+    #[serde(default)]
+    pub duration: Duration,
+    #[serde(default)]
+    pub compression_format: Option<CompressionFormat>,
+    /// What the dump tool reported on stderr.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseFailure {
+    pub database: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MultiBackupReport {
+    pub succeeded: Vec<BackupReport>,
+    pub failed: Vec<DatabaseFailure>,
+}
+
+impl MultiBackupReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+const DEFAULT_BACKUP_CONCURRENCY: usize = 2;
+
 pub struct DbBkp {
     database_connection: DatabaseConnection,
     storage_provider: StorageProvider,
+    audit_log: Option<AuditLog>,
+    encryption: Option<EncryptionConfig>,
+    correlation_id: String,
+    #[cfg(feature = "metrics")]
+    metrics: Option<std::sync::Arc<dyn metrics::MetricsRecorder>>,
 }
 
 impl DbBkp {
@@ -41,9 +275,106 @@ impl DbBkp {
         Self {
             database_connection,
             storage_provider,
+            audit_log: None,
+            encryption: None,
+            correlation_id: new_correlation_id(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
+    /// Records backups and restores in `audit_log`, see `AuditLog::record`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Encrypts new backups and decrypts encrypted ones, see `EncryptingWriter`. Backups
+    /// without the encryption header are still read as they are.
+    pub fn with_encryption(mut self, encryption: EncryptionConfig) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Reports backups and restores to `metrics`. Cleanups are reported by the storage, see
+    /// `StorageProvider::with_metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: std::sync::Arc<dyn metrics::MetricsRecorder>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Logs the end of `operation` with its backup and size, or its error.
+    fn log_operation(
+        &self,
+        operation: AuditOperation,
+        start: Instant,
+        outcome: Result<(&str, Option<u64>), &anyhow::Error>,
+    ) {
+        let database = self.database_connection.config.database.as_str();
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        match outcome {
+            Ok((path, bytes)) => info!(
+                operation = operation.as_str(),
+                database,
+                path,
+                bytes,
+                duration_ms,
+                status = AuditOutcome::Success.as_str();
+                "Finished {} of {} ({}) in {:.1}s",
+                operation.as_str(),
+                database,
+                path,
+                duration_ms as f64 / 1000.0
+            ),
+            Err(e) => {
+                let error = format!("{:#}", e);
+                warn!(
+                    operation = operation.as_str(),
+                    database,
+                    duration_ms,
+                    status = AuditOutcome::Failure.as_str(),
+                    error = error.as_str();
+                    "Failed {} of {}: {}",
+                    operation.as_str(),
+                    database,
+                    error
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_metrics(
+        &self,
+        operation: AuditOperation,
+        start: Instant,
+        succeeded: bool,
+        bytes: Option<u64>,
+    ) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_operation(
+                operation,
+                &self.database_connection.config.database,
+                if succeeded {
+                    AuditOutcome::Success
+                } else {
+                    AuditOutcome::Failure
+                },
+                start.elapsed(),
+                bytes,
+            );
+        }
+    }
+
+    fn audit_record(&self, operation: AuditOperation) -> AuditRecord {
+        let mut record = AuditRecord::new(operation, &self.correlation_id);
+        record.database = Some(self.database_connection.config.database.clone());
+        record.storage = Some(self.storage_provider.config.name().to_string());
+        record
+    }
+
     pub async fn test(&self) -> Result<bool> {
         let is_database_connected = self.database_connection.connection.test().await?;
         let is_storage_connected = self.storage_provider.test().await?;
@@ -57,15 +388,11 @@ impl DbBkp {
         return Ok(true);
     }
 
-    pub async fn backup_with(&self, options: Option<BackupOptions>) -> Result<String> {
-        let options = match options {
-            Some(options) => options,
-            None => BackupOptions {
-                name: None,
-                compression_format: None,
-                compression_level: None,
-            },
-        };
+    fn resolve_backup_options(
+        &self,
+        options: Option<BackupOptions>,
+    ) -> (String, CompressionFormat, u32) {
+        let options = options.unwrap_or_default();
 
         let compression_format = options
             .compression_format
@@ -73,40 +400,554 @@ impl DbBkp {
         let compression_level = options.compression_level.unwrap_or(9);
         let name = match options.name {
             Some(name) => name,
+            None if options.physical => {
+                get_base_backup_name(&self.database_connection.config, &compression_format)
+            }
             None => get_default_backup_name(&self.database_connection.config, &compression_format),
         };
 
-        let writer = self.storage_provider.create_writer(&name).await?;
-        let mut compressed_writed = Compressor::new(
-            writer,
+        (name, compression_format, compression_level)
+    }
+
+    fn resolve_masking(&self, options: Option<&BackupOptions>) -> Result<Vec<MaskRule>> {
+        let rules = options
+            .map(|options| options.masking.clone())
+            .unwrap_or_default();
+
+        if !rules.is_empty()
+            && self.database_connection.config.connection_type != ConnectionType::PostgreSql
+        {
+            return Err(anyhow!("Column masking is only supported for PostgreSQL"));
+        }
+
+        if !rules.is_empty()
+            && options.is_some_and(|options| options.directory_format || options.physical)
+        {
+            return Err(anyhow!(
+                "Column masking only applies to plain dumps, not directory-format or base backups"
+            ));
+        }
+
+        Ok(rules)
+    }
+
+    /// Runs the checks of a backup without dumping the database: both connections, a test
+    /// write to the storage, the client version, and whether the database size, before
+    /// compression, fits the free space of the storage, along with the backups `retention`
+    /// keeps. Failed connections are errors, the rest are warnings of the plan.
+    pub async fn plan_backup(
+        &self,
+        options: Option<BackupOptions>,
+        retention: Option<RetentionPolicy>,
+    ) -> Result<BackupPlan> {
+        self.test().await?;
+        self.storage_provider.test_write().await?;
+
+        let (name, compression_format, compression_level) = self.resolve_backup_options(options);
+        let connection = &self.database_connection.connection;
+
+        let server_version = connection.get_metadata().await?.version;
+        let client_version = connection.get_client_version().await?;
+        let estimated_size = connection.get_database_size().await?;
+        let available_space = self.storage_provider.available_space().await?;
+
+        let mut warnings = vec![];
+
+        match (&server_version, &client_version) {
+            (Version::PostgreSQL(server), Version::PostgreSQL(client)) => {
+                if let Some(warning) = get_version_warning(server, client) {
+                    warnings.push(warning.message);
+                }
+            }
+            (server, client) if client.major() < server.major() => warnings.push(format!(
+                "Client utilities version ({}) is older than the server ({})",
+                client.major(),
+                server.major()
+            )),
+            _ => {}
+        }
+
+        if let Some(available_space) = available_space {
+            if estimated_size > available_space {
+                warnings.push(format!(
+                    "The database takes {} bytes before compression and the storage has {} bytes free, the backup may not fit",
+                    estimated_size, available_space
+                ));
+            }
+        }
+
+        let mut retained_size = None;
+        if let Some(retention) = retention {
+            let stored: u64 = self
+                .storage_provider
+                .list()
+                .await?
+                .iter()
+                .map(|entry| entry.metadata.content_length)
+                .sum();
+            let reclaimed = self
+                .storage_provider
+                .cleanup_with_options(&CleanupOptions::new(retention, true))
+                .await?
+                .reclaimed;
+            let retained = stored.saturating_sub(reclaimed) + estimated_size;
+
+            // The space of the backups the retention deletes becomes free after the cleanup
+            if let Some(capacity) = available_space.map(|available| available + stored) {
+                if retained > capacity {
+                    warnings.push(format!(
+                        "The backups kept by the retention would take {} bytes, more than the {} bytes the storage can hold",
+                        retained, capacity
+                    ));
+                }
+            }
+            retained_size = Some(retained);
+        }
+
+        Ok(BackupPlan {
+            name,
+            database: self.database_connection.config.database.clone(),
+            server_version,
+            client_version,
             compression_format,
+            compression_level,
+            estimated_size,
+            available_space,
+            retained_size,
+            warnings,
+        })
+    }
+
+    pub async fn backup_with(&self, options: Option<BackupOptions>) -> Result<String> {
+        let report = self.backup_with_report(options).await?;
+        Ok(report.name)
+    }
+
+    pub async fn backup_with_report(&self, options: Option<BackupOptions>) -> Result<BackupReport> {
+        let start = Instant::now();
+        let result = self.run_backup(options).await;
+
+        self.log_operation(
+            AuditOperation::Backup,
+            start,
+            result
+                .as_ref()
+                .map(|report| (report.name.as_str(), Some(report.size))),
+        );
+
+        #[cfg(feature = "metrics")]
+        self.record_metrics(
+            AuditOperation::Backup,
+            start,
+            result.is_ok(),
+            result.as_ref().ok().map(|report| report.size),
+        );
+
+        let mut record = self.audit_record(AuditOperation::Backup);
+        if let Ok(report) = &result {
+            record.backup = Some(report.name.clone());
+            record.bytes = Some(report.size);
+        }
+
+        audit::record(self.audit_log.as_ref(), record, result)
+    }
+
+    async fn run_backup(&self, options: Option<BackupOptions>) -> Result<BackupReport> {
+        let destinations = std::slice::from_ref(&self.storage_provider);
+        let (report, _) = self
+            .run_backup_to_destinations(destinations, options, RequirePolicy::All)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Dumps the database once and streams it to every destination, see `FanoutWriter` for
+    /// how failing destinations are handled under each `RequirePolicy`.
+    ///
+    /// Manifests are written to the destinations that succeeded, failing to write one only fails
+    /// the backup with `RequirePolicy::All`. An error is returned when the policy is not met,
+    /// otherwise the report lists the tolerated failures.
+    pub async fn backup_to_destinations(
+        &self,
+        destinations: &[StorageProvider],
+        options: Option<BackupOptions>,
+        require: RequirePolicy,
+    ) -> Result<(BackupReport, FanoutReport)> {
+        let start = Instant::now();
+        let result = self
+            .run_backup_to_destinations(destinations, options, require)
+            .await;
+
+        self.log_operation(
+            AuditOperation::Backup,
+            start,
+            result
+                .as_ref()
+                .map(|(report, _)| (report.name.as_str(), Some(report.size))),
+        );
+
+        #[cfg(feature = "metrics")]
+        self.record_metrics(
+            AuditOperation::Backup,
+            start,
+            result.is_ok(),
+            result.as_ref().ok().map(|(report, _)| report.size),
+        );
+
+        let mut record = self.audit_record(AuditOperation::Backup);
+        record.storage = Some(
+            destinations
+                .iter()
+                .map(|destination| destination.config.name())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        if let Ok((report, _)) = &result {
+            record.backup = Some(report.name.clone());
+            record.bytes = Some(report.size);
+        }
+
+        audit::record(self.audit_log.as_ref(), record, result)
+    }
+
+    async fn run_backup_to_destinations(
+        &self,
+        destinations: &[StorageProvider],
+        options: Option<BackupOptions>,
+        require: RequirePolicy,
+    ) -> Result<(BackupReport, FanoutReport)> {
+        let start = Instant::now();
+        let checksum = options
+            .as_ref()
+            .and_then(|options| options.resolve_checksum());
+        let dump_options = options
+            .as_ref()
+            .map(|options| databases::BackupOptions {
+                jobs: options.jobs,
+                exclusions: options.exclusions.clone(),
+                snapshot: options.snapshot.clone(),
+                directory_format: options.directory_format,
+                scope: options.scope,
+                include_tables: options.include_tables.clone(),
+                exclude_tables: options.exclude_tables.clone(),
+                physical: options.physical,
+                progress: options.progress.clone(),
+            })
+            .unwrap_or_default();
+        let scope = dump_options.scope;
+        let physical = dump_options.physical;
+        let masking = self.resolve_masking(options.as_ref())?;
+        let rate_limit = options
+            .as_ref()
+            .and_then(|options| options.rate_limit_bytes_per_sec);
+        let (name, compression_format, compression_level) = self.resolve_backup_options(options);
+        let change_token = self.get_change_token().await;
+        let server_version = self
+            .database_connection
+            .connection
+            .get_metadata()
+            .await?
+            .version;
+
+        let writer = ThrottledWriter::new(
+            FanoutWriter::open(destinations, &name, require).await?,
+            rate_limit,
+        );
+        let compressed_writer = Compressor::new(
+            EncryptingWriter::new(
+                HashingWriter::new(
+                    HashingWriter::new(writer, Some(ChecksumAlgorithm::Sha256)),
+                    checksum,
+                ),
+                self.encryption.as_ref(),
+            )?,
+            compression_format.clone(),
             Compression::new(compression_level),
         );
+        let mut masking_writer =
+            MaskingWriter::new(ProgressWriter::new(compressed_writer, None), masking);
 
-        self.database_connection
+        let result = self
+            .database_connection
             .connection
-            .backup(&mut compressed_writed)
-            .await?;
+            .backup_with_options(&mut masking_writer, dump_options)
+            .await
+            .and_then(|diagnostics| {
+                let counting_writer = masking_writer.finish()?;
+                let uncompressed_size = counting_writer.bytes();
+                let hashing_writer = counting_writer.into_inner().finish()?.finish()?;
+                Ok((hashing_writer, uncompressed_size, diagnostics))
+            });
+
+        let (hashing_writer, uncompressed_size, diagnostics) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                FanoutWriter::abort(destinations, &name).await;
+                return Err(e);
+            }
+        };
+
+        let size = hashing_writer.bytes_written();
+        let (sidecar_writer, checksum) = hashing_writer.finish();
+        let (writer, sidecar) = sidecar_writer.finish();
+        let fanout_report = writer.into_inner().finish(destinations).await?;
+
+        let config = &self.database_connection.config;
+        let manifest = BackupManifest {
+            name: name.clone(),
+            database: config.database.clone(),
+            connection_type: config.connection_type.clone(),
+            created_at: Utc::now(),
+            change_token,
+            server_version: Some(server_version),
+            compression_format: Some(compression_format.clone()),
+            size: Some(size),
+            checksum: checksum.clone(),
+            scope,
+            encrypted: self.encryption.is_some(),
+            uncompressed_size: Some(uncompressed_size),
+            physical,
+        };
+
+        // With `All`, a destination without its manifest fails the backup like the data would
+        for destination in &fanout_report.succeeded {
+            let storage = &destinations[destination.index];
+            let result = async {
+                if let Some(sidecar) = &sidecar {
+                    storage.write_sidecar(&name, sidecar).await?;
+                }
+                storage.write_manifest(&manifest).await
+            }
+            .await;
+
+            match result {
+                Err(e) if require == RequirePolicy::All => return Err(e),
+                Err(e) => warn!("Failed to write manifest to {}: {}", destination.name, e),
+                Ok(()) => {}
+            }
+        }
+
+        Ok((
+            BackupReport {
+                name,
+                size,
+                checksum,
+                duration: start.elapsed(),
+                compression_format: Some(compression_format),
+                diagnostics,
+            },
+            fanout_report,
+        ))
+    }
+
+    /// Takes a backup only when the database changed since the last backup
+    /// of the same configuration, returning `None` when it was skipped.
+    ///
+    /// Change detection is heuristic: it compares cumulative row counters
+    /// (`pg_stat_database` on PostgreSQL, table statistics on MySQL) with the
+    /// value recorded in the previous backup's manifest. Schema-only changes
+    /// are not detected, and a statistics reset always triggers a backup.
+    pub async fn backup_if_changed(
+        &self,
+        options: Option<BackupOptions>,
+    ) -> Result<Option<String>> {
+        let current_token = self.get_change_token().await;
+        let last_manifest = self.get_last_manifest().await?;
+
+        if let (Some(current_token), Some(manifest)) = (&current_token, &last_manifest) {
+            if manifest.change_token.as_ref() == Some(current_token) {
+                info!(
+                    "No changes detected since {}, skipping backup",
+                    manifest.name
+                );
+                return Ok(None);
+            }
+        }
+
+        let name = self.backup_with(options).await?;
 
-        let mut writer = compressed_writed.finish()?;
-        writer.flush()?;
+        Ok(Some(name))
+    }
+
+    /// Returns the backup of the configured database already taken today, if any. The day
+    /// boundary follows `timezone`, UTC by default.
+    pub async fn find_backup_today(&self, timezone: Option<&Tz>) -> Result<Option<Entry>> {
+        let now = Utc::now();
+        let today = match timezone {
+            Some(timezone) => now.with_timezone(timezone).date_naive(),
+            None => now.date_naive(),
+        };
+
+        self.storage_provider
+            .find_backup_on(&self.database_connection.config.name, today, timezone)
+            .await
+    }
 
-        Ok(name)
+    /// Takes a backup unless one of the same configuration was already taken today, returning
+    /// `None` when it was skipped. Safe to call from retried or overlapping cron jobs.
+    pub async fn backup_if_missing_today(
+        &self,
+        options: Option<BackupOptions>,
+        timezone: Option<&Tz>,
+    ) -> Result<Option<String>> {
+        if let Some(entry) = self.find_backup_today(timezone).await? {
+            info!(
+                "Backup {} was already taken today, skipping backup",
+                entry.metadata.name
+            );
+            return Ok(None);
+        }
+
+        let name = self.backup_with(options).await?;
+
+        Ok(Some(name))
+    }
+
+    async fn get_change_token(&self) -> Option<String> {
+        match self.database_connection.connection.get_change_token().await {
+            Ok(token) => token,
+            Err(e) => {
+                warn!("Failed to get database change token: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn get_last_manifest(&self) -> Result<Option<BackupManifest>> {
+        let pattern = get_backup_name_pattern(&self.database_connection.config.name);
+        let entries = self.storage_provider.list().await?;
+
+        let last_entry = entries
+            .iter()
+            .find(|entry| pattern.is_match(&entry.metadata.name));
+
+        match last_entry {
+            Some(entry) => self.storage_provider.read_manifest(&entry.path).await,
+            None => Ok(None),
+        }
     }
 
     pub async fn backup(&self) -> Result<String> {
         self.backup_with(None).await
     }
 
+    pub async fn inspect_backup(
+        &self,
+        name: &str,
+        compression_format: Option<CompressionFormat>,
+    ) -> Result<DumpContent> {
+        let reader = self.storage_provider.create_reader(name).await?;
+        let encryption = self.encryption.clone();
+
+        let content = tokio::task::spawn_blocking(move || -> Result<DumpContent> {
+            let reader = DecryptingReader::new(reader, encryption.as_ref())?;
+            let (detected_format, reader) = Decompressor::sniff_format(reader)?;
+
+            Ok(inspect_dump(Decompressor::new(
+                reader,
+                compression_format.unwrap_or(detected_format),
+            ))?)
+        })
+        .await?
+        .context(format!("Failed to inspect backup {}", name))?;
+
+        Ok(content)
+    }
+
+    /// Compares the encoding of a backup, or `client_encoding` when given, with the encoding of
+    /// the target database and returns warnings for conversions that will fail or lose data.
+    pub async fn check_restore_encoding(
+        &self,
+        name: &str,
+        client_encoding: Option<&str>,
+    ) -> Result<Vec<String>> {
+        if self.database_connection.config.connection_type != ConnectionType::PostgreSql {
+            return Ok(vec![]);
+        }
+
+        let source = match client_encoding {
+            Some(encoding) => Some(encoding.to_string()),
+            None => {
+                let header = self.storage_provider.read_dump_header(name).await?;
+                parse_client_encoding(&header)
+            }
+        };
+
+        let target = self
+            .database_connection
+            .connection
+            .get_metadata()
+            .await?
+            .encoding;
+
+        Ok(encoding_warnings(source.as_deref(), target.as_deref()))
+    }
+
     pub async fn restore(&self, options: RestoreOptions) -> Result<()> {
+        self.restore_with_report(options).await?;
+        Ok(())
+    }
+
+    pub async fn restore_with_report(&self, options: RestoreOptions) -> Result<RestoreReport> {
+        let mut record = self.audit_record(AuditOperation::Restore);
+        record.backup = Some(options.name.clone());
+
+        let start = Instant::now();
+        let name = options.name.clone();
+        let result = self.run_restore(options).await;
+
+        self.log_operation(
+            AuditOperation::Restore,
+            start,
+            result.as_ref().map(|_| (name.as_str(), None)),
+        );
+
+        #[cfg(feature = "metrics")]
+        self.record_metrics(AuditOperation::Restore, start, result.is_ok(), None);
+
+        audit::record(self.audit_log.as_ref(), record, result)
+    }
+
+    async fn run_restore(&self, options: RestoreOptions) -> Result<RestoreReport> {
+        let schema_only = options.schema_only.unwrap_or(false);
+        let validate_only = options.validate_only.unwrap_or(false);
+        let run_analyze = !validate_only && options.run_analyze.unwrap_or(!schema_only);
+
+        let manifest = match self.storage_provider.read_manifest(&options.name).await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                warn!("{:#}", e);
+                None
+            }
+        };
+
+        if manifest.as_ref().is_some_and(|manifest| manifest.physical) {
+            return Err(anyhow!(
+                "{} is a base backup, stage it with restore-base instead",
+                options.name
+            ));
+        }
+
+        // The manifest records the compression; without one it is detected
+        let recorded_format = manifest.and_then(|manifest| manifest.compression_format);
+
+        let reader = ThrottledReader::new(
+            self.storage_provider.create_reader(&options.name).await?,
+            options.rate_limit_bytes_per_sec,
+        );
+        let reader = DecryptingReader::new(reader, self.encryption.as_ref())?;
+
+        // Backups written without compression, or by other tools, restore as they are
+        let (detected_format, reader) = Decompressor::sniff_format(reader)?;
         let compression_format = options
             .compression_format
-            .unwrap_or(CompressionFormat::Gzip);
-
-        let reader = self.storage_provider.create_reader(&options.name).await?;
+            .or(recorded_format)
+            .unwrap_or(detected_format);
         let mut compressed_reader = Decompressor::new(reader, compression_format);
 
-        self.database_connection
+        let name = options.name;
+        let diagnostics = self
+            .database_connection
             .connection
             .restore_with_options(
                 &mut compressed_reader,
@@ -115,13 +956,416 @@ impl DbBkp {
                         Some(drop) => drop,
                         None => false,
                     },
+                    message_level: options.message_level.unwrap_or_default(),
+                    no_tablespaces: options.no_tablespaces.unwrap_or(false),
+                    client_encoding: options.client_encoding,
+                    schema_only,
+                    termination_timeout: options.termination_timeout,
+                    terminate_connections: options.terminate_connections.unwrap_or(true),
+                    jobs: options.jobs,
+                    single_transaction: options.single_transaction.unwrap_or(false),
+                    validate_only,
+                    progress: options.progress,
                 },
             )
             .await?;
 
+        if !run_analyze {
+            return Ok(RestoreReport {
+                name,
+                analyze_duration: None,
+                diagnostics,
+            });
+        }
+
+        let start = Instant::now();
+
+        self.database_connection
+            .connection
+            .analyze(options.analyze_jobs)
+            .await
+            .context("The restore completed but gathering statistics failed")?;
+
+        let analyze_duration = start.elapsed();
+        info!("Statistics gathered in {:?}", analyze_duration);
+
+        Ok(RestoreReport {
+            name,
+            analyze_duration: Some(analyze_duration),
+            diagnostics,
+        })
+    }
+
+    /// Restores into a new database next to the configured one, runs the checks against it and,
+    /// when they pass, renames the configured database out of the way and the new one into its
+    /// place. The configured database is left untouched until the checks pass, and renamed back
+    /// if the new one cannot take its place.
+    pub async fn restore_and_promote(&self, options: PromoteOptions) -> Result<PromoteReport> {
+        let config = &self.database_connection.config;
+
+        if config.connection_type != ConnectionType::PostgreSql {
+            return Err(anyhow!(
+                "Promoting a restored database is only supported for PostgreSQL"
+            ));
+        }
+
+        if options.restore.validate_only.unwrap_or(false) {
+            return Err(anyhow!("A validation-only restore cannot be promoted"));
+        }
+
+        let live = config.database.clone();
+        let timestamp = Utc::now();
+        let candidate = get_promotion_name(&live, "restore", timestamp);
+        let previous = get_promotion_name(&live, "previous", timestamp);
+        let termination_timeout = options.restore.termination_timeout;
+        let connection = &self.database_connection.connection;
+
+        let mut candidate_config = config.clone();
+        candidate_config.database = candidate.clone();
+
+        let candidate_core = DbBkp {
+            database_connection: DatabaseConnection::new(candidate_config).await?,
+            storage_provider: self.storage_provider.clone(),
+            audit_log: self.audit_log.clone(),
+            encryption: self.encryption.clone(),
+            correlation_id: self.correlation_id.clone(),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+        };
+
+        info!("Restoring {} into {}", options.restore.name, candidate);
+
+        let restore = RestoreOptions {
+            drop_database_first: Some(true),
+            ..options.restore
+        };
+
+        let checked = match candidate_core.restore_with_report(restore).await {
+            Ok(report) => candidate_core
+                .run_checks(&options.checks)
+                .await
+                .map(|_| report),
+            Err(e) => Err(e),
+        };
+
+        let report = match checked {
+            Ok(report) => report,
+            Err(e) => {
+                if let Err(drop_error) = connection.drop_database(&candidate).await {
+                    warn!("Failed to drop {}: {:#}", candidate, drop_error);
+                }
+
+                return Err(e.context(format!("{} was left untouched", live)));
+            }
+        };
+
+        let live_exists = connection.list_databases().await?.contains(&live);
+
+        let renames = match live_exists {
+            true => vec![
+                (live.as_str(), previous.as_str()),
+                (candidate.as_str(), live.as_str()),
+            ],
+            false => vec![(candidate.as_str(), live.as_str())],
+        };
+
+        info!("Renaming {} to {}", candidate, live);
+
+        if let Err(e) = connection
+            .rename_databases(&renames, termination_timeout)
+            .await
+        {
+            return Err(e.context(format!(
+                "Failed to promote {}, {} is still in place",
+                candidate, live
+            )));
+        }
+
+        let previous = match live_exists {
+            true if options.keep_previous => Some(previous),
+            true => {
+                // The promotion already happened, so a leftover copy only needs a warning
+                if let Err(e) = connection.drop_database(&previous).await {
+                    warn!("Failed to drop the previous database {}: {:#}", previous, e);
+                }
+
+                None
+            }
+            false => None,
+        };
+
+        Ok(PromoteReport {
+            candidate,
+            previous,
+            restore: report,
+            checks_passed: options.checks.len(),
+        })
+    }
+
+    async fn run_checks(&self, checks: &[String]) -> Result<()> {
+        for check in checks {
+            info!("Checking {}", check);
+
+            if !self.database_connection.connection.run_check(check).await? {
+                return Err(anyhow!("Check returned false: {}", check));
+            }
+        }
+
         Ok(())
     }
 
+    /// Restores the most recent backup of the configured database over it, with the default
+    /// options. Decryption and decompression follow the backup and its manifest.
+    pub async fn restore_latest(&self) -> Result<RestoreReport> {
+        let entry = self
+            .storage_provider
+            .get_nth_latest(&self.database_connection.config.name, 0)
+            .await?;
+
+        self.restore_with_report(RestoreOptions::new(entry.path))
+            .await
+    }
+
+    /// Verifies the most recent backup of the configured database without taking a new one.
+    pub async fn verify_latest(&self, options: VerifyOptions) -> Result<VerifyReport> {
+        let entry = self
+            .storage_provider
+            .get_nth_latest(&self.database_connection.config.name, 0)
+            .await?;
+
+        self.verify(&entry.path, options).await
+    }
+
+    pub async fn verify(&self, name: &str, options: VerifyOptions) -> Result<VerifyReport> {
+        let start = Instant::now();
+
+        let scratch_config = match options.level {
+            VerifyLevel::Checksum => None,
+            VerifyLevel::Restore => {
+                let config = &self.database_connection.config;
+                let database = options.scratch_database.clone().ok_or_else(|| {
+                    anyhow!("A scratch database is required to verify by restoring")
+                })?;
+
+                if database == config.database {
+                    return Err(anyhow!(
+                        "The scratch database must differ from the backed up database {}",
+                        config.database
+                    ));
+                }
+
+                let mut config = config.clone();
+                config.database = database;
+                Some(config)
+            }
+        };
+
+        let info = self.storage_provider.get_backup_info(name).await?;
+        let compression_format = info.compression_format.clone();
+        let manifest = info.manifest.clone();
+        let reader = self.storage_provider.create_reader(name).await?;
+        let encryption = self.encryption.clone();
+
+        let check = tokio::task::spawn_blocking(move || {
+            check_integrity(
+                reader,
+                compression_format,
+                encryption.as_ref(),
+                manifest.as_ref(),
+            )
+        })
+        .await?
+        .context(format!("Backup {} failed verification", name))?;
+
+        if let Some(config) = scratch_config {
+            info!(
+                "Restoring {} into scratch database {}",
+                name, config.database
+            );
+
+            let scratch = DatabaseConnection::new(config).await?;
+            let reader = self.storage_provider.create_reader(name).await?;
+            let reader = DecryptingReader::new(reader, self.encryption.as_ref())?;
+            let mut reader = Decompressor::new(reader, info.compression_format.clone());
+
+            scratch
+                .connection
+                .restore_with_options(
+                    &mut reader,
+                    databases::RestoreOptions {
+                        drop_database_first: true,
+                        message_level: options.message_level.unwrap_or_default(),
+                        no_tablespaces: true,
+                        client_encoding: None,
+                        schema_only: false,
+                        termination_timeout: None,
+                        terminate_connections: true,
+                        jobs: None,
+                        single_transaction: false,
+                        validate_only: false,
+                        progress: None,
+                    },
+                )
+                .await
+                .context(format!("Backup {} failed to restore", name))?;
+        }
+
+        Ok(VerifyReport {
+            name: name.into(),
+            level: options.level,
+            created_at: info
+                .manifest
+                .map(|manifest| manifest.created_at)
+                .or(info.last_modified),
+            size: check.size,
+            checksum: check.checksum,
+            checksum_verified: check.checksum_verified,
+            restored: options.level == VerifyLevel::Restore,
+            duration: start.elapsed(),
+        })
+    }
+
+    /// Verifies every stored backup, or those matching `prefix`, and reports each outcome
+    /// instead of stopping at the first failure.
+    pub async fn verify_all(&self, options: VerifyAllOptions) -> Result<VerifyAllReport> {
+        let entries: Vec<Entry> = self
+            .storage_provider
+            .list()
+            .await?
+            .into_iter()
+            .filter(|entry| match &options.prefix {
+                Some(prefix) => entry.metadata.name.starts_with(prefix),
+                None => true,
+            })
+            .collect();
+
+        let concurrency = get_verify_concurrency(options.verify.level, options.concurrency);
+
+        info!(
+            "Verifying {} backups, {} at a time",
+            entries.len(),
+            concurrency
+        );
+
+        let results = stream::iter(entries)
+            .map(|entry| {
+                let options = options.verify.clone();
+                async move {
+                    let result = self.verify(&entry.path, options).await;
+                    (entry.path, result)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        Ok(VerifyAllReport::from_results(results))
+    }
+
+    /// Backs up each of `databases` on the configured server to its own file. Every name must
+    /// exist on the server; once started, a failing database does not stop the others.
+    pub async fn backup_databases(
+        &self,
+        databases: &[String],
+        options: Option<BackupOptions>,
+        concurrency: Option<usize>,
+    ) -> Result<MultiBackupReport> {
+        if options
+            .as_ref()
+            .is_some_and(|options| options.name.is_some())
+        {
+            return Err(anyhow!(
+                "A backup name cannot be given when backing up several databases"
+            ));
+        }
+
+        if options
+            .as_ref()
+            .is_some_and(|options| options.snapshot.is_some())
+        {
+            return Err(anyhow!(
+                "A snapshot belongs to a single database and cannot be shared between several"
+            ));
+        }
+
+        if databases.len() > 1 {
+            warn!("Databases are dumped from separate snapshots, not consistent with each other");
+        }
+
+        let available = self.database_connection.connection.list_databases().await?;
+        let missing: Vec<&str> = databases
+            .iter()
+            .filter(|database| !available.contains(database))
+            .map(|database| database.as_str())
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Databases not found on the server: {}",
+                missing.join(", ")
+            ));
+        }
+
+        let concurrency = concurrency.unwrap_or(DEFAULT_BACKUP_CONCURRENCY).max(1);
+
+        info!(
+            "Backing up {} databases, {} at a time",
+            databases.len(),
+            concurrency
+        );
+
+        let results: Vec<(String, Result<BackupReport>)> = stream::iter(databases)
+            .map(|database| {
+                let options = options.clone();
+                async move {
+                    let result = self.backup_database(database, options).await;
+                    (database.clone(), result)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        let mut report = MultiBackupReport::default();
+
+        for (database, result) in results {
+            match result {
+                Ok(backup) => report.succeeded.push(backup),
+                Err(e) => report.failed.push(DatabaseFailure {
+                    database,
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+
+        report.succeeded.sort_by(|a, b| a.name.cmp(&b.name));
+        report.failed.sort_by(|a, b| a.database.cmp(&b.database));
+
+        Ok(report)
+    }
+
+    async fn backup_database(
+        &self,
+        database: &str,
+        options: Option<BackupOptions>,
+    ) -> Result<BackupReport> {
+        let mut config = self.database_connection.config.clone();
+        config.database = database.into();
+        config.name = database.into();
+
+        let core = DbBkp {
+            database_connection: DatabaseConnection::new(config).await?,
+            storage_provider: self.storage_provider.clone(),
+            audit_log: self.audit_log.clone(),
+            encryption: self.encryption.clone(),
+            correlation_id: self.correlation_id.clone(),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+        };
+
+        core.backup_with_report(options).await
+    }
+
     pub async fn list_with_options(&self, options: ListOptions) -> Result<Vec<Entry>> {
         let entries = self.storage_provider.list_with_options(options).await?;
         Ok(entries)
@@ -133,3 +1377,34 @@ impl DbBkp {
         Ok(entries)
     }
 }
+
+/// Dumps `connection` as is, without compression or encryption, straight into `filename` on
+/// `storage`, so the dump never lands on local disk, and returns its size. The storage writer
+/// is flushed once the dump completes. When either side fails, the partial file is deleted.
+pub async fn backup_to_storage(
+    connection: &DatabaseConnection,
+    storage: &StorageProvider,
+    filename: &str,
+) -> Result<u64> {
+    let mut writer = HashingWriter::new(storage.create_writer(filename).await?, None);
+
+    let result = match connection.connection.backup(&mut writer).await {
+        Ok(()) => writer
+            .flush()
+            .context(format!("Failed to write {} to storage", filename)),
+        Err(e) => Err(e),
+    };
+
+    let size = writer.bytes_written();
+    drop(writer);
+
+    if let Err(e) = result {
+        if let Err(delete_error) = storage.delete(filename).await {
+            warn!("Failed to remove {}: {:#}", filename, delete_error);
+        }
+
+        return Err(e);
+    }
+
+    Ok(size)
+}
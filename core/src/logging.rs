@@ -0,0 +1,179 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use log::{
+    kv::{self, Key, Value, VisitSource, VisitValue},
+    Record,
+};
+use serde_json::{Map, Number};
+
+/// How log records are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `[timestamp LEVEL target] message`, for people.
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the fields of the record next to the message.
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Pretty => "pretty",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("Unsupported log format: {}", s)),
+        }
+    }
+}
+
+/// Installs the global logger. `RUST_LOG` overrides `default_filter`.
+pub fn init_logging(format: LogFormat, default_filter: &str) -> Result<()> {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_filter));
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", format_json(record)));
+    }
+
+    builder.try_init().context("Failed to install the logger")
+}
+
+/// Renders `record` as a JSON object. Fields never replace the timestamp, level, target or message.
+pub fn format_json(record: &Record) -> serde_json::Value {
+    let mut object = Map::new();
+    object.insert(
+        "timestamp".to_string(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    object.insert("level".to_string(), record.level().as_str().into());
+    object.insert("target".to_string(), record.target().into());
+    object.insert("message".to_string(), record.args().to_string().into());
+
+    // Collecting into a map cannot fail
+    let _ = record.key_values().visit(&mut JsonFields(&mut object));
+
+    serde_json::Value::Object(object)
+}
+
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0
+            .entry(key.as_str().to_string())
+            .or_insert_with(|| to_json(&value));
+        Ok(())
+    }
+}
+
+/// Numbers, booleans and `None` keep their JSON type, anything else is written as its `Display`.
+fn to_json(value: &Value) -> serde_json::Value {
+    let mut json = JsonValue(serde_json::Value::Null);
+    let _ = value.visit(&mut json);
+    json.0
+}
+
+struct JsonValue(serde_json::Value);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        self.0 = value.to_string().into();
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = Number::from_f64(value).map_or(serde_json::Value::Null, serde_json::Value::Number);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod logging_tests {
+    use log::{kv::ToValue, Level};
+
+    use super::*;
+
+    #[test]
+    fn test_01_format_json() {
+        let path: Option<&str> = None;
+        let fields: [(&str, Value); 6] = [
+            ("operation", Value::from("backup")),
+            ("path", path.to_value()),
+            ("bytes", Value::from(1024u64)),
+            ("duration_ms", Value::from(1500u64)),
+            ("ratio", Value::from(0.5f64)),
+            ("message", Value::from("ignored")),
+        ];
+        let json = format_json(
+            &Record::builder()
+                .level(Level::Info)
+                .target("dbkp_core")
+                .args(format_args!("Backup {} completed", "app.sql.gz"))
+                .key_values(&fields)
+                .build(),
+        );
+
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "dbkp_core");
+        assert_eq!(json["message"], "Backup app.sql.gz completed");
+        assert_eq!(json["operation"], "backup");
+        assert!(json["path"].is_null());
+        assert_eq!(json["bytes"], 1024);
+        assert_eq!(json["duration_ms"], 1500);
+        assert_eq!(json["ratio"], 0.5);
+        assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_02_parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("Pretty".parse::<LogFormat>().unwrap(), LogFormat::Pretty);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
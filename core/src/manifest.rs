@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compression::CompressionFormat,
+    databases::{version::Version, BackupScope, ConnectionType},
+};
+
+pub const MANIFEST_EXTENSION: &str = ".meta.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub name: String,
+    pub database: String,
+    pub connection_type: ConnectionType,
+    pub created_at: DateTime<Utc>,
+    pub change_token: Option<String>,
+    #[serde(default)]
+    pub server_version: Option<Version>,
+    #[serde(default)]
+    pub compression_format: Option<CompressionFormat>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Backups written before scopes existed contain everything.
+    #[serde(default)]
+    pub scope: BackupScope,
+    /// Whether the backup was encrypted, see `EncryptingWriter`.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Size of the dump before compression and encryption.
+    #[serde(default)]
+    pub uncompressed_size: Option<u64>,
+    /// Whether the backup is a base backup of the data directory, see `physical`.
+    #[serde(default)]
+    pub physical: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub compression_format: CompressionFormat,
+    /// Whether the backup starts with the encryption header, see `EncryptingWriter`.
+    pub encrypted: bool,
+    pub server_version: Option<String>,
+    pub manifest: Option<BackupManifest>,
+}
+
+pub fn get_manifest_name(backup_name: &str) -> String {
+    format!("{}{}", backup_name, MANIFEST_EXTENSION)
+}
+
+pub fn is_manifest(name: &str) -> bool {
+    name.ends_with(MANIFEST_EXTENSION)
+}
+
+/// Extracts the server version from the comment header of a plain pg_dump or mysqldump file.
+pub fn parse_dump_header(header: &str) -> Option<String> {
+    header
+        .lines()
+        .take(50)
+        .filter_map(|line| line.strip_prefix("--"))
+        .map(|line| line.trim())
+        .find_map(|line| {
+            if let Some(version) = line.strip_prefix("Dumped from database version") {
+                Some(format!("PostgreSQL {}", version.trim()))
+            } else {
+                line.strip_prefix("Server version")
+                    .map(|version| format!("MySQL {}", version.trim()))
+            }
+        })
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use super::parse_dump_header;
+
+    #[test]
+    fn test_01_parse_postgresql_header() {
+        let header = "--\n-- PostgreSQL database dump\n--\n\n\
+            -- Dumped from database version 16.2 (Debian 16.2-1.pgdg120+2)\n\
+            -- Dumped by pg_dump version 16.2\n\nSET statement_timeout = 0;\n";
+
+        assert_eq!(
+            parse_dump_header(header),
+            Some("PostgreSQL 16.2 (Debian 16.2-1.pgdg120+2)".into())
+        );
+    }
+
+    #[test]
+    fn test_02_parse_mysql_header() {
+        let header = "-- MySQL dump 10.13  Distrib 8.0.36, for Linux (x86_64)\n--\n\
+            -- Host: localhost    Database: test\n\
+            -- ------------------------------------------------------\n\
+            -- Server version\t8.0.36\n";
+
+        assert_eq!(parse_dump_header(header), Some("MySQL 8.0.36".into()));
+        assert_eq!(parse_dump_header("CREATE TABLE test (id INT);"), None);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const NULL_FIELD: &[u8] = b"\\N";
+const END_OF_COPY: &[u8] = b"\\.";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaskStrategy {
+    /// Replace the value with NULL.
+    Null,
+    /// Replace the value with the SHA-256 hex digest of the original, keeping distinct values
+    /// distinct.
+    Hash,
+    /// Replace the value with a constant.
+    Fixed(String),
+}
+
+impl fmt::Display for MaskStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskStrategy::Null => write!(f, "null"),
+            MaskStrategy::Hash => write!(f, "hash"),
+            MaskStrategy::Fixed(value) => write!(f, "fixed:{}", value),
+        }
+    }
+}
+
+impl FromStr for MaskStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(value) = s.strip_prefix("fixed:") {
+            return Ok(MaskStrategy::Fixed(value.into()));
+        }
+
+        match s.to_lowercase().as_str() {
+            "null" => Ok(MaskStrategy::Null),
+            "hash" => Ok(MaskStrategy::Hash),
+            _ => Err(anyhow!(
+                "Unsupported masking strategy: {} (use 'null', 'hash' or 'fixed:<value>')",
+                s
+            )),
+        }
+    }
+}
+
+/// A column to mask, written `[schema.]table.column=strategy`. Without a schema the rule
+/// applies to tables of that name in every schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaskRule {
+    pub schema: Option<String>,
+    pub table: String,
+    pub column: String,
+    pub strategy: MaskStrategy,
+}
+
+impl MaskRule {
+    fn matches(&self, schema: Option<&str>, table: &str) -> bool {
+        self.table == table
+            && match &self.schema {
+                Some(rule_schema) => Some(rule_schema.as_str()) == schema,
+                None => true,
+            }
+    }
+}
+
+impl FromStr for MaskRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, strategy) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid mask rule {}, expected table.column=strategy", s))?;
+
+        let parts: Vec<&str> = target.split('.').collect();
+        let (schema, table, column) = match parts.as_slice() {
+            [table, column] => (None, *table, *column),
+            [schema, table, column] => (Some(schema.to_string()), *table, *column),
+            _ => {
+                return Err(anyhow!(
+                    "Invalid mask target {}, expected [schema.]table.column",
+                    target
+                ))
+            }
+        };
+
+        Ok(MaskRule {
+            schema,
+            table: table.into(),
+            column: column.into(),
+            strategy: strategy.parse()?,
+        })
+    }
+}
+
+/// Masks columns of PostgreSQL `COPY ... FROM stdin` blocks while a plain dump is written.
+///
+/// Column positions come from the column list of each COPY header, so no schema parsing is
+/// needed. Only COPY data is rewritten: values inside INSERT statements, function bodies,
+/// defaults or JSON documents of other columns are left untouched.
+pub struct MaskingWriter<W: Write + Send + Unpin> {
+    inner: W,
+    rules: Vec<MaskRule>,
+    buffer: Vec<u8>,
+    /// Strategy per column index of the COPY block being written, if it has masked columns.
+    active: Option<HashMap<usize, MaskStrategy>>,
+}
+
+impl<W: Write + Send + Unpin> MaskingWriter<W> {
+    pub fn new(inner: W, rules: Vec<MaskRule>) -> Self {
+        Self {
+            inner,
+            rules,
+            buffer: vec![],
+            active: None,
+        }
+    }
+
+    /// Writes any incomplete trailing line and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let rest = std::mem::take(&mut self.buffer);
+        if !rest.is_empty() {
+            self.process_line(&rest)?;
+        }
+
+        Ok(self.inner)
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+
+        match &self.active {
+            Some(columns) => {
+                if content == END_OF_COPY {
+                    self.active = None;
+                    return self.inner.write_all(line);
+                }
+
+                let masked = mask_row(content, columns);
+                self.inner.write_all(&masked)?;
+                if content.len() < line.len() {
+                    self.inner.write_all(b"\n")?;
+                }
+
+                Ok(())
+            }
+            None => {
+                if content.starts_with(b"COPY ") {
+                    self.active = self.get_masked_columns(&String::from_utf8_lossy(content));
+                }
+
+                self.inner.write_all(line)
+            }
+        }
+    }
+
+    fn get_masked_columns(&self, header: &str) -> Option<HashMap<usize, MaskStrategy>> {
+        let (schema, table, columns) = parse_copy_header(header)?;
+
+        let masked: HashMap<usize, MaskStrategy> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(schema.as_deref(), &table))
+            .filter_map(|rule| {
+                columns
+                    .iter()
+                    .position(|column| *column == rule.column)
+                    .map(|index| (index, rule.strategy.clone()))
+            })
+            .collect();
+
+        (!masked.is_empty()).then_some(masked)
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for MaskingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.rules.is_empty() {
+            return self.inner.write(buf);
+        }
+
+        self.buffer.extend_from_slice(buf);
+
+        let mut start = 0;
+        while let Some(offset) = self.buffer[start..].iter().position(|b| *b == b'\n') {
+            let end = start + offset + 1;
+            let line = self.buffer[start..end].to_vec();
+            self.process_line(&line)?;
+            start = end;
+        }
+
+        self.buffer.drain(..start);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn mask_row(row: &[u8], columns: &HashMap<usize, MaskStrategy>) -> Vec<u8> {
+    let mut masked = Vec::with_capacity(row.len());
+
+    for (index, field) in row.split(|b| *b == b'\t').enumerate() {
+        if index > 0 {
+            masked.push(b'\t');
+        }
+
+        match columns.get(&index) {
+            // NULLs stay NULL so nullability and foreign keys are preserved
+            Some(_) if field == NULL_FIELD => masked.extend_from_slice(field),
+            Some(MaskStrategy::Null) => masked.extend_from_slice(NULL_FIELD),
+            Some(MaskStrategy::Hash) => {
+                masked.extend_from_slice(format!("{:x}", Sha256::digest(field)).as_bytes())
+            }
+            Some(MaskStrategy::Fixed(value)) => {
+                masked.extend_from_slice(escape_copy_value(value).as_bytes())
+            }
+            None => masked.extend_from_slice(field),
+        }
+    }
+
+    masked
+}
+
+fn escape_copy_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Parses `COPY schema.table (col_a, "Col B") FROM stdin;` into its unquoted parts.
+fn parse_copy_header(header: &str) -> Option<(Option<String>, String, Vec<String>)> {
+    let rest = header.strip_prefix("COPY ")?;
+    let open = rest.find(" (")?;
+    let close = rest.rfind(") FROM stdin")?;
+
+    let mut names = split_identifiers(&rest[..open], '.');
+    let table = names.pop()?;
+    let schema = names.pop();
+    let columns = split_identifiers(&rest[open + 2..close], ',');
+
+    Some((schema, table, columns))
+}
+
+fn split_identifiers(input: &str, separator: char) -> Vec<String> {
+    let mut identifiers = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                identifiers.push(current.trim().to_string());
+                current.clear();
+            }
+            c => current.push(c),
+        }
+    }
+
+    identifiers.push(current.trim().to_string());
+    identifiers
+}
+
+#[cfg(test)]
+mod masking_tests {
+    use std::io::Write;
+
+    use super::{parse_copy_header, MaskRule, MaskingWriter};
+
+    #[test]
+    fn test_01_parse_rules_and_header() {
+        let rule: MaskRule = "public.users.email=fixed:a@b.c".parse().unwrap();
+
+        assert_eq!(rule.schema, Some("public".into()));
+        assert_eq!(rule.column, "email");
+        assert!("users=hash".parse::<MaskRule>().is_err());
+        assert!("users.email=blur".parse::<MaskRule>().is_err());
+
+        let (schema, table, columns) =
+            parse_copy_header("COPY \"Sales\".\"Users\" (id, \"E-mail\", name) FROM stdin;")
+                .unwrap();
+
+        assert_eq!(schema, Some("Sales".into()));
+        assert_eq!(table, "Users");
+        assert_eq!(columns, vec!["id", "E-mail", "name"]);
+    }
+
+    #[test]
+    fn test_02_mask_copy_blocks() {
+        let rules = vec![
+            "users.email=hash".parse().unwrap(),
+            "users.ssn=null".parse().unwrap(),
+            "users.name=fixed:Jane\tDoe".parse().unwrap(),
+        ];
+
+        let dump = "CREATE TABLE public.users (id integer, email text, ssn text, name text);\n\
+            COPY public.users (id, email, ssn, name) FROM stdin;\n\
+            1\tbob@example.com\t123-45-6789\tBob\n\
+            2\t\\N\t\\N\tAlice\n\
+            \\.\n\
+            COPY public.orders (id, email) FROM stdin;\n\
+            1\tbob@example.com\n\
+            \\.\n";
+
+        let mut writer = MaskingWriter::new(vec![], rules);
+
+        // Split mid-line to exercise buffering
+        let (first, second) = dump.as_bytes().split_at(120);
+        writer.write_all(first).unwrap();
+        writer.write_all(second).unwrap();
+
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(
+            lines[2],
+            "1\t5ff860bf1190596c7188ab851db691f0f3169c453936e9e1eba2f9a47f7a0018\t\\N\tJane\\tDoe"
+        );
+        assert_eq!(lines[3], "2\t\\N\t\\N\tJane\\tDoe");
+        assert_eq!(lines[6], "1\tbob@example.com");
+        assert_eq!(lines.len(), dump.lines().count());
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    future::Future,
+    net::SocketAddr,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    audit::{AuditOperation, AuditOutcome},
+    storage::provider::CleanupReport,
+};
+
+/// Upper bounds of the duration histogram buckets, in seconds.
+const DURATION_BUCKETS: [f64; 9] = [1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0];
+/// Longest request head read before answering, the endpoint takes no body.
+const MAX_REQUEST_SIZE: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Receives the outcome of backups, restores and cleanups, to export them to a monitoring
+/// system. See `PrometheusRecorder` for the built-in one.
+pub trait MetricsRecorder: Send + Sync {
+    /// A backup or restore of `database` finished. `bytes` is the size written to the storage
+    /// for backups.
+    fn record_operation(
+        &self,
+        operation: AuditOperation,
+        database: &str,
+        outcome: AuditOutcome,
+        duration: Duration,
+        bytes: Option<u64>,
+    );
+
+    /// A cleanup of `storage` finished, dry runs excluded.
+    fn record_cleanup(&self, storage: &str, report: &CleanupReport);
+}
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations at most each of `DURATION_BUCKETS`, not cumulated.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    /// By operation, database and outcome.
+    operations: BTreeMap<(&'static str, String, &'static str), u64>,
+    /// By operation and database.
+    durations: BTreeMap<(&'static str, String), Histogram>,
+    bytes: BTreeMap<(&'static str, String), u64>,
+    last_success: BTreeMap<(&'static str, String), i64>,
+    /// By storage.
+    cleanup_deleted: BTreeMap<String, u64>,
+    cleanup_failed: BTreeMap<String, u64>,
+    cleanup_reclaimed: BTreeMap<String, u64>,
+}
+
+/// Keeps the metrics in memory and renders them in the Prometheus text format, see `render`
+/// and `serve`. Series start when first recorded and reset when the process restarts, which
+/// Prometheus counters handle.
+#[derive(Debug, Default, Clone)]
+pub struct PrometheusRecorder {
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn record_operation(
+        &self,
+        operation: AuditOperation,
+        database: &str,
+        outcome: AuditOutcome,
+        duration: Duration,
+        bytes: Option<u64>,
+    ) {
+        let Ok(mut metrics) = self.metrics.lock() else {
+            return;
+        };
+
+        let operation = operation.as_str();
+
+        *metrics
+            .operations
+            .entry((operation, database.into(), outcome.as_str()))
+            .or_default() += 1;
+        metrics
+            .durations
+            .entry((operation, database.into()))
+            .or_default()
+            .observe(duration.as_secs_f64());
+
+        if let Some(bytes) = bytes {
+            *metrics
+                .bytes
+                .entry((operation, database.into()))
+                .or_default() += bytes;
+        }
+
+        if outcome == AuditOutcome::Success {
+            metrics
+                .last_success
+                .insert((operation, database.into()), Utc::now().timestamp());
+        }
+    }
+
+    fn record_cleanup(&self, storage: &str, report: &CleanupReport) {
+        let Ok(mut metrics) = self.metrics.lock() else {
+            return;
+        };
+
+        *metrics.cleanup_deleted.entry(storage.into()).or_default() += report.deleted.len() as u64;
+        *metrics.cleanup_failed.entry(storage.into()).or_default() += report.failed.len() as u64;
+        *metrics.cleanup_reclaimed.entry(storage.into()).or_default() += report.reclaimed;
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(output: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+}
+
+impl PrometheusRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = match self.metrics.lock() {
+            Ok(metrics) => metrics,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut output = String::new();
+
+        write_header(
+            &mut output,
+            "dbkp_operations_total",
+            "counter",
+            "Backups and restores finished, by outcome.",
+        );
+        for ((operation, database, outcome), count) in &metrics.operations {
+            let _ = writeln!(
+                output,
+                "dbkp_operations_total{{operation=\"{}\",database=\"{}\",outcome=\"{}\"}} {}",
+                operation,
+                escape_label(database),
+                outcome,
+                count
+            );
+        }
+
+        write_header(
+            &mut output,
+            "dbkp_operation_duration_seconds",
+            "histogram",
+            "Duration of backups and restores, failed ones included.",
+        );
+        for ((operation, database), histogram) in &metrics.durations {
+            let labels = format!(
+                "operation=\"{}\",database=\"{}\"",
+                operation,
+                escape_label(database)
+            );
+            let mut cumulated = 0;
+
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulated += count;
+                let _ = writeln!(
+                    output,
+                    "dbkp_operation_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulated
+                );
+            }
+
+            let _ = writeln!(
+                output,
+                "dbkp_operation_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                output,
+                "dbkp_operation_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                output,
+                "dbkp_operation_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        write_header(
+            &mut output,
+            "dbkp_transferred_bytes_total",
+            "counter",
+            "Bytes written to storage by backups, after compression and encryption.",
+        );
+        for ((operation, database), bytes) in &metrics.bytes {
+            let _ = writeln!(
+                output,
+                "dbkp_transferred_bytes_total{{operation=\"{}\",database=\"{}\"}} {}",
+                operation,
+                escape_label(database),
+                bytes
+            );
+        }
+
+        write_header(
+            &mut output,
+            "dbkp_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful backup or restore.",
+        );
+        for ((operation, database), timestamp) in &metrics.last_success {
+            let _ = writeln!(
+                output,
+                "dbkp_last_success_timestamp_seconds{{operation=\"{}\",database=\"{}\"}} {}",
+                operation,
+                escape_label(database),
+                timestamp
+            );
+        }
+
+        for (name, help, values) in [
+            (
+                "dbkp_cleanup_deleted_total",
+                "Backups deleted by cleanups.",
+                &metrics.cleanup_deleted,
+            ),
+            (
+                "dbkp_cleanup_failed_total",
+                "Deletions of cleanups that failed.",
+                &metrics.cleanup_failed,
+            ),
+            (
+                "dbkp_cleanup_reclaimed_bytes_total",
+                "Size of the backups deleted by cleanups.",
+                &metrics.cleanup_reclaimed,
+            ),
+        ] {
+            write_header(&mut output, name, "counter", help);
+            for (storage, value) in values {
+                let _ = writeln!(
+                    output,
+                    "{}{{storage=\"{}\"}} {}",
+                    name,
+                    escape_label(storage),
+                    value
+                );
+            }
+        }
+
+        output
+    }
+}
+
+async fn respond(mut stream: TcpStream, recorder: &PrometheusRecorder) -> Result<()> {
+    let mut request = vec![];
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.split_whitespace();
+    let target = (parts.next(), parts.next());
+
+    let (status, content_type, body) = match target {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            recorder.render(),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not Found\n".into()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Serves `GET /metrics` on `address` until `shutdown` completes. Anything else gets a 404.
+pub async fn serve(
+    recorder: PrometheusRecorder,
+    address: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to listen on {}", address))?;
+    let mut shutdown = pin!(shutdown);
+
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    loop {
+        let stream = tokio::select! {
+            _ = &mut shutdown => return Ok(()),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a metrics connection: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        let recorder = recorder.clone();
+        tokio::spawn(async move {
+            if let Ok(Err(e)) =
+                tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &recorder)).await
+            {
+                warn!("Failed to serve metrics: {:#}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::time::Duration;
+
+    use crate::{
+        audit::{AuditOperation, AuditOutcome},
+        storage::provider::CleanupReport,
+    };
+
+    use super::{MetricsRecorder, PrometheusRecorder};
+
+    #[test]
+    fn test_01_render_metrics() {
+        let recorder = PrometheusRecorder::new();
+
+        recorder.record_operation(
+            AuditOperation::Backup,
+            "app",
+            AuditOutcome::Success,
+            Duration::from_secs(12),
+            Some(2048),
+        );
+        recorder.record_operation(
+            AuditOperation::Backup,
+            "app",
+            AuditOutcome::Failure,
+            Duration::from_millis(500),
+            None,
+        );
+        recorder.record_cleanup(
+            "s3 \"eu\"",
+            &CleanupReport {
+                deleted: vec!["app-1.gz".into(), "app-2.gz".into()],
+                reclaimed: 4096,
+                failed: vec![],
+            },
+        );
+
+        let output = recorder.render();
+
+        for line in [
+            "dbkp_operations_total{operation=\"backup\",database=\"app\",outcome=\"failure\"} 1",
+            "dbkp_operations_total{operation=\"backup\",database=\"app\",outcome=\"success\"} 1",
+            "dbkp_operation_duration_seconds_bucket{operation=\"backup\",database=\"app\",le=\"1\"} 1",
+            "dbkp_operation_duration_seconds_bucket{operation=\"backup\",database=\"app\",le=\"15\"} 2",
+            "dbkp_operation_duration_seconds_bucket{operation=\"backup\",database=\"app\",le=\"+Inf\"} 2",
+            "dbkp_operation_duration_seconds_sum{operation=\"backup\",database=\"app\"} 12.5",
+            "dbkp_transferred_bytes_total{operation=\"backup\",database=\"app\"} 2048",
+            "dbkp_cleanup_deleted_total{storage=\"s3 \\\"eu\\\"\"} 2",
+            "dbkp_cleanup_reclaimed_bytes_total{storage=\"s3 \\\"eu\\\"\"} 4096",
+            "# TYPE dbkp_operation_duration_seconds histogram",
+        ] {
+            assert!(output.lines().any(|l| l == line), "{}\n{}", line, output);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_02_serve_metrics() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, TcpStream},
+        };
+
+        let recorder = PrometheusRecorder::new();
+        recorder.record_cleanup("local", &CleanupReport::default());
+
+        // Reserve a free port, then hand it to the server
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(super::serve(recorder, address, async {
+            let _ = stopped.await;
+        }));
+
+        let get = |path: &'static str| async move {
+            for _ in 0..50 {
+                if let Ok(mut stream) = TcpStream::connect(address).await {
+                    stream
+                        .write_all(format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path).as_bytes())
+                        .await
+                        .unwrap();
+                    let mut response = String::new();
+                    stream.read_to_string(&mut response).await.unwrap();
+                    return response;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("The metrics server did not start");
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("dbkp_cleanup_deleted_total{storage=\"local\"} 0"));
+
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+
+        stop.send(()).unwrap();
+        server.await.unwrap().expect("Failed to serve");
+    }
+}
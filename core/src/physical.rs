@@ -0,0 +1,472 @@
+use std::{
+    fs,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::{
+    common::get_default_backup_name,
+    compression::{CompressionFormat, Compressor, Decompressor},
+    databases::DatabaseConfig,
+    encryption::{DecryptingReader, EncryptingWriter, EncryptionConfig},
+    storage::{provider::StorageProvider, Entry},
+};
+
+/// Prefix of the names of base backups, which keeps them out of the logical backups of the
+/// same configuration.
+pub const BASE_BACKUP_PREFIX: &str = "base-";
+/// Directory of the storage WAL files are archived under, one subdirectory per cluster.
+pub const WAL_DIRECTORY: &str = "wal";
+/// File PostgreSQL looks for in the data directory to start in targeted recovery.
+const RECOVERY_SIGNAL: &str = "recovery.signal";
+/// Settings file of the data directory read after `postgresql.conf`.
+const AUTO_CONF: &str = "postgresql.auto.conf";
+
+/// How a staged base backup recovers once the server starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryOptions {
+    /// Shell command fetching an archived WAL file, with `%f` replaced by its name and `%p` by
+    /// the path to copy it to, e.g. `dbkp wal-fetch --workspace prod %f %p`.
+    pub restore_command: String,
+    /// Stop replaying WAL at this time and promote, instead of replaying every archived file.
+    pub target_time: Option<DateTime<Utc>>,
+}
+
+/// Name of a new base backup of `config`, like `get_default_backup_name` with
+/// `BASE_BACKUP_PREFIX` in front.
+pub fn get_base_backup_name(config: &DatabaseConfig, format: &CompressionFormat) -> String {
+    format!(
+        "{}{}",
+        BASE_BACKUP_PREFIX,
+        get_default_backup_name(config, format)
+    )
+}
+
+/// The most recent base backup of the configuration named `cluster`, not of one whose name
+/// starts with it.
+pub async fn get_latest_base_backup(storage: &StorageProvider, cluster: &str) -> Result<Entry> {
+    storage
+        .get_nth_latest(&format!("{}{}", BASE_BACKUP_PREFIX, cluster), 0)
+        .await
+}
+
+/// Where the WAL file `file_name` of `cluster` is archived, refusing names PostgreSQL would
+/// never archive so they cannot escape the directory.
+pub fn get_wal_path(cluster: &str, file_name: &str) -> Result<String> {
+    let is_valid = |name: &str| {
+        !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+    };
+
+    if !is_valid(file_name) {
+        return Err(anyhow!("Invalid WAL file name: {:?}", file_name));
+    }
+
+    if !is_valid(cluster) {
+        return Err(anyhow!("Invalid cluster name: {:?}", cluster));
+    }
+
+    Ok(format!("{}/{}/{}", WAL_DIRECTORY, cluster, file_name))
+}
+
+/// Lines appended to `postgresql.auto.conf` to recover from the WAL archive.
+pub fn get_recovery_settings(options: &RecoveryOptions) -> String {
+    let quote = |value: &str| format!("'{}'", value.replace('\'', "''"));
+
+    let mut settings = format!("restore_command = {}\n", quote(&options.restore_command));
+
+    if let Some(target_time) = options.target_time {
+        settings.push_str(&format!(
+            "recovery_target_time = {}\nrecovery_target_action = 'promote'\n",
+            quote(&target_time.format("%Y-%m-%d %H:%M:%S%.6f+00").to_string())
+        ));
+    }
+
+    settings
+}
+
+/// Archives the WAL file at `path`, compressed and encrypted like backups, as PostgreSQL's
+/// `archive_command`. Archiving a file again succeeds when the archived copy is identical and
+/// fails otherwise, so a diverged timeline never overwrites the archive.
+pub async fn push_wal(
+    storage: &StorageProvider,
+    encryption: Option<&EncryptionConfig>,
+    cluster: &str,
+    path: &Path,
+) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("{} is not a WAL file", path.display()))?;
+    let object = get_wal_path(cluster, &file_name)?;
+    let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    if let Some(archived) = read_wal(storage, encryption, &object).await? {
+        if archived == content {
+            info!(
+                operation = "wal-push",
+                cluster,
+                path = object.as_str(),
+                status = "skipped";
+                "{} is already archived",
+                file_name
+            );
+            return Ok(());
+        }
+
+        return Err(anyhow!(
+            "{} is already archived with a different content",
+            file_name
+        ));
+    }
+
+    let writer = storage.create_writer(&object).await?;
+    let mut compressor = Compressor::new(
+        EncryptingWriter::new(writer, encryption)?,
+        CompressionFormat::Gzip,
+        Compression::default(),
+    );
+
+    compressor.write_all(&content)?;
+    compressor
+        .finish()?
+        .finish()?
+        .flush()
+        .with_context(|| format!("Failed to archive {}", file_name))?;
+
+    info!(
+        operation = "wal-push",
+        cluster,
+        path = object.as_str(),
+        bytes = content.len(),
+        status = "success";
+        "Archived {}",
+        file_name
+    );
+
+    Ok(())
+}
+
+/// Copies the archived WAL file `file_name` to `destination`, as PostgreSQL's
+/// `restore_command`. Returns `false` when it was never archived, which ends recovery.
+pub async fn fetch_wal(
+    storage: &StorageProvider,
+    encryption: Option<&EncryptionConfig>,
+    cluster: &str,
+    file_name: &str,
+    destination: &Path,
+) -> Result<bool> {
+    let object = get_wal_path(cluster, file_name)?;
+
+    let content = match read_wal(storage, encryption, &object).await? {
+        Some(content) => content,
+        None => return Ok(false),
+    };
+
+    // Written aside and renamed, so the server never reads a partial file
+    let directory = match destination.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut file = NamedTempFile::new_in(directory)
+        .with_context(|| format!("Failed to create a file in {}", directory.display()))?;
+    file.write_all(&content)?;
+    file.persist(destination)
+        .with_context(|| format!("Failed to write {}", destination.display()))?;
+
+    Ok(true)
+}
+
+async fn read_wal(
+    storage: &StorageProvider,
+    encryption: Option<&EncryptionConfig>,
+    object: &str,
+) -> Result<Option<Vec<u8>>> {
+    let archived = match storage.backend().read(object).await? {
+        Some(archived) => archived,
+        None => return Ok(None),
+    };
+
+    let reader = DecryptingReader::new(Cursor::new(archived), encryption)?;
+    let (format, reader) = Decompressor::sniff_format(reader)?;
+
+    let mut content = vec![];
+    Decompressor::new(reader, format)
+        .read_to_end(&mut content)
+        .with_context(|| format!("Failed to read {}", object))?;
+
+    Ok(Some(content))
+}
+
+/// Unpacks a base backup into `data_directory`, which must be empty or missing, and sets it
+/// up to recover from the WAL archive as `recovery` describes once the server starts.
+pub fn stage_base_backup(
+    reader: impl Read,
+    data_directory: &Path,
+    recovery: &RecoveryOptions,
+) -> Result<()> {
+    if data_directory.exists()
+        && fs::read_dir(data_directory)
+            .with_context(|| format!("Failed to read {}", data_directory.display()))?
+            .next()
+            .is_some()
+    {
+        return Err(anyhow!("{} is not empty", data_directory.display()));
+    }
+
+    fs::create_dir_all(data_directory)
+        .with_context(|| format!("Failed to create {}", data_directory.display()))?;
+
+    // The server refuses to start from a data directory others can read
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(data_directory, fs::Permissions::from_mode(0o700))?;
+    }
+
+    tar::Archive::new(reader)
+        .unpack(data_directory)
+        .context("Failed to unpack the base backup")?;
+
+    if !data_directory.join("PG_VERSION").exists() {
+        return Err(anyhow!(
+            "The backup is not a base backup, PG_VERSION is missing"
+        ));
+    }
+
+    fs::write(data_directory.join(RECOVERY_SIGNAL), "")?;
+
+    let mut auto_conf = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_directory.join(AUTO_CONF))?;
+    auto_conf.write_all(get_recovery_settings(recovery).as_bytes())?;
+
+    Ok(())
+}
+
+/// Downloads the base backup `name`, decrypting and decompressing it, and stages it in
+/// `data_directory` with `stage_base_backup`. The server is left for the caller to start.
+pub async fn restore_base_backup(
+    storage: &StorageProvider,
+    encryption: Option<&EncryptionConfig>,
+    name: &str,
+    data_directory: &Path,
+    recovery: &RecoveryOptions,
+) -> Result<()> {
+    if let Some(manifest) = storage.read_manifest(name).await? {
+        if !manifest.physical {
+            return Err(anyhow!("{} is a dump, not a base backup", name));
+        }
+    }
+
+    let reader = storage.create_reader(name).await?;
+    let reader = DecryptingReader::new(reader, encryption)?;
+    let (format, reader) = Decompressor::sniff_format(reader)?;
+
+    stage_base_backup(Decompressor::new(reader, format), data_directory, recovery)?;
+
+    info!("Staged {} in {}", name, data_directory.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod physical_tests {
+    use chrono::{TimeZone, Utc};
+    use tempfile::tempdir;
+
+    use crate::{
+        encryption::{EncryptionConfig, KdfParams},
+        storage::provider::{LocalStorageConfig, StorageConfig, StorageProvider},
+    };
+
+    use super::{
+        fetch_wal, get_latest_base_backup, get_recovery_settings, get_wal_path, push_wal,
+        stage_base_backup, RecoveryOptions,
+    };
+
+    #[test]
+    fn test_01_get_wal_path() {
+        assert_eq!(
+            get_wal_path("prod", "000000010000000000000003").unwrap(),
+            "wal/prod/000000010000000000000003"
+        );
+        assert_eq!(
+            get_wal_path("prod", "00000002.history").unwrap(),
+            "wal/prod/00000002.history"
+        );
+
+        for name in [
+            "",
+            "../postgresql.conf",
+            ".hidden",
+            "pg_wal/000000010000000000000003",
+        ] {
+            assert!(get_wal_path("prod", name).is_err(), "{:?}", name);
+        }
+        assert!(get_wal_path("../prod", "000000010000000000000003").is_err());
+    }
+
+    #[test]
+    fn test_02_get_recovery_settings() {
+        let mut options = RecoveryOptions {
+            restore_command: "dbkp wal-fetch --workspace 'prod' %f %p".into(),
+            target_time: None,
+        };
+
+        assert_eq!(
+            get_recovery_settings(&options),
+            "restore_command = 'dbkp wal-fetch --workspace ''prod'' %f %p'\n"
+        );
+
+        options.target_time = Some(Utc.with_ymd_and_hms(2025, 1, 15, 14, 30, 0).unwrap());
+        assert!(get_recovery_settings(&options).ends_with(
+            "recovery_target_time = '2025-01-15 14:30:00.000000+00'\nrecovery_target_action = 'promote'\n"
+        ));
+    }
+
+    #[test]
+    fn test_03_stage_base_backup() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (path, content) in [("PG_VERSION", "15\n"), ("postgresql.auto.conf", "")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o600);
+            header.set_cksum();
+            archive
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let dir = tempdir().unwrap();
+        let data_directory = dir.path().join("data");
+        let options = RecoveryOptions {
+            restore_command: "cp /archive/%f %p".into(),
+            target_time: None,
+        };
+
+        stage_base_backup(archive.as_slice(), &data_directory, &options).expect("Failed to stage");
+
+        assert!(data_directory.join("recovery.signal").exists());
+        assert_eq!(
+            std::fs::read_to_string(data_directory.join("postgresql.auto.conf")).unwrap(),
+            "restore_command = 'cp /archive/%f %p'\n"
+        );
+
+        // A data directory in use is never overwritten
+        assert!(stage_base_backup(archive.as_slice(), &data_directory, &options).is_err());
+
+        // Neither is a dump taken for a base backup
+        let other = dir.path().join("other");
+        assert!(stage_base_backup(&b""[..], &other, &options).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_04_push_and_fetch_wal() {
+        let dir = tempdir().unwrap();
+        let storage = StorageProvider::new(StorageConfig::Local(LocalStorageConfig {
+            id: "wal".into(),
+            name: "wal".into(),
+            location: dir.path().join("storage").to_string_lossy().to_string(),
+        }))
+        .expect("Failed to create provider");
+        let encryption = EncryptionConfig::new("secret").with_kdf_params(KdfParams {
+            memory: 64,
+            passes: 1,
+            lanes: 1,
+        });
+
+        let segment = dir.path().join("000000010000000000000003");
+        std::fs::write(&segment, vec![7u8; 64 * 1024]).unwrap();
+
+        push_wal(&storage, Some(&encryption), "prod", &segment)
+            .await
+            .expect("Failed to push");
+        // PostgreSQL retries an archived file after a crash
+        push_wal(&storage, Some(&encryption), "prod", &segment)
+            .await
+            .expect("Failed to push again");
+
+        let destination = dir.path().join("RECOVERYXLOG");
+        assert!(fetch_wal(
+            &storage,
+            Some(&encryption),
+            "prod",
+            "000000010000000000000003",
+            &destination
+        )
+        .await
+        .unwrap());
+        assert_eq!(std::fs::read(&destination).unwrap(), vec![7u8; 64 * 1024]);
+
+        // Another cluster, or a file never archived, ends recovery
+        assert!(!fetch_wal(
+            &storage,
+            Some(&encryption),
+            "staging",
+            "000000010000000000000003",
+            &destination
+        )
+        .await
+        .unwrap());
+
+        // A different file under an archived name is refused
+        std::fs::write(&segment, vec![8u8; 64 * 1024]).unwrap();
+        assert!(push_wal(&storage, Some(&encryption), "prod", &segment)
+            .await
+            .is_err());
+
+        // WAL files are not backups
+        assert!(storage.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_05_get_latest_base_backup() {
+        let dir = tempdir().unwrap();
+        let storage = StorageProvider::new(StorageConfig::Local(LocalStorageConfig {
+            id: "base".into(),
+            name: "base".into(),
+            location: dir.path().to_string_lossy().to_string(),
+        }))
+        .expect("Failed to create provider");
+
+        for name in [
+            "base-app-2026-01-01-000000-abcdef.gz",
+            "base-app-replica-2026-01-02-000000-abcdef.gz",
+            "app-2026-01-03-000000-abcdef.gz",
+        ] {
+            storage
+                .backend()
+                .write(name, vec![0; 16])
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let entry = get_latest_base_backup(&storage, "app")
+            .await
+            .expect("Failed to find the base backup");
+        assert_eq!(entry.metadata.name, "base-app-2026-01-01-000000-abcdef.gz");
+
+        let entry = get_latest_base_backup(&storage, "app-replica")
+            .await
+            .expect("Failed to find the base backup");
+        assert_eq!(
+            entry.metadata.name,
+            "base-app-replica-2026-01-02-000000-abcdef.gz"
+        );
+        assert!(get_latest_base_backup(&storage, "ap").await.is_err());
+    }
+}
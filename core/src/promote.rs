@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{RestoreOptions, RestoreReport};
+
+/// PostgreSQL truncates identifiers longer than this many bytes.
+const MAX_DATABASE_NAME_LENGTH: usize = 63;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PromoteOptions {
+    /// The restore into the candidate database, which is always created from scratch.
+    pub restore: RestoreOptions,
+    /// Queries run against the candidate before promoting it, each returning a single boolean,
+    /// e.g. `SELECT count(*) > 0 FROM users`.
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// Keep the replaced database under its new name instead of dropping it, for rollbacks.
+    #[serde(default)]
+    pub keep_previous: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromoteReport {
+    /// Name the backup was restored under before taking the place of the live database.
+    pub candidate: String,
+    /// Where the replaced database was kept, when there was one and `keep_previous` was set.
+    pub previous: Option<String>,
+    pub restore: RestoreReport,
+    pub checks_passed: usize,
+}
+
+/// Name of a database derived from `database`, e.g. `app_restore_20240115143022`, shortening
+/// `database` so the suffix survives PostgreSQL's identifier length limit.
+pub fn get_promotion_name(database: &str, label: &str, timestamp: DateTime<Utc>) -> String {
+    let suffix = format!("_{}_{}", label, timestamp.format("%Y%m%d%H%M%S"));
+    let mut length = database
+        .len()
+        .min(MAX_DATABASE_NAME_LENGTH.saturating_sub(suffix.len()));
+
+    while !database.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    format!("{}{}", &database[..length], suffix)
+}
+
+#[cfg(test)]
+mod promote_tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::get_promotion_name;
+
+    #[test]
+    fn test_01_get_promotion_name() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 15, 14, 30, 22).unwrap();
+
+        assert_eq!(
+            get_promotion_name("app", "restore", timestamp),
+            "app_restore_20240115143022"
+        );
+
+        let name = get_promotion_name(&"é".repeat(40), "previous", timestamp);
+        assert!(name.len() <= 63);
+        assert!(name.ends_with("_previous_20240115143022"));
+    }
+}
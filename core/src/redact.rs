@@ -0,0 +1,133 @@
+/// Written in place of passwords, keys and passphrases by `Debug` and the `redacted` configs.
+pub const REDACTED: &str = "***";
+
+/// `Some("***")` for a set secret, so `Debug` still tells whether there is one.
+pub(crate) fn redact_option(secret: &Option<String>) -> Option<&'static str> {
+    secret.as_ref().map(|_| REDACTED)
+}
+
+#[cfg(test)]
+mod redact_tests {
+    use crate::{
+        databases::{
+            ssh_tunnel::{SshAuthMethod, SshJumpHost, SshTunnelConfig},
+            ConnectionType, DatabaseConfig,
+        },
+        storage::provider::{S3StorageConfig, StorageConfig, StorageCredentials},
+    };
+
+    #[test]
+    fn test_01_debug_hides_secrets() {
+        let config = DatabaseConfig::builder(ConnectionType::PostgreSql)
+            .with_host("localhost")
+            .with_database("app")
+            .with_username("postgres")
+            .with_password(Some("db-secret".into()))
+            .with_ssh_tunnel(Some(SshTunnelConfig {
+                host: "bastion".into(),
+                port: 22,
+                username: "admin".into(),
+                auth_method: SshAuthMethod::Password {
+                    password: "ssh-secret".into(),
+                },
+                jump_hosts: vec![SshJumpHost {
+                    host: "jump".into(),
+                    port: 22,
+                    username: "admin".into(),
+                    auth_method: SshAuthMethod::PrivateKey {
+                        key_path: "~/.ssh/id_ed25519".into(),
+                        passphrase_key: Some("key-secret".into()),
+                    },
+                }],
+                known_hosts: None,
+                host_key_verification: Default::default(),
+                local_ports: None,
+            }))
+            .build()
+            .unwrap();
+
+        let debug = format!("{:?}", config);
+        for secret in ["db-secret", "ssh-secret", "key-secret"] {
+            assert!(!debug.contains(secret), "{}", debug);
+        }
+        assert!(debug.contains("password: Some(\"***\")"), "{}", debug);
+        assert!(debug.contains("~/.ssh/id_ed25519"), "{}", debug);
+
+        let storage = StorageConfig::S3(S3StorageConfig {
+            id: "s3".into(),
+            name: "s3".into(),
+            region: "us-east-1".into(),
+            endpoint: None,
+            bucket: "backups".into(),
+            access_key: Some("AKIAEXAMPLE".into()),
+            secret_key: Some("s3-secret".into()),
+            location: "app".into(),
+            retry: Default::default(),
+            upload: Default::default(),
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            storage_class: None,
+            enable_virtual_host_style: false,
+        });
+        let debug = format!("{:?}", storage);
+        assert!(!debug.contains("s3-secret"), "{}", debug);
+        assert!(debug.contains("AKIAEXAMPLE"), "{}", debug);
+
+        let credentials = StorageCredentials::Basic {
+            username: "backup".into(),
+            password: "sftp-secret".into(),
+        };
+        assert_eq!(
+            format!("{:?}", credentials),
+            "Basic { username: \"backup\", password: \"***\" }"
+        );
+    }
+
+    #[test]
+    fn test_02_redacted_configs() {
+        let config = DatabaseConfig::builder(ConnectionType::MySql)
+            .with_host("localhost")
+            .with_database("app")
+            .with_username("root")
+            .with_password(Some("db-secret".into()))
+            .build()
+            .unwrap();
+
+        let redacted = config.redacted();
+        assert_eq!(redacted.password, Some("***".into()));
+        assert_eq!(redacted.username, "root");
+        // Serializing the config itself keeps the secrets, e.g. for workspace files
+        assert!(serde_json::to_string(&config)
+            .unwrap()
+            .contains("db-secret"));
+        assert!(!serde_json::to_string(&redacted)
+            .unwrap()
+            .contains("db-secret"));
+    }
+
+    #[cfg(feature = "ssh")]
+    #[test]
+    fn test_03_redacted_sftp_storage() {
+        use crate::storage::provider::SftpStorageConfig;
+
+        let storage = StorageConfig::Sftp(SftpStorageConfig {
+            id: "sftp".into(),
+            name: "sftp".into(),
+            host: "backup.example.com".into(),
+            port: 22,
+            credentials: StorageCredentials::PrivateKey {
+                username: "backup".into(),
+                key_path: "~/.ssh/id_ed25519".into(),
+                passphrase: Some("key-secret".into()),
+            },
+            location: "/srv/backups".into(),
+        });
+        match storage.redacted() {
+            StorageConfig::Sftp(SftpStorageConfig {
+                credentials: StorageCredentials::PrivateKey { passphrase, .. },
+                ..
+            }) => assert_eq!(passphrase, Some("***".into())),
+            config => panic!("Expected an SFTP storage, got {:?}", config),
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::pin,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{
+    DateTime, Datelike, Duration, DurationRound, LocalResult, NaiveDate, NaiveDateTime, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    storage::provider::{CleanupOptions, CleanupReport},
+    BackupOptions, BackupReport, DbBkp,
+};
+
+/// Longest sleep between two checks of the schedule, so clock changes and runs recorded by
+/// another process are noticed.
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+/// How far ahead a cron expression is searched before it is considered to never match, e.g.
+/// `0 0 30 2 *`.
+const MAX_CRON_SEARCH_YEARS: i32 = 5;
+
+/// A standard 5-field cron expression: minute, hour, day of month, month and day of week, each
+/// a `*`, a value, a range `a-b`, a step `*/n` or `a-b/n`, or a list of those. Sunday is 0 or 7.
+/// `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are accepted too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day of month field is `*`. When both day fields are restricted, a day
+    /// matching either runs, like cron does.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid step in cron field: {}", part))?;
+                if step == 0 {
+                    return Err(anyhow!("Invalid step in cron field: {}", part));
+                }
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+
+        let parse = |value: &str| {
+            value
+                .parse::<u32>()
+                .map_err(|_| anyhow!("Invalid value in cron field: {}", part))
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse(start)?, parse(end)?)
+        } else {
+            let start = parse(range)?;
+            // `5/15` runs from 5 to the end of the range, every 15
+            (start, if step.is_some() { max } else { start })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(anyhow!(
+                "Cron field {} is out of range {}-{}",
+                part,
+                min,
+                max
+            ));
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(anyhow!(
+                "A cron expression has 5 fields (minute hour day-of-month month day-of-week): {}",
+                expression
+            ));
+        };
+
+        let mut days_of_week = parse_cron_field(day_of_week, 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_cron_field(minute, 0, 59)?,
+            hours: parse_cron_field(hour, 0, 23)?,
+            days_of_month: parse_cron_field(day_of_month, 1, 31)?,
+            months: parse_cron_field(month, 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_day_of_week: day_of_week.starts_with('*'),
+        })
+    }
+}
+
+impl CronSchedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+
+    /// First local time strictly after `after` matching the expression.
+    fn next_local(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start_of_day = |date: NaiveDate| date.and_hms_opt(0, 0, 0);
+        let limit = after.year() + MAX_CRON_SEARCH_YEARS;
+
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+
+        while time.year() <= limit {
+            if self.months & (1 << time.month()) == 0 {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = start_of_day(NaiveDate::from_ymd_opt(year, month, 1)?)?;
+            } else if !self.matches_day(time.date()) {
+                time = start_of_day(time.date().succ_opt()?)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+
+        None
+    }
+
+    /// First time strictly after `after` matching the expression in `timezone`. Local times
+    /// skipped by a DST change never run; repeated ones run at their first occurrence.
+    pub fn next_after(&self, after: DateTime<Utc>, timezone: &Tz) -> Option<DateTime<Utc>> {
+        let mut local = after.with_timezone(timezone).naive_local();
+
+        loop {
+            local = self.next_local(local)?;
+
+            match timezone.from_local_datetime(&local) {
+                LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => {
+                    let time = time.with_timezone(&Utc);
+                    // The first occurrence of a repeated hour may already be past
+                    if time > after {
+                        return Some(time);
+                    }
+                }
+                LocalResult::None => {}
+            }
+        }
+    }
+}
+
+/// When scheduled backups run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// This long after the start of the previous run.
+    Every(Duration),
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// First run strictly after `last_run`.
+    pub fn next_after(&self, last_run: DateTime<Utc>, timezone: &Tz) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => last_run.checked_add_signed(*interval),
+            Schedule::Cron(cron) => cron.next_after(last_run, timezone),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SchedulerState {
+    last_run: Option<DateTime<Utc>>,
+}
+
+/// One scheduled backup, with the cleanup that followed it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledRun {
+    pub started_at: DateTime<Utc>,
+    pub backup: BackupReport,
+    /// `None` without a retention, or when the cleanup failed as a whole.
+    pub cleanup: Option<CleanupReport>,
+}
+
+/// Takes backups on a schedule and applies the retention after each successful one, as a
+/// long-lived alternative to calling the CLI from cron.
+///
+/// The start of the last run is kept in a state file, so a restarted scheduler waits for the
+/// next slot instead of running again. Runs missed while it was stopped are caught up once,
+/// as soon as it starts.
+pub struct Scheduler {
+    core: DbBkp,
+    schedule: Schedule,
+    timezone: Tz,
+    backup_options: Option<BackupOptions>,
+    cleanup: Option<CleanupOptions>,
+    state_path: Option<PathBuf>,
+}
+
+impl Scheduler {
+    pub fn new(core: DbBkp, schedule: Schedule) -> Self {
+        Self {
+            core,
+            schedule,
+            timezone: Tz::UTC,
+            backup_options: None,
+            cleanup: None,
+            state_path: None,
+        }
+    }
+
+    /// Timezone cron expressions are evaluated in, UTC by default.
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    pub fn with_backup_options(mut self, options: BackupOptions) -> Self {
+        self.backup_options = Some(options);
+        self
+    }
+
+    /// Cleans the storage up with `options` after every successful backup.
+    pub fn with_cleanup(mut self, options: CleanupOptions) -> Self {
+        self.cleanup = Some(options);
+        self
+    }
+
+    /// Keeps the time of the last run in this JSON file. Without it, every start waits for
+    /// the next slot.
+    pub fn with_state_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.state_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Start of the last run recorded in the state file.
+    pub fn last_run(&self) -> Result<Option<DateTime<Utc>>> {
+        let Some(path) = &self.state_path else {
+            return Ok(None);
+        };
+
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read scheduler state {}", path.display()))
+            }
+        };
+
+        let state: SchedulerState = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse scheduler state {}", path.display()))?;
+
+        Ok(state.last_run)
+    }
+
+    fn record_run(&self, started_at: DateTime<Utc>) -> Result<()> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+
+        let content = serde_json::to_vec_pretty(&SchedulerState {
+            last_run: Some(started_at),
+        })?;
+        // Written aside and renamed so a crash never leaves a partial state
+        let temp_path = path.with_extension("tmp");
+
+        fs::write(&temp_path, content)
+            .with_context(|| format!("Failed to write scheduler state {}", temp_path.display()))?;
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to write scheduler state {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// When the next backup runs, given the last one: right away when a run was missed,
+    /// otherwise at the next slot after `now` on a first start.
+    pub fn next_run(
+        &self,
+        last_run: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>> {
+        let next = self
+            .schedule
+            .next_after(last_run.unwrap_or(now), &self.timezone)
+            .ok_or_else(|| anyhow!("The schedule never runs"))?;
+
+        Ok(next.max(now))
+    }
+
+    /// Takes one backup now, then applies the retention if it succeeded. The run is recorded
+    /// whether it succeeds or not, so a failing backup waits for the next slot.
+    pub async fn run_once(&self) -> Result<ScheduledRun> {
+        let started_at = Utc::now().duration_trunc(Duration::seconds(1))?;
+        self.record_run(started_at)?;
+
+        let backup = self
+            .core
+            .backup_with_report(self.backup_options.clone())
+            .await?;
+
+        let cleanup = match &self.cleanup {
+            Some(options) => match self
+                .core
+                .storage_provider
+                .cleanup_with_options(options)
+                .await
+            {
+                Ok(report) => {
+                    info!(
+                        operation = "cleanup",
+                        storage = self.core.storage_provider.config.name(),
+                        deleted = report.deleted.len(),
+                        failed = report.failed.len(),
+                        bytes = report.reclaimed,
+                        status = if report.is_success() { "success" } else { "failure" };
+                        "Cleanup deleted {} backups, {} failed",
+                        report.deleted.len(),
+                        report.failed.len()
+                    );
+                    Some(report)
+                }
+                Err(e) => {
+                    warn!("Cleanup failed: {:#}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(ScheduledRun {
+            started_at,
+            backup,
+            cleanup,
+        })
+    }
+
+    /// Runs backups on the schedule until `shutdown` completes, e.g. `tokio::signal::ctrl_c()`.
+    /// A backup in progress is finished first. Failed runs are logged and the schedule goes on.
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let mut shutdown = pin!(shutdown);
+        let started_at = Utc::now();
+        let mut last_run = None;
+        let mut announced = None;
+
+        loop {
+            // Without a state file, or before it is written, runs are counted from the start
+            last_run = last_run.max(self.last_run()?);
+
+            let now = Utc::now();
+            let next = self.next_run(last_run.or(Some(started_at)), now)?;
+
+            if next > now {
+                if announced != Some(next) {
+                    info!("Next backup at {}", next);
+                    announced = Some(next);
+                }
+
+                let wait = (next - now).to_std().unwrap_or_default().min(MAX_SLEEP);
+
+                tokio::select! {
+                    _ = &mut shutdown => break,
+                    _ = tokio::time::sleep(wait) => continue,
+                }
+            }
+
+            last_run = Some(now);
+
+            if let Err(e) = self.run_once().await {
+                error!("Scheduled backup failed: {:#}", e);
+            }
+        }
+
+        info!("Scheduler stopped");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod scheduler_tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use super::{CronSchedule, Schedule};
+
+    fn time(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_01_parse_cron() {
+        for expression in [
+            "0 2 * * *",
+            "*/15 * * * 1-5",
+            "0 0 1,15 * *",
+            "@daily",
+            "0 3 * * 7",
+        ] {
+            assert!(expression.parse::<CronSchedule>().is_ok(), "{}", expression);
+        }
+
+        for expression in [
+            "",
+            "0 2 * *",
+            "60 * * * *",
+            "0 24 * * *",
+            "*/0 * * * *",
+            "a * * * *",
+        ] {
+            assert!(
+                expression.parse::<CronSchedule>().is_err(),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_02_next_cron_run() {
+        let next = |expression: &str, after: &str| {
+            expression
+                .parse::<CronSchedule>()
+                .unwrap()
+                .next_after(time(after), &Tz::UTC)
+        };
+
+        assert_eq!(
+            next("0 2 * * *", "2025-01-15T01:59:30Z"),
+            Some(time("2025-01-15T02:00:00Z"))
+        );
+        // A run recorded at its slot does not fire again
+        assert_eq!(
+            next("0 2 * * *", "2025-01-15T02:00:00Z"),
+            Some(time("2025-01-16T02:00:00Z"))
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2025-01-15T10:16:00Z"),
+            Some(time("2025-01-15T10:30:00Z"))
+        );
+        // 2025-01-18 is a Saturday
+        assert_eq!(
+            next("30 6 * * 1-5", "2025-01-17T07:00:00Z"),
+            Some(time("2025-01-20T06:30:00Z"))
+        );
+        assert_eq!(
+            next("0 0 1 * *", "2025-12-31T12:00:00Z"),
+            Some(time("2026-01-01T00:00:00Z"))
+        );
+        // Both day fields restricted: either matches
+        assert_eq!(
+            next("0 0 15 * 0", "2025-01-13T00:00:00Z"),
+            Some(time("2025-01-15T00:00:00Z"))
+        );
+        assert_eq!(next("0 0 30 2 *", "2025-01-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_03_next_cron_run_in_timezone() {
+        let paris: Tz = "Europe/Paris".parse().unwrap();
+        let cron: CronSchedule = "30 2 * * *".parse().unwrap();
+
+        assert_eq!(
+            cron.next_after(time("2025-01-15T00:00:00Z"), &paris),
+            Some(time("2025-01-15T01:30:00Z"))
+        );
+        // 02:30 does not exist on 2025-03-30 in Paris
+        assert_eq!(
+            cron.next_after(time("2025-03-29T02:00:00Z"), &paris),
+            Some(time("2025-03-31T00:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_04_next_interval_run() {
+        let schedule = Schedule::Every(Duration::hours(6));
+        let last_run = Utc.with_ymd_and_hms(2025, 1, 15, 2, 0, 0).unwrap();
+
+        assert_eq!(
+            schedule.next_after(last_run, &Tz::UTC),
+            Some(Utc.with_ymd_and_hms(2025, 1, 15, 8, 0, 0).unwrap())
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    ops::Range,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use opendal::{ErrorKind, Operator};
+
+use super::{
+    io::{ResumableReader, StorageReader, StorageWriter, READ_CHUNK_SIZE},
+    provider::UploadOptions,
+    Entry, EntryMetadata,
+};
+
+/// The storage operations the rest of dbkp is built on: backups, retention, verification and
+/// copies only go through this trait. `OpendalBackend` implements it for the built-in storages;
+/// implement it to store backups anywhere else and plug it in with
+/// `StorageProvider::from_backend`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Every file under the root, recursively. Directories are not returned.
+    async fn list(&self) -> Result<Vec<Entry>>;
+
+    /// Every file under the root as it is listed, in no particular order. Backends able to
+    /// list incrementally should override it so large storages are never held in memory.
+    async fn list_stream(&self) -> Result<BoxStream<'_, Result<Entry>>> {
+        Ok(futures::stream::iter(self.list().await?.into_iter().map(Ok)).boxed())
+    }
+
+    /// Files sorted by path, after `start_after`, until `limit` of them pass `filter`.
+    /// Backends able to resume a listing should override it so pages do not load everything.
+    async fn list_after(
+        &self,
+        start_after: Option<&str>,
+        limit: usize,
+        filter: &(dyn for<'e> Fn(&'e Entry) -> bool + Send + Sync),
+    ) -> Result<Vec<Entry>> {
+        Ok(get_page(self.list().await?, start_after, limit, filter))
+    }
+
+    /// Returns `None` when `path` does not exist.
+    async fn stat(&self, path: &str) -> Result<Option<EntryMetadata>>;
+
+    /// The object must only become visible once the writer is flushed.
+    async fn create_writer(&self, path: &str) -> Result<Box<dyn Write + Send + Unpin>>;
+
+    async fn create_reader(&self, path: &str) -> Result<Box<dyn Read + Send + Unpin>>;
+
+    /// Reads a whole small object such as a manifest, `None` when it does not exist.
+    async fn read(&self, path: &str) -> Result<Option<Vec<u8>>>;
+
+    async fn read_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>>;
+
+    async fn write(&self, path: &str, content: Vec<u8>) -> Result<()>;
+
+    /// Deleting a path that does not exist succeeds.
+    async fn delete(&self, path: &str) -> Result<()>;
+
+    /// Bytes free for new objects, `None` when the storage cannot tell, like object stores.
+    async fn available_space(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+fn get_page(
+    entries: Vec<Entry>,
+    start_after: Option<&str>,
+    limit: usize,
+    filter: &(dyn for<'e> Fn(&'e Entry) -> bool + Send + Sync),
+) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = entries
+        .into_iter()
+        .filter(|entry| match start_after {
+            Some(start_after) => entry.path.as_str() > start_after,
+            None => true,
+        })
+        .filter(|entry| filter(entry))
+        .collect();
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries.truncate(limit);
+    entries
+}
+
+pub struct OpendalBackend {
+    operator: Operator,
+    /// Root of a local filesystem storage, whose listings carry no sizes.
+    local_root: Option<PathBuf>,
+    upload: Option<UploadOptions>,
+    max_resumes: usize,
+    read_chunk_size: usize,
+}
+
+impl OpendalBackend {
+    pub fn new(operator: Operator, local_root: Option<PathBuf>) -> Self {
+        Self {
+            operator,
+            local_root,
+            upload: None,
+            max_resumes: 0,
+            read_chunk_size: READ_CHUNK_SIZE,
+        }
+    }
+
+    /// Uploads backups in parts of `upload.chunk_size`, `upload.concurrency` at a time, instead
+    /// of the service defaults.
+    pub fn with_upload(mut self, upload: UploadOptions) -> Self {
+        self.upload = Some(upload);
+        self
+    }
+
+    /// Resumes interrupted downloads from the last byte read, up to `max_resumes` times in a
+    /// row, instead of failing the read.
+    pub fn with_resume(mut self, max_resumes: usize) -> Self {
+        self.max_resumes = max_resumes;
+        self
+    }
+
+    /// Downloads in ranged reads of `read_chunk_size` bytes at most instead of 8MB, the memory
+    /// a reader holds at once.
+    pub fn with_read_chunk_size(mut self, read_chunk_size: usize) -> Self {
+        self.read_chunk_size = read_chunk_size;
+        self
+    }
+
+    pub fn operator(&self) -> &Operator {
+        &self.operator
+    }
+
+    fn get_entry(&self, opendal_entry: &opendal::Entry) -> Entry {
+        let mut entry = Entry::from(opendal_entry);
+
+        if let Some(root) = &self.local_root {
+            entry.metadata.content_length = fs::metadata(root.join(&entry.path))
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+        }
+
+        entry
+    }
+}
+
+#[async_trait]
+impl StorageBackend for OpendalBackend {
+    async fn list(&self) -> Result<Vec<Entry>> {
+        let entries = self
+            .operator
+            .list_with("")
+            .recursive(true)
+            .await
+            .context("Failed to list backups")?;
+
+        Ok(entries
+            .iter()
+            .filter(|entry| entry.metadata().is_file())
+            .map(|entry| self.get_entry(entry))
+            .collect())
+    }
+
+    async fn list_stream(&self) -> Result<BoxStream<'_, Result<Entry>>> {
+        let lister = self
+            .operator
+            .lister_with("")
+            .recursive(true)
+            .await
+            .context("Failed to list backups")?;
+
+        Ok(lister
+            .try_filter(|entry| futures::future::ready(entry.metadata().is_file()))
+            .map(|entry| {
+                entry
+                    .map(|entry| self.get_entry(&entry))
+                    .context("Failed to list backups")
+            })
+            .boxed())
+    }
+
+    async fn list_after(
+        &self,
+        start_after: Option<&str>,
+        limit: usize,
+        filter: &(dyn for<'e> Fn(&'e Entry) -> bool + Send + Sync),
+    ) -> Result<Vec<Entry>> {
+        if !self.operator.info().full_capability().list_with_start_after {
+            // Services without native cursors (e.g. local filesystem) are listed and sorted
+            return Ok(get_page(self.list().await?, start_after, limit, filter));
+        }
+
+        let mut lister = self.operator.lister_with("").recursive(true);
+
+        if let Some(start_after) = start_after {
+            lister = lister.start_after(start_after);
+        }
+
+        let mut lister = lister.await.context("Failed to list backups")?;
+        let mut entries = vec![];
+
+        while let Some(opendal_entry) = lister.try_next().await? {
+            if !opendal_entry.metadata().is_file() {
+                continue;
+            }
+
+            let entry = self.get_entry(&opendal_entry);
+
+            if filter(&entry) {
+                entries.push(entry);
+            }
+
+            if entries.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<EntryMetadata>> {
+        match self.operator.stat(path).await {
+            Ok(metadata) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                Ok(Some(EntryMetadata::from_opendal(name, &metadata)))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to stat {}", path)),
+        }
+    }
+
+    async fn create_writer(&self, path: &str) -> Result<Box<dyn Write + Send + Unpin>> {
+        let writer = match &self.upload {
+            Some(upload) => {
+                self.operator
+                    .writer_with(path)
+                    .chunk(upload.chunk_size)
+                    .concurrent(upload.concurrency)
+                    .await?
+            }
+            None => self.operator.writer(path).await?,
+        };
+
+        Ok(Box::new(StorageWriter::new(writer)?))
+    }
+
+    async fn create_reader(&self, path: &str) -> Result<Box<dyn Read + Send + Unpin>> {
+        if self.max_resumes == 0 {
+            return Ok(Box::new(StorageReader::new(
+                self.operator.clone(),
+                path.to_string(),
+                self.read_chunk_size,
+            )));
+        }
+
+        let operator = self.operator.clone();
+        let path = path.to_string();
+        let chunk_size = self.read_chunk_size;
+
+        Ok(Box::new(ResumableReader::new(
+            move |offset| StorageReader::new_at(operator.clone(), path.clone(), offset, chunk_size),
+            self.max_resumes,
+        )))
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match self.operator.read(path).await {
+            Ok(content) => Ok(Some(content.to_vec())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to read {}", path)),
+        }
+    }
+
+    async fn read_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        let content = self
+            .operator
+            .read_with(path)
+            .range(range)
+            .await
+            .context(format!("Failed to read {}", path))?;
+
+        Ok(content.to_vec())
+    }
+
+    async fn write(&self, path: &str, content: Vec<u8>) -> Result<()> {
+        self.operator
+            .write(path, content)
+            .await
+            .context(format!("Failed to write {}", path))?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.operator
+            .delete(path)
+            .await
+            .context(format!("Failed to delete {}", path))?;
+
+        Ok(())
+    }
+
+    async fn available_space(&self) -> Result<Option<u64>> {
+        let Some(root) = &self.local_root else {
+            return Ok(None);
+        };
+
+        let space = fs4::available_space(root)
+            .with_context(|| format!("Failed to read the free space of {}", root.display()))?;
+
+        Ok(Some(space))
+    }
+}
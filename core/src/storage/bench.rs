@@ -0,0 +1,143 @@
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::provider::StorageProvider;
+
+const BENCH_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub const DEFAULT_BENCH_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub size: u64,
+    pub write_duration: Duration,
+    pub read_duration: Duration,
+    /// Time to write and close a one byte object.
+    pub write_latency: Duration,
+    /// Time to read back a one byte object.
+    pub read_latency: Duration,
+}
+
+impl BenchReport {
+    /// Write throughput in bytes per second.
+    pub fn write_throughput(&self) -> f64 {
+        self.size as f64 / self.write_duration.as_secs_f64()
+    }
+
+    /// Read throughput in bytes per second.
+    pub fn read_throughput(&self) -> f64 {
+        self.size as f64 / self.read_duration.as_secs_f64()
+    }
+}
+
+/// Fills `buffer` with pseudo-random bytes so the storage cannot benefit from compression.
+fn fill_synthetic(buffer: &mut [u8], seed: &mut u64) {
+    for byte in buffer.iter_mut() {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *byte = *seed as u8;
+    }
+}
+
+fn write_object(mut writer: Box<dyn Write + Send + Unpin>, size: u64) -> Result<Duration> {
+    let mut buffer = vec![0u8; BENCH_CHUNK_SIZE];
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    let mut remaining = size;
+
+    let start = Instant::now();
+
+    while remaining > 0 {
+        let len = remaining.min(BENCH_CHUNK_SIZE as u64) as usize;
+        fill_synthetic(&mut buffer[..len], &mut seed);
+        writer.write_all(&buffer[..len])?;
+        remaining -= len as u64;
+    }
+
+    writer.flush()?;
+
+    Ok(start.elapsed())
+}
+
+fn read_object(mut reader: Box<dyn Read + Send + Unpin>, size: u64) -> Result<Duration> {
+    let mut buffer = vec![0u8; BENCH_CHUNK_SIZE];
+    let mut total = 0;
+
+    let start = Instant::now();
+
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+    }
+
+    let elapsed = start.elapsed();
+
+    if total != size {
+        return Err(anyhow!("Read {} bytes back, expected {}", total, size));
+    }
+
+    Ok(elapsed)
+}
+
+impl StorageProvider {
+    /// Writes `size` bytes of synthetic data through the storage, reads them back and deletes
+    /// the object, measuring throughput and latency of both directions.
+    pub async fn bench(&self, size: u64) -> Result<BenchReport> {
+        if size == 0 {
+            return Err(anyhow!("Benchmark size must be greater than zero"));
+        }
+
+        let name = format!(".dbkp-bench-{}", Uuid::new_v4());
+        let probe_name = format!("{}-probe", name);
+
+        let result = self.run_bench(&name, &probe_name, size).await;
+
+        for path in [&name, &probe_name] {
+            if let Err(e) = self.delete(path).await {
+                warn!("Failed to clean up benchmark object {}: {}", path, e);
+            }
+        }
+
+        result
+    }
+
+    async fn run_bench(&self, name: &str, probe_name: &str, size: u64) -> Result<BenchReport> {
+        let probe_writer = self.create_writer(probe_name).await?;
+        let writer = self.create_writer(name).await?;
+
+        let (write_latency, write_duration) =
+            tokio::task::spawn_blocking(move || -> Result<(Duration, Duration)> {
+                Ok((write_object(probe_writer, 1)?, write_object(writer, size)?))
+            })
+            .await?
+            .context("Failed to write benchmark object")?;
+
+        let probe_reader = self.create_reader(probe_name).await?;
+        let reader = self.create_reader(name).await?;
+
+        let (read_latency, read_duration) =
+            tokio::task::spawn_blocking(move || -> Result<(Duration, Duration)> {
+                Ok((read_object(probe_reader, 1)?, read_object(reader, size)?))
+            })
+            .await?
+            .context("Failed to read benchmark object")?;
+
+        Ok(BenchReport {
+            size,
+            write_duration,
+            read_duration,
+            write_latency,
+            read_latency,
+        })
+    }
+}
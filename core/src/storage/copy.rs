@@ -0,0 +1,190 @@
+use std::{
+    io::{self, BufWriter, Write},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{checksum::get_sidecar_name, manifest::get_manifest_name};
+
+use super::provider::StorageProvider;
+
+const COPY_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyOptions {
+    pub max_retries: Option<u32>,
+    pub retry_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyFailure {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyReport {
+    pub copied: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<CopyFailure>,
+}
+
+impl StorageProvider {
+    /// Copies every backup and its manifest to `destination`.
+    ///
+    /// Backups already present at the destination with the same size (and the same checksum
+    /// when both manifests record one) are skipped, so an interrupted migration can simply be
+    /// run again. Failures are retried with exponential backoff, then recorded in the report
+    /// instead of aborting the whole copy.
+    pub async fn copy_to(
+        &self,
+        destination: &StorageProvider,
+        options: CopyOptions,
+    ) -> Result<CopyReport> {
+        let max_retries = options.max_retries.unwrap_or(3);
+        let retry_delay = options.retry_delay.unwrap_or(Duration::from_millis(500));
+
+        let mut report = CopyReport::default();
+        let storage = destination.config.name();
+
+        for entry in self.list().await? {
+            let name = entry.path;
+            let path = name.as_str();
+
+            match self.is_copied(destination, path).await {
+                Ok(true) => {
+                    info!(
+                        operation = "copy",
+                        storage,
+                        path,
+                        status = "skipped";
+                        "{} already exists at destination, skipping",
+                        path
+                    );
+                    report.skipped.push(name);
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to check {} at destination: {}", name, e),
+            }
+
+            let mut attempt = 0;
+
+            loop {
+                match self.copy_backup(destination, path).await {
+                    Ok(()) => {
+                        info!(
+                            operation = "copy",
+                            storage,
+                            path,
+                            attempts = attempt + 1,
+                            status = "success";
+                            "Copied {}",
+                            path
+                        );
+                        report.copied.push(name);
+                        break;
+                    }
+                    Err(e) if attempt < max_retries => {
+                        let delay = retry_delay * 2u32.pow(attempt);
+                        attempt += 1;
+                        warn!(
+                            "Failed to copy {} (attempt {}/{}), retrying in {:?}: {}",
+                            name, attempt, max_retries, delay, e
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(e) => {
+                        let error = format!("{:#}", e);
+                        warn!(
+                            operation = "copy",
+                            storage,
+                            path,
+                            attempts = attempt + 1,
+                            status = "failure",
+                            error = error.as_str();
+                            "Failed to copy {}: {}",
+                            path,
+                            error
+                        );
+                        report.failed.push(CopyFailure { name, error });
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn is_copied(&self, destination: &StorageProvider, name: &str) -> Result<bool> {
+        let destination_metadata = match destination.backend().stat(name).await? {
+            Some(metadata) => metadata,
+            None => return Ok(false),
+        };
+
+        let metadata = self.stat(name).await?;
+
+        if metadata.content_length != destination_metadata.content_length {
+            return Ok(false);
+        }
+
+        let manifest = self.read_manifest(name).await?;
+        let destination_manifest = destination.read_manifest(name).await?;
+
+        match (manifest, destination_manifest) {
+            (Some(manifest), Some(destination_manifest)) => {
+                match (manifest.checksum, destination_manifest.checksum) {
+                    (Some(checksum), Some(destination_checksum)) => {
+                        Ok(checksum == destination_checksum)
+                    }
+                    _ => Ok(true),
+                }
+            }
+            // A missing manifest means the previous copy was interrupted
+            (Some(_), None) => Ok(false),
+            (None, _) => Ok(true),
+        }
+    }
+
+    async fn copy_backup(&self, destination: &StorageProvider, name: &str) -> Result<()> {
+        let mut reader = self.create_reader(name).await?;
+        let writer = destination.create_writer(name).await?;
+
+        tokio::task::spawn_blocking(move || -> io::Result<()> {
+            // Storage writers upload every write, so data is sent in large chunks
+            let mut writer = BufWriter::with_capacity(COPY_CHUNK_SIZE, writer);
+            io::copy(&mut reader, &mut writer)?;
+            writer.flush()
+        })
+        .await?
+        .context(format!("Failed to copy {}", name))?;
+
+        if let Some(sidecar) = self.backend().read(&get_sidecar_name(name)).await? {
+            destination
+                .backend()
+                .write(&get_sidecar_name(name), sidecar)
+                .await
+                .context(format!(
+                    "Failed to copy checksum sidecar {}",
+                    get_sidecar_name(name)
+                ))?;
+        }
+
+        // The manifest goes last so a present manifest implies a complete backup
+        if let Some(manifest) = self.read_manifest(name).await? {
+            destination
+                .write_manifest(&manifest)
+                .await
+                .context(format!(
+                    "Failed to copy manifest {}",
+                    get_manifest_name(name)
+                ))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{self, Write};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::tee::{TeePolicy, TeeWriter};
+
+use super::provider::StorageProvider;
+
+/// How many destinations of a fan-out backup must succeed for the backup to succeed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RequirePolicy {
+    #[default]
+    All,
+    Any,
+    Quorum(usize),
+}
+
+impl RequirePolicy {
+    pub fn required(&self, total: usize) -> usize {
+        match self {
+            RequirePolicy::All => total,
+            RequirePolicy::Any => 1,
+            RequirePolicy::Quorum(count) => (*count).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Destination {
+    pub index: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DestinationFailure {
+    pub index: usize,
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FanoutReport {
+    pub succeeded: Vec<Destination>,
+    pub failed: Vec<DestinationFailure>,
+}
+
+/// Writes one stream to the same path on several storages.
+///
+/// With `RequirePolicy::All` the first failing destination aborts the whole stream, since the
+/// backup cannot succeed anymore. With `Any` and `Quorum` failing destinations are dropped and
+/// the others keep receiving data, until fewer destinations are left than the policy requires.
+pub struct FanoutWriter {
+    tee: TeeWriter,
+    /// Destination index of each tee sink.
+    indices: Vec<usize>,
+    names: Vec<String>,
+    failed: Vec<DestinationFailure>,
+    path: String,
+    required: usize,
+}
+
+impl FanoutWriter {
+    pub async fn open(
+        destinations: &[StorageProvider],
+        path: &str,
+        require: RequirePolicy,
+    ) -> Result<Self> {
+        if destinations.is_empty() {
+            return Err(anyhow!("At least one destination is required"));
+        }
+
+        let required = require.required(destinations.len());
+        let policy = match require {
+            RequirePolicy::All => TeePolicy::FailFast,
+            _ => TeePolicy::BestEffort,
+        };
+
+        let mut tee = TeeWriter::new(policy).with_min_sinks(required);
+        let mut indices = vec![];
+        let mut failed = vec![];
+        let names: Vec<String> = destinations
+            .iter()
+            .map(|destination| destination.config.name().to_string())
+            .collect();
+
+        for (index, destination) in destinations.iter().enumerate() {
+            match destination.create_writer(path).await {
+                Ok(writer) => {
+                    tee = tee.with_sink(writer);
+                    indices.push(index);
+                }
+                Err(e) => failed.push(DestinationFailure {
+                    index,
+                    name: names[index].clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        if indices.len() < required {
+            drop(tee);
+            Self::abort(destinations, path).await;
+
+            return Err(anyhow!(
+                "Only {} of {} destinations could be opened, {} required: {}",
+                indices.len(),
+                destinations.len(),
+                required,
+                format_failures(&failed)
+            ));
+        }
+
+        Ok(Self {
+            tee,
+            indices,
+            names,
+            failed,
+            path: path.into(),
+            required,
+        })
+    }
+
+    /// Closes every destination and checks the outcome against the policy.
+    ///
+    /// Partial objects left on failed destinations are deleted. When the policy is not met,
+    /// the copies on successful destinations are deleted too so a failed backup leaves nothing
+    /// behind.
+    pub async fn finish(self, destinations: &[StorageProvider]) -> Result<FanoutReport> {
+        let Self {
+            mut tee,
+            indices,
+            names,
+            failed,
+            path,
+            required,
+        } = self;
+
+        let flushed = tee.flush();
+
+        let mut report = FanoutReport {
+            succeeded: vec![],
+            failed,
+        };
+
+        for failure in tee.failures() {
+            let index = indices[failure.index];
+            report.failed.push(DestinationFailure {
+                index,
+                name: names[index].clone(),
+                error: failure.error.clone(),
+            });
+        }
+
+        for (sink, index) in tee.into_sinks().into_iter().zip(indices) {
+            if sink.is_none() {
+                continue;
+            }
+
+            match &flushed {
+                Ok(()) => report.succeeded.push(Destination {
+                    index,
+                    name: names[index].clone(),
+                }),
+                Err(e) => report.failed.push(DestinationFailure {
+                    index,
+                    name: names[index].clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        report.failed.sort_by_key(|failure| failure.index);
+
+        let policy_met = report.succeeded.len() >= required;
+
+        for (index, destination) in destinations.iter().enumerate() {
+            let succeeded = report
+                .succeeded
+                .iter()
+                .any(|destination| destination.index == index);
+
+            if policy_met && succeeded {
+                continue;
+            }
+
+            if let Err(e) = destination.delete(&path).await {
+                warn!("Failed to remove {} from {}: {}", path, names[index], e);
+            }
+        }
+
+        if !policy_met {
+            return Err(anyhow!(
+                "Only {} of {} destinations succeeded, {} required: {}",
+                report.succeeded.len(),
+                destinations.len(),
+                required,
+                format_failures(&report.failed)
+            ));
+        }
+
+        Ok(report)
+    }
+
+    /// Removes the partial object from every destination after the stream was aborted.
+    pub async fn abort(destinations: &[StorageProvider], path: &str) {
+        for destination in destinations {
+            if let Err(e) = destination.delete(path).await {
+                warn!(
+                    "Failed to remove {} from {}: {}",
+                    path,
+                    destination.config.name(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+fn format_failures(failures: &[DestinationFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| format!("{} ({})", failure.name, failure.error))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Write for FanoutWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tee.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Flushing closes the destination writers, which only `finish` may do
+        Ok(())
+    }
+}
@@ -5,23 +5,58 @@ use std::{
         Arc, Mutex as StdMutex,
     },
     thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use futures::StreamExt;
-use log::debug;
-use opendal::{Operator, Writer};
-use tokio::sync::Mutex as TokioMutex;
+use log::{debug, warn};
+use opendal::{BufferStream, Operator, Writer};
+use tokio::{
+    runtime::{Builder, Runtime},
+    sync::Mutex as TokioMutex,
+};
+
+/// Largest ranged read of a download, see `OpendalBackend::with_read_chunk_size`.
+pub const READ_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+const UPLOAD_WORKER_THREADS: usize = 2;
+/// Wait before resuming a failed download, multiplied by the failures in a row.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// Runtime the parts of a concurrent upload keep running on between writes. It is shut down in
+/// the background since the writer may be dropped from async code.
+struct UploadRuntime(Option<Runtime>);
+
+impl UploadRuntime {
+    fn runtime(&self) -> &Runtime {
+        self.0.as_ref().expect("Upload runtime already shut down")
+    }
+}
+
+impl Drop for UploadRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct StorageWriter {
     writer: Arc<TokioMutex<Writer>>,
+    runtime: Arc<UploadRuntime>,
 }
 
 impl StorageWriter {
-    pub fn new(writer: Writer) -> Self {
-        StorageWriter {
+    pub fn new(writer: Writer) -> std::io::Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(UPLOAD_WORKER_THREADS)
+            .enable_all()
+            .build()?;
+
+        Ok(StorageWriter {
             writer: Arc::new(TokioMutex::new(writer)),
-        }
+            runtime: Arc::new(UploadRuntime(Some(runtime))),
+        })
     }
 
     async fn write_async(&mut self, buf: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
@@ -47,9 +82,9 @@ impl Write for StorageWriter {
         let (tx, rx) = channel();
 
         std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
+            let runtime = this.runtime.clone();
 
-            let result: Result<usize, Error> = rt.block_on(async {
+            let result: Result<usize, Error> = runtime.runtime().block_on(async {
                 let len = this
                     .write_async(&buf_copy.clone())
                     .await
@@ -77,9 +112,9 @@ impl Write for StorageWriter {
         let (tx, rx) = channel();
 
         std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
+            let runtime = this.runtime.clone();
 
-            let result: Result<(), Error> = rt.block_on(async {
+            let result: Result<(), Error> = runtime.runtime().block_on(async {
                 let result = this
                     .flush_async()
                     .await
@@ -104,7 +139,13 @@ impl Write for StorageWriter {
 enum FetchResult {
     DataAvailable,
     EndOfStream,
-    Error(String),
+    Error(ErrorKind, String),
+}
+
+/// Keeps the kind of opendal errors, so a missing file is not taken for a broken download.
+fn fetch_error(message: String, e: opendal::Error) -> FetchResult {
+    let kind = Error::from(e).kind();
+    FetchResult::Error(kind, message)
 }
 
 enum ReadRequest {
@@ -119,7 +160,13 @@ pub struct StorageReader {
 }
 
 impl StorageReader {
-    pub fn new(operator: Operator, filename: String) -> Self {
+    pub fn new(operator: Operator, filename: String, chunk_size: usize) -> Self {
+        Self::new_at(operator, filename, 0, chunk_size)
+    }
+
+    /// Reads `filename` from byte `offset` to the end, in ranged reads of `chunk_size` bytes
+    /// at most.
+    pub fn new_at(operator: Operator, filename: String, offset: u64, chunk_size: usize) -> Self {
         // I didn't find a way to make this simpler. It seems that a reader
         // created from an operator cannot be shared accross threads without
         // producing a deadlock when we need to read from it.
@@ -140,33 +187,39 @@ impl StorageReader {
                 };
 
                 rt.block_on(async {
-                    let metadata = operator.stat(filename.as_str()).await.unwrap();
-                    let file_size = metadata.content_length() as usize;
-                    let chunk_size = if file_size > 512 { 512 } else { file_size };
-
-                    let mut stream = operator
-                        .reader_with(filename.as_str())
-                        .chunk(chunk_size as usize)
+                    // Open errors are reported by the first read instead of killing the worker
+                    let mut stream = open_stream(&operator, &filename, offset, chunk_size)
                         .await
-                        .unwrap()
-                        .into_stream(0u64..(file_size as u64))
-                        .await
-                        .unwrap();
+                        .map_err(|e| {
+                            let message = format!("Failed to open {}: {}", filename, e);
+                            (Error::from(e).kind(), message)
+                        });
 
                     while let Ok(request) = rx.recv() {
                         match request {
                             ReadRequest::FetchMoreData(tx) => {
+                                let stream = match &mut stream {
+                                    Ok(stream) => stream,
+                                    Err((kind, e)) => {
+                                        let _ = tx.send(FetchResult::Error(*kind, e.clone()));
+                                        continue;
+                                    }
+                                };
+
                                 let result = match stream.next().await {
                                     Some(Ok(chunk)) => {
                                         if let Ok(mut buffer) = buffer.lock() {
                                             buffer.extend_from_slice(&chunk.to_bytes());
                                             FetchResult::DataAvailable
                                         } else {
-                                            FetchResult::Error(format!("Failed to lock buffer"))
+                                            FetchResult::Error(
+                                                ErrorKind::Other,
+                                                "Failed to lock buffer".to_string(),
+                                            )
                                         }
                                     }
                                     Some(Err(e)) => {
-                                        FetchResult::Error(format!("Error reading chunk: {}", e))
+                                        fetch_error(format!("Error reading chunk: {}", e), e)
                                     }
                                     None => FetchResult::EndOfStream,
                                 };
@@ -190,6 +243,36 @@ impl StorageReader {
     }
 }
 
+/// Size of the ranged reads of a `file_size` bytes file: `chunk_size`, large enough to keep
+/// requests to a few per hundred megabytes, smaller only for files that fit in one read.
+pub fn get_read_chunk_size(file_size: u64, chunk_size: usize) -> usize {
+    usize::try_from(file_size)
+        .unwrap_or(usize::MAX)
+        .clamp(1, chunk_size.max(1))
+}
+
+/// Chunks are fetched one at a time when the buffer runs dry, so memory stays bounded by the
+/// chunk size whatever the size of the backup.
+async fn open_stream(
+    operator: &Operator,
+    filename: &str,
+    offset: u64,
+    chunk_size: usize,
+) -> opendal::Result<BufferStream> {
+    let metadata = operator.stat(filename).await?;
+    let file_size = metadata.content_length();
+
+    operator
+        .reader_with(filename)
+        .chunk(get_read_chunk_size(
+            file_size.saturating_sub(offset),
+            chunk_size,
+        ))
+        .await?
+        .into_stream(offset.min(file_size)..file_size)
+        .await
+}
+
 impl Drop for StorageReader {
     fn drop(&mut self) {
         let _ = self.tx.send(ReadRequest::Stop);
@@ -232,7 +315,84 @@ impl Read for StorageReader {
         match fetch_result {
             FetchResult::DataAvailable => self.read(buf),
             FetchResult::EndOfStream => Ok(0),
-            FetchResult::Error(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+            FetchResult::Error(kind, e) => Err(Error::new(kind, e)),
+        }
+    }
+}
+
+/// Reopens a download where it stopped when a read fails, so restoring a large backup over a
+/// flaky link does not start over. `open` returns a reader from the given offset; up to
+/// `max_resumes` failures in a row are resumed, missing files and denied reads never are.
+pub struct ResumableReader<R, F> {
+    open: F,
+    reader: R,
+    offset: u64,
+    failures: usize,
+    max_resumes: usize,
+    delay: Duration,
+}
+
+impl<R: Read, F: FnMut(u64) -> R> ResumableReader<R, F> {
+    pub fn new(mut open: F, max_resumes: usize) -> Self {
+        let reader = open(0);
+
+        Self {
+            open,
+            reader,
+            offset: 0,
+            failures: 0,
+            max_resumes,
+            delay: RESUME_DELAY,
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Bytes read so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+fn is_resumable(e: &Error) -> bool {
+    !matches!(
+        e.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidInput
+            | ErrorKind::Unsupported
+    )
+}
+
+impl<R: Read, F: FnMut(u64) -> R> Read for ResumableReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            match self.reader.read(buf) {
+                Ok(n) => {
+                    if n > 0 {
+                        self.failures = 0;
+                    }
+
+                    self.offset += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if self.failures < self.max_resumes && is_resumable(&e) => {
+                    self.failures += 1;
+
+                    warn!(
+                        "Download failed after {} bytes, resuming ({}/{}): {}",
+                        self.offset, self.failures, self.max_resumes, e
+                    );
+
+                    thread::sleep(self.delay * self.failures as u32);
+                    self.reader = (self.open)(self.offset);
+                }
+                Err(e) => return Err(e),
+            }
         }
     }
 }
@@ -1,8 +1,14 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+pub mod backend;
+pub mod bench;
+pub mod copy;
+pub mod fanout;
 pub mod io;
 pub mod provider;
+#[cfg(feature = "ssh")]
+pub mod sftp;
 mod test;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -36,33 +42,36 @@ pub struct Entry {
     pub metadata: EntryMetadata,
 }
 
+impl EntryMetadata {
+    pub(crate) fn from_opendal(name: &str, metadata: &opendal::Metadata) -> Self {
+        Self {
+            name: name.to_string(),
+            cache_control: metadata.cache_control().map(|it| it.to_string()),
+            content_disposition: metadata.content_disposition().map(|it| it.to_string()),
+            content_encoding: metadata.content_encoding().map(|it| it.to_string()),
+            content_length: metadata.content_length(),
+            content_md5: metadata.content_md5().map(|it| it.to_string()),
+            content_type: metadata.content_type().map(|it| it.to_string()),
+            etag: metadata.etag().map(|it| it.to_string()),
+            is_current: metadata.is_current(),
+            is_deleted: metadata.is_deleted(),
+            is_file: metadata.is_file(),
+            last_modified: metadata.last_modified(),
+            mode: match metadata.mode() {
+                opendal::EntryMode::DIR => EntryMode::DIR,
+                opendal::EntryMode::FILE => EntryMode::FILE,
+                opendal::EntryMode::Unknown => EntryMode::Unknown,
+            },
+            version: metadata.version().map(|it| it.to_string()),
+        }
+    }
+}
+
 impl From<&opendal::Entry> for Entry {
     fn from(opendal_entry: &opendal::Entry) -> Self {
-        let path = opendal_entry.path().to_string();
-        let metadata = opendal_entry.metadata();
-
         Self {
-            path,
-            metadata: EntryMetadata {
-                name: opendal_entry.name().to_string(),
-                cache_control: metadata.cache_control().map(|it| it.to_string()),
-                content_disposition: metadata.content_disposition().map(|it| it.to_string()),
-                content_encoding: metadata.content_encoding().map(|it| it.to_string()),
-                content_length: metadata.content_length(),
-                content_md5: metadata.content_md5().map(|it| it.to_string()),
-                content_type: metadata.content_type().map(|it| it.to_string()),
-                etag: metadata.etag().map(|it| it.to_string()),
-                is_current: metadata.is_current(),
-                is_deleted: metadata.is_deleted(),
-                is_file: metadata.is_file(),
-                last_modified: metadata.last_modified(),
-                mode: match metadata.mode() {
-                    opendal::EntryMode::DIR => EntryMode::DIR,
-                    opendal::EntryMode::FILE => EntryMode::FILE,
-                    opendal::EntryMode::Unknown => EntryMode::Unknown,
-                },
-                version: metadata.version().map(|it| it.to_string()),
-            },
+            path: opendal_entry.path().to_string(),
+            metadata: EntryMetadata::from_opendal(opendal_entry.name(), opendal_entry.metadata()),
         }
     }
 }
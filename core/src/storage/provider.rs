@@ -1,26 +1,66 @@
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, Utc};
-use log::{info, warn};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use log::{debug, info, warn};
 use opendal::{
-    layers::LoggingLayer,
-    services::{Fs, S3},
-    BufferStream, Operator,
+    layers::{LoggingLayer, RetryLayer},
+    services::{Fs, Gcs, Webdav, S3},
+    Operator,
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    fs,
-    io::{Read, Write},
-    path::Path,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Cursor, Read, Write},
+    path::PathBuf,
+    str::FromStr,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::Duration as StdDuration,
+};
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, new_correlation_id, AuditLog, AuditOperation, AuditOutcome, AuditRecord},
+    catalog::{Catalog, CatalogEntry},
+    checksum::{
+        format_sidecar, get_sidecar_name, is_sidecar, parse_sidecar, ChecksumAlgorithm,
+        HashingReader,
+    },
+    common::{
+        extract_timestamp_from_filename, get_backup_group, get_backup_name_pattern,
+        get_retention_cutoff, is_prunable,
+    },
+    compression::Decompressor,
+    encryption::is_encrypted,
+    manifest::{get_manifest_name, is_manifest, parse_dump_header, BackupInfo, BackupManifest},
+    redact::{redact_option, REDACTED},
+    storage::{Entry, EntryMetadata},
 };
-use tokio::sync::Mutex;
 
-use crate::{common::extract_timestamp_from_filename, storage::Entry};
+use super::backend::{OpendalBackend, StorageBackend};
+#[cfg(feature = "ssh")]
+use super::sftp::SftpBackend;
 
-use super::io::{StorageReader, StorageWriter};
+const DUMP_HEADER_SIZE: u64 = 16384;
+const DEFAULT_PAGE_SIZE: usize = 50;
+const DEFAULT_MIN_BACKUP_AGE_HOURS: i64 = 1;
+const DEFAULT_CLEANUP_CONCURRENCY: usize = 4;
+const DEFAULT_MAX_RETRIES: usize = 3;
+const DEFAULT_UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 4;
+const DEFAULT_MANIFEST_CONCURRENCY: usize = 8;
+/// Smallest part S3 accepts, except for the last one.
+const MIN_UPLOAD_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+/// Extensions of the backups written by dbkp, plus plain and custom-format dumps.
+pub const DEFAULT_BACKUP_EXTENSIONS: [&str; 6] = ["gz", "zip", "zz", "tar", "sql", "dump"];
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// `Debug` hides the passwords, secret keys and key passphrases.
+#[derive(Clone, Serialize, Deserialize)]
 pub enum StorageCredentials {
     None,
     Basic {
@@ -38,131 +78,704 @@ pub enum StorageCredentials {
     },
 }
 
+impl StorageCredentials {
+    pub fn redacted(&self) -> Self {
+        match self {
+            StorageCredentials::None => StorageCredentials::None,
+            StorageCredentials::Basic { username, .. } => StorageCredentials::Basic {
+                username: username.clone(),
+                password: REDACTED.into(),
+            },
+            StorageCredentials::AccessKey { access_key, .. } => StorageCredentials::AccessKey {
+                access_key: access_key.clone(),
+                secret_key: REDACTED.into(),
+            },
+            StorageCredentials::PrivateKey {
+                username,
+                key_path,
+                passphrase,
+            } => StorageCredentials::PrivateKey {
+                username: username.clone(),
+                key_path: key_path.clone(),
+                passphrase: passphrase.as_ref().map(|_| REDACTED.into()),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for StorageCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageCredentials::None => f.write_str("None"),
+            StorageCredentials::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &REDACTED)
+                .finish(),
+            StorageCredentials::AccessKey { access_key, .. } => f
+                .debug_struct("AccessKey")
+                .field("access_key", access_key)
+                .field("secret_key", &REDACTED)
+                .finish(),
+            StorageCredentials::PrivateKey {
+                username,
+                key_path,
+                passphrase,
+            } => f
+                .debug_struct("PrivateKey")
+                .field("username", username)
+                .field("key_path", key_path)
+                .field("passphrase", &redact_option(passphrase))
+                .finish(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StorageType {
     FileSystem,
     S3,
-    // WebDAV,
-    // SFTP,
+    WebDav,
+    Sftp,
+    Gcs,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalStorageConfig {
+    #[serde(default)]
     pub id: String,
     pub name: String,
     pub location: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Retries of the storage operations failing with a transient error, such as a timeout or a
+/// throttled request, with an exponential backoff between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Retries after the first attempt, and resumptions of an interrupted download in a row, 0
+    /// disables them.
+    pub max_retries: usize,
+    pub min_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Multiplies the delay after each retry.
+    pub factor: f32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            min_delay_ms: 1_000,
+            max_delay_ms: 30_000,
+            factor: 2.0,
+        }
+    }
+}
+
+/// How backups are uploaded to S3: in parts of `chunk_size` bytes, `concurrency` of them in
+/// flight at once. Larger and more parts speed up big backups at the cost of memory, up to
+/// `chunk_size * concurrency` bytes buffered per upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadOptions {
+    pub chunk_size: usize,
+    pub concurrency: usize,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_UPLOAD_CHUNK_SIZE,
+            concurrency: DEFAULT_UPLOAD_CONCURRENCY,
+        }
+    }
+}
+
+impl UploadOptions {
+    fn validate(&self) -> Result<()> {
+        if self.chunk_size < MIN_UPLOAD_CHUNK_SIZE {
+            return Err(anyhow!(
+                "The upload chunk size must be at least 5MB, S3 rejects smaller parts"
+            ));
+        }
+
+        if self.concurrency == 0 {
+            return Err(anyhow!("The upload concurrency must be at least 1"));
+        }
+
+        Ok(())
+    }
+}
+
+impl RetryConfig {
+    /// Wraps `operator` with opendal's `RetryLayer`, which only retries errors opendal marks as
+    /// temporary, and logs every retry.
+    fn apply(&self, operator: Operator) -> Operator {
+        if self.max_retries == 0 {
+            return operator;
+        }
+
+        debug!(
+            "Retrying storage operations up to {} times, after {}ms to {}ms",
+            self.max_retries, self.min_delay_ms, self.max_delay_ms
+        );
+
+        operator.layer(
+            RetryLayer::new()
+                .with_max_times(self.max_retries)
+                .with_min_delay(StdDuration::from_millis(self.min_delay_ms))
+                .with_max_delay(StdDuration::from_millis(self.max_delay_ms))
+                .with_factor(self.factor)
+                .with_jitter()
+                .with_notify(|e: &opendal::Error, delay: StdDuration| {
+                    warn!(
+                        "Storage operation failed, retrying in {:.1}s: {}",
+                        delay.as_secs_f64(),
+                        e
+                    )
+                }),
+        )
+    }
+}
+
+/// Encryption of the objects by S3 as it stores them. It is unrelated to the encryption of
+/// backups with a passphrase, which happens before the upload, and both can be used together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerSideEncryption {
+    /// SSE-S3, with keys managed by S3.
+    #[serde(rename = "AES256")]
+    Aes256,
+    /// SSE-KMS, with `sse_kms_key_id` or else the key managed by AWS.
+    #[serde(rename = "aws:kms")]
+    AwsKms,
+}
+
+impl ServerSideEncryption {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerSideEncryption::Aes256 => "AES256",
+            ServerSideEncryption::AwsKms => "aws:kms",
+        }
+    }
+}
+
+impl fmt::Display for ServerSideEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ServerSideEncryption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "aes256" | "sse-s3" => Ok(ServerSideEncryption::Aes256),
+            "aws:kms" | "kms" | "sse-kms" => Ok(ServerSideEncryption::AwsKms),
+            _ => Err(anyhow!("Unsupported server-side encryption: {}", s)),
+        }
+    }
+}
+
+/// `Debug` hides the secret key. Without keys, the credentials come from the environment, the
+/// AWS profile, web identity (EKS) or the instance metadata (EC2, ECS).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct S3StorageConfig {
+    #[serde(default)]
     pub id: String,
     pub name: String,
     pub region: String,
     pub endpoint: Option<String>,
     pub bucket: String,
-    pub access_key: String,
-    pub secret_key: String,
+    #[serde(default)]
+    pub access_key: Option<String>,
+    #[serde(default)]
+    pub secret_key: Option<String>,
     pub location: String,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub upload: UploadOptions,
+    #[serde(default)]
+    pub server_side_encryption: Option<ServerSideEncryption>,
+    /// KMS key of the `aws:kms` encryption.
+    #[serde(default)]
+    pub sse_kms_key_id: Option<String>,
+    /// Storage class of every object written, e.g. `STANDARD_IA` or `GLACIER_IR`, the bucket
+    /// default when unset. Objects in `GLACIER` or `DEEP_ARCHIVE` must be restored in S3 before
+    /// they can be read.
+    #[serde(default)]
+    pub storage_class: Option<String>,
+    /// Addresses the bucket as `https://bucket.endpoint` rather than `https://endpoint/bucket`.
+    /// Path style, the default, is the one MinIO, Ceph and most self-hosted stores accept.
+    #[serde(default)]
+    pub enable_virtual_host_style: bool,
+}
+
+impl fmt::Debug for S3StorageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Destructured so a new field cannot be left out
+        let S3StorageConfig {
+            id,
+            name,
+            region,
+            endpoint,
+            bucket,
+            access_key,
+            secret_key,
+            location,
+            retry,
+            upload,
+            server_side_encryption,
+            sse_kms_key_id,
+            storage_class,
+            enable_virtual_host_style,
+        } = self;
+
+        f.debug_struct("S3StorageConfig")
+            .field("id", id)
+            .field("name", name)
+            .field("region", region)
+            .field("endpoint", endpoint)
+            .field("bucket", bucket)
+            .field("access_key", access_key)
+            .field("secret_key", &redact_option(secret_key))
+            .field("location", location)
+            .field("retry", retry)
+            .field("upload", upload)
+            .field("server_side_encryption", server_side_encryption)
+            .field("sse_kms_key_id", sse_kms_key_id)
+            .field("storage_class", storage_class)
+            .field("enable_virtual_host_style", enable_virtual_host_style)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebDavStorageConfig {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub endpoint: String,
+    /// `None`, or `Basic` for HTTP basic authentication.
+    pub credentials: StorageCredentials,
+    pub location: String,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+#[cfg(feature = "ssh")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpStorageConfig {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// `Basic` for a password, or `PrivateKey`.
+    pub credentials: StorageCredentials,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcsStorageConfig {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub bucket: String,
+    /// Service account key file, `None` for anonymous access.
+    pub credential_path: Option<String>,
+    pub endpoint: Option<String>,
+    pub location: String,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomStorageConfig {
+    pub id: String,
+    pub name: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StorageConfig {
     Local(LocalStorageConfig),
     S3(S3StorageConfig),
+    WebDav(WebDavStorageConfig),
+    #[cfg(feature = "ssh")]
+    Sftp(SftpStorageConfig),
+    Gcs(GcsStorageConfig),
+    /// A `StorageBackend` supplied in code, see `StorageProvider::from_backend`.
+    Custom(CustomStorageConfig),
+}
+
+impl StorageConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            StorageConfig::Local(config) => &config.name,
+            StorageConfig::S3(config) => &config.name,
+            StorageConfig::WebDav(config) => &config.name,
+            #[cfg(feature = "ssh")]
+            StorageConfig::Sftp(config) => &config.name,
+            StorageConfig::Gcs(config) => &config.name,
+            StorageConfig::Custom(config) => &config.name,
+        }
+    }
+
+    /// A copy with `***` in place of the passwords and keys, for configs shown rather than
+    /// used.
+    pub fn redacted(&self) -> Self {
+        match self {
+            StorageConfig::S3(config) => StorageConfig::S3(S3StorageConfig {
+                secret_key: config.secret_key.as_ref().map(|_| REDACTED.into()),
+                ..config.clone()
+            }),
+            StorageConfig::WebDav(config) => StorageConfig::WebDav(WebDavStorageConfig {
+                credentials: config.credentials.redacted(),
+                ..config.clone()
+            }),
+            #[cfg(feature = "ssh")]
+            StorageConfig::Sftp(config) => StorageConfig::Sftp(SftpStorageConfig {
+                credentials: config.credentials.redacted(),
+                ..config.clone()
+            }),
+            config => config.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct StorageProvider {
     pub config: StorageConfig,
-    pub operator: Operator,
+    backend: Arc<dyn StorageBackend>,
+    audit_log: Option<AuditLog>,
+    catalog: Option<Catalog>,
+    backup_extensions: Vec<String>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<dyn crate::metrics::MetricsRecorder>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ListOptions {
     pub latest_only: Option<bool>,
+    /// Page size of `list_page`. `list_with_options` always returns every backup, so cleanups
+    /// never miss the ones past a limit.
     pub limit: Option<usize>,
+    /// Only backups whose name starts with this.
+    #[serde(default)]
+    pub name_prefix: Option<String>,
+    /// Only backups taken at or after this.
+    #[serde(default)]
+    pub after: Option<DateTime<Utc>>,
+    /// Only backups taken before this.
+    #[serde(default)]
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl ListOptions {
+    /// Whether `entry` passes the name and date filters. With a date filter, backups are dated
+    /// like they are sorted, and those without any date are left out.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        if let Some(prefix) = &self.name_prefix {
+            if !entry.metadata.name.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+
+        if self.after.is_none() && self.before.is_none() {
+            return true;
+        }
+
+        let timestamp = match get_entry_timestamp(entry) {
+            Some(timestamp) => timestamp,
+            None => return false,
+        };
+
+        self.after.is_none_or(|after| timestamp >= after)
+            && self.before.is_none_or(|before| timestamp < before)
+    }
+}
+
+/// Which backups `cleanup` deletes. Backups are ranked newest first by the timestamp in their
+/// name, those of each config separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Deletes the backups older than this many days.
+    MaxAge(u64),
+    /// Keeps this many of the most recent backups and deletes the rest, whatever their age.
+    KeepLast(usize),
+    /// Deletes the backups older than `max_age_days` except the `keep_last` most recent ones,
+    /// and any backup beyond the `max_count` most recent ones.
+    Combined {
+        max_age_days: u64,
+        keep_last: usize,
+        max_count: Option<usize>,
+    },
+    /// Grandfather-father-son: keeps every backup of the last `daily` days, the most recent
+    /// backup of each ISO week for the last `weekly` weeks, and of each month for the last
+    /// `monthly` months. See `select_gfs`.
+    Gfs {
+        daily: u32,
+        weekly: u32,
+        monthly: u32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct CleanupOptions {
+    pub retention: RetentionPolicy,
+    pub dry_run: bool,
+    /// Backups younger than this are never deleted, whatever the retention.
+    pub min_backup_age: Duration,
+    /// Backups deleted at once.
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupFailure {
+    pub name: String,
+    pub error: String,
+}
+
+/// What `cleanup` deleted, or would delete on a dry run, and the deletions that failed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub deleted: Vec<String>,
+    /// Size of the deleted backups.
+    pub reclaimed: u64,
+    pub failed: Vec<CleanupFailure>,
+}
+
+impl CleanupReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl CleanupOptions {
+    pub fn new(retention: RetentionPolicy, dry_run: bool) -> Self {
+        Self {
+            retention,
+            dry_run,
+            min_backup_age: Duration::hours(DEFAULT_MIN_BACKUP_AGE_HOURS),
+            concurrency: DEFAULT_CLEANUP_CONCURRENCY,
+        }
+    }
 }
 
 impl StorageProvider {
     pub fn new(config: StorageConfig) -> anyhow::Result<Self> {
-        let operator = match &config {
+        let backend: Arc<dyn StorageBackend> = match &config {
             StorageConfig::Local(config) => {
                 let builder = Fs::default().root(&config.location);
-                Operator::new(builder)?
+                let operator = Operator::new(builder)?
                     .layer(LoggingLayer::default())
-                    .finish()
+                    .finish();
+
+                Arc::new(OpendalBackend::new(
+                    operator,
+                    Some(PathBuf::from(&config.location)),
+                ))
             }
             StorageConfig::S3(config) => {
                 let mut builder = S3::default()
                     .root(&config.location)
                     .bucket(&config.bucket)
-                    .region(&config.region)
-                    .access_key_id(&config.access_key)
-                    .secret_access_key(&config.secret_key);
+                    .region(&config.region);
+
+                let access_key = config.access_key.as_deref().filter(|key| !key.is_empty());
+                let secret_key = config.secret_key.as_deref().filter(|key| !key.is_empty());
+                builder = match (access_key, secret_key) {
+                    (Some(access_key), Some(secret_key)) => builder
+                        .access_key_id(access_key)
+                        .secret_access_key(secret_key),
+                    // The default chain of opendal: environment, profile, web identity, IMDS
+                    (None, None) => builder,
+                    _ => {
+                        return Err(anyhow!(
+                            "S3 storage needs both the access key and the secret key, or neither to use the default AWS credentials"
+                        ))
+                    }
+                };
+
+                builder = match (config.server_side_encryption, &config.sse_kms_key_id) {
+                    (None, None) => builder,
+                    (Some(ServerSideEncryption::Aes256), None) => {
+                        builder.server_side_encryption_with_s3_key()
+                    }
+                    (Some(ServerSideEncryption::AwsKms), None) => {
+                        builder.server_side_encryption_with_aws_managed_kms_key()
+                    }
+                    (Some(ServerSideEncryption::AwsKms), Some(key_id)) => {
+                        builder.server_side_encryption_with_customer_managed_kms_key(key_id)
+                    }
+                    (_, Some(_)) => {
+                        return Err(anyhow!(
+                            "A KMS key id needs the aws:kms server-side encryption"
+                        ))
+                    }
+                };
+
+                if let Some(storage_class) = &config.storage_class {
+                    builder = builder.default_storage_class(storage_class);
+                }
+                if config.enable_virtual_host_style {
+                    builder = builder.enable_virtual_host_style();
+                }
 
                 builder = match &config.endpoint {
                     Some(endpoint) => builder.endpoint(endpoint),
                     None => builder,
                 };
 
-                Operator::new(builder)?
+                let operator = Operator::new(builder)?
+                    .layer(LoggingLayer::default())
+                    .finish();
+
+                config.upload.validate()?;
+
+                Arc::new(
+                    OpendalBackend::new(config.retry.apply(operator), None)
+                        .with_upload(config.upload)
+                        .with_resume(config.retry.max_retries),
+                )
+            }
+            StorageConfig::WebDav(config) => {
+                let mut builder = Webdav::default()
+                    .endpoint(&config.endpoint)
+                    .root(&config.location);
+
+                builder = match &config.credentials {
+                    StorageCredentials::None => builder,
+                    StorageCredentials::Basic { username, password } => {
+                        builder.username(username).password(password)
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "WebDAV storage {} only supports basic credentials",
+                            config.name
+                        ))
+                    }
+                };
+
+                let operator = Operator::new(builder)?
                     .layer(LoggingLayer::default())
-                    .finish()
+                    .finish();
+
+                Arc::new(
+                    OpendalBackend::new(config.retry.apply(operator), None)
+                        .with_resume(config.retry.max_retries),
+                )
+            }
+            #[cfg(feature = "ssh")]
+            StorageConfig::Sftp(config) => Arc::new(SftpBackend::new(config.clone())?),
+            StorageConfig::Gcs(config) => {
+                let mut builder = Gcs::default().root(&config.location).bucket(&config.bucket);
+
+                builder = match &config.credential_path {
+                    Some(path) => builder.credential_path(path),
+                    // Without a key file, requests are not signed rather than looking for
+                    // credentials of the machine
+                    None => builder
+                        .allow_anonymous()
+                        .disable_config_load()
+                        .disable_vm_metadata(),
+                };
+
+                builder = match &config.endpoint {
+                    Some(endpoint) => builder.endpoint(endpoint),
+                    None => builder,
+                };
+
+                let operator = Operator::new(builder)?
+                    .layer(LoggingLayer::default())
+                    .finish();
+
+                Arc::new(
+                    OpendalBackend::new(config.retry.apply(operator), None)
+                        .with_resume(config.retry.max_retries),
+                )
+            }
+            StorageConfig::Custom(config) => {
+                return Err(anyhow!(
+                    "Custom storage {} must be created with StorageProvider::from_backend",
+                    config.name
+                ));
             }
         };
 
-        Ok(StorageProvider { config, operator })
+        Ok(Self::from_parts(config, backend))
+    }
+
+    /// Creates a provider over a custom backend, which then gets listings, retention,
+    /// verification and copies like the built-in storages.
+    pub fn from_backend(
+        config: CustomStorageConfig,
+        backend: impl StorageBackend + 'static,
+    ) -> Self {
+        Self::from_parts(StorageConfig::Custom(config), Arc::new(backend))
+    }
+
+    fn from_parts(config: StorageConfig, backend: Arc<dyn StorageBackend>) -> Self {
+        StorageProvider {
+            config,
+            backend,
+            audit_log: None,
+            catalog: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            backup_extensions: DEFAULT_BACKUP_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn backend(&self) -> &dyn StorageBackend {
+        self.backend.as_ref()
     }
 
     pub async fn test(&self) -> Result<bool> {
-        self.operator
-            .list_with("/")
-            .recursive(true)
-            .limit(1)
-            .await?;
+        self.backend.list_after(None, 1, &|_| true).await?;
 
         Ok(true)
     }
 
+    /// Bytes free on the storage, see `StorageBackend::available_space`.
+    pub async fn available_space(&self) -> Result<Option<u64>> {
+        self.backend.available_space().await
+    }
+
+    pub async fn test_write(&self) -> Result<()> {
+        let path = format!(".dbkp-write-test-{}", Uuid::new_v4());
+
+        self.backend
+            .write(&path, "dbkp".as_bytes().to_vec())
+            .await
+            .context("Storage is not writable")?;
+
+        self.delete(&path).await?;
+
+        Ok(())
+    }
+
     pub async fn list(&self) -> Result<Vec<Entry>> {
-        self.list_with_options(ListOptions {
-            latest_only: None,
-            limit: None,
-        })
-        .await
+        self.list_with_options(ListOptions::default()).await
     }
 
     pub async fn list_with_options(&self, options: ListOptions) -> Result<Vec<Entry>> {
-        let limit = options.limit.unwrap_or(1000);
         let latest_only = options.latest_only.unwrap_or(false);
 
-        let result = self
-            .operator
-            .list_with("")
-            .recursive(true)
-            .limit(limit)
-            .await
-            .context(format!("Failed to list backups"))?;
-
-        let mut filtered_results: Vec<Entry> = result
+        let mut filtered_results: Vec<Entry> = self
+            .backend
+            .list()
+            .await?
             .into_iter()
-            .map(|opendal_entry| {
-                let mut entry = Entry::from(&opendal_entry);
-                entry.metadata.content_length = self.get_content_length(&entry);
-                entry
-            })
-            .filter(|entry| entry.metadata.is_file)
+            .filter(|entry| self.is_backup(&entry.metadata.name) && options.matches(entry))
             .collect();
 
-        filtered_results.sort_by(|a, b| {
-            let a_timestamp =
-                extract_timestamp_from_filename(&a.metadata.name).unwrap_or(DateTime::default());
-
-            let b_timestamp =
-                extract_timestamp_from_filename(&b.metadata.name).unwrap_or(DateTime::default());
-
-            b_timestamp.cmp(&a_timestamp)
-        });
+        filtered_results.sort_by_cached_key(|entry| std::cmp::Reverse(get_sort_timestamp(entry)));
 
         if latest_only {
             match filtered_results.first() {
@@ -174,93 +787,659 @@ impl StorageProvider {
         Ok(filtered_results)
     }
 
-    pub async fn create_writer(&self, filename: &str) -> Result<Box<dyn Write + Send + Unpin>> {
-        let op_writer = self.operator.writer(filename).await?;
-        Ok(Box::new(StorageWriter::new(op_writer)))
+    /// Lists the backups like `list_with_options`, each with its manifest. A manifest that is
+    /// missing or cannot be read is left out with a warning rather than failing the listing.
+    pub async fn list_with_manifests(
+        &self,
+        options: ListOptions,
+    ) -> Result<Vec<(Entry, Option<BackupManifest>)>> {
+        let entries = self.list_with_options(options).await?;
+
+        Ok(self.read_manifests(entries).await)
     }
 
-    pub async fn create_stream(&self, filename: &str) -> Result<Arc<Mutex<BufferStream>>> {
-        let metadata = self.operator.stat(filename).await?;
-        let file_size = metadata.content_length() as usize;
-        let chunk_size = if file_size > 512 { 512 } else { file_size };
+    /// Pairs each of `entries` with its manifest, see `list_with_manifests`.
+    pub async fn read_manifests(
+        &self,
+        entries: Vec<Entry>,
+    ) -> Vec<(Entry, Option<BackupManifest>)> {
+        stream::iter(entries)
+            .map(|entry| async move {
+                let manifest = match self.read_manifest(&entry.path).await {
+                    Ok(manifest) => manifest,
+                    Err(e) => {
+                        warn!("{:#}", e);
+                        None
+                    }
+                };
 
-        let stream = self
-            .operator
-            .reader_with(filename)
-            .chunk(chunk_size as usize)
+                (entry, manifest)
+            })
+            .buffered(DEFAULT_MANIFEST_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Streams the backups as the storage lists them, unsorted, without loading the whole
+    /// listing first.
+    pub async fn list_stream(&self) -> Result<BoxStream<'_, Result<Entry>>> {
+        Ok(self
+            .backend
+            .list_stream()
             .await?
-            .into_stream(0u64..(file_size as u64))
+            .try_filter(|entry| future::ready(self.is_backup(&entry.metadata.name)))
+            .boxed())
+    }
+
+    /// Returns one page of backups and a continuation cursor when more are available.
+    ///
+    /// Unlike `list_with_options`, pages are ordered by path rather than by the timestamp
+    /// embedded in the name, so the whole catalog never has to be loaded and sorted. Since
+    /// names start with the configuration name followed by a UTC timestamp, backups of a
+    /// given configuration still come out oldest first. `latest_only` is ignored.
+    pub async fn list_page(
+        &self,
+        options: ListOptions,
+        cursor: Option<String>,
+    ) -> Result<(Vec<Entry>, Option<String>)> {
+        let page_size = options.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+
+        // One more than a page tells whether another page follows
+        let mut entries = self
+            .backend
+            .list_after(cursor.as_deref(), page_size + 1, &|entry| {
+                self.is_backup(&entry.metadata.name) && options.matches(entry)
+            })
             .await?;
 
-        Ok(Arc::new(Mutex::new(stream)))
+        let next_cursor = if entries.len() > page_size {
+            entries.truncate(page_size);
+            entries.last().map(|entry| entry.path.clone())
+        } else {
+            None
+        };
+
+        Ok((entries, next_cursor))
+    }
+
+    /// Returns the backup `offset` positions before the most recent one taken for `config_name`.
+    pub async fn get_nth_latest(&self, config_name: &str, offset: usize) -> Result<Entry> {
+        let pattern = get_backup_name_pattern(config_name);
+        let entries: Vec<Entry> = self
+            .list()
+            .await?
+            .into_iter()
+            .filter(|entry| pattern.is_match(&entry.metadata.name))
+            .collect();
+
+        let count = entries.len();
+
+        entries.into_iter().nth(offset).ok_or_else(|| {
+            anyhow!(
+                "Cannot select backup at offset {}: only {} backup(s) found for {}",
+                offset,
+                count,
+                config_name
+            )
+        })
+    }
+
+    /// Returns the most recent backup of `config_name` whose filename timestamp falls on `date`
+    /// in `timezone`, UTC by default.
+    pub async fn find_backup_on(
+        &self,
+        config_name: &str,
+        date: NaiveDate,
+        timezone: Option<&Tz>,
+    ) -> Result<Option<Entry>> {
+        let pattern = get_backup_name_pattern(config_name);
+
+        let entry = self.list().await?.into_iter().find(|entry| {
+            if !pattern.is_match(&entry.metadata.name) {
+                return false;
+            }
+
+            match extract_timestamp_from_filename(&entry.metadata.name) {
+                Ok(timestamp) => {
+                    let day = match timezone {
+                        Some(timezone) => timestamp.with_timezone(timezone).date_naive(),
+                        None => timestamp.date_naive(),
+                    };
+                    day == date
+                }
+                Err(_) => false,
+            }
+        });
+
+        Ok(entry)
+    }
+
+    pub async fn create_writer(&self, filename: &str) -> Result<Box<dyn Write + Send + Unpin>> {
+        self.backend.create_writer(filename).await
     }
 
     pub async fn create_reader(&self, filename: &str) -> Result<Box<dyn Read + Send + Unpin>> {
-        Ok(Box::new(StorageReader::new(
-            self.operator.clone(),
-            filename.to_string(),
-        )))
+        self.backend.create_reader(filename).await
+    }
+
+    pub async fn write_manifest(&self, manifest: &BackupManifest) -> Result<()> {
+        let content = serde_json::to_vec_pretty(manifest)?;
+
+        self.backend
+            .write(&get_manifest_name(&manifest.name), content)
+            .await
+            .context(format!("Failed to write manifest for {}", manifest.name))?;
+
+        if let Some(catalog) = &self.catalog {
+            let entry = CatalogEntry::from_manifest(self.config.name(), manifest);
+            if let Err(e) = catalog.upsert(entry) {
+                warn!("Failed to add {} to the catalog: {:#}", manifest.name, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `checksum`, a `sha256:<hex>` checksum of the stored backup `filename`, to its
+    /// `.sha256` sidecar.
+    pub async fn write_sidecar(&self, filename: &str, checksum: &str) -> Result<()> {
+        let content = format_sidecar(checksum, filename)?;
+        let mut writer = self.create_writer(&get_sidecar_name(filename)).await?;
+
+        tokio::task::spawn_blocking(move || -> io::Result<()> {
+            writer.write_all(content.as_bytes())?;
+            writer.flush()
+        })
+        .await?
+        .context(format!("Failed to write checksum sidecar for {}", filename))
+    }
+
+    /// Reads the backup `filename` to the end and compares its SHA-256 with its sidecar,
+    /// failing when there is no sidecar.
+    pub async fn verify(&self, filename: &str) -> Result<bool> {
+        let content = self
+            .backend
+            .read(&get_sidecar_name(filename))
+            .await
+            .context(format!("Failed to read checksum sidecar for {}", filename))?
+            .ok_or_else(|| anyhow!("No checksum sidecar for {}", filename))?;
+        let expected = parse_sidecar(&String::from_utf8_lossy(&content))?;
+
+        let reader = self.create_reader(filename).await?;
+        let checksum = tokio::task::spawn_blocking(move || -> io::Result<String> {
+            let mut reader = HashingReader::new(reader, ChecksumAlgorithm::Sha256);
+            io::copy(&mut reader, &mut io::sink())?;
+            Ok(reader.finish())
+        })
+        .await?
+        .context(format!("Failed to read backup {}", filename))?;
+
+        Ok(checksum == expected)
+    }
+
+    pub async fn read_manifest(&self, filename: &str) -> Result<Option<BackupManifest>> {
+        let content = match self
+            .backend
+            .read(&get_manifest_name(filename))
+            .await
+            .context(format!("Failed to read manifest for {}", filename))?
+        {
+            Some(content) => content,
+            None => return Ok(None),
+        };
+
+        let manifest: BackupManifest = serde_json::from_slice(&content)
+            .context(format!("Failed to parse manifest for {}", filename))?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Returns the beginning of a backup, decompressed, for header inspection.
+    pub async fn read_dump_header(&self, name: &str) -> Result<String> {
+        let header = self.read_header(name).await?;
+
+        let (compression_format, reader) = Decompressor::detect_format(Cursor::new(header))?;
+
+        // The header is truncated, so decompression stops with an error once it runs out
+        let mut content = vec![];
+        let _ = Decompressor::new(reader, compression_format)
+            .take(DUMP_HEADER_SIZE)
+            .read_to_end(&mut content);
+
+        Ok(String::from_utf8_lossy(&content).into_owned())
+    }
+
+    /// Returns the metadata of `name`, failing when it does not exist.
+    pub async fn stat(&self, name: &str) -> Result<EntryMetadata> {
+        self.backend
+            .stat(name)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to find backup {}", name))
+    }
+
+    /// Whether `path` exists, without listing the storage. Errors other than a missing file,
+    /// such as denied access, are returned.
+    pub async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.backend.stat(path).await?.is_some())
+    }
+
+    async fn read_header(&self, name: &str) -> Result<Vec<u8>> {
+        let metadata = self.stat(name).await?;
+
+        self.backend
+            .read_range(name, 0..DUMP_HEADER_SIZE.min(metadata.content_length))
+            .await
+            .context(format!("Failed to read backup {}", name))
+    }
+
+    pub async fn get_backup_info(&self, name: &str) -> Result<BackupInfo> {
+        let metadata = self.stat(name).await?;
+        let manifest = self.read_manifest(name).await?;
+
+        let header = self
+            .backend
+            .read_range(name, 0..DUMP_HEADER_SIZE.min(metadata.content_length))
+            .await
+            .context(format!("Failed to read backup {}", name))?;
+
+        let encrypted = is_encrypted(&header);
+        let (detected_format, reader) = Decompressor::detect_format(Cursor::new(header))?;
+        let compression_format = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.compression_format.clone())
+            .unwrap_or(detected_format);
+
+        let server_version = match manifest
+            .as_ref()
+            .and_then(|manifest| manifest.server_version.as_ref())
+        {
+            Some(version) => Some(version.to_string()),
+            None if encrypted => None,
+            None => {
+                // The header is truncated, so decompression stops with an error once it runs out
+                let mut content = vec![];
+                let _ = Decompressor::new(reader, compression_format.clone())
+                    .take(DUMP_HEADER_SIZE)
+                    .read_to_end(&mut content);
+
+                parse_dump_header(&String::from_utf8_lossy(&content))
+            }
+        };
+
+        Ok(BackupInfo {
+            name: name.to_string(),
+            size: metadata.content_length,
+            last_modified: metadata.last_modified,
+            compression_format,
+            encrypted,
+            server_version,
+            manifest,
+        })
     }
 
     pub async fn delete(&self, path: &str) -> Result<()> {
-        self.operator
-            .delete(&path)
+        self.backend
+            .delete(path)
             .await
             .context(format!("Failed to delete backup {}", path))?;
 
         Ok(())
     }
 
-    pub async fn cleanup(&self, retention_days: u64, dry_run: bool) -> Result<(usize, u64)> {
-        let backups = self.list().await?;
+    /// Reports the outcome of every cleanup but dry runs to `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Arc<dyn crate::metrics::MetricsRecorder>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Records the deletions of `cleanup` in `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
 
-        let cutoff = SystemTime::now()
-            .checked_sub(Duration::from_secs(retention_days * 86400))
-            .ok_or_else(|| anyhow!("Failed to calculate cutoff date"))?;
+    /// Keeps `catalog` up to date with the backups written and deleted through this provider.
+    /// Catalog errors are logged and never fail the backup or deletion.
+    pub fn with_catalog(mut self, catalog: Catalog) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
 
-        let cutoff_datetime: DateTime<Utc> = cutoff.into();
+    /// Replaces the extensions of the files listed as backups, e.g. `["gz", "sql"]`.
+    pub fn with_backup_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.backup_extensions = extensions
+            .into_iter()
+            .map(|extension| extension.trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
 
-        let mut deleted_count = 0;
-        let mut deleted_size = 0;
+    /// Whether `name` is a backup rather than a manifest, a checksum sidecar or a foreign file
+    /// sharing the location, such as `.DS_Store` or a log. Listings and retention only consider
+    /// backups.
+    pub fn is_backup(&self, name: &str) -> bool {
+        if is_manifest(name) || is_sidecar(name) {
+            return false;
+        }
 
-        for backup in backups {
-            match extract_timestamp_from_filename(&backup.metadata.name) {
-                Ok(timestamp) => {
-                    if timestamp < cutoff_datetime {
-                        let size = backup.metadata.content_length;
-                        deleted_size += size;
-                        deleted_count += 1;
-
-                        if !dry_run {
-                            self.delete(&backup.path).await?;
-                            info!("Successfully deleted {}", backup.path);
+        match name.rsplit_once('.') {
+            // Uncompressed backups are named with a trailing dot
+            Some((stem, "")) => !stem.is_empty(),
+            Some((stem, extension)) => {
+                !stem.is_empty()
+                    && self
+                        .backup_extensions
+                        .iter()
+                        .any(|known| known.eq_ignore_ascii_case(extension))
+            }
+            None => false,
+        }
+    }
+
+    async fn delete_backup(&self, backup: &Entry) -> Result<()> {
+        self.delete(&backup.path).await?;
+        self.delete(&get_manifest_name(&backup.path)).await?;
+        self.delete(&get_sidecar_name(&backup.path)).await?;
+
+        if let Some(catalog) = &self.catalog {
+            if let Err(e) = catalog.remove(self.config.name(), &backup.path) {
+                warn!("Failed to remove {} from the catalog: {:#}", backup.path, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn cleanup(
+        &self,
+        retention: RetentionPolicy,
+        dry_run: bool,
+    ) -> Result<CleanupReport> {
+        self.cleanup_with_options(&CleanupOptions::new(retention, dry_run))
+            .await
+    }
+
+    /// Deletes the backups past the retention, `concurrency` at a time, and reports which were
+    /// deleted and their size. Age-only retention deletes as the listing streams in, while
+    /// count-based retention ranks the whole listing, newest first, before deleting. A failed
+    /// deletion is reported without stopping the others; only a failed listing fails the
+    /// cleanup.
+    pub async fn cleanup_with_options(&self, options: &CleanupOptions) -> Result<CleanupReport> {
+        let correlation_id = new_correlation_id();
+        let now = Utc::now();
+
+        let prunable = match options.retention {
+            RetentionPolicy::MaxAge(days) => {
+                let cutoff_datetime = get_retention_cutoff(now, days)?;
+
+                self.list_stream()
+                    .await?
+                    .try_filter(move |backup| {
+                        future::ready(is_past_cutoff(
+                            backup,
+                            cutoff_datetime,
+                            now,
+                            options.min_backup_age,
+                        ))
+                    })
+                    .boxed()
+            }
+            RetentionPolicy::KeepLast(keep_last) => {
+                let backups = self.list().await?;
+                let prunable = select_by_rank(
+                    backups,
+                    None,
+                    keep_last,
+                    Some(keep_last),
+                    now,
+                    options.min_backup_age,
+                );
+
+                stream::iter(prunable.into_iter().map(Ok)).boxed()
+            }
+            RetentionPolicy::Combined {
+                max_age_days,
+                keep_last,
+                max_count,
+            } => {
+                let cutoff_datetime = get_retention_cutoff(now, max_age_days)?;
+                let backups = self.list().await?;
+                let prunable = select_by_rank(
+                    backups,
+                    Some(cutoff_datetime),
+                    keep_last,
+                    max_count,
+                    now,
+                    options.min_backup_age,
+                );
+
+                stream::iter(prunable.into_iter().map(Ok)).boxed()
+            }
+            RetentionPolicy::Gfs {
+                daily,
+                weekly,
+                monthly,
+            } => {
+                let backups = self.list().await?;
+                let prunable = select_gfs(backups, daily, weekly, monthly, now)
+                    .into_iter()
+                    .filter(|backup| is_past_cutoff(backup, now, now, options.min_backup_age))
+                    .collect::<Vec<_>>();
+
+                stream::iter(prunable.into_iter().map(Ok)).boxed()
+            }
+        };
+
+        let report = prunable
+            .map_ok(|backup| {
+                let correlation_id = &correlation_id;
+
+                async move {
+                    if options.dry_run {
+                        return Ok((backup, Ok(())));
+                    }
+
+                    let mut record = AuditRecord::new(AuditOperation::Delete, correlation_id);
+                    record.storage = Some(self.config.name().to_string());
+                    record.backup = Some(backup.path.clone());
+                    record.bytes = Some(backup.metadata.content_length);
+
+                    let result = self.delete_backup(&backup).await;
+                    let result = audit::record(self.audit_log.as_ref(), record, result);
+
+                    let storage = self.config.name();
+                    let path = backup.path.as_str();
+                    let bytes = backup.metadata.content_length;
+                    match &result {
+                        Ok(()) => info!(
+                            operation = AuditOperation::Delete.as_str(),
+                            storage,
+                            path,
+                            bytes,
+                            status = AuditOutcome::Success.as_str();
+                            "Successfully deleted {}",
+                            path
+                        ),
+                        Err(e) => {
+                            let error = format!("{:#}", e);
+                            warn!(
+                                operation = AuditOperation::Delete.as_str(),
+                                storage,
+                                path,
+                                bytes,
+                                status = AuditOutcome::Failure.as_str(),
+                                error = error.as_str();
+                                "Failed to delete {}: {}",
+                                path,
+                                error
+                            );
                         }
                     }
+
+                    Ok((backup, result))
                 }
-                Err(_) => {
-                    warn!("Failed to extract timestamp from {}", backup.metadata.name);
+            })
+            .try_buffer_unordered(options.concurrency.max(1))
+            .try_fold(CleanupReport::default(), |mut report, (backup, result)| {
+                match result {
+                    Ok(()) => {
+                        report.reclaimed += backup.metadata.content_length;
+                        report.deleted.push(backup.path);
+                    }
+                    Err(e) => report.failed.push(CleanupFailure {
+                        name: backup.path,
+                        error: format!("{:#}", e),
+                    }),
                 }
-            };
+
+                future::ready(Ok(report))
+            })
+            .await?;
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.as_ref().filter(|_| !options.dry_run) {
+            metrics.record_cleanup(self.config.name(), &report);
         }
 
-        Ok((deleted_count, deleted_size))
+        Ok(report)
     }
+}
 
-    fn get_content_length(&self, entry: &Entry) -> u64 {
-        match &self.config {
-            StorageConfig::Local(local_config) => {
-                let full_path = Path::new(&local_config.location).join(&entry.path);
-                let content_length = match fs::metadata(&full_path).context(format!(
-                    "Failed to get metadata for {}",
-                    full_path.display()
-                )) {
-                    Ok(metadata) => metadata.len(),
-                    Err(_) => 0,
-                };
+/// When `entry` was taken according to its name, or its storage modification time for names
+/// without a timestamp, so they sort among the others instead of last.
+fn get_entry_timestamp(entry: &Entry) -> Option<DateTime<Utc>> {
+    extract_timestamp_from_filename(&entry.metadata.name)
+        .ok()
+        .or(entry.metadata.last_modified)
+}
 
-                content_length
-            }
-            _ => entry.metadata.content_length,
+fn get_sort_timestamp(entry: &Entry) -> DateTime<Utc> {
+    get_entry_timestamp(entry).unwrap_or_default()
+}
+
+/// Whether `backup` is older than `cutoff` and can be deleted. Backups without a timestamp in
+/// their name are never deleted.
+fn is_past_cutoff(
+    backup: &Entry,
+    cutoff: DateTime<Utc>,
+    now: DateTime<Utc>,
+    min_age: Duration,
+) -> bool {
+    match extract_timestamp_from_filename(&backup.metadata.name) {
+        Ok(timestamp) => is_prunable(
+            timestamp,
+            backup.metadata.last_modified,
+            cutoff,
+            now,
+            min_age,
+        ),
+        Err(_) => {
+            warn!("Failed to extract timestamp from {}", backup.metadata.name);
+            false
         }
     }
 }
+
+/// Picks the backups to delete from `backups`, sorted newest first. The backups of each config,
+/// see `get_backup_group`, are ranked separately: the `keep_last` most recent ones are kept
+/// whatever their age, the ones past `max_count` are deleted whatever their age, and the
+/// others are deleted once older than `cutoff`. Backups without a timestamp in their name are
+/// neither ranked nor deleted.
+fn select_by_rank(
+    backups: Vec<Entry>,
+    cutoff: Option<DateTime<Utc>>,
+    keep_last: usize,
+    max_count: Option<usize>,
+    now: DateTime<Utc>,
+    min_age: Duration,
+) -> Vec<Entry> {
+    let mut ranks: HashMap<String, usize> = HashMap::new();
+
+    backups
+        .into_iter()
+        .filter(
+            |backup| match extract_timestamp_from_filename(&backup.metadata.name) {
+                Ok(_) => true,
+                Err(_) => {
+                    warn!("Failed to extract timestamp from {}", backup.metadata.name);
+                    false
+                }
+            },
+        )
+        .filter_map(|backup| {
+            let next_rank = ranks
+                .entry(get_backup_group(&backup.metadata.name).to_string())
+                .or_default();
+            let rank = *next_rank;
+            *next_rank += 1;
+
+            let cutoff = if max_count.is_some_and(|max_count| rank >= max_count) {
+                now
+            } else if rank >= keep_last {
+                cutoff?
+            } else {
+                return None;
+            };
+
+            is_past_cutoff(&backup, cutoff, now, min_age).then_some(backup)
+        })
+        .collect()
+}
+
+/// Returns the backups a grandfather-father-son retention deletes, newest first, ignoring the
+/// minimum backup age. Every backup of the last `daily` days is kept, as is the most recent
+/// backup of each ISO week within the last `weekly` weeks and of each month within the last
+/// `monthly` months. Weeks and months are those of the UTC timestamp in the backup name, and
+/// backups without one are never deleted. The backups of each config, see `get_backup_group`,
+/// fill their own weeks and months.
+pub fn select_gfs(
+    backups: Vec<Entry>,
+    daily: u32,
+    weekly: u32,
+    monthly: u32,
+    now: DateTime<Utc>,
+) -> Vec<Entry> {
+    let mut dated: Vec<(DateTime<Utc>, Entry)> = backups
+        .into_iter()
+        .filter_map(
+            |backup| match extract_timestamp_from_filename(&backup.metadata.name) {
+                Ok(timestamp) => Some((timestamp, backup)),
+                Err(_) => {
+                    warn!("Failed to extract timestamp from {}", backup.metadata.name);
+                    None
+                }
+            },
+        )
+        .collect();
+    dated.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let daily_cutoff = now
+        .checked_sub_signed(Duration::days(daily.into()))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let weekly_cutoff = now
+        .checked_sub_signed(Duration::weeks(weekly.into()))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let monthly_cutoff = now
+        .checked_sub_months(Months::new(monthly))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+    let mut weeks = HashSet::new();
+    let mut months = HashSet::new();
+
+    dated
+        .into_iter()
+        .filter_map(|(timestamp, backup)| {
+            // Newest first, so the first backup seen in a week or month represents it
+            let group = get_backup_group(&backup.metadata.name).to_string();
+            let first_of_week = weeks.insert((group.clone(), timestamp.iso_week()));
+            let first_of_month = months.insert((group, timestamp.year(), timestamp.month()));
+
+            let keep = timestamp >= daily_cutoff
+                || (first_of_week && timestamp >= weekly_cutoff)
+                || (first_of_month && timestamp >= monthly_cutoff);
+
+            (!keep).then_some(backup)
+        })
+        .collect()
+}
@@ -0,0 +1,376 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    net::TcpStream,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::DateTime;
+use log::{trace, warn};
+use ssh2::{ErrorCode, FileStat, Session, Sftp};
+use uuid::Uuid;
+
+use super::{
+    backend::StorageBackend,
+    provider::{SftpStorageConfig, StorageCredentials},
+    Entry, EntryMetadata, EntryMode,
+};
+
+/// `LIBSSH2_FX_NO_SUCH_FILE`
+const NO_SUCH_FILE: i32 = 2;
+
+/// Stores backups under `location` on an SSH server, over libssh2 like `SshTunnel`. The
+/// session is opened on first use and shared by every operation.
+pub struct SftpBackend {
+    config: SftpStorageConfig,
+    sftp: Arc<Mutex<Option<Arc<Sftp>>>>,
+}
+
+impl SftpBackend {
+    pub fn new(config: SftpStorageConfig) -> Result<Self> {
+        match &config.credentials {
+            StorageCredentials::Basic { .. } | StorageCredentials::PrivateKey { .. } => {}
+            _ => {
+                return Err(anyhow!(
+                    "SFTP storage {} requires password or private key credentials",
+                    config.name
+                ))
+            }
+        }
+
+        Ok(Self {
+            config,
+            sftp: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn connect(config: &SftpStorageConfig) -> Result<Sftp> {
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .with_context(|| format!("Failed to connect to {}:{}", config.host, config.port))?;
+
+        let mut session = Session::new().context("Failed to create SSH session")?;
+        session.set_tcp_stream(tcp);
+        session.handshake().context("SSH handshake failed")?;
+
+        match &config.credentials {
+            StorageCredentials::Basic { username, password } => session
+                .userauth_password(username, password)
+                .context("SSH password authentication failed")?,
+            StorageCredentials::PrivateKey {
+                username,
+                key_path,
+                passphrase,
+            } => session
+                .userauth_pubkey_file(username, None, Path::new(key_path), passphrase.as_deref())
+                .context("SSH key authentication failed")?,
+            _ => return Err(anyhow!("Unsupported SFTP credentials")),
+        }
+
+        trace!("SFTP session opened on {}", config.host);
+
+        session.sftp().context("Failed to start the SFTP subsystem")
+    }
+
+    /// Runs `operation` on a blocking thread with the shared session, opening it if needed.
+    async fn run<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&Arc<Sftp>, &Path) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let config = self.config.clone();
+        let cached = self.sftp.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let sftp = {
+                let mut cached = cached
+                    .lock()
+                    .map_err(|_| anyhow!("SFTP session poisoned"))?;
+
+                match cached.as_ref() {
+                    Some(sftp) => sftp.clone(),
+                    None => cached.insert(Arc::new(Self::connect(&config)?)).clone(),
+                }
+            };
+
+            let root = match config.location.as_str() {
+                "" => ".",
+                location => location,
+            };
+
+            operation(&sftp, Path::new(root))
+        })
+        .await?
+    }
+}
+
+fn is_not_found(e: &ssh2::Error) -> bool {
+    e.code() == ErrorCode::SFTP(NO_SUCH_FILE)
+}
+
+fn get_metadata(name: &str, stat: &FileStat) -> EntryMetadata {
+    EntryMetadata {
+        mode: if stat.is_dir() {
+            EntryMode::DIR
+        } else if stat.is_file() {
+            EntryMode::FILE
+        } else {
+            EntryMode::Unknown
+        },
+        name: name.to_string(),
+        is_file: stat.is_file(),
+        is_current: None,
+        is_deleted: false,
+        cache_control: None,
+        content_disposition: None,
+        content_length: stat.size.unwrap_or(0),
+        content_md5: None,
+        content_type: None,
+        content_encoding: None,
+        etag: None,
+        last_modified: stat
+            .mtime
+            .and_then(|mtime| DateTime::from_timestamp(mtime as i64, 0)),
+        version: None,
+    }
+}
+
+fn list_recursive(
+    sftp: &Sftp,
+    root: &Path,
+    directory: &Path,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    let children = match sftp.readdir(directory) {
+        Ok(children) => children,
+        // A storage nothing was written to yet is empty
+        Err(e) if is_not_found(&e) && directory == root => return Ok(()),
+        Err(e) => return Err(e).context(format!("Failed to list {}", directory.display())),
+    };
+
+    for (path, stat) in children {
+        if stat.is_dir() {
+            list_recursive(sftp, root, &path, entries)?;
+        } else if stat.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            entries.push(Entry {
+                path: relative.to_string_lossy().to_string(),
+                metadata: get_metadata(&name, &stat),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates `directory` and its missing parents.
+fn create_dir_all(sftp: &Sftp, directory: &Path) -> Result<()> {
+    if directory.as_os_str().is_empty() || sftp.stat(directory).is_ok_and(|stat| stat.is_dir()) {
+        return Ok(());
+    }
+
+    if let Some(parent) = directory.parent() {
+        create_dir_all(sftp, parent)?;
+    }
+
+    match sftp.mkdir(directory, 0o755) {
+        Ok(()) => Ok(()),
+        // Created concurrently
+        Err(_) if sftp.stat(directory).is_ok_and(|stat| stat.is_dir()) => Ok(()),
+        Err(e) => Err(e).context(format!("Failed to create {}", directory.display())),
+    }
+}
+
+/// Writes to a temporary file next to `path`, renamed over it on flush so readers never see a
+/// partial backup. Dropping the writer before flushing removes the temporary file.
+pub struct SftpWriter {
+    sftp: Arc<Sftp>,
+    file: Option<ssh2::File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl SftpWriter {
+    fn new(sftp: Arc<Sftp>, path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(&sftp, parent)?;
+        }
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(format!(".{}.part", Uuid::new_v4().simple()));
+        let temp_path = PathBuf::from(temp_path);
+
+        let file = sftp
+            .create(&temp_path)
+            .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+
+        Ok(Self {
+            sftp,
+            file: Some(file),
+            temp_path,
+            path,
+            committed: false,
+        })
+    }
+}
+
+impl Write for SftpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("The SFTP writer is already flushed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.committed {
+            return Ok(());
+        }
+
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        if self.sftp.rename(&self.temp_path, &self.path, None).is_err() {
+            // Servers without the posix-rename extension refuse to replace a file
+            match self.sftp.unlink(&self.path) {
+                Err(e) if !is_not_found(&e) => return Err(e.into()),
+                _ => {}
+            }
+
+            self.sftp.rename(&self.temp_path, &self.path, None)?;
+        }
+
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for SftpWriter {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Err(e) = self.sftp.unlink(&self.temp_path) {
+                warn!("Failed to remove {}: {}", self.temp_path.display(), e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SftpBackend {
+    async fn list(&self) -> Result<Vec<Entry>> {
+        self.run(|sftp, root| {
+            let mut entries = vec![];
+            list_recursive(sftp, root, root, &mut entries)?;
+            Ok(entries)
+        })
+        .await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<EntryMetadata>> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| match sftp.stat(&root.join(&path)) {
+            Ok(stat) => {
+                let name = path.rsplit('/').next().unwrap_or(&path);
+                Ok(Some(get_metadata(name, &stat)))
+            }
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to stat {}", path)),
+        })
+        .await
+    }
+
+    async fn create_writer(&self, path: &str) -> Result<Box<dyn Write + Send + Unpin>> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| {
+            let writer = SftpWriter::new(sftp.clone(), root.join(&path))?;
+            Ok(Box::new(writer) as Box<dyn Write + Send + Unpin>)
+        })
+        .await
+    }
+
+    async fn create_reader(&self, path: &str) -> Result<Box<dyn Read + Send + Unpin>> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| {
+            let file = sftp
+                .open(root.join(&path))
+                .with_context(|| format!("Failed to open {}", path))?;
+
+            Ok(Box::new(file) as Box<dyn Read + Send + Unpin>)
+        })
+        .await
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| {
+            let mut file = match sftp.open(root.join(&path)) {
+                Ok(file) => file,
+                Err(e) if is_not_found(&e) => return Ok(None),
+                Err(e) => return Err(e).context(format!("Failed to read {}", path)),
+            };
+
+            let mut content = vec![];
+            file.read_to_end(&mut content)
+                .with_context(|| format!("Failed to read {}", path))?;
+
+            Ok(Some(content))
+        })
+        .await
+    }
+
+    async fn read_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| {
+            let mut file = sftp
+                .open(root.join(&path))
+                .with_context(|| format!("Failed to read {}", path))?;
+
+            file.seek(SeekFrom::Start(range.start))?;
+
+            let mut content = vec![];
+            file.take(range.end.saturating_sub(range.start))
+                .read_to_end(&mut content)
+                .with_context(|| format!("Failed to read {}", path))?;
+
+            Ok(content)
+        })
+        .await
+    }
+
+    async fn write(&self, path: &str, content: Vec<u8>) -> Result<()> {
+        let mut writer = self.create_writer(path).await?;
+        let path = path.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            writer.write_all(&content)?;
+            writer
+                .flush()
+                .with_context(|| format!("Failed to write {}", path))
+        })
+        .await?
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let path = path.to_string();
+
+        self.run(move |sftp, root| match sftp.unlink(&root.join(&path)) {
+            Ok(()) => Ok(()),
+            Err(e) if is_not_found(&e) => Ok(()),
+            Err(e) => Err(e).context(format!("Failed to delete {}", path)),
+        })
+        .await
+    }
+}
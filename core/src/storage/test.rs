@@ -1,7 +1,40 @@
 #[cfg(test)]
 mod provider_test {
-    use crate::test_utils::test_utils::{get_local_provider, get_s3_provider, initialize_test};
-    use std::io::{Cursor, Read, Write};
+    use crate::{
+        catalog::Catalog,
+        checksum::{ChecksumAlgorithm, HashingWriter},
+        compression::{CompressionFormat, Compressor, Decompressor},
+        databases::{BackupScope, ConnectionType},
+        manifest::{get_manifest_name, BackupManifest},
+        storage::{
+            backend::{OpendalBackend, StorageBackend},
+            copy::CopyOptions,
+            fanout::{FanoutReport, FanoutWriter, RequirePolicy},
+            io::{get_read_chunk_size, ResumableReader, READ_CHUNK_SIZE},
+            provider::{
+                select_gfs, CleanupOptions, CustomStorageConfig, GcsStorageConfig, ListOptions,
+                LocalStorageConfig, RetentionPolicy, RetryConfig, S3StorageConfig,
+                ServerSideEncryption, StorageConfig, StorageProvider, UploadOptions,
+            },
+            Entry, EntryMetadata, EntryMode,
+        },
+        test_utils::test_utils::{get_local_provider, get_s3_provider, initialize_test},
+    };
+    use async_trait::async_trait;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use flate2::Compression;
+    use futures::TryStreamExt;
+    use std::{
+        collections::{BTreeMap, HashSet},
+        io::{Cursor, ErrorKind, Read, Write},
+        ops::Range,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex as StdMutex,
+        },
+        time::Duration,
+    };
 
     #[tokio::test]
     async fn test_01_local() {
@@ -11,7 +44,7 @@ mod provider_test {
 
         let mut content_reader = Cursor::new(content);
         let mut writer = provider
-            .create_writer("test")
+            .create_writer("test.gz")
             .await
             .expect("Failed to create writer");
 
@@ -35,7 +68,7 @@ mod provider_test {
         assert!(is_connected);
 
         let mut reader = provider
-            .create_reader("test")
+            .create_reader("test.gz")
             .await
             .expect("Failed to create reader");
 
@@ -62,7 +95,7 @@ mod provider_test {
         let provider = get_s3_provider().expect("Failed to get s3 provider");
 
         let mut writer = provider
-            .create_writer("test-01")
+            .create_writer("test-01.gz")
             .await
             .expect("Failed to create writer");
 
@@ -83,7 +116,7 @@ mod provider_test {
         writer.flush().expect("Failed to flush");
 
         let mut reader = provider
-            .create_reader("test-01")
+            .create_reader("test-01.gz")
             .await
             .expect("Failed to create reader");
 
@@ -105,10 +138,8 @@ mod provider_test {
         let provider = get_local_provider().expect("Failed to get s3 provider");
 
         let entries = provider
-            .operator
-            .list_with("/")
-            .recursive(true)
-            .limit(10)
+            .backend()
+            .list()
             .await
             .expect("Failed to list dumps in");
 
@@ -120,13 +151,1855 @@ mod provider_test {
         let provider = get_s3_provider().expect("Failed to get s3 provider");
 
         let entries = provider
-            .operator
-            .list_with("/")
-            .recursive(true)
-            .limit(10)
+            .backend()
+            .list()
             .await
             .expect("Failed to list dumps in");
 
         println!("{:?}", entries);
     }
+
+    #[tokio::test]
+    async fn test_04_manifest_local() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        let missing = provider
+            .read_manifest("missing.gz")
+            .await
+            .expect("Failed to read missing manifest");
+
+        assert!(missing.is_none());
+
+        provider
+            .write_manifest(&BackupManifest {
+                name: "test-2025-01-01-000000-abcdef.gz".into(),
+                database: "test".into(),
+                connection_type: ConnectionType::PostgreSql,
+                created_at: Utc::now(),
+                change_token: Some("1-2-3".into()),
+                server_version: None,
+                compression_format: None,
+                size: None,
+                checksum: None,
+                scope: BackupScope::All,
+                encrypted: false,
+                uncompressed_size: None,
+                physical: false,
+            })
+            .await
+            .expect("Failed to write manifest");
+
+        let manifest = provider
+            .read_manifest("test-2025-01-01-000000-abcdef.gz")
+            .await
+            .expect("Failed to read manifest")
+            .expect("Manifest not found");
+
+        assert_eq!(manifest.change_token, Some("1-2-3".into()));
+
+        let entries = provider.list().await.expect("Failed to list entries");
+        assert!(entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_05_backup_info_local() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+        let name = "test-2025-01-01-000000-abcdef.gz";
+
+        let writer = provider
+            .create_writer(name)
+            .await
+            .expect("Failed to create writer");
+        let mut compressor = Compressor::new(writer, CompressionFormat::Gzip, Compression::fast());
+
+        compressor
+            .write_all(
+                "--\n-- PostgreSQL database dump\n--\n\n\
+                -- Dumped from database version 16.2\n\
+                -- Dumped by pg_dump version 16.2\n"
+                    .as_bytes(),
+            )
+            .expect("Failed to write dump");
+
+        let mut writer = compressor.finish().expect("Failed to finish compressor");
+        writer.flush().expect("Failed to flush writer");
+
+        let info = provider
+            .get_backup_info(name)
+            .await
+            .expect("Failed to get backup info");
+
+        assert!(info.manifest.is_none());
+        assert!(info.size > 0);
+        assert!(matches!(info.compression_format, CompressionFormat::Gzip));
+        assert_eq!(info.server_version, Some("PostgreSQL 16.2".into()));
+    }
+
+    #[tokio::test]
+    async fn test_06_nth_latest_local() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "test-2025-01-01-000000-abcdef.gz",
+            "test-2025-01-03-000000-abcdef.gz",
+            "test-2025-01-02-000000-abcdef.gz",
+            "other-2025-01-04-000000-abcdef.gz",
+            // Another config whose name starts with the one looked up
+            "test-staging-2025-01-05-000000-abcdef.gz",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let entry = provider
+            .get_nth_latest("test", 1)
+            .await
+            .expect("Failed to get backup");
+
+        assert_eq!(entry.metadata.name, "test-2025-01-02-000000-abcdef.gz");
+        assert!(provider.get_nth_latest("test", 3).await.is_err());
+
+        let entry = provider
+            .get_nth_latest("test", 0)
+            .await
+            .expect("Failed to get backup");
+        assert_eq!(entry.metadata.name, "test-2025-01-03-000000-abcdef.gz");
+
+        let entry = provider
+            .get_nth_latest("test-staging", 0)
+            .await
+            .expect("Failed to get backup");
+        assert_eq!(
+            entry.metadata.name,
+            "test-staging-2025-01-05-000000-abcdef.gz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_07_list_page_local() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for day in 1..=5 {
+            let name = format!("test-2025-01-0{}-000000-abcdef.gz", day);
+
+            provider
+                .backend()
+                .write(&name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let options = ListOptions {
+            latest_only: None,
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let mut names = vec![];
+        let mut cursor = None;
+        let mut pages = 0;
+
+        loop {
+            let (entries, next_cursor) = provider
+                .list_page(options.clone(), cursor)
+                .await
+                .expect("Failed to list page");
+
+            pages += 1;
+            names.extend(entries.into_iter().map(|entry| entry.metadata.name));
+
+            match next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, 3);
+        assert_eq!(
+            names,
+            (1..=5)
+                .map(|day| format!("test-2025-01-0{}-000000-abcdef.gz", day))
+                .collect::<Vec<_>>()
+        );
+
+        // The limit only sizes pages, a full listing is never truncated
+        let entries = provider
+            .list_with_options(options)
+            .await
+            .expect("Failed to list");
+        assert_eq!(entries.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_08_copy_local() {
+        initialize_test();
+        let source = get_local_provider().expect("Failed to get local provider");
+        let destination = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "test-2025-01-01-000000-abcdef.gz",
+            "test-2025-01-02-000000-abcdef.gz",
+        ] {
+            source
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        source
+            .write_manifest(&BackupManifest {
+                name: "test-2025-01-02-000000-abcdef.gz".into(),
+                database: "test".into(),
+                connection_type: ConnectionType::PostgreSql,
+                created_at: Utc::now(),
+                change_token: None,
+                server_version: None,
+                compression_format: None,
+                size: Some(4),
+                checksum: Some("sha256:abc".into()),
+                scope: BackupScope::All,
+                encrypted: false,
+                uncompressed_size: None,
+                physical: false,
+            })
+            .await
+            .expect("Failed to write manifest");
+
+        let report = source
+            .copy_to(&destination, CopyOptions::default())
+            .await
+            .expect("Failed to copy backups");
+
+        assert_eq!(report.copied.len(), 2);
+        assert!(report.skipped.is_empty() && report.failed.is_empty());
+
+        let manifest = destination
+            .read_manifest("test-2025-01-02-000000-abcdef.gz")
+            .await
+            .expect("Failed to read manifest")
+            .expect("Manifest not copied");
+        assert_eq!(manifest.checksum, Some("sha256:abc".into()));
+
+        let report = source
+            .copy_to(&destination, CopyOptions::default())
+            .await
+            .expect("Failed to copy backups");
+
+        assert!(report.copied.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_09_bench_local() {
+        initialize_test();
+        let storage_provider = get_local_provider().expect("Failed to get local provider");
+
+        let report = storage_provider
+            .bench(3 * 1024 * 1024 + 17)
+            .await
+            .expect("Failed to run benchmark");
+
+        assert_eq!(report.size, 3 * 1024 * 1024 + 17);
+        assert!(report.write_throughput() > 0.0);
+        assert!(report.read_throughput() > 0.0);
+        assert!(storage_provider
+            .backend()
+            .list()
+            .await
+            .expect("Failed to list storage")
+            .iter()
+            .all(|entry| !entry.metadata.name.starts_with(".dbkp-bench-")));
+    }
+
+    async fn fan_out(
+        destinations: &[StorageProvider],
+        require: RequirePolicy,
+    ) -> anyhow::Result<FanoutReport> {
+        let mut writer = FanoutWriter::open(destinations, "fanout.gz", require).await?;
+        writer.write_all(b"dump")?;
+        writer.finish(destinations).await
+    }
+
+    fn get_failing_provider() -> StorageProvider {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let location = dir.path().to_str().unwrap().to_string();
+
+        let provider = StorageProvider::new(StorageConfig::Local(LocalStorageConfig {
+            id: "failing".into(),
+            name: "failing".into(),
+            location: location.clone(),
+        }))
+        .expect("Failed to create provider");
+
+        // Replace the storage root by a regular file so nothing can be written below it
+        dir.close().expect("Failed to remove temp dir");
+        std::fs::write(&location, b"").expect("Failed to create file");
+
+        provider
+    }
+
+    async fn exists(provider: &StorageProvider) -> bool {
+        provider
+            .backend()
+            .stat("fanout.gz")
+            .await
+            .expect("Failed to stat backup")
+            .is_some()
+    }
+
+    #[tokio::test]
+    async fn test_10_fanout_policies() {
+        initialize_test();
+        let first = get_local_provider().expect("Failed to get local provider");
+        let second = get_local_provider().expect("Failed to get local provider");
+
+        let report = fan_out(&[first.clone(), second.clone()], RequirePolicy::All)
+            .await
+            .expect("All destinations should succeed");
+        assert_eq!(report.succeeded.len(), 2);
+        assert!(exists(&first).await && exists(&second).await);
+
+        let first = get_local_provider().expect("Failed to get local provider");
+        let destinations = [first.clone(), get_failing_provider()];
+
+        assert!(fan_out(&destinations, RequirePolicy::All).await.is_err());
+        assert!(!exists(&first).await);
+
+        let report = fan_out(&destinations, RequirePolicy::Any)
+            .await
+            .expect("One destination is enough");
+        assert_eq!(report.succeeded.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].index, 1);
+        assert!(exists(&first).await);
+
+        let destinations = [
+            get_local_provider().expect("Failed to get local provider"),
+            get_local_provider().expect("Failed to get local provider"),
+            get_failing_provider(),
+        ];
+
+        let report = fan_out(&destinations, RequirePolicy::Quorum(2))
+            .await
+            .expect("Two destinations meet the quorum");
+        assert_eq!(report.succeeded.len(), 2);
+
+        let destinations = [
+            get_local_provider().expect("Failed to get local provider"),
+            get_failing_provider(),
+            get_failing_provider(),
+        ];
+
+        assert!(fan_out(&destinations, RequirePolicy::Quorum(2))
+            .await
+            .is_err());
+        assert!(!exists(&destinations[0]).await);
+    }
+
+    #[tokio::test]
+    async fn test_11_find_backup_on_local() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "daily-2025-03-01-230000-abcdef.gz",
+            "daily-2025-03-02-080000-abcdef.gz",
+            "other-2025-03-03-080000-abcdef.gz",
+            "daily-staging-2025-03-03-090000-abcdef.gz",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let find = |day: u32, timezone: Option<Tz>| {
+            let provider = &provider;
+            async move {
+                provider
+                    .find_backup_on(
+                        "daily",
+                        NaiveDate::from_ymd_opt(2025, 3, day).unwrap(),
+                        timezone.as_ref(),
+                    )
+                    .await
+                    .expect("Failed to find backup")
+                    .map(|entry| entry.metadata.name)
+            }
+        };
+
+        assert_eq!(
+            find(2, None).await,
+            Some("daily-2025-03-02-080000-abcdef.gz".into())
+        );
+        // The backup of daily-staging does not count as one of daily
+        assert_eq!(find(3, None).await, None);
+
+        // 23:00 UTC on March 1st is already March 2nd in Paris
+        assert_eq!(find(1, Some(chrono_tz::Europe::Paris)).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_12_cleanup_keeps_recent_backups() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        let recent = format!("db-{}-abcdef.gz", Utc::now().format("%Y-%m-%d-%H%M%S"));
+
+        for name in [recent.as_str(), "db-2020-01-01-000000-abcdef.gz"] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        // A retention of 0 days puts the just-created backup past the cutoff
+        let deleted = provider
+            .cleanup(RetentionPolicy::MaxAge(0), true)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(
+            deleted, 1,
+            "The recent backup should be in its grace period"
+        );
+
+        let mut options = CleanupOptions::new(RetentionPolicy::MaxAge(0), true);
+        options.min_backup_age = chrono::Duration::zero();
+        let deleted = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 2);
+    }
+
+    struct CountingReader<R: Read> {
+        inner: R,
+        count: Arc<AtomicU64>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.count.fetch_add(n as u64, Ordering::SeqCst);
+            Ok(n)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_13_stream_compressed_backup() {
+        initialize_test();
+        let chunk_size = 256 * 1024;
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let operator = opendal::Operator::new(
+            opendal::services::Fs::default().root(dir.path().to_str().unwrap()),
+        )
+        .expect("Failed to create operator")
+        .finish();
+        let backend =
+            OpendalBackend::new(operator, Some(dir.path().into())).with_read_chunk_size(chunk_size);
+
+        // Poorly compressible rows so the stored backup takes many reads
+        let mut seed: u64 = 42;
+        let mut dump = vec![];
+        while dump.len() < 8 * 1024 * 1024 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            writeln!(dump, "{}\t{:x}", dump.len(), seed).unwrap();
+        }
+
+        let mut compressor =
+            Compressor::new(vec![], CompressionFormat::Gzip, Compression::default());
+        compressor.write_all(&dump).unwrap();
+        let compressed = compressor.finish().unwrap();
+        assert!(compressed.len() > 4 * chunk_size);
+
+        backend
+            .write("large.gz", compressed.clone())
+            .await
+            .expect("Failed to write backup");
+
+        let count = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: backend
+                .create_reader("large.gz")
+                .await
+                .expect("Failed to create reader"),
+            count: count.clone(),
+        };
+
+        let restored = tokio::task::spawn_blocking(move || {
+            let mut decompressor = Decompressor::new(reader, CompressionFormat::Gzip);
+
+            let mut head = vec![0; 4096];
+            decompressor.read_exact(&mut head).unwrap();
+            // Only the chunks needed so far were fetched
+            assert!(count.load(Ordering::SeqCst) <= 2 * chunk_size as u64);
+
+            let mut rest = vec![];
+            decompressor.read_to_end(&mut rest).unwrap();
+            head.extend(rest);
+            (head, count.load(Ordering::SeqCst))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(restored.0, dump);
+        assert_eq!(restored.1, compressed.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_14_read_missing_backup() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        let mut reader = provider
+            .create_reader("missing.gz")
+            .await
+            .expect("Failed to create reader");
+
+        let result = tokio::task::spawn_blocking(move || reader.read(&mut [0; 16]))
+            .await
+            .unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_15_list_ignores_foreign_files() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "db-2020-01-01-000000-abcdef.gz",
+            "db-2020-01-01-000000-abcdef.gz.meta.json",
+            "db-2020-01-02-000000-abcdef.",
+            "db-2020-01-03-000000-abcdef.log",
+            ".DS_Store",
+            "README",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write file");
+        }
+
+        let names = |entries: Vec<Entry>| {
+            let mut names: Vec<String> = entries
+                .into_iter()
+                .map(|entry| entry.metadata.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let entries = provider.list().await.expect("Failed to list entries");
+        assert_eq!(
+            names(entries),
+            vec![
+                "db-2020-01-01-000000-abcdef.gz",
+                "db-2020-01-02-000000-abcdef."
+            ]
+        );
+
+        // Retention only deletes the recognized backup and its manifest
+        let deleted = provider
+            .cleanup(RetentionPolicy::MaxAge(0), false)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 2);
+
+        let remaining = provider
+            .backend()
+            .list()
+            .await
+            .expect("Failed to list files")
+            .len();
+        assert_eq!(remaining, 3);
+
+        let provider = provider.with_backup_extensions(vec![".log".into()]);
+        let entries = provider.list().await.expect("Failed to list entries");
+        assert_eq!(names(entries), vec!["db-2020-01-03-000000-abcdef.log"]);
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryBackend {
+        files: Arc<StdMutex<BTreeMap<String, Vec<u8>>>>,
+        /// Paths whose deletion fails.
+        undeletable: Arc<StdMutex<HashSet<String>>>,
+    }
+
+    struct MemoryWriter {
+        files: Arc<StdMutex<BTreeMap<String, Vec<u8>>>>,
+        path: String,
+        content: Vec<u8>,
+    }
+
+    impl Write for MemoryWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.content.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files.insert(self.path.clone(), self.content.clone());
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for MemoryBackend {
+        async fn list(&self) -> anyhow::Result<Vec<Entry>> {
+            let files = self.files.lock().unwrap();
+            let entries = files
+                .keys()
+                .map(|path| Entry {
+                    path: path.clone(),
+                    metadata: self.get_metadata(path, &files[path]),
+                })
+                .collect();
+
+            Ok(entries)
+        }
+
+        async fn stat(&self, path: &str) -> anyhow::Result<Option<EntryMetadata>> {
+            let files = self.files.lock().unwrap();
+            Ok(files
+                .get(path)
+                .map(|content| self.get_metadata(path, content)))
+        }
+
+        async fn create_writer(&self, path: &str) -> anyhow::Result<Box<dyn Write + Send + Unpin>> {
+            Ok(Box::new(MemoryWriter {
+                files: self.files.clone(),
+                path: path.into(),
+                content: vec![],
+            }))
+        }
+
+        async fn create_reader(&self, path: &str) -> anyhow::Result<Box<dyn Read + Send + Unpin>> {
+            let content = self.read(path).await?.unwrap_or_default();
+            Ok(Box::new(Cursor::new(content)))
+        }
+
+        async fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.files.lock().unwrap().get(path).cloned())
+        }
+
+        async fn read_range(&self, path: &str, range: Range<u64>) -> anyhow::Result<Vec<u8>> {
+            let content = self.read(path).await?.unwrap_or_default();
+            Ok(content[range.start as usize..range.end as usize].to_vec())
+        }
+
+        async fn write(&self, path: &str, content: Vec<u8>) -> anyhow::Result<()> {
+            self.files.lock().unwrap().insert(path.into(), content);
+            Ok(())
+        }
+
+        async fn delete(&self, path: &str) -> anyhow::Result<()> {
+            if self.undeletable.lock().unwrap().contains(path) {
+                return Err(anyhow::anyhow!("Access denied"));
+            }
+
+            self.files.lock().unwrap().remove(path);
+            Ok(())
+        }
+    }
+
+    impl MemoryBackend {
+        fn get_metadata(&self, path: &str, content: &[u8]) -> EntryMetadata {
+            EntryMetadata {
+                mode: EntryMode::FILE,
+                name: path.into(),
+                is_file: true,
+                is_current: None,
+                is_deleted: false,
+                cache_control: None,
+                content_disposition: None,
+                content_length: content.len() as u64,
+                content_md5: None,
+                content_type: None,
+                content_encoding: None,
+                etag: None,
+                last_modified: None,
+                version: None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_16_custom_backend() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+        let name = "test-2025-01-01-000000-abcdef.gz";
+
+        let mut compressor = Compressor::new(
+            provider
+                .create_writer(name)
+                .await
+                .expect("Failed to create writer"),
+            CompressionFormat::Gzip,
+            Compression::default(),
+        );
+        compressor
+            .write_all(b"-- Dumped from database version 16.2\n")
+            .unwrap();
+        compressor.finish().unwrap().flush().unwrap();
+
+        provider
+            .write_manifest(&BackupManifest {
+                name: name.into(),
+                database: "test".into(),
+                connection_type: ConnectionType::PostgreSql,
+                created_at: Utc::now(),
+                change_token: None,
+                server_version: None,
+                compression_format: Some(CompressionFormat::Gzip),
+                size: None,
+                checksum: None,
+                scope: BackupScope::All,
+                encrypted: false,
+                uncompressed_size: None,
+                physical: false,
+            })
+            .await
+            .expect("Failed to write manifest");
+
+        let entries = provider.list().await.expect("Failed to list entries");
+        assert_eq!(entries.len(), 1);
+
+        let info = provider
+            .get_backup_info(name)
+            .await
+            .expect("Failed to get backup info");
+        assert_eq!(info.server_version, Some("PostgreSQL 16.2".into()));
+        assert!(info.manifest.is_some());
+
+        let deleted = provider
+            .cleanup(RetentionPolicy::MaxAge(0), false)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 1);
+        assert!(backend.files.lock().unwrap().is_empty());
+    }
+    #[tokio::test]
+    async fn test_17_catalog() {
+        initialize_test();
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let catalog = Catalog::new(dir.path().join("catalog.json"));
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            MemoryBackend::default(),
+        )
+        .with_catalog(catalog.clone());
+
+        let name = "test-2025-01-01-000000-abcdef.gz";
+        provider
+            .backend()
+            .write(name, b"dump".to_vec())
+            .await
+            .expect("Failed to write backup");
+        provider
+            .write_manifest(&BackupManifest {
+                name: name.into(),
+                database: "test".into(),
+                connection_type: ConnectionType::PostgreSql,
+                created_at: Utc::now(),
+                change_token: None,
+                server_version: None,
+                compression_format: Some(CompressionFormat::Gzip),
+                size: Some(4),
+                checksum: Some("sha256:00".into()),
+                scope: BackupScope::All,
+                encrypted: false,
+                uncompressed_size: None,
+                physical: false,
+            })
+            .await
+            .expect("Failed to write manifest");
+
+        let entries = catalog.load().expect("Failed to load catalog");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].checksum, Some("sha256:00".into()));
+
+        // Written behind the catalog's back, without a manifest
+        provider
+            .backend()
+            .write("other-2025-01-02-000000-abcdef.gz", b"dump".to_vec())
+            .await
+            .expect("Failed to write backup");
+
+        let count = catalog.reindex(&provider).await.expect("Failed to reindex");
+        assert_eq!(count, 2);
+
+        let entries = catalog.load().expect("Failed to load catalog");
+        assert_eq!(entries[0].database, None);
+        assert!(entries[0].created_at.is_some());
+        assert_eq!(entries[1].database, Some("test".into()));
+
+        provider
+            .cleanup_with_options(&CleanupOptions {
+                retention: RetentionPolicy::MaxAge(0),
+                dry_run: false,
+                min_backup_age: chrono::Duration::zero(),
+                concurrency: 2,
+            })
+            .await
+            .expect("Failed to clean up");
+        assert!(catalog.load().expect("Failed to load catalog").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_18_streaming_cleanup() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for day in 1..=20 {
+            let name = format!("db-2020-01-{:02}-000000-abcdef.gz", day);
+            provider
+                .backend()
+                .write(&name, vec![0; day])
+                .await
+                .expect("Failed to write backup");
+        }
+
+        provider
+            .backend()
+            .write("notes.txt", "not a backup".as_bytes().to_vec())
+            .await
+            .expect("Failed to write file");
+
+        let listed: Vec<Entry> = provider
+            .list_stream()
+            .await
+            .expect("Failed to list")
+            .try_collect()
+            .await
+            .expect("Failed to list");
+        assert_eq!(listed.len(), 20);
+
+        let mut options = CleanupOptions::new(RetentionPolicy::MaxAge(1), true);
+        options.concurrency = 8;
+
+        let dry_run = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up");
+        assert_eq!(dry_run.deleted.len(), 20);
+        assert_eq!(dry_run.reclaimed, 210);
+        assert_eq!(provider.list().await.expect("Failed to list").len(), 20);
+
+        options.dry_run = false;
+        let deleted = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up");
+        assert_eq!(deleted.reclaimed, dry_run.reclaimed);
+        assert_eq!(
+            deleted.deleted.into_iter().collect::<HashSet<_>>(),
+            dry_run.deleted.into_iter().collect::<HashSet<_>>()
+        );
+        assert!(provider.list().await.expect("Failed to list").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_19_count_based_cleanup() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        let recent = format!("db-{}-abcdef.gz", Utc::now().format("%Y-%m-%d-%H%M%S"));
+        let mut names = vec![recent];
+        names.extend((1..=5).map(|day| format!("db-2020-01-{:02}-000000-abcdef.gz", day)));
+
+        for name in &names {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let cleanup = |retention| {
+            let mut options = CleanupOptions::new(retention, true);
+            options.min_backup_age = chrono::Duration::zero();
+            let provider = &provider;
+            async move {
+                provider
+                    .cleanup_with_options(&options)
+                    .await
+                    .expect("Failed to clean up")
+                    .deleted
+                    .len()
+            }
+        };
+
+        assert_eq!(cleanup(RetentionPolicy::KeepLast(4)).await, 2);
+        assert_eq!(cleanup(RetentionPolicy::KeepLast(10)).await, 0);
+
+        // The 3 most recent are kept although 2 of them are past the age
+        let combined = RetentionPolicy::Combined {
+            max_age_days: 1,
+            keep_last: 3,
+            max_count: None,
+        };
+        assert_eq!(cleanup(combined).await, 3);
+
+        // Without age limit but capped at 2 backups
+        let capped = RetentionPolicy::Combined {
+            max_age_days: 36500,
+            keep_last: 1,
+            max_count: Some(2),
+        };
+        assert_eq!(cleanup(capped).await, 4);
+
+        // The recent backup is still in its grace period
+        let deleted = provider
+            .cleanup(RetentionPolicy::KeepLast(0), false)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 5);
+
+        let remaining = provider.list().await.expect("Failed to list");
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].metadata.name, names[0]);
+    }
+
+    #[test]
+    fn test_20_gfs_retention() {
+        let backend = MemoryBackend::default();
+        let now = Utc.with_ymd_and_hms(2025, 6, 15, 12, 0, 0).unwrap();
+
+        let mut names: Vec<String> = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .iter_days()
+            .take_while(|day| *day <= now.date_naive())
+            .map(|day| format!("db-{}-000000-abcdef.gz", day.format("%Y-%m-%d")))
+            .collect();
+        names.push("notes.gz".into());
+
+        let backups = names
+            .iter()
+            .map(|name| Entry {
+                path: name.clone(),
+                metadata: backend.get_metadata(name, b""),
+            })
+            .collect();
+
+        let deleted: HashSet<String> = select_gfs(backups, 7, 4, 12, now)
+            .into_iter()
+            .map(|backup| backup.path)
+            .collect();
+
+        let mut kept: Vec<&str> = names
+            .iter()
+            .filter(|name| !deleted.contains(*name))
+            .map(|name| name.get(3..13).unwrap_or(name))
+            .collect();
+        kept.sort();
+
+        // The last backup of each month for a year and of each week for 4 weeks, every backup
+        // of the last 7 days, and the backup without a timestamp
+        assert_eq!(
+            kept,
+            vec![
+                "2024-06-30",
+                "2024-07-31",
+                "2024-08-31",
+                "2024-09-30",
+                "2024-10-31",
+                "2024-11-30",
+                "2024-12-31",
+                "2025-01-31",
+                "2025-02-28",
+                "2025-03-31",
+                "2025-04-30",
+                "2025-05-25",
+                "2025-05-31",
+                "2025-06-01",
+                "2025-06-08",
+                "2025-06-09",
+                "2025-06-10",
+                "2025-06-11",
+                "2025-06-12",
+                "2025-06-13",
+                "2025-06-14",
+                "2025-06-15",
+                "notes.gz",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_21_retry_config() {
+        // Configurations saved before retries were configurable get the defaults
+        let config: StorageConfig = serde_json::from_str(
+            r#"{"S3": {"id": "s3", "name": "s3", "region": "us-east-1", "endpoint": null,
+                "bucket": "backups", "access_key": "key", "secret_key": "secret",
+                "location": "db"}}"#,
+        )
+        .expect("Failed to parse storage config");
+
+        match &config {
+            StorageConfig::S3(config) => assert_eq!(config.retry, RetryConfig::default()),
+            _ => panic!("Expected an S3 storage"),
+        }
+
+        let retry: RetryConfig =
+            serde_json::from_str(r#"{"max_retries": 0}"#).expect("Failed to parse retry config");
+        assert_eq!(retry.max_retries, 0);
+        assert_eq!(retry.max_delay_ms, RetryConfig::default().max_delay_ms);
+
+        StorageProvider::new(config).expect("Failed to create a provider with retries");
+    }
+
+    #[tokio::test]
+    async fn test_22_checksum_sidecar() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+
+        let name = "test-2025-01-01-000000-abcdef.gz";
+        let mut writer = HashingWriter::new(
+            provider
+                .create_writer(name)
+                .await
+                .expect("Failed to create writer"),
+            Some(ChecksumAlgorithm::Sha256),
+        );
+        writer.write_all(b"dump").expect("Failed to write backup");
+        let (mut writer, checksum) = writer.finish();
+        writer.flush().expect("Failed to flush backup");
+
+        assert!(provider.verify(name).await.is_err());
+
+        provider
+            .write_sidecar(name, &checksum.unwrap())
+            .await
+            .expect("Failed to write sidecar");
+
+        assert!(provider.verify(name).await.expect("Failed to verify"));
+
+        let names: Vec<String> = provider
+            .list()
+            .await
+            .expect("Failed to list")
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(names, vec![name.to_string()]);
+
+        backend
+            .write(name, b"dumq".to_vec())
+            .await
+            .expect("Failed to corrupt backup");
+
+        assert!(!provider.verify(name).await.expect("Failed to verify"));
+    }
+
+    #[tokio::test]
+    async fn test_23_upload_options() {
+        initialize_test();
+
+        let config = |upload: serde_json::Value| {
+            let config = serde_json::json!({"S3": {"id": "s3", "name": "s3",
+                "region": "us-east-1", "endpoint": null, "bucket": "backups",
+                "access_key": "key", "secret_key": "secret", "location": "db",
+                "upload": upload}});
+
+            serde_json::from_value::<StorageConfig>(config).expect("Failed to parse config")
+        };
+
+        match config(serde_json::json!({})) {
+            StorageConfig::S3(config) => assert_eq!(config.upload, UploadOptions::default()),
+            _ => panic!("Expected an S3 storage"),
+        }
+
+        assert!(StorageProvider::new(config(serde_json::json!({"chunk_size": 1024}))).is_err());
+        assert!(StorageProvider::new(config(serde_json::json!({"concurrency": 0}))).is_err());
+
+        // Parts uploaded concurrently outlive the write that started them
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let operator = opendal::Operator::new(
+            opendal::services::Fs::default().root(dir.path().to_str().unwrap()),
+        )
+        .expect("Failed to create operator")
+        .finish();
+        let backend =
+            OpendalBackend::new(operator, Some(dir.path().into())).with_upload(UploadOptions {
+                chunk_size: 5 * 1024 * 1024,
+                concurrency: 4,
+            });
+
+        let content: Vec<u8> = (0..12 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut writer = backend
+            .create_writer("large.gz")
+            .await
+            .expect("Failed to create writer");
+
+        for chunk in content.chunks(1024 * 1024) {
+            writer.write_all(chunk).expect("Failed to write");
+        }
+        writer.flush().expect("Failed to flush");
+        drop(writer);
+
+        let uploaded = backend
+            .read("large.gz")
+            .await
+            .expect("Failed to read")
+            .expect("Missing upload");
+        assert!(uploaded == content);
+    }
+
+    #[test]
+    fn test_24_read_chunk_size() {
+        assert_eq!(READ_CHUNK_SIZE, 8 * 1024 * 1024);
+        assert_eq!(get_read_chunk_size(0, READ_CHUNK_SIZE), 1);
+        assert_eq!(get_read_chunk_size(100, READ_CHUNK_SIZE), 100);
+        assert_eq!(
+            get_read_chunk_size(8 * 1024 * 1024, READ_CHUNK_SIZE),
+            8 * 1024 * 1024
+        );
+        assert_eq!(
+            get_read_chunk_size(5 * 1024 * 1024 * 1024, READ_CHUNK_SIZE),
+            8 * 1024 * 1024
+        );
+        assert_eq!(get_read_chunk_size(100, 16), 16);
+        assert_eq!(get_read_chunk_size(100, 0), 1);
+    }
+
+    /// Reads `content` from `offset`, failing with `kind` once `fail_at` is reached.
+    struct FlakyReader {
+        content: Arc<Vec<u8>>,
+        offset: usize,
+        fail_at: Option<usize>,
+        kind: ErrorKind,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let end = self.fail_at.unwrap_or(self.content.len());
+
+            if self.offset == end && end < self.content.len() {
+                return Err(std::io::Error::new(self.kind, "Connection reset"));
+            }
+
+            let n = buf.len().min(end - self.offset);
+            buf[..n].copy_from_slice(&self.content[self.offset..self.offset + n]);
+            self.offset += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_25_resume_interrupted_read() {
+        let content: Arc<Vec<u8>> = Arc::new((0..100_000).map(|i| (i % 251) as u8).collect());
+
+        let reader = |kind: ErrorKind, max_resumes: usize, opened: Arc<StdMutex<Vec<u64>>>| {
+            let content = content.clone();
+
+            ResumableReader::new(
+                move |offset| {
+                    let mut opened = opened.lock().unwrap();
+                    opened.push(offset);
+
+                    FlakyReader {
+                        content: content.clone(),
+                        offset: offset as usize,
+                        // The first two downloads break midway
+                        fail_at: match opened.len() {
+                            1 => Some(30_000),
+                            2 => Some(70_000),
+                            _ => None,
+                        },
+                        kind,
+                    }
+                },
+                max_resumes,
+            )
+            .with_delay(Duration::ZERO)
+        };
+
+        let opened = Arc::new(StdMutex::new(vec![]));
+        let mut resumed = reader(ErrorKind::ConnectionReset, 1, opened.clone());
+        let mut restored = vec![];
+        resumed.read_to_end(&mut restored).expect("Failed to read");
+
+        assert!(restored == *content);
+        assert_eq!(resumed.offset(), 100_000);
+        assert_eq!(*opened.lock().unwrap(), vec![0, 30_000, 70_000]);
+
+        let mut failing = reader(ErrorKind::ConnectionReset, 0, Arc::default());
+        assert!(failing.read_to_end(&mut vec![]).is_err());
+
+        let opened = Arc::new(StdMutex::new(vec![]));
+        let mut missing = reader(ErrorKind::NotFound, 3, opened.clone());
+        assert!(missing.read_to_end(&mut vec![]).is_err());
+        assert_eq!(opened.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_26_foreign_timestamps() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "db-2020-01-01-000000-abcdef.gz",
+            "db-2020-01-02T00:00:00Z.gz",
+            "db_20200103_000000.gz",
+            "db-1578096000.gz",
+            "manual.gz",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let names: Vec<String> = provider
+            .list()
+            .await
+            .expect("Failed to list")
+            .into_iter()
+            .map(|entry| entry.metadata.name)
+            .filter(|name| name != "manual.gz")
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "db-1578096000.gz",
+                "db_20200103_000000.gz",
+                "db-2020-01-02T00:00:00Z.gz",
+                "db-2020-01-01-000000-abcdef.gz",
+            ]
+        );
+
+        // They are neither deleted nor counted among the backups kept
+        let mut options = CleanupOptions::new(RetentionPolicy::KeepLast(1), false);
+        options.min_backup_age = chrono::Duration::zero();
+        let deleted = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 3);
+
+        let remaining: HashSet<String> = provider
+            .list()
+            .await
+            .expect("Failed to list")
+            .into_iter()
+            .map(|entry| entry.metadata.name)
+            .collect();
+        assert_eq!(
+            remaining,
+            HashSet::from(["manual.gz".to_string(), "db-1578096000.gz".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_27_list_with_manifests() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "db-2020-01-01-000000-abcdef.gz",
+            "db-2020-01-02-000000-abcdef.gz",
+            "db-2020-01-03-000000-abcdef.gz",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        provider
+            .write_manifest(&BackupManifest {
+                name: "db-2020-01-03-000000-abcdef.gz".into(),
+                database: "db".into(),
+                connection_type: ConnectionType::PostgreSql,
+                created_at: Utc::now(),
+                change_token: None,
+                server_version: None,
+                compression_format: Some(CompressionFormat::Gzip),
+                size: Some(4),
+                checksum: None,
+                scope: BackupScope::SchemaOnly,
+                encrypted: true,
+                uncompressed_size: Some(1024),
+                physical: false,
+            })
+            .await
+            .expect("Failed to write manifest");
+
+        // An unreadable manifest does not fail the listing
+        provider
+            .backend()
+            .write(
+                &get_manifest_name("db-2020-01-02-000000-abcdef.gz"),
+                "{".as_bytes().to_vec(),
+            )
+            .await
+            .expect("Failed to write manifest");
+
+        let entries = provider
+            .list_with_manifests(ListOptions::default())
+            .await
+            .expect("Failed to list");
+
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|(entry, _)| entry.metadata.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "db-2020-01-03-000000-abcdef.gz",
+                "db-2020-01-02-000000-abcdef.gz",
+                "db-2020-01-01-000000-abcdef.gz",
+            ]
+        );
+
+        let manifest = entries[0].1.as_ref().expect("Missing manifest");
+        assert!(manifest.encrypted);
+        assert_eq!(manifest.uncompressed_size, Some(1024));
+        assert_eq!(manifest.scope, BackupScope::SchemaOnly);
+        assert!(entries[1].1.is_none());
+        assert!(entries[2].1.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_28_list_filters() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+
+        for name in [
+            "app-2025-01-01-000000-abcdef.gz",
+            "app-2025-01-02-000000-abcdef.gz",
+            "app-2025-01-03-000000-abcdef.gz",
+            "billing-2025-01-02-000000-abcdef.gz",
+        ] {
+            provider
+                .backend()
+                .write(name, "dump".as_bytes().to_vec())
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let list = |options: ListOptions| {
+            let provider = provider.clone();
+
+            async move {
+                provider
+                    .list_with_options(options)
+                    .await
+                    .expect("Failed to list")
+                    .into_iter()
+                    .map(|entry| entry.metadata.name)
+                    .collect::<Vec<String>>()
+            }
+        };
+
+        let names = list(ListOptions {
+            name_prefix: Some("app-".into()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            names,
+            vec![
+                "app-2025-01-03-000000-abcdef.gz",
+                "app-2025-01-02-000000-abcdef.gz",
+                "app-2025-01-01-000000-abcdef.gz",
+            ]
+        );
+
+        // After is inclusive, before is not
+        let names = list(ListOptions {
+            after: Some(Utc.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap()),
+            before: Some(Utc.with_ymd_and_hms(2025, 1, 3, 0, 0, 0).unwrap()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            names.into_iter().collect::<HashSet<String>>(),
+            HashSet::from([
+                "app-2025-01-02-000000-abcdef.gz".to_string(),
+                "billing-2025-01-02-000000-abcdef.gz".to_string(),
+            ])
+        );
+
+        let names = list(ListOptions {
+            name_prefix: Some("app-".into()),
+            after: Some(Utc.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap()),
+            latest_only: Some(true),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(names, vec!["app-2025-01-03-000000-abcdef.gz"]);
+    }
+
+    #[tokio::test]
+    async fn test_29_cleanup_large_storage() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+
+        // Hourly backups from 2020, more than any listing page
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        for hour in 0..1500 {
+            let timestamp = start + chrono::Duration::hours(hour);
+            let name = format!("db-{}-abcdef.gz", timestamp.format("%Y-%m-%d-%H%M%S"));
+
+            backend
+                .write(&name, vec![])
+                .await
+                .expect("Failed to write backup");
+        }
+
+        let mut options = CleanupOptions::new(RetentionPolicy::KeepLast(10), false);
+        options.min_backup_age = chrono::Duration::zero();
+        let deleted = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 1490);
+
+        let remaining = provider.list().await.expect("Failed to list");
+        assert_eq!(remaining.len(), 10);
+        assert_eq!(
+            remaining[0].metadata.name,
+            format!(
+                "db-{}-abcdef.gz",
+                (start + chrono::Duration::hours(1499)).format("%Y-%m-%d-%H%M%S")
+            )
+        );
+
+        // Age-based retention streams the listing and reaches the end of it too
+        let deleted = provider
+            .cleanup(RetentionPolicy::MaxAge(1), false)
+            .await
+            .expect("Failed to clean up")
+            .deleted
+            .len();
+        assert_eq!(deleted, 10);
+    }
+
+    #[tokio::test]
+    async fn test_30_cleanup_failures() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+
+        for day in 1..=5 {
+            let name = format!("db-2020-01-0{}-000000-abcdef.gz", day);
+            backend
+                .write(&name, vec![0; day])
+                .await
+                .expect("Failed to write backup");
+        }
+
+        backend
+            .undeletable
+            .lock()
+            .unwrap()
+            .insert("db-2020-01-02-000000-abcdef.gz".into());
+
+        let mut options = CleanupOptions::new(RetentionPolicy::MaxAge(1), true);
+        options.concurrency = 1;
+
+        // A dry run deletes nothing, so nothing fails
+        let report = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up");
+        assert!(report.is_success());
+        assert_eq!(report.deleted.len(), 5);
+        assert_eq!(backend.files.lock().unwrap().len(), 5);
+
+        // The failed deletion does not stop the others
+        options.dry_run = false;
+        let report = provider
+            .cleanup_with_options(&options)
+            .await
+            .expect("Failed to clean up");
+        assert!(!report.is_success());
+        assert_eq!(report.deleted.len(), 4);
+        assert_eq!(report.reclaimed, 13);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].name, "db-2020-01-02-000000-abcdef.gz");
+        assert!(report.failed[0].error.contains("Access denied"));
+
+        let remaining: Vec<String> = backend.files.lock().unwrap().keys().cloned().collect();
+        assert_eq!(remaining, vec!["db-2020-01-02-000000-abcdef.gz"]);
+    }
+
+    #[test]
+    fn test_31_s3_default_credentials() {
+        initialize_test();
+        let config = S3StorageConfig {
+            id: "s3".into(),
+            name: "s3".into(),
+            region: "us-east-1".into(),
+            endpoint: None,
+            bucket: "backups".into(),
+            access_key: None,
+            secret_key: None,
+            location: "app".into(),
+            retry: RetryConfig::default(),
+            upload: UploadOptions::default(),
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            storage_class: None,
+            enable_virtual_host_style: false,
+        };
+
+        // Without keys, opendal loads the credentials when a request is made
+        StorageProvider::new(StorageConfig::S3(config.clone()))
+            .expect("Failed to create the provider");
+
+        let error = StorageProvider::new(StorageConfig::S3(S3StorageConfig {
+            access_key: Some("AKIAEXAMPLE".into()),
+            secret_key: Some("".into()),
+            ..config.clone()
+        }))
+        .err()
+        .expect("A single key should be rejected");
+        assert!(error
+            .to_string()
+            .contains("both the access key and the secret key"));
+
+        // Keys are optional in saved configs
+        let config: S3StorageConfig = serde_json::from_value(serde_json::json!({
+            "name": "s3", "region": "eu-west-1", "endpoint": null, "bucket": "backups",
+            "location": "db",
+        }))
+        .expect("Failed to parse the config");
+        assert_eq!(config.access_key, None);
+        assert_eq!(config.secret_key, None);
+    }
+
+    #[test]
+    fn test_32_s3_server_side_encryption() {
+        initialize_test();
+        assert_eq!(
+            "aws:kms".parse::<ServerSideEncryption>().unwrap(),
+            ServerSideEncryption::AwsKms
+        );
+        assert_eq!(
+            "aes256".parse::<ServerSideEncryption>().unwrap(),
+            ServerSideEncryption::Aes256
+        );
+        assert!("AES128".parse::<ServerSideEncryption>().is_err());
+
+        let config: S3StorageConfig = serde_json::from_value(serde_json::json!({
+            "name": "s3", "region": "eu-west-1", "endpoint": null, "bucket": "backups",
+            "location": "db", "server_side_encryption": "aws:kms",
+            "sse_kms_key_id": "arn:aws:kms:eu-west-1:111122223333:key/example",
+        }))
+        .expect("Failed to parse the config");
+        assert_eq!(
+            config.server_side_encryption,
+            Some(ServerSideEncryption::AwsKms)
+        );
+
+        for (server_side_encryption, sse_kms_key_id) in [
+            (
+                Some(ServerSideEncryption::AwsKms),
+                config.sse_kms_key_id.clone(),
+            ),
+            (Some(ServerSideEncryption::AwsKms), None),
+            (Some(ServerSideEncryption::Aes256), None),
+        ] {
+            StorageProvider::new(StorageConfig::S3(S3StorageConfig {
+                server_side_encryption,
+                sse_kms_key_id,
+                ..config.clone()
+            }))
+            .expect("Failed to create the provider");
+        }
+
+        let error = StorageProvider::new(StorageConfig::S3(S3StorageConfig {
+            server_side_encryption: Some(ServerSideEncryption::Aes256),
+            ..config
+        }))
+        .err()
+        .expect("A KMS key without aws:kms should be rejected");
+        assert!(error.to_string().contains("aws:kms"));
+    }
+
+    #[test]
+    fn test_33_s3_storage_class() {
+        initialize_test();
+        let config: S3StorageConfig = serde_json::from_value(serde_json::json!({
+            "name": "s3", "region": "eu-west-1", "endpoint": null, "bucket": "backups",
+            "location": "db",
+        }))
+        .expect("Failed to parse the config");
+        assert_eq!(config.storage_class, None);
+
+        let config = S3StorageConfig {
+            storage_class: Some("GLACIER_IR".into()),
+            ..config
+        };
+        assert!(format!("{:?}", config).contains("GLACIER_IR"));
+        StorageProvider::new(StorageConfig::S3(config)).expect("Failed to create the provider");
+    }
+
+    #[test]
+    fn test_34_s3_addressing_style() {
+        initialize_test();
+        let config: S3StorageConfig = serde_json::from_value(serde_json::json!({
+            "name": "minio", "region": "us-east-1", "endpoint": "http://127.0.0.1:9000",
+            "bucket": "db.backups", "location": "db",
+        }))
+        .expect("Failed to parse the config");
+        assert!(!config.enable_virtual_host_style);
+
+        // Path style, the default, accepts buckets with dots
+        StorageProvider::new(StorageConfig::S3(config.clone()))
+            .expect("Failed to create the provider");
+
+        // They cannot be part of a host name
+        assert!(StorageProvider::new(StorageConfig::S3(S3StorageConfig {
+            enable_virtual_host_style: true,
+            ..config
+        }))
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_35_exists() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+
+        backend
+            .write("db-2020-01-01-000000-abcdef.gz", vec![0; 10])
+            .await
+            .expect("Failed to write backup");
+
+        assert!(provider
+            .exists("db-2020-01-01-000000-abcdef.gz")
+            .await
+            .expect("Failed to check the backup"));
+        assert!(!provider
+            .exists("db-2020-01-02-000000-abcdef.gz")
+            .await
+            .expect("Failed to check the backup"));
+
+        let provider = get_local_provider().expect("Failed to create the provider");
+        assert!(!provider
+            .exists("missing.gz")
+            .await
+            .expect("Failed to check the backup"));
+    }
+
+    #[tokio::test]
+    async fn test_36_retention_per_config() {
+        initialize_test();
+        let backend = MemoryBackend::default();
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            backend.clone(),
+        );
+
+        // app is backed up every day, app-staging, whose name starts with it, every third day
+        for day in 1..=9 {
+            let mut names = vec![format!("app-2020-01-0{}-000000-abcdef.gz", day)];
+            if day % 3 == 0 {
+                names.push(format!("app-staging-2020-01-0{}-000000-abcdef.gz", day));
+            }
+            for name in names {
+                backend
+                    .write(&name, vec![0; 10])
+                    .await
+                    .expect("Failed to write backup");
+            }
+        }
+
+        let cleanup = |retention| {
+            let mut options = CleanupOptions::new(retention, true);
+            options.min_backup_age = chrono::Duration::zero();
+            let provider = &provider;
+            async move {
+                let mut deleted = provider
+                    .cleanup_with_options(&options)
+                    .await
+                    .expect("Failed to clean up")
+                    .deleted;
+                deleted.sort();
+                deleted
+            }
+        };
+
+        let deleted = cleanup(RetentionPolicy::KeepLast(2)).await;
+        assert_eq!(deleted.len(), 8);
+        assert_eq!(
+            deleted
+                .iter()
+                .filter(|name| name.contains("staging"))
+                .count(),
+            1
+        );
+        assert_eq!(deleted[7], "app-staging-2020-01-03-000000-abcdef.gz");
+
+        let capped = RetentionPolicy::Combined {
+            max_age_days: 36500,
+            keep_last: 0,
+            max_count: Some(3),
+        };
+        let deleted = cleanup(capped).await;
+        assert_eq!(deleted.len(), 6);
+        assert!(deleted.iter().all(|name| !name.contains("staging")));
+    }
+
+    #[test]
+    fn test_37_gfs_retention_per_config() {
+        let backend = MemoryBackend::default();
+        let now = Utc.with_ymd_and_hms(2025, 6, 30, 12, 0, 0).unwrap();
+
+        // app is backed up every day of May, app-staging on the 10th only
+        let mut names: Vec<String> = (1..=31)
+            .map(|day| format!("app-2025-05-{:02}-000000-abcdef.gz", day))
+            .collect();
+        names.push("app-staging-2025-05-10-000000-abcdef.gz".into());
+
+        let backups = names
+            .iter()
+            .rev()
+            .map(|name| Entry {
+                path: name.clone(),
+                metadata: backend.get_metadata(name, b""),
+            })
+            .collect();
+
+        let deleted: HashSet<String> = select_gfs(backups, 0, 0, 12, now)
+            .into_iter()
+            .map(|backup| backup.path)
+            .collect();
+
+        // Each config keeps its own backup of May
+        assert_eq!(deleted.len(), 30);
+        assert!(!deleted.contains("app-2025-05-31-000000-abcdef.gz"));
+        assert!(!deleted.contains("app-staging-2025-05-10-000000-abcdef.gz"));
+    }
+
+    #[tokio::test]
+    async fn test_38_gcs_provider() {
+        initialize_test();
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Failed to find a free port")
+            .port();
+
+        let provider = StorageProvider::new(StorageConfig::Gcs(GcsStorageConfig {
+            id: "gcs".into(),
+            name: "gcs".into(),
+            bucket: "backups".into(),
+            credential_path: None,
+            endpoint: Some(format!("http://127.0.0.1:{}", port)),
+            location: "app".into(),
+            retry: RetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            },
+        }))
+        .expect("Failed to create the provider");
+
+        // Anonymous requests reach the endpoint, which is not listening
+        assert!(provider.test().await.is_err());
+
+        // Retries are optional in saved configs
+        let config: GcsStorageConfig = serde_json::from_value(serde_json::json!({
+            "name": "gcs", "bucket": "backups", "credential_path": null, "endpoint": null,
+            "location": "db",
+        }))
+        .expect("Failed to parse the config");
+        assert_eq!(config.retry.max_retries, RetryConfig::default().max_retries);
+    }
+
+    #[tokio::test]
+    async fn test_39_available_space() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to get local provider");
+        provider.test_write().await.expect("Failed to write");
+
+        let space = provider
+            .available_space()
+            .await
+            .expect("Failed to read the free space");
+        assert!(space.is_some_and(|space| space > 0));
+
+        // Object stores cannot tell
+        let provider = StorageProvider::from_backend(
+            CustomStorageConfig {
+                id: "memory".into(),
+                name: "memory".into(),
+            },
+            MemoryBackend::default(),
+        );
+        assert_eq!(provider.available_space().await.unwrap(), None);
+    }
 }
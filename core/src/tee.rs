@@ -0,0 +1,249 @@
+use std::io::{self, Error, Write};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TeePolicy {
+    /// Abort the whole stream as soon as any sink fails.
+    #[default]
+    FailFast,
+    /// Drop failing sinks and keep writing to the others, failing only when none are left.
+    BestEffort,
+}
+
+#[derive(Debug, Clone)]
+pub struct TeeFailure {
+    pub index: usize,
+    pub error: String,
+}
+
+/// Fans a single stream out to several sinks in one pass. Without sinks, writes are discarded.
+pub struct TeeWriter {
+    sinks: Vec<Option<Box<dyn Write + Send + Unpin>>>,
+    policy: TeePolicy,
+    min_sinks: usize,
+    failures: Vec<TeeFailure>,
+}
+
+impl TeeWriter {
+    pub fn new(policy: TeePolicy) -> Self {
+        Self {
+            sinks: vec![],
+            policy,
+            min_sinks: 0,
+            failures: vec![],
+        }
+    }
+
+    /// With `BestEffort`, fail the stream as soon as fewer than `min_sinks` sinks are left.
+    pub fn with_min_sinks(mut self, min_sinks: usize) -> Self {
+        self.min_sinks = min_sinks;
+        self
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn Write + Send + Unpin>) -> Self {
+        self.sinks.push(Some(sink));
+        self
+    }
+
+    pub fn failures(&self) -> &[TeeFailure] {
+        &self.failures
+    }
+
+    /// Returns the sinks in insertion order, `None` for the ones dropped after a failure.
+    pub fn into_sinks(self) -> Vec<Option<Box<dyn Write + Send + Unpin>>> {
+        self.sinks
+    }
+
+    fn apply<F>(&mut self, mut operation: F) -> io::Result<()>
+    where
+        F: FnMut(&mut (dyn Write + Send + Unpin)) -> io::Result<()>,
+    {
+        for (index, slot) in self.sinks.iter_mut().enumerate() {
+            let Some(sink) = slot else {
+                continue;
+            };
+
+            if let Err(e) = operation(sink.as_mut()) {
+                match self.policy {
+                    TeePolicy::FailFast => {
+                        return Err(Error::new(
+                            e.kind(),
+                            format!("Sink {} failed: {}", index, e),
+                        ));
+                    }
+                    TeePolicy::BestEffort => {
+                        self.failures.push(TeeFailure {
+                            index,
+                            error: e.to_string(),
+                        });
+                        *slot = None;
+                    }
+                }
+            }
+        }
+
+        let remaining = self.sinks.iter().filter(|sink| sink.is_some()).count();
+
+        if remaining == 0 && !self.sinks.is_empty() {
+            return Err(Error::other("All tee sinks failed"));
+        }
+
+        if remaining < self.min_sinks {
+            return Err(Error::other(format!(
+                "Only {} tee sinks left, {} required",
+                remaining, self.min_sinks
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.apply(|sink| sink.write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.apply(|sink| sink.flush())
+    }
+}
+
+#[cfg(test)]
+mod tee_tests {
+    use std::{
+        io::{self, Error, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{TeePolicy, TeeWriter};
+
+    #[derive(Clone, Default)]
+    struct SharedSink {
+        content: Arc<Mutex<Vec<u8>>>,
+        fail_after: Option<usize>,
+    }
+
+    impl SharedSink {
+        fn failing_after(bytes: usize) -> Self {
+            Self {
+                fail_after: Some(bytes),
+                ..Default::default()
+            }
+        }
+
+        fn content(&self) -> Vec<u8> {
+            self.content.lock().unwrap().clone()
+        }
+    }
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut content = self.content.lock().unwrap();
+
+            if let Some(limit) = self.fail_after {
+                if content.len() + buf.len() > limit {
+                    return Err(Error::other("sink is full"));
+                }
+            }
+
+            content.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_01_fans_out_to_all_sinks() {
+        let first = SharedSink::default();
+        let second = SharedSink::default();
+
+        let mut tee = TeeWriter::new(TeePolicy::FailFast)
+            .with_sink(Box::new(first.clone()))
+            .with_sink(Box::new(second.clone()));
+
+        tee.write_all(b"hello ").expect("Failed to write");
+        tee.write_all(b"world").expect("Failed to write");
+        tee.flush().expect("Failed to flush");
+
+        assert_eq!(first.content(), b"hello world");
+        assert_eq!(second.content(), b"hello world");
+        assert!(tee.failures().is_empty());
+    }
+
+    #[test]
+    fn test_02_fail_fast() {
+        let healthy = SharedSink::default();
+
+        let mut tee = TeeWriter::new(TeePolicy::FailFast)
+            .with_sink(Box::new(SharedSink::failing_after(4)))
+            .with_sink(Box::new(healthy.clone()));
+
+        tee.write_all(b"abc").expect("Failed to write");
+
+        let error = tee.write_all(b"def").expect_err("Write should fail");
+        assert!(error.to_string().contains("Sink 0 failed"));
+    }
+
+    #[test]
+    fn test_03_best_effort() {
+        let healthy = SharedSink::default();
+
+        let mut tee = TeeWriter::new(TeePolicy::BestEffort)
+            .with_sink(Box::new(SharedSink::failing_after(4)))
+            .with_sink(Box::new(healthy.clone()));
+
+        tee.write_all(b"abc").expect("Failed to write");
+        tee.write_all(b"def").expect("Failed to write");
+
+        assert_eq!(healthy.content(), b"abcdef");
+        assert_eq!(tee.failures().len(), 1);
+        assert_eq!(tee.failures()[0].index, 0);
+
+        let sinks = tee.into_sinks();
+        assert!(sinks[0].is_none());
+        assert!(sinks[1].is_some());
+    }
+
+    #[test]
+    fn test_04_best_effort_fails_when_no_sink_left() {
+        let mut tee =
+            TeeWriter::new(TeePolicy::BestEffort).with_sink(Box::new(SharedSink::failing_after(0)));
+
+        assert!(tee.write_all(b"abc").is_err());
+    }
+
+    #[test]
+    fn test_05_best_effort_min_sinks() {
+        let healthy = SharedSink::default();
+
+        let mut tee = TeeWriter::new(TeePolicy::BestEffort)
+            .with_min_sinks(2)
+            .with_sink(Box::new(SharedSink::failing_after(4)))
+            .with_sink(Box::new(healthy.clone()))
+            .with_sink(Box::new(SharedSink::default()));
+
+        tee.write_all(b"abc").expect("Failed to write");
+        tee.write_all(b"def").expect("Failed to write");
+
+        let mut tee = TeeWriter::new(TeePolicy::BestEffort)
+            .with_min_sinks(2)
+            .with_sink(Box::new(SharedSink::failing_after(4)))
+            .with_sink(Box::new(healthy));
+
+        let error = tee.write_all(b"abcdef").expect_err("Write should fail");
+        assert!(error.to_string().contains("2 required"));
+    }
+
+    #[test]
+    fn test_06_no_sinks() {
+        let mut tee = TeeWriter::new(TeePolicy::FailFast);
+
+        tee.write_all(b"abc").expect("Failed to write");
+        tee.flush().expect("Failed to flush");
+        assert!(tee.into_sinks().is_empty());
+    }
+}
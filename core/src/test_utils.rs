@@ -13,7 +13,10 @@ pub mod test_utils {
 
     use crate::{
         databases::{postgres::connection::PostgreSqlConnection, ConnectionType, DatabaseConfig},
-        storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig, StorageProvider},
+        storage::provider::{
+            LocalStorageConfig, RetryConfig, S3StorageConfig, StorageConfig, StorageProvider,
+            UploadOptions,
+        },
     };
 
     pub fn initialize_test() {
@@ -89,7 +92,17 @@ pub mod test_utils {
                 env::var("POSTGRESQL_NAME").unwrap_or_default()
             },
             port,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         })
         .await?;
 
@@ -116,12 +129,18 @@ pub mod test_utils {
         let config = StorageConfig::S3(S3StorageConfig {
             id: "test".into(),
             name: "s3".into(),
-            access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
-            secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+            access_key: env::var("S3_ACCESS_KEY").ok(),
+            secret_key: env::var("S3_SECRET_KEY").ok(),
             bucket: env::var("S3_BUCKET").unwrap_or_else(|_| "test-bkp".to_string()),
             endpoint: Some(endpoint),
             region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
             location,
+            retry: RetryConfig::default(),
+            upload: UploadOptions::default(),
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            storage_class: None,
+            enable_virtual_host_style: false,
         });
 
         let provider = StorageProvider::new(config)?;
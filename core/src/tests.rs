@@ -1,17 +1,24 @@
 #[cfg(test)]
 mod vprdbbkp_tests {
     use anyhow::Result;
-    use std::env;
+    use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions};
+    use std::{env, time::Duration};
     use tempfile::tempdir;
 
     use crate::{
+        backup_to_storage,
+        compression::CompressionFormat,
         databases::{
-            ssh_tunnel::{SshAuthMethod, SshTunnelConfig},
+            ssh_tunnel::{HostKeyVerification, SshAuthMethod, SshTunnelConfig},
             ConnectionType, DatabaseConfig, DatabaseConnection,
         },
-        storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig, StorageProvider},
+        scheduler::{Schedule, Scheduler},
+        storage::provider::{
+            CleanupOptions, LocalStorageConfig, RetentionPolicy, RetryConfig, S3StorageConfig,
+            StorageConfig, StorageProvider, UploadOptions,
+        },
         test_utils::test_utils::{get_mysql_pool, get_postgresql_pool, initialize_test},
-        DbBkp, RestoreOptions,
+        BackupOptions, DbBkp, RestoreOptions, VerifyOptions,
     };
 
     fn get_local_provider() -> Result<StorageProvider> {
@@ -38,12 +45,18 @@ mod vprdbbkp_tests {
         let config = StorageConfig::S3(S3StorageConfig {
             id: "test".into(),
             name: "s3".into(),
-            access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
-            secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+            access_key: env::var("S3_ACCESS_KEY").ok(),
+            secret_key: env::var("S3_SECRET_KEY").ok(),
             bucket: env::var("S3_BUCKET").unwrap_or_else(|_| "test-bkp".to_string()),
             endpoint: Some(endpoint),
             region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
             location,
+            retry: RetryConfig::default(),
+            upload: UploadOptions::default(),
+            server_side_encryption: None,
+            sse_kms_key_id: None,
+            storage_class: None,
+            enable_virtual_host_style: false,
         });
 
         let provider = StorageProvider::new(config)?;
@@ -66,7 +79,17 @@ mod vprdbbkp_tests {
             username: env::var("POSTGRESQL_USERNAME").unwrap_or_default(),
             database: env::var("POSTGRESQL_NAME").unwrap_or_default(),
             port,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         };
 
         Ok(config)
@@ -87,7 +110,17 @@ mod vprdbbkp_tests {
             username: env::var("MYSQL_USERNAME").unwrap_or_default(),
             database: env::var("MYSQL_NAME").unwrap_or_default(),
             port,
+            application_name: None,
+            pgpass_file: None,
             ssh_tunnel: None,
+            path: None,
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
         };
 
         Ok(config)
@@ -114,6 +147,10 @@ mod vprdbbkp_tests {
                 key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                 passphrase_key: None,
             },
+            jump_hosts: vec![],
+            known_hosts: None,
+            host_key_verification: HostKeyVerification::Strict,
+            local_ports: None,
         });
 
         Ok(config)
@@ -184,6 +221,19 @@ mod vprdbbkp_tests {
                 name: backup_name,
                 compression_format: None,
                 drop_database_first: Some(true),
+                message_level: None,
+                no_tablespaces: None,
+                client_encoding: None,
+                schema_only: None,
+                termination_timeout: None,
+                terminate_connections: None,
+                run_analyze: None,
+                analyze_jobs: None,
+                jobs: None,
+                single_transaction: None,
+                validate_only: None,
+                rate_limit_bytes_per_sec: None,
+                progress: None,
             })
             .await
             .expect("Failed to restore");
@@ -210,6 +260,88 @@ mod vprdbbkp_tests {
         assert!(test3_exists, "test3 should be restored");
     }
 
+    #[tokio::test]
+    async fn test_04_postgresql_parallel_backup() {
+        initialize_test();
+        let config = get_postgresql_config().expect("Failed to get postgresql config");
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+
+        let database_connection = DatabaseConnection::new(config.clone())
+            .await
+            .expect("Failed to get database connection");
+
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+
+        let engine = DbBkp::new(database_connection, storage_provider);
+
+        for query in [
+            "DROP TABLE IF EXISTS parallel_orders",
+            "DROP TABLE IF EXISTS parallel_users",
+            "CREATE TABLE parallel_users (id SERIAL PRIMARY KEY, name TEXT)",
+            // Sorts before parallel_users, so its data is dumped before the rows it references
+            "CREATE TABLE parallel_orders (id SERIAL PRIMARY KEY, user_id INTEGER REFERENCES parallel_users (id))",
+            "INSERT INTO parallel_users (name) VALUES ('test1'), ('test2')",
+            "INSERT INTO parallel_orders (user_id) VALUES (1), (2), (2)",
+        ] {
+            sqlx::query(query)
+                .execute(&db_pool)
+                .await
+                .expect("Failed to prepare test data");
+        }
+
+        let report = engine
+            .backup_with_report(Some(BackupOptions {
+                jobs: Some(4),
+                ..Default::default()
+            }))
+            .await
+            .expect("Failed to backup");
+
+        sqlx::query("DELETE FROM parallel_orders")
+            .execute(&db_pool)
+            .await
+            .expect("Failed to delete test data");
+
+        engine
+            .restore(RestoreOptions {
+                name: report.name,
+                compression_format: None,
+                drop_database_first: Some(false),
+                message_level: None,
+                no_tablespaces: None,
+                client_encoding: None,
+                schema_only: None,
+                termination_timeout: None,
+                terminate_connections: None,
+                run_analyze: None,
+                analyze_jobs: None,
+                jobs: None,
+                single_transaction: None,
+                validate_only: None,
+                rate_limit_bytes_per_sec: None,
+                progress: None,
+            })
+            .await
+            .expect("Failed to restore");
+
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+
+        let orders: Vec<(i32,)> = sqlx::query_as("SELECT user_id FROM parallel_orders ORDER BY id")
+            .fetch_all(&db_pool)
+            .await
+            .expect("Failed to fetch restored data");
+
+        assert_eq!(orders, vec![(1,), (2,), (2,)]);
+
+        let (next_id,): (i32,) =
+            sqlx::query_as("INSERT INTO parallel_users (name) VALUES ('test3') RETURNING id")
+                .fetch_one(&db_pool)
+                .await
+                .expect("Sequence should be restored");
+
+        assert_eq!(next_id, 3);
+    }
+
     #[ignore]
     #[tokio::test]
     async fn test_02_postgresql_tunneled_backup() {
@@ -298,6 +430,19 @@ mod vprdbbkp_tests {
                 name: backup_name,
                 compression_format: None,
                 drop_database_first: Some(true),
+                message_level: None,
+                no_tablespaces: None,
+                client_encoding: None,
+                schema_only: None,
+                termination_timeout: None,
+                terminate_connections: None,
+                run_analyze: None,
+                analyze_jobs: None,
+                jobs: None,
+                single_transaction: None,
+                validate_only: None,
+                rate_limit_bytes_per_sec: None,
+                progress: None,
             })
             .await
             .expect("Failed to restore");
@@ -325,4 +470,331 @@ mod vprdbbkp_tests {
         let test3_exists = restored_rows.iter().any(|(name, _)| name == "test3");
         assert!(test3_exists, "test3 should be restored");
     }
+
+    #[tokio::test]
+    async fn test_05_backup_to_storage() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("app.db");
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .expect("Failed to create database");
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create table");
+
+        let config = |path: &std::path::Path| DatabaseConfig {
+            id: "test".into(),
+            name: "test".into(),
+            connection_type: ConnectionType::Sqlite,
+            host: "".into(),
+            port: 0,
+            database: "app".into(),
+            username: "".into(),
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: Some(path.to_string_lossy().to_string()),
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        };
+
+        let storage = get_local_provider().expect("Failed to get local provider");
+        let database = DatabaseConnection::new(config(&path))
+            .await
+            .expect("Failed to get connection");
+
+        let size = backup_to_storage(&database, &storage, "app.sqlite")
+            .await
+            .expect("Failed to backup to storage");
+
+        let content = storage
+            .backend()
+            .read("app.sqlite")
+            .await
+            .expect("Failed to read backup")
+            .expect("Missing backup");
+        assert_eq!(content.len() as u64, size);
+        assert!(content.starts_with(b"SQLite format 3\0"));
+
+        // A failed dump leaves nothing behind
+        let missing = DatabaseConnection::new(config(&dir.path().join("missing.db")))
+            .await
+            .expect("Failed to get connection");
+
+        assert!(backup_to_storage(&missing, &storage, "missing.sqlite")
+            .await
+            .is_err());
+        assert!(storage
+            .backend()
+            .stat("missing.sqlite")
+            .await
+            .expect("Failed to stat")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_06_backup_report() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("app.db");
+
+        SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .expect("Failed to create database");
+
+        let database = DatabaseConnection::new(DatabaseConfig {
+            id: "test".into(),
+            name: "test".into(),
+            connection_type: ConnectionType::Sqlite,
+            host: "".into(),
+            port: 0,
+            database: "app".into(),
+            username: "".into(),
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: Some(path.to_string_lossy().to_string()),
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        })
+        .await
+        .expect("Failed to get connection");
+        let storage = get_local_provider().expect("Failed to get local provider");
+
+        let report = DbBkp::new(database, storage.clone())
+            .backup_with_report(Some(BackupOptions {
+                compression_format: Some(CompressionFormat::Zlib),
+                ..Default::default()
+            }))
+            .await
+            .expect("Failed to backup");
+
+        let content = storage
+            .backend()
+            .read(&report.name)
+            .await
+            .expect("Failed to read backup")
+            .expect("Missing backup");
+
+        assert_eq!(report.size, content.len() as u64);
+        assert!(report.duration > Duration::ZERO);
+        assert!(matches!(
+            report.compression_format,
+            Some(CompressionFormat::Zlib)
+        ));
+
+        let manifest = storage
+            .read_manifest(&report.name)
+            .await
+            .expect("Failed to read manifest")
+            .expect("Missing manifest");
+
+        assert!(!manifest.encrypted);
+        assert!(manifest.uncompressed_size.is_some_and(|size| size > 0));
+    }
+
+    #[tokio::test]
+    async fn test_07_restore_latest() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("app.db");
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .expect("Failed to create database");
+
+        let database = DatabaseConnection::new(DatabaseConfig {
+            id: "test".into(),
+            name: "test".into(),
+            connection_type: ConnectionType::Sqlite,
+            host: "".into(),
+            port: 0,
+            database: "app".into(),
+            username: "".into(),
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: Some(path.to_string_lossy().to_string()),
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        })
+        .await
+        .expect("Failed to get connection");
+        let storage = get_local_provider().expect("Failed to get local provider");
+
+        // A newer backup of another config whose name starts with this one
+        storage
+            .backend()
+            .write(
+                "test-staging-2020-01-03-000000-abcdef.gz",
+                b"not this database".to_vec(),
+            )
+            .await
+            .expect("Failed to write backup");
+
+        let engine = DbBkp::new(database, storage);
+
+        sqlx::query("CREATE TABLE items (id INTEGER)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create table");
+
+        for (day, format) in [(1, CompressionFormat::Zlib), (2, CompressionFormat::Gzip)] {
+            sqlx::query("INSERT INTO items VALUES (?)")
+                .bind(day)
+                .execute(&mut connection)
+                .await
+                .expect("Failed to insert");
+
+            engine
+                .backup_with(Some(BackupOptions {
+                    name: Some(format!("test-2020-01-0{}-000000-abcdef.gz", day)),
+                    compression_format: Some(format),
+                    ..Default::default()
+                }))
+                .await
+                .expect("Failed to backup");
+        }
+
+        sqlx::query("INSERT INTO items VALUES (3)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to insert");
+        drop(connection);
+
+        let report = engine
+            .verify_latest(VerifyOptions::default())
+            .await
+            .expect("Failed to verify");
+        assert_eq!(report.name, "test-2020-01-02-000000-abcdef.gz");
+
+        let report = engine.restore_latest().await.expect("Failed to restore");
+        assert_eq!(report.name, "test-2020-01-02-000000-abcdef.gz");
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&path)
+            .connect()
+            .await
+            .expect("Failed to open database");
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(&mut connection)
+            .await
+            .expect("Failed to count");
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_08_scheduler_run_once() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join("app.db");
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .expect("Failed to create database");
+        sqlx::query("CREATE TABLE items (id INTEGER)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create table");
+        drop(connection);
+
+        let database = DatabaseConnection::new(DatabaseConfig {
+            id: "test".into(),
+            name: "test".into(),
+            connection_type: ConnectionType::Sqlite,
+            host: "".into(),
+            port: 0,
+            database: "app".into(),
+            username: "".into(),
+            password: None,
+            application_name: None,
+            pgpass_file: None,
+            ssh_tunnel: None,
+            path: Some(path.to_string_lossy().to_string()),
+            pool_max_connections: None,
+            acquire_timeout_secs: None,
+            statement_timeout_secs: None,
+            connect_timeout_secs: None,
+            keepalive_idle_secs: None,
+            binary_path_override: None,
+            ssl_mode: None,
+        })
+        .await
+        .expect("Failed to get connection");
+        let storage = get_local_provider().expect("Failed to get local provider");
+        let engine = DbBkp::new(database, storage.clone());
+
+        engine
+            .backup_with(Some(BackupOptions {
+                name: Some("test-2020-01-01-000000-abcdef.gz".into()),
+                ..Default::default()
+            }))
+            .await
+            .expect("Failed to backup");
+
+        let mut cleanup = CleanupOptions::new(RetentionPolicy::KeepLast(1), false);
+        cleanup.min_backup_age = chrono::Duration::zero();
+
+        let state_path = dir.path().join("scheduler.json");
+        let scheduler = Scheduler::new(
+            engine,
+            Schedule::Cron("0 2 * * *".parse().expect("Failed to parse")),
+        )
+        .with_cleanup(cleanup)
+        .with_state_file(&state_path);
+
+        assert_eq!(scheduler.last_run().expect("Failed to read state"), None);
+
+        let run = scheduler.run_once().await.expect("Failed to run");
+        let cleanup = run.cleanup.expect("Missing cleanup report");
+        assert_eq!(cleanup.deleted, vec!["test-2020-01-01-000000-abcdef.gz"]);
+        assert_eq!(storage.list().await.expect("Failed to list").len(), 1);
+
+        // A restart waits for the next slot instead of running again
+        let last_run = scheduler.last_run().expect("Failed to read state");
+        assert_eq!(last_run, Some(run.started_at));
+        let next = scheduler
+            .next_run(last_run, run.started_at)
+            .expect("Failed to schedule");
+        assert!(next > run.started_at);
+        assert_eq!(next.format("%H:%M").to_string(), "02:00");
+
+        // Runs missed while stopped are caught up at once
+        let later = run.started_at + chrono::Duration::days(3);
+        assert_eq!(
+            scheduler
+                .next_run(last_run, later)
+                .expect("Failed to schedule"),
+            later
+        );
+    }
 }
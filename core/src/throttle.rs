@@ -0,0 +1,163 @@
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Token bucket refilled at `rate` bytes per second, holding at most one second of bytes. It
+/// starts empty, so a stream never runs ahead of the rate by more than one second.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: 0.0,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Largest transfer allowed at once, one second of bytes.
+    fn capacity(&self) -> usize {
+        (self.rate as usize).max(1)
+    }
+
+    /// Takes `bytes` from the bucket, sleeping off the debt when it runs dry.
+    fn consume(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate) - bytes as f64;
+        self.refilled_at = now;
+
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+    }
+}
+
+/// Limits the bytes written through it to `rate` per second, or only passes them through
+/// without one. Zero is unlimited.
+pub struct ThrottledWriter<W: Write + Send + Unpin> {
+    inner: W,
+    bucket: Option<TokenBucket>,
+}
+
+impl<W: Write + Send + Unpin> ThrottledWriter<W> {
+    pub fn new(inner: W, rate: Option<u64>) -> Self {
+        Self {
+            inner,
+            bucket: rate.filter(|rate| *rate > 0).map(TokenBucket::new),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(bucket) = &mut self.bucket else {
+            return self.inner.write(buf);
+        };
+
+        let n = self.inner.write(&buf[..buf.len().min(bucket.capacity())])?;
+        bucket.consume(n);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Limits the bytes read through it to `rate` per second, or only passes them through without
+/// one. Zero is unlimited.
+pub struct ThrottledReader<R: Read + Send + Unpin> {
+    inner: R,
+    bucket: Option<TokenBucket>,
+}
+
+impl<R: Read + Send + Unpin> ThrottledReader<R> {
+    pub fn new(inner: R, rate: Option<u64>) -> Self {
+        Self {
+            inner,
+            bucket: rate.filter(|rate| *rate > 0).map(TokenBucket::new),
+        }
+    }
+}
+
+impl<R: Read + Send + Unpin> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(bucket) = &mut self.bucket else {
+            return self.inner.read(buf);
+        };
+
+        let len = buf.len().min(bucket.capacity());
+        let n = self.inner.read(&mut buf[..len])?;
+        bucket.consume(n);
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod throttle_tests {
+    use std::{
+        io::{Read, Write},
+        time::{Duration, Instant},
+    };
+
+    use super::{ThrottledReader, ThrottledWriter};
+
+    #[test]
+    fn test_01_throttled_writer() {
+        let data = vec![7u8; 250_000];
+
+        let start = Instant::now();
+        let mut writer = ThrottledWriter::new(Vec::new(), Some(1_000_000));
+        for chunk in data.chunks(10_000) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        assert_eq!(writer.into_inner(), data);
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+
+        // Writes larger than one second of bytes are split
+        let mut writer = ThrottledWriter::new(Vec::new(), Some(100_000));
+        assert_eq!(writer.write(&data).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn test_02_throttled_reader() {
+        let data = vec![7u8; 250_000];
+
+        let start = Instant::now();
+        let mut read = Vec::new();
+        ThrottledReader::new(&data[..], Some(1_000_000))
+            .read_to_end(&mut read)
+            .unwrap();
+
+        let elapsed = start.elapsed();
+        assert_eq!(read, data);
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+
+        // No rate, or zero, is unlimited
+        for rate in [None, Some(0)] {
+            let start = Instant::now();
+            let mut read = Vec::new();
+            ThrottledReader::new(&data[..], rate)
+                .read_to_end(&mut read)
+                .unwrap();
+            assert_eq!(read, data);
+            assert!(start.elapsed() < Duration::from_millis(100));
+        }
+    }
+}
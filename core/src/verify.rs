@@ -0,0 +1,292 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::{ChecksumAlgorithm, HashingReader},
+    compression::{CompressionFormat, Decompressor},
+    encryption::{DecryptingReader, EncryptionConfig},
+    manifest::BackupManifest,
+};
+
+const DEFAULT_VERIFY_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyLevel {
+    /// Decompress the whole backup and compare it against its manifest checksum and size.
+    #[default]
+    Checksum,
+    /// Run the checksum checks, then restore the backup into a scratch database.
+    Restore,
+}
+
+impl VerifyLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VerifyLevel::Checksum => "checksum",
+            VerifyLevel::Restore => "restore",
+        }
+    }
+}
+
+impl fmt::Display for VerifyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for VerifyLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "checksum" => Ok(VerifyLevel::Checksum),
+            "restore" => Ok(VerifyLevel::Restore),
+            _ => Err(anyhow!("Unsupported verify level: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub name: String,
+    pub level: VerifyLevel,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Size of the stored, compressed object.
+    pub size: u64,
+    pub checksum: String,
+    /// Whether the manifest had a checksum to compare against.
+    pub checksum_verified: bool,
+    pub restored: bool,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyFailure {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyAllReport {
+    pub passed: Vec<VerifyReport>,
+    pub failed: Vec<VerifyFailure>,
+}
+
+impl VerifyAllReport {
+    pub fn from_results(results: Vec<(String, Result<VerifyReport>)>) -> Self {
+        let mut report = Self::default();
+
+        for (name, result) in results {
+            match result {
+                Ok(verified) => report.passed.push(verified),
+                Err(e) => report.failed.push(VerifyFailure {
+                    name,
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+
+        report.passed.sort_by(|a, b| a.name.cmp(&b.name));
+        report.failed.sort_by(|a, b| a.name.cmp(&b.name));
+
+        report
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Checksum checks only read from storage and run `requested` at a time. Test restores all go
+/// through the same scratch database, so they run one after the other.
+pub fn get_verify_concurrency(level: VerifyLevel, requested: Option<usize>) -> usize {
+    match level {
+        VerifyLevel::Checksum => requested.unwrap_or(DEFAULT_VERIFY_CONCURRENCY).max(1),
+        VerifyLevel::Restore => 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityCheck {
+    pub size: u64,
+    pub checksum: String,
+    pub checksum_verified: bool,
+}
+
+/// Reads a stored backup to the end, decompressing it so truncated or corrupted streams fail,
+/// and compares its size and checksum with the manifest when one is available. The checksum is
+/// computed with the manifest's algorithm, SHA-256 when there is none.
+pub fn check_integrity(
+    reader: impl Read + Send + Unpin,
+    compression_format: CompressionFormat,
+    encryption: Option<&EncryptionConfig>,
+    manifest: Option<&BackupManifest>,
+) -> Result<IntegrityCheck> {
+    let expected = manifest.and_then(|manifest| manifest.checksum.as_ref());
+    let algorithm = match expected {
+        Some(expected) => ChecksumAlgorithm::from_checksum(expected)?,
+        None => ChecksumAlgorithm::default(),
+    };
+
+    let reader = DecryptingReader::new(HashingReader::new(reader, algorithm), encryption)?;
+    let mut decompressor = Decompressor::new(reader, compression_format);
+
+    io::copy(&mut decompressor, &mut io::sink()).context("Failed to decompress backup")?;
+
+    // Trailing bytes after the compressed stream still count towards the stored checksum
+    let mut reader = decompressor.into_inner().into_inner();
+    io::copy(&mut reader, &mut io::sink())?;
+
+    let size = reader.bytes_read();
+    let checksum = reader.finish();
+
+    if let Some(expected) = manifest.and_then(|manifest| manifest.size) {
+        if expected != size {
+            return Err(anyhow!(
+                "Backup size {} does not match the manifest size {}",
+                size,
+                expected
+            ));
+        }
+    }
+
+    if let Some(expected) = expected {
+        if *expected != checksum {
+            return Err(anyhow!(
+                "Backup checksum {} does not match the manifest checksum {}",
+                checksum,
+                expected
+            ));
+        }
+    }
+
+    Ok(IntegrityCheck {
+        size,
+        checksum,
+        checksum_verified: expected.is_some(),
+    })
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use std::{
+        io::{Cursor, Write},
+        time::Duration,
+    };
+
+    use anyhow::anyhow;
+    use chrono::Utc;
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::{
+        compression::CompressionFormat,
+        databases::{BackupScope, ConnectionType},
+        manifest::BackupManifest,
+    };
+
+    use super::{
+        check_integrity, get_verify_concurrency, VerifyAllReport, VerifyLevel, VerifyReport,
+    };
+
+    fn manifest(size: u64, checksum: &str) -> BackupManifest {
+        BackupManifest {
+            name: "test.gz".into(),
+            database: "test".into(),
+            connection_type: ConnectionType::PostgreSql,
+            created_at: Utc::now(),
+            change_token: None,
+            server_version: None,
+            compression_format: Some(CompressionFormat::Gzip),
+            size: Some(size),
+            checksum: Some(checksum.into()),
+            scope: BackupScope::All,
+            encrypted: false,
+            uncompressed_size: None,
+            physical: false,
+        }
+    }
+
+    #[test]
+    fn test_01_check_integrity() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(b"CREATE TABLE test (id INT);\n")
+            .expect("Failed to compress");
+        let data = encoder.finish().expect("Failed to compress");
+
+        let check = check_integrity(
+            Cursor::new(data.clone()),
+            CompressionFormat::Gzip,
+            None,
+            None,
+        )
+        .expect("Failed to check backup");
+
+        assert_eq!(check.size, data.len() as u64);
+        assert!(!check.checksum_verified);
+
+        let valid = manifest(check.size, &check.checksum);
+        let check = check_integrity(
+            Cursor::new(data.clone()),
+            CompressionFormat::Gzip,
+            None,
+            Some(&valid),
+        )
+        .expect("Failed to check backup");
+
+        assert!(check.checksum_verified);
+
+        let wrong = manifest(check.size, "sha256:00");
+        assert!(check_integrity(
+            Cursor::new(data.clone()),
+            CompressionFormat::Gzip,
+            None,
+            Some(&wrong)
+        )
+        .is_err());
+
+        let truncated = data[..data.len() - 6].to_vec();
+        assert!(
+            check_integrity(Cursor::new(truncated), CompressionFormat::Gzip, None, None).is_err()
+        );
+    }
+
+    #[test]
+    fn test_02_verify_all_report() {
+        let report = |name: &str| VerifyReport {
+            name: name.into(),
+            level: VerifyLevel::Checksum,
+            created_at: None,
+            size: 1,
+            checksum: "sha256:00".into(),
+            checksum_verified: true,
+            restored: false,
+            duration: Duration::ZERO,
+        };
+
+        let report = VerifyAllReport::from_results(vec![
+            ("db-b.gz".into(), Ok(report("db-b.gz"))),
+            (
+                "db-c.gz".into(),
+                Err(anyhow!("Backup size 1 does not match")),
+            ),
+            ("db-a.gz".into(), Ok(report("db-a.gz"))),
+        ]);
+
+        assert!(!report.is_success());
+        assert_eq!(report.passed[0].name, "db-a.gz");
+        assert_eq!(report.failed[0].name, "db-c.gz");
+
+        assert_eq!(get_verify_concurrency(VerifyLevel::Checksum, Some(0)), 1);
+        assert_eq!(get_verify_concurrency(VerifyLevel::Checksum, Some(8)), 8);
+        assert_eq!(get_verify_concurrency(VerifyLevel::Restore, Some(8)), 1);
+    }
+}